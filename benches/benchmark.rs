@@ -1,7 +1,13 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use criterion::{criterion_group, criterion_main, Criterion};
 use serde_klv::{
-    from_bytes, from_bytes_with_checksum,
+    decode_batch, from_bytes, from_bytes_with_checksum,
+    st0102::{Classification, SecurityLocalSet},
+    to_bytes, to_bytes_with_capacity,
     uasdls::{UASDatalinkLS, CRC},
+    KLVMap,
 };
 
 const KLV_FRAME_DATA: &[u8] = &[
@@ -18,6 +24,59 @@ const KLV_FRAME_DATA: &[u8] = &[
     0x02, 0x1c, 0x5f,
 ];
 
+// 確保回数を数えるアロケータ
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// 1回の呼び出しあたりの確保回数
+fn allocations<F: FnMut()>(mut f: F) -> usize {
+    const N: usize = 100;
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..N {
+        f();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) / N
+}
+
+// 確保回数は時間と別に表示する
+fn bench_allocations(_c: &mut Criterion) {
+    let x = from_bytes::<UASDatalinkLS>(KLV_FRAME_DATA).unwrap();
+    let cases: [(&str, &mut dyn FnMut()); 3] = [
+        ("klv_parse_UASDLS_sample", &mut || {
+            let _x = from_bytes::<UASDatalinkLS>(KLV_FRAME_DATA).unwrap();
+        }),
+        ("klv_serialize_UASDLS_sample", &mut || {
+            let _buf = to_bytes(&x).unwrap();
+        }),
+        ("klv_serialize_UASDLS_sample_with_capacity", &mut || {
+            let _buf = to_bytes_with_capacity(&x, KLV_FRAME_DATA.len()).unwrap();
+        }),
+    ];
+    for (name, f) in cases {
+        println!("{:<48} allocations: {}", name, allocations(f));
+    }
+}
+
 fn bench_main(c: &mut Criterion) {
     c.bench_function("klv_parse_UASDLS_sample", |b| {
         b.iter(|| {
@@ -29,7 +88,49 @@ fn bench_main(c: &mut Criterion) {
             let _x: UASDatalinkLS = from_bytes_with_checksum(KLV_FRAME_DATA, CRC {}).unwrap();
         })
     });
+    let x = from_bytes::<UASDatalinkLS>(KLV_FRAME_DATA).unwrap();
+    c.bench_function("klv_serialize_UASDLS_sample", |b| {
+        b.iter(|| {
+            let _buf = to_bytes(&x).unwrap();
+        })
+    });
+    c.bench_function("klv_serialize_UASDLS_sample_with_capacity", |b| {
+        b.iter(|| {
+            let _buf = to_bytes_with_capacity(&x, KLV_FRAME_DATA.len()).unwrap();
+        })
+    });
+    // 入れ子のLocal Setを含む場合
+    let mut nested = from_bytes::<UASDatalinkLS>(KLV_FRAME_DATA).unwrap();
+    nested.security_local_set = Some(SecurityLocalSet {
+        security_classification: Classification::Restricted,
+        classifying_country: "//JP",
+        version: 12,
+        ..Default::default()
+    });
+    c.bench_function("klv_serialize_UASDLS_nested_sample", |b| {
+        b.iter(|| {
+            let _buf = to_bytes(&nested).unwrap();
+        })
+    });
+}
+
+// 多数のパケットをKLVMapとして読む
+fn bench_batch(c: &mut Criterion) {
+    let packets = vec![KLV_FRAME_DATA; 1000];
+    c.bench_function("klvmap_parse_UASDLS_1000", |b| {
+        b.iter(|| {
+            let _x: Vec<_> = packets
+                .iter()
+                .map(|x| KLVMap::try_from_bytes(x).unwrap())
+                .collect();
+        })
+    });
+    c.bench_function("klvmap_decode_batch_UASDLS_1000", |b| {
+        b.iter(|| {
+            let _x = decode_batch(&packets);
+        })
+    });
 }
 
-criterion_group!(benches, bench_main);
+criterion_group!(benches, bench_allocations, bench_main, bench_batch);
 criterion_main!(benches);
@@ -0,0 +1,304 @@
+//! Attribute macro of tag metadata for `serde_klv`
+//!
+//! Use it through `serde_klv::klv` of the `derive` feature.
+//! `#[klv]` rewrites `#[klv(...)]` of the struct and its fields
+//! into `#[serde(rename = ..., with = ...)]`, so it must be placed above
+//! `#[derive(Serialize, Deserialize)]`.
+//!
+//! Struct attributes
+//!
+//! - `key = "..."`: Universal Key of the packet
+//!
+//! Field attributes
+//!
+//! - `tag = 13`: tag number of the field
+//! - `imap(min, max, len)`: `serde_klv::imap::Imap` with float `min`, `max` and `len` bytes
+//! - `scaled(Repr, SCALE)`: `serde_klv::scaled::Scaled::<Repr, SCALE>`
+//! - `varlen`: `serde_klv::varlen`
+//! - `with = "path"`: any other adapter module
+//! - `unknown`: catch-all field of `serde_klv::unknown::UnknownTags`
+//!
+//! `#[derive(MaxEncodedLen)]` implements `serde_klv::MaxEncodedLen`
+//! by the sum of the records of the fields.
+
+use proc_macro::TokenStream;
+use proc_macro2::{TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote};
+use syn::{
+    parse::Parser, parse_macro_input, parse_quote, punctuated::Punctuated, Data, DeriveInput, Expr,
+    Fields, ItemStruct, LitInt, LitStr, Token, Type,
+};
+
+/// Rewrite `#[klv(...)]` attributes to serde attributes
+///
+/// ```ignore
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::klv;
+///
+/// #[klv(key = "TEST")]
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// struct Position {
+///     #[klv(tag = 13, imap(-90.0, 90.0, 4))]
+///     latitude: f64,
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn klv(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut item = parse_macro_input!(input as ItemStruct);
+    match expand(args.into(), &mut item) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+// フィールドの値の変換方法
+enum Adapter {
+    Imap(Expr, Expr, Expr),
+    Scaled(Type, Expr),
+    Varlen,
+    With(LitStr),
+}
+
+fn expand(args: TokenStream2, item: &mut ItemStruct) -> syn::Result<TokenStream2> {
+    let mut key: Option<LitStr> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("key") {
+            key = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported klv attribute, expected `key`"))
+        }
+    });
+    parser.parse2(args)?;
+    if let Some(key) = key {
+        item.attrs.push(parse_quote!(#[serde(rename = #key)]));
+    }
+
+    let struct_ident = item.ident.clone();
+    let fields = match &mut item.fields {
+        Fields::Named(f) => &mut f.named,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &item.ident,
+                "klv supports only struct with named fields",
+            ))
+        }
+    };
+
+    let mut modules = vec![];
+    for field in fields.iter_mut() {
+        let (klv_attrs, rest): (Vec<_>, Vec<_>) = field
+            .attrs
+            .drain(..)
+            .partition(|a| a.path().is_ident("klv"));
+        field.attrs = rest;
+
+        let mut tag: Option<LitInt> = None;
+        let mut adapter: Option<Adapter> = None;
+        let mut unknown = false;
+        for attr in klv_attrs {
+            attr.parse_nested_meta(|meta| {
+                let next = if meta.path.is_ident("tag") {
+                    if tag.is_some() {
+                        return Err(meta.error("duplicate tag"));
+                    }
+                    let lit: LitInt = meta.value()?.parse()?;
+                    lit.base10_parse::<u128>()?;
+                    tag = Some(lit);
+                    return Ok(());
+                } else if meta.path.is_ident("unknown") {
+                    unknown = true;
+                    return Ok(());
+                } else if meta.path.is_ident("imap") {
+                    let content;
+                    syn::parenthesized!(content in meta.input);
+                    let args = Punctuated::<Expr, Token![,]>::parse_terminated(&content)?;
+                    let mut args = args.into_iter();
+                    match (args.next(), args.next(), args.next(), args.next()) {
+                        (Some(min), Some(max), Some(len), None) => Adapter::Imap(min, max, len),
+                        _ => return Err(meta.error("imap expects (min, max, len)")),
+                    }
+                } else if meta.path.is_ident("scaled") {
+                    let content;
+                    syn::parenthesized!(content in meta.input);
+                    let repr: Type = content.parse()?;
+                    content.parse::<Token![,]>()?;
+                    let scale: Expr = content.parse()?;
+                    Adapter::Scaled(repr, scale)
+                } else if meta.path.is_ident("varlen") {
+                    Adapter::Varlen
+                } else if meta.path.is_ident("with") {
+                    Adapter::With(meta.value()?.parse()?)
+                } else {
+                    return Err(meta.error(
+                        "unsupported klv attribute, expected `tag`, `imap`, `scaled`, `varlen`, `with` or `unknown`",
+                    ));
+                };
+                if adapter.is_some() {
+                    return Err(meta.error("only one adapter can be set to a field"));
+                }
+                adapter = Some(next);
+                Ok(())
+            })?;
+        }
+
+        if unknown {
+            if tag.is_some() || adapter.is_some() {
+                return Err(syn::Error::new_spanned(
+                    &field.ident,
+                    "unknown can not be combined with other klv attributes",
+                ));
+            }
+            field
+                .attrs
+                .push(parse_quote!(#[serde(rename = "$unknown_tags", default)]));
+            continue;
+        }
+        if let Some(tag) = tag {
+            let name = tag.base10_digits().to_string();
+            field.attrs.push(parse_quote!(#[serde(rename = #name)]));
+        }
+        let with = match adapter {
+            None => continue,
+            Some(Adapter::Imap(min, max, len)) => {
+                // Imapは値を持つためフィールドごとにwith用のモジュールを生成する
+                let field_ident = field.ident.as_ref().expect("named field");
+                let module = format_ident!("__klv_imap_{}_{}", struct_ident, field_ident);
+                modules.push(quote! {
+                    #[doc(hidden)]
+                    #[allow(non_snake_case)]
+                    mod #module {
+                        const IMAP: ::serde_klv::imap::Imap =
+                            ::serde_klv::imap::Imap::new(#min, #max, #len);
+
+                        pub fn serialize<T, S>(
+                            value: &T,
+                            serializer: S,
+                        ) -> ::core::result::Result<S::Ok, S::Error>
+                        where
+                            T: ::serde_klv::scaled::ScaledFloat,
+                            S: ::serde::Serializer,
+                        {
+                            IMAP.serialize(value, serializer)
+                        }
+
+                        pub fn deserialize<'de, T, D>(
+                            deserializer: D,
+                        ) -> ::core::result::Result<T, D::Error>
+                        where
+                            T: ::serde_klv::scaled::ScaledFloat,
+                            D: ::serde::Deserializer<'de>,
+                        {
+                            IMAP.deserialize(deserializer)
+                        }
+                    }
+                });
+                module.to_string()
+            }
+            Some(Adapter::Scaled(repr, scale)) => format!(
+                "::serde_klv::scaled::Scaled::<{}, {}>",
+                quote!(#repr),
+                quote!(#scale)
+            ),
+            Some(Adapter::Varlen) => "::serde_klv::varlen".to_string(),
+            Some(Adapter::With(path)) => path.value(),
+        };
+        field.attrs.push(parse_quote!(#[serde(with = #with)]));
+    }
+
+    Ok(quote! {
+        #item
+        #(#modules)*
+    })
+}
+
+/// Derive `serde_klv::MaxEncodedLen` from the fields
+///
+/// Each field is counted as a record of 1 byte tag.
+/// `#[max_len(N)]` gives the bound of the value for fields without `MaxEncodedLen`,
+/// such as `String` or fields with an adapter.
+///
+/// See `serde_klv::MaxEncodedLen` for the example, compiled with the `derive` feature of `serde_klv`.
+#[proc_macro_derive(MaxEncodedLen, attributes(max_len))]
+pub fn derive_max_encoded_len(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_max_encoded_len(input) {
+        Ok(ts) => ts.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_max_encoded_len(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(f) => &f.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "MaxEncodedLen supports only struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "MaxEncodedLen supports only struct",
+            ))
+        }
+    };
+
+    let mut generics = input.generics.clone();
+    let mut lens = vec![];
+    for field in fields {
+        if field.attrs.iter().any(is_serde_skip) {
+            continue;
+        }
+        let mut max_len: Option<Expr> = None;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("max_len")) {
+            if max_len.is_some() {
+                return Err(syn::Error::new_spanned(attr, "duplicate max_len"));
+            }
+            max_len = Some(attr.parse_args()?);
+        }
+        let len = match max_len {
+            Some(len) => quote!(#len),
+            None => {
+                // 型の上限を使う場合のみ境界を加える
+                let ty = &field.ty;
+                generics
+                    .make_where_clause()
+                    .predicates
+                    .push(parse_quote!(#ty: ::serde_klv::MaxEncodedLen));
+                quote!(<#ty as ::serde_klv::MaxEncodedLen>::MAX_VALUE_LEN)
+            }
+        };
+        lens.push(quote!(::serde_klv::size::max_record_len(1, #len)));
+    }
+
+    let sum = if lens.is_empty() {
+        quote!(0)
+    } else {
+        quote!(#(#lens)+*)
+    };
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::serde_klv::MaxEncodedLen for #ident #ty_generics #where_clause {
+            const MAX_VALUE_LEN: usize = #sum;
+        }
+    })
+}
+
+// #[serde(skip)]または#[serde(skip_serializing)]のフィールドは書き込まれない
+fn is_serde_skip(attr: &syn::Attribute) -> bool {
+    if !attr.path().is_ident("serde") {
+        return false;
+    }
+    let Ok(list) = attr.meta.require_list() else {
+        return false;
+    };
+    list.tokens.clone().into_iter().any(|t| match t {
+        TokenTree::Ident(i) => i == "skip" || i == "skip_serializing",
+        _ => false,
+    })
+}
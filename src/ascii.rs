@@ -0,0 +1,167 @@
+//! String restricted to ISO 646 (ASCII)
+//!
+//! MISB requires some string tags to be ISO 646.
+//! [`AsciiString`] checks the characters on construction,
+//! so the serializer writes only ASCII bytes for the field.
+//! Deserialize returns error for non-ASCII string regardless of [`crate::StringPolicy`].
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{ascii::AsciiString, from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "3")]
+//!     mission_id: AsciiString,
+//! }
+//!
+//! let t = Packet {
+//!     mission_id: AsciiString::new("MISSION01").unwrap(),
+//! };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(from_bytes::<Packet>(&buf).unwrap(), t);
+//! assert!(AsciiString::new("ミッション").is_err());
+//! ```
+
+use std::fmt;
+use std::ops::Deref;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::{Error, Result};
+
+/// String of ASCII characters
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AsciiString(String);
+
+impl AsciiString {
+    /// return [`Error::Encode`] if the string has non-ASCII character
+    pub fn new(s: impl Into<String>) -> Result<Self> {
+        let s = s.into();
+        match s.bytes().position(|x| !x.is_ascii()) {
+            None => Ok(Self(s)),
+            Some(i) => Err(Error::Encode(format!(
+                "non-ASCII byte {:#04x} at {} of string",
+                s.as_bytes()[i],
+                i
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl Deref for AsciiString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for AsciiString {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+impl TryFrom<String> for AsciiString {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl TryFrom<&str> for AsciiString {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self> {
+        Self::new(s)
+    }
+}
+
+impl From<AsciiString> for String {
+    fn from(s: AsciiString) -> Self {
+        s.0
+    }
+}
+
+impl Serialize for AsciiString {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for AsciiString {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Self::new(s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::AsciiString;
+    use crate::{from_bytes, from_bytes_with_config, DeserializerConfig, StringPolicy};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestString<'a> {
+        #[serde(rename = "10")]
+        ascii: AsciiString,
+        #[serde(rename = "11")]
+        str: &'a str,
+    }
+
+    #[test]
+    fn test_string_policy() {
+        // 0xe9はLatin-1の'é'、UTF-8としては不正
+        let buf = [b'T', b'E', b'S', b'T', 7, 10, 1, b'A', 11, 2, b'B', 0xe9];
+        assert!(from_bytes::<TestString>(&buf).is_err());
+        let config = DeserializerConfig {
+            string_policy: StringPolicy::Ascii,
+            ..Default::default()
+        };
+        assert!(from_bytes_with_config::<TestString>(&buf, config).is_err());
+
+        // 借用する&strは変換後の文字列を受け取れない
+        let config = DeserializerConfig {
+            string_policy: StringPolicy::Latin1,
+            ..Default::default()
+        };
+        assert!(from_bytes_with_config::<TestString>(&buf, config).is_err());
+
+        // ASCIIのみであれば借用できる
+        let buf = [b'T', b'E', b'S', b'T', 7, 10, 1, b'A', 11, 2, b'B', b'C'];
+        let x = from_bytes_with_config::<TestString>(&buf, config).unwrap();
+        assert_eq!(x.ascii.as_str(), "A");
+        assert_eq!(x.str, "BC");
+
+        // AsciiStringはポリシーに関わらず検査する
+        let buf = [b'T', b'E', b'S', b'T', 7, 10, 2, 0xc3, 0xa9, 11, 1, b'B'];
+        assert!(from_bytes::<TestString>(&buf).is_err());
+        let config = DeserializerConfig {
+            replace_invalid_chars: true,
+            ..Default::default()
+        };
+        assert!(from_bytes_with_config::<TestString>(&buf, config).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! Bit field adapter for packed boolean flags
+//!
+//! Use with `#[serde(with = "serde_klv::bits")]` for a type implementing [`BitFields`],
+//! such as ST 0601 tag 47 Generic Flag Data.
+//! The value is written as big-endian unsigned integer of [`BitFields::LEN`] bytes.
+//!
+//! [`bit_fields!`](crate::bit_fields) defines a struct of bools with the bit number of each field,
+//! bit 0 is the least significant bit.
+//! The struct also implements `Serialize` and `Deserialize`, so it can be used in `Option` without `with`.
+//! A type of `bitflags` crate can implement [`BitFields`] by `bits()` and `from_bits_retain()`.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{bit_fields, from_bytes, to_bytes};
+//!
+//! bit_fields! {
+//!     #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+//!     pub struct Flags: 1 {
+//!         pub laser_range = 0,
+//!         pub auto_track = 1,
+//!         pub image_invalid = 5,
+//!     }
+//! }
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "47", with = "serde_klv::bits")]
+//!     flags: Flags,
+//!     #[serde(rename = "48", with = "serde_klv::bits")]
+//!     raw: u16,
+//! }
+//!
+//! let t = Packet {
+//!     flags: Flags { auto_track: true, image_invalid: true, ..Default::default() },
+//!     raw: 0x0102,
+//! };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(&buf[4..], &[7, 47, 1, 0b0010_0010, 48, 2, 1, 2]);
+//! assert_eq!(from_bytes::<Packet>(&buf).unwrap(), t);
+//! ```
+
+use std::fmt;
+
+use serde::{
+    de::{self, Visitor},
+    ser, Deserializer, Serializer,
+};
+
+/// Type packed into 1 to 4 bytes of bits
+pub trait BitFields: Sized {
+    /// byte size of the field, 1 to 4
+    const LEN: usize;
+
+    fn to_bits(&self) -> u32;
+
+    /// build from the bits, unknown bits may be dropped
+    fn from_bits(bits: u32) -> Self;
+}
+
+impl BitFields for u8 {
+    const LEN: usize = 1;
+
+    fn to_bits(&self) -> u32 {
+        *self as u32
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        bits as u8
+    }
+}
+
+impl BitFields for u16 {
+    const LEN: usize = 2;
+
+    fn to_bits(&self) -> u32 {
+        *self as u32
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        bits as u16
+    }
+}
+
+impl BitFields for u32 {
+    const LEN: usize = 4;
+
+    fn to_bits(&self) -> u32 {
+        *self
+    }
+
+    fn from_bits(bits: u32) -> Self {
+        bits
+    }
+}
+
+/// serialize bits by [`BitFields::LEN`] big-endian bytes
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: BitFields,
+    S: Serializer,
+{
+    if !(1..=4).contains(&T::LEN) {
+        return Err(ser::Error::custom(format!(
+            "bit field length {} is not in 1 to 4",
+            T::LEN
+        )));
+    }
+    let bits = value.to_bits();
+    if T::LEN < 4 && bits >> (8 * T::LEN) != 0 {
+        return Err(ser::Error::custom(format!(
+            "bits {:#x} does not fit in {} bytes",
+            bits,
+            T::LEN
+        )));
+    }
+    serializer.serialize_bytes(&bits.to_be_bytes()[4 - T::LEN..])
+}
+
+/// deserialize bits from up to 4 bytes
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: BitFields,
+    D: Deserializer<'de>,
+{
+    let bits = deserializer.deserialize_bytes(BitsVisitor)?;
+    if T::LEN < 4 && bits >> (8 * T::LEN) != 0 {
+        return Err(de::Error::custom(format!(
+            "bits {:#x} does not fit in {} bytes",
+            bits,
+            T::LEN
+        )));
+    }
+    Ok(T::from_bits(bits))
+}
+
+struct BitsVisitor;
+
+impl<'de> Visitor<'de> for BitsVisitor {
+    type Value = u32;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("bit field up to 4 bytes")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if v.len() > 4 {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        Ok(v.iter().fold(0_u32, |acc, b| (acc << 8) | *b as u32))
+    }
+}
+
+/// Define a struct of bool fields packed into bits
+///
+/// `struct Name: LEN { field = BIT, ... }` implements [`BitFields`] with `LEN` bytes,
+/// [`crate::MaxEncodedLen`], `Serialize` and `Deserialize` by [`crate::bits`].
+#[macro_export]
+macro_rules! bit_fields {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident: $len:literal {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident = $bit:literal
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field: bool,
+            )*
+        }
+
+        impl $crate::bits::BitFields for $name {
+            const LEN: usize = $len;
+
+            fn to_bits(&self) -> u32 {
+                let mut bits = 0_u32;
+                $(
+                    if self.$field {
+                        bits |= 1 << $bit;
+                    }
+                )*
+                bits
+            }
+
+            fn from_bits(bits: u32) -> Self {
+                Self {
+                    $($field: bits & (1 << $bit) != 0,)*
+                }
+            }
+        }
+
+        impl $crate::size::MaxEncodedLen for $name {
+            const MAX_VALUE_LEN: usize = $len;
+        }
+
+        impl ::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error>
+            where
+                S: ::serde::Serializer,
+            {
+                $crate::bits::serialize(self, serializer)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::core::result::Result<Self, D::Error>
+            where
+                D: ::serde::Deserializer<'de>,
+            {
+                $crate::bits::deserialize(deserializer)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{from_bytes, to_bytes};
+
+    bit_fields! {
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+        struct TestFlags: 3 {
+            low = 0,
+            high = 23,
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestBits {
+        #[serde(rename = "10", skip_serializing_if = "Option::is_none")]
+        flags: Option<TestFlags>,
+        #[serde(rename = "11", with = "crate::bits")]
+        u8: u8,
+    }
+
+    #[test]
+    fn test_bits() {
+        let t = TestBits {
+            flags: Some(TestFlags {
+                low: true,
+                high: true,
+            }),
+            u8: 0x80,
+        };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(&buf[4..], &[8, 10, 3, 0x80, 0, 1, 11, 1, 0x80]);
+        assert_eq!(from_bytes::<TestBits>(&buf).unwrap(), t);
+
+        // 定義されていないビットは捨てられる
+        let buf = [b'T', b'E', b'S', b'T', 8, 10, 3, 0, 0x10, 1, 11, 1, 0];
+        let x = from_bytes::<TestBits>(&buf).unwrap();
+        assert_eq!(
+            x.flags,
+            Some(TestFlags {
+                low: true,
+                high: false
+            })
+        );
+
+        // 長さを超えるビット
+        let buf = [b'T', b'E', b'S', b'T', 4, 11, 2, 1, 0];
+        assert!(from_bytes::<TestBits>(&buf).is_err());
+    }
+}
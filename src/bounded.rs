@@ -0,0 +1,188 @@
+//! Integer restricted to a range
+//!
+//! [`Bounded`] checks the value on construction, serialize and deserialize,
+//! so invalid telemetry is rejected at the boundary of the application.
+//! The bounds are inclusive and given as `i128` const parameters.
+//!
+//! `NonZeroU8` to `NonZeroU64` and the signed ones are supported by serde as is,
+//! and zero is rejected on deserialize.
+//!
+//! Example
+//!
+//! ```rust
+//! use std::num::NonZeroU16;
+//!
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{bounded::Bounded, from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "5")]
+//!     heading: Bounded<u16, 0, 359>,
+//!     #[serde(rename = "6")]
+//!     id: NonZeroU16,
+//! }
+//!
+//! let t = Packet {
+//!     heading: Bounded::new(180).unwrap(),
+//!     id: NonZeroU16::new(1).unwrap(),
+//! };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(from_bytes::<Packet>(&buf).unwrap(), t);
+//! assert!(Bounded::<u16, 0, 359>::new(360).is_err());
+//!
+//! // heading 360
+//! let buf = [b'T', b'E', b'S', b'T', 8, 5, 2, 0x01, 0x68, 6, 2, 0, 1];
+//! assert!(from_bytes::<Packet>(&buf).is_err());
+//! ```
+
+use std::fmt;
+use std::ops::Deref;
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::{Error, Result};
+
+/// Integer in `MIN..=MAX`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bounded<T, const MIN: i128, const MAX: i128>(T);
+
+impl<T, const MIN: i128, const MAX: i128> Bounded<T, MIN, MAX>
+where
+    T: Copy + Into<i128>,
+{
+    /// return [`Error::Encode`] if the value is out of the range
+    pub fn new(value: T) -> Result<Self> {
+        check::<MIN, MAX>(value.into())
+            .map(|_| Self(value))
+            .map_err(Error::Encode)
+    }
+
+    pub fn get(&self) -> T {
+        self.0
+    }
+}
+
+fn check<const MIN: i128, const MAX: i128>(value: i128) -> std::result::Result<(), String> {
+    if (MIN..=MAX).contains(&value) {
+        Ok(())
+    } else {
+        Err(format!("value {} is out of range {}..={}", value, MIN, MAX))
+    }
+}
+
+impl<T, const MIN: i128, const MAX: i128> Deref for Bounded<T, MIN, MAX> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: fmt::Display, const MIN: i128, const MAX: i128> fmt::Display for Bounded<T, MIN, MAX> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(formatter)
+    }
+}
+
+impl<T, const MIN: i128, const MAX: i128> Serialize for Bounded<T, MIN, MAX>
+where
+    T: Copy + Into<i128> + Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        check::<MIN, MAX>(self.0.into()).map_err(ser::Error::custom)?;
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T, const MIN: i128, const MAX: i128> Deserialize<'de> for Bounded<T, MIN, MAX>
+where
+    T: Copy + Into<i128> + Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = T::deserialize(deserializer)?;
+        check::<MIN, MAX>(value.into()).map_err(de::Error::custom)?;
+        Ok(Self(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::{NonZeroI32, NonZeroU64, NonZeroU8};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::Bounded;
+    use crate::error::{DecodeErrorKind, Error};
+    use crate::{from_bytes, to_bytes};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestBounded {
+        #[serde(rename = "10")]
+        heading: Bounded<u16, 0, 360>,
+        #[serde(rename = "11")]
+        pitch: Bounded<i8, -20, 20>,
+        #[serde(rename = "12")]
+        nonzero_u8: NonZeroU8,
+        #[serde(rename = "13")]
+        nonzero_i32: NonZeroI32,
+        #[serde(rename = "14")]
+        nonzero_u64: Option<NonZeroU64>,
+    }
+
+    #[test]
+    fn test_bounded() {
+        let t = TestBounded {
+            heading: Bounded::new(360).unwrap(),
+            pitch: Bounded::new(-20).unwrap(),
+            nonzero_u8: NonZeroU8::new(1).unwrap(),
+            nonzero_i32: NonZeroI32::new(-1).unwrap(),
+            nonzero_u64: NonZeroU64::new(u64::MAX),
+        };
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(&buf[4..], &[
+            26,
+            10, 2, 0x01, 0x68,
+            11, 1, 0xec,
+            12, 1, 1,
+            13, 4, 0xff, 0xff, 0xff, 0xff,
+            14, 8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ]);
+        assert_eq!(from_bytes::<TestBounded>(&buf).unwrap(), t);
+        assert_eq!(*t.heading, 360);
+        assert_eq!(t.pitch.get(), -20);
+        assert!(Bounded::<i8, -20, 20>::new(21).is_err());
+
+        // 範囲外の値はタグの位置を持つエラーになる
+        let mut broken = buf.clone();
+        broken[8] = 0x69;
+        match from_bytes::<TestBounded>(&broken) {
+            Err(Error::Decode {
+                path,
+                kind: DecodeErrorKind::Custom(msg),
+                ..
+            }) => {
+                assert_eq!(path, vec![10]);
+                assert_eq!(msg, "value 361 is out of range 0..=360");
+            }
+            x => unreachable!("{:?}", x),
+        }
+        let mut broken = buf.clone();
+        broken[11] = 0xeb;
+        assert!(from_bytes::<TestBounded>(&broken).is_err());
+
+        // NonZeroは0を受け付けない
+        let mut broken = buf;
+        broken[14] = 0;
+        assert!(from_bytes::<TestBounded>(&broken).is_err());
+    }
+}
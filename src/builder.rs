@@ -0,0 +1,226 @@
+use byteorder::{BigEndian, WriteBytesExt};
+
+use crate::checksum::CheckSumCalc;
+use crate::error::{Error, Result};
+use crate::LengthOctet;
+
+/// Build KLV packet field-by-field without serde
+///
+/// Example
+/// ```
+/// use serde::Deserialize;
+/// use serde_klv::{from_bytes, KLVBuilder};
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Packet<'a> {
+///     #[serde(rename = "10")]
+///     u16: u16,
+///     #[serde(rename = "11")]
+///     str: &'a str,
+///     #[serde(rename = "12")]
+///     child: Child,
+/// }
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// struct Child {
+///     #[serde(rename = "1")]
+///     u8: u8,
+/// }
+///
+/// let mut child = KLVBuilder::nested();
+/// child.push_u8(1, 255);
+/// let buf = KLVBuilder::new(b"TEST")
+///     .unwrap()
+///     .push_u16(10, 1234)
+///     .push_str(11, "abc")
+///     .push_nested(12, &child)
+///     .finish();
+/// let x = from_bytes::<Packet>(&buf).unwrap();
+/// assert_eq!(x, Packet { u16: 1234, str: "abc", child: Child { u8: 255 } });
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KLVBuilder {
+    universal_key: Vec<u8>,
+    content: Vec<u8>,
+}
+
+impl KLVBuilder {
+    /// create builder of packet with universal key
+    pub fn new(universal_key: &[u8]) -> Result<Self> {
+        match universal_key.len() {
+            1 | 2 | 4 | 16 => Ok(Self {
+                universal_key: universal_key.to_vec(),
+                content: vec![],
+            }),
+            x => Err(Error::Key(format!(
+                "universal key support length only {{1,2,4,16}} got {}",
+                x
+            ))),
+        }
+    }
+
+    /// create builder of nested local set without universal key
+    pub fn nested() -> Self {
+        Self::default()
+    }
+
+    /// push record of raw value bytes
+    pub fn push_bytes(&mut self, tag: u8, value: &[u8]) -> &mut Self {
+        self.content.push(tag);
+        // Vecへの書き込みは失敗しない
+        LengthOctet::length_to_buf(&mut self.content, value.len()).unwrap();
+        self.content.extend_from_slice(value);
+        self
+    }
+
+    pub fn push_bool(&mut self, tag: u8, v: bool) -> &mut Self {
+        self.push_bytes(tag, &[v as u8])
+    }
+
+    pub fn push_u8(&mut self, tag: u8, v: u8) -> &mut Self {
+        self.push_bytes(tag, &[v])
+    }
+
+    pub fn push_u16(&mut self, tag: u8, v: u16) -> &mut Self {
+        self.push_bytes(tag, &v.to_be_bytes())
+    }
+
+    pub fn push_u32(&mut self, tag: u8, v: u32) -> &mut Self {
+        self.push_bytes(tag, &v.to_be_bytes())
+    }
+
+    pub fn push_u64(&mut self, tag: u8, v: u64) -> &mut Self {
+        self.push_bytes(tag, &v.to_be_bytes())
+    }
+
+    pub fn push_i8(&mut self, tag: u8, v: i8) -> &mut Self {
+        self.push_bytes(tag, &v.to_be_bytes())
+    }
+
+    pub fn push_i16(&mut self, tag: u8, v: i16) -> &mut Self {
+        self.push_bytes(tag, &v.to_be_bytes())
+    }
+
+    pub fn push_i32(&mut self, tag: u8, v: i32) -> &mut Self {
+        self.push_bytes(tag, &v.to_be_bytes())
+    }
+
+    pub fn push_i64(&mut self, tag: u8, v: i64) -> &mut Self {
+        self.push_bytes(tag, &v.to_be_bytes())
+    }
+
+    pub fn push_f32(&mut self, tag: u8, v: f32) -> &mut Self {
+        self.push_bytes(tag, &v.to_be_bytes())
+    }
+
+    pub fn push_f64(&mut self, tag: u8, v: f64) -> &mut Self {
+        self.push_bytes(tag, &v.to_be_bytes())
+    }
+
+    pub fn push_str(&mut self, tag: u8, v: &str) -> &mut Self {
+        self.push_bytes(tag, v.as_bytes())
+    }
+
+    /// push nested local set. universal key of the nested builder is ignored
+    pub fn push_nested(&mut self, tag: u8, nested: &KLVBuilder) -> &mut Self {
+        self.push_bytes(tag, &nested.content)
+    }
+
+    /// encode packet
+    pub fn finish(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(
+            self.universal_key.len()
+                + LengthOctet::encoded_len(self.content.len())
+                + self.content.len(),
+        );
+        buf.extend_from_slice(&self.universal_key);
+        LengthOctet::length_to_buf(&mut buf, self.content.len()).unwrap();
+        buf.extend_from_slice(&self.content);
+        buf
+    }
+
+    /// encode packet with checksum record at last
+    pub fn finish_with_checksum<C: CheckSumCalc>(&self, crc: C) -> Vec<u8> {
+        let width = crc.width();
+        let mut buf = self.universal_key.clone();
+        // K + L + V(width)
+        LengthOctet::length_to_buf(&mut buf, self.content.len() + 2 + width.size()).unwrap();
+        let content_start = buf.len();
+        buf.extend_from_slice(&self.content);
+        buf.extend_from_slice(&[crc.tag(), width.size() as u8]);
+        let covered =
+            crc.coverage()
+                .covered_range_of(self.universal_key.len(), content_start, buf.len());
+        let crc_code = width.truncate(crc.checksum(&buf[covered]));
+        buf.write_uint::<BigEndian>(crc_code as u64, width.size())
+            .unwrap();
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{from_bytes_with_checksum, to_bytes, KLVBuilder, WrappedCRC};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TESTDATA00000000")]
+    struct TestBuilder<'a> {
+        #[serde(rename = "10")]
+        i8: i8,
+        #[serde(rename = "11")]
+        u64: u64,
+        #[serde(rename = "12")]
+        f64: f64,
+        #[serde(rename = "13")]
+        str: &'a str,
+        #[serde(rename = "14")]
+        child: TestChild,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestChild {
+        #[serde(rename = "1")]
+        bool: bool,
+        #[serde(rename = "2")]
+        i32: i32,
+    }
+
+    // serdeでシリアライズした結果と一致する
+    #[test]
+    fn test_builder() {
+        let long_str = "s".repeat(200);
+        let t = TestBuilder {
+            i8: -1,
+            u64: u64::MAX,
+            f64: 1.5,
+            str: &long_str,
+            child: TestChild {
+                bool: true,
+                i32: i32::MIN,
+            },
+        };
+        let mut child = KLVBuilder::nested();
+        child.push_bool(1, true).push_i32(2, i32::MIN);
+        let mut builder = KLVBuilder::new(b"TESTDATA00000000").unwrap();
+        builder
+            .push_i8(10, -1)
+            .push_u64(11, u64::MAX)
+            .push_f64(12, 1.5)
+            .push_str(13, t.str)
+            .push_nested(14, &child);
+        assert_eq!(builder.finish(), to_bytes(&t).unwrap());
+
+        let buf = builder.finish_with_checksum(WrappedCRC::default());
+        let x: TestBuilder = from_bytes_with_checksum(&buf, WrappedCRC::default()).unwrap();
+        assert_eq!(t, x);
+    }
+
+    #[test]
+    fn test_builder_universal_key_error() {
+        assert!(KLVBuilder::new(b"ABC").is_err());
+        assert!(KLVBuilder::new(&[]).is_err());
+    }
+}
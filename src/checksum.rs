@@ -1,39 +1,282 @@
-pub(crate) const CHECKSUM_KEY_LENGTH: &[u8; 2] = &[0x01, 0x02];
+use std::ops::Range;
 
+/// Byte width of checksum value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckSumWidth {
+    U8,
+    U16,
+    U32,
+}
+
+impl CheckSumWidth {
+    /// number of bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::U16 => 2,
+            Self::U32 => 4,
+        }
+    }
+
+    /// drop the bits that do not fit in the width
+    pub fn truncate(&self, value: u32) -> u32 {
+        match self {
+            Self::U8 => value & u8::MAX as u32,
+            Self::U16 => value & u16::MAX as u32,
+            Self::U32 => value,
+        }
+    }
+}
+
+/// Range of bytes covered by checksum
+///
+/// Example
+/// ```
+/// use serde_klv::CheckSumCoverage;
+///
+/// // 4 bytes universal key, 1 byte length and 6 bytes of records before the checksum value
+/// let len = 4 + 1 + 6;
+/// assert_eq!(CheckSumCoverage::UntilChecksumLength.covered_range_of(4, 5, len), 0..11);
+/// assert_eq!(CheckSumCoverage::FromLengthUntilChecksumLength.covered_range_of(4, 5, len), 4..11);
+/// assert_eq!(CheckSumCoverage::ContentUntilChecksumRecord.covered_range_of(4, 5, len), 5..9);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckSumCoverage {
+    /// from universal key to the length of checksum record (MISB ST 0601)
+    UntilChecksumLength,
+    /// from universal key to the byte before checksum record
+    UntilChecksumRecord,
+    /// from length of the packet to the length of checksum record, excluding universal key
+    FromLengthUntilChecksumLength,
+    /// from the first record to the length of checksum record
+    ContentUntilChecksumLength,
+    /// records before checksum record only
+    ContentUntilChecksumRecord,
+}
+
+impl CheckSumCoverage {
+    /// slice covered bytes from packet without checksum value
+    ///
+    /// The packet is assumed to start with the covered bytes, see [`Self::covered_range`].
+    #[deprecated(
+        since = "0.4.0",
+        note = "wrong for the coverages excluding the header, use `covered_range_of`"
+    )]
+    pub fn covered<'a>(&self, packet: &'a [u8]) -> &'a [u8] {
+        &packet[self.covered_range_of(0, 0, packet.len())]
+    }
+
+    /// range of covered bytes from length of packet without checksum value
+    ///
+    /// Universal key and length of the packet are treated as empty,
+    /// so the coverages excluding them return `0..len`.
+    #[deprecated(
+        since = "0.4.0",
+        note = "wrong for the coverages excluding the header, use `covered_range_of`"
+    )]
+    pub fn covered_range(&self, len: usize) -> Range<usize> {
+        self.covered_range_of(0, 0, len)
+    }
+
+    /// range of covered bytes in the packet
+    ///
+    /// `key_len` is the length of universal key, `content_start` is the offset of the first record
+    /// and `len` is the length of packet without checksum value.
+    pub fn covered_range_of(
+        &self,
+        key_len: usize,
+        content_start: usize,
+        len: usize,
+    ) -> Range<usize> {
+        match self {
+            Self::UntilChecksumLength => 0..len,
+            Self::UntilChecksumRecord => 0..len - 2,
+            Self::FromLengthUntilChecksumLength => key_len..len,
+            Self::ContentUntilChecksumLength => content_start..len,
+            Self::ContentUntilChecksumRecord => content_start..len - 2,
+        }
+    }
+}
+
+/// Where to find checksum record on verification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumLocation {
+    /// last record of the packet with [`CheckSumCalc::tag`]
+    #[default]
+    LastRecord,
+    /// first top level record of the tag, ignoring [`CheckSumCalc::tag`]
+    Tag(u8),
+    /// record with [`CheckSumCalc::tag`] starting at the byte offset in the packet
+    Offset(usize),
+}
+
+/// Result of checksum verification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    /// checksum is matched
+    Valid { value: u32, covered: Range<usize> },
+    /// checksum is unmatched. `value` is written in packet and `calced` is computed
+    Unmatched {
+        value: u32,
+        calced: u32,
+        covered: Range<usize>,
+    },
+    /// packet has not checksum record
+    NotFound,
+}
+
+impl ChecksumStatus {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, Self::Valid { .. })
+    }
+}
+
+/// Checksum calculation and its placement in packet
+///
+/// Checksum record is always placed at the end of packet.
 pub trait CheckSumCalc {
-    fn checksum(&self, bytes: &[u8]) -> u16;
+    /// calculate checksum from covered bytes
+    fn checksum(&self, bytes: &[u8]) -> u32;
+
+    /// tag of checksum record
+    fn tag(&self) -> u8 {
+        1
+    }
+
+    /// width of checksum value
+    fn width(&self) -> CheckSumWidth {
+        CheckSumWidth::U16
+    }
+
+    /// bytes covered by checksum
+    fn coverage(&self) -> CheckSumCoverage {
+        CheckSumCoverage::UntilChecksumLength
+    }
+}
+
+// 参照やトレイトオブジェクトもそのまま渡せるようにする
+impl<C: CheckSumCalc + ?Sized> CheckSumCalc for &C {
+    fn checksum(&self, bytes: &[u8]) -> u32 {
+        (**self).checksum(bytes)
+    }
+
+    fn tag(&self) -> u8 {
+        (**self).tag()
+    }
+
+    fn width(&self) -> CheckSumWidth {
+        (**self).width()
+    }
+
+    fn coverage(&self) -> CheckSumCoverage {
+        (**self).coverage()
+    }
+}
+
+/// Predefined 16bit checksum algorithms of [`WrappedCRC`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Crc16Algo {
+    /// CRC-16/ISO-IEC-14443-3-A
+    #[default]
+    Iso14443A,
+    /// CRC-16/CCITT-FALSE, also known as CRC-16/IBM-3740
+    Ccitt,
+    /// CRC-16/KERMIT
+    Kermit,
+    /// CRC-16/MODBUS
+    Modbus,
+    /// 16bit running sum of MISB ST 0601, not a CRC
+    Misb0601,
 }
 
 /// use crc crate `Crc<u16>`
+///
+/// Example
+/// ```
+/// use serde_klv::{CheckSumCalc, Crc16Algo, WrappedCRC};
+///
+/// let crc = WrappedCRC::with_algorithm(Crc16Algo::Kermit);
+/// assert_eq!(crc.checksum(b"123456789"), 0x2189);
+/// let sum = WrappedCRC::with_algorithm(Crc16Algo::Misb0601);
+/// assert_eq!(sum.checksum(&[0x01, 0x02, 0x03]), 0x0402);
+/// ```
 pub struct WrappedCRC {
-    crc: crc::Crc<u16>,
+    // NoneはMISB ST 0601の加算
+    crc: Option<crc::Crc<u16>>,
+    coverage: CheckSumCoverage,
 }
 
 impl Default for WrappedCRC {
     fn default() -> Self {
-        Self {
-            crc: crc::Crc::<u16>::new(&crc::CRC_16_ISO_IEC_14443_3_A),
-        }
+        Self::with_algorithm(Crc16Algo::default())
     }
 }
 
 impl CheckSumCalc for WrappedCRC {
-    fn checksum(&self, bytes: &[u8]) -> u16 {
-        self.crc.checksum(bytes)
+    fn checksum(&self, bytes: &[u8]) -> u32 {
+        match &self.crc {
+            Some(crc) => crc.checksum(bytes) as u32,
+            None => misb0601_checksum(bytes) as u32,
+        }
+    }
+
+    fn coverage(&self) -> CheckSumCoverage {
+        self.coverage
     }
 }
 
 impl WrappedCRC {
     pub fn new(crc: crc::Crc<u16>) -> Self {
-        Self { crc }
+        Self {
+            crc: Some(crc),
+            coverage: CheckSumCoverage::UntilChecksumLength,
+        }
+    }
+
+    /// change the bytes covered by checksum, [`CheckSumCoverage::UntilChecksumLength`] by default
+    ///
+    /// Example
+    /// ```
+    /// use serde_klv::{CheckSumCalc, CheckSumCoverage, WrappedCRC};
+    ///
+    /// let crc = WrappedCRC::default().with_coverage(CheckSumCoverage::ContentUntilChecksumRecord);
+    /// assert_eq!(crc.coverage(), CheckSumCoverage::ContentUntilChecksumRecord);
+    /// ```
+    pub fn with_coverage(mut self, coverage: CheckSumCoverage) -> Self {
+        self.coverage = coverage;
+        self
+    }
+
+    /// checksum by the predefined algorithm
+    pub fn with_algorithm(algo: Crc16Algo) -> Self {
+        let crc = match algo {
+            Crc16Algo::Iso14443A => &crc::CRC_16_ISO_IEC_14443_3_A,
+            Crc16Algo::Ccitt => &crc::CRC_16_IBM_3740,
+            Crc16Algo::Kermit => &crc::CRC_16_KERMIT,
+            Crc16Algo::Modbus => &crc::CRC_16_MODBUS,
+            Crc16Algo::Misb0601 => {
+                return Self {
+                    crc: None,
+                    coverage: CheckSumCoverage::UntilChecksumLength,
+                }
+            }
+        };
+        Self::new(crc::Crc::<u16>::new(crc))
     }
 }
 
+// MISB ST 0601の16bit加算。偶数番目のbyteを上位byteとして足し合わせる
+pub(crate) fn misb0601_checksum(bytes: &[u8]) -> u16 {
+    bytes.iter().enumerate().fold(0_u16, |acc, (i, b)| {
+        acc.wrapping_add((*b as u16) << (8 * ((i + 1) % 2)))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::ops::Deref;
 
-    use byteorder::BigEndian;
+    use byteorder::{BigEndian, ByteOrder};
     use cosmic_ray::Ray;
     use rand::Rng;
     use serde::{Deserialize, Serialize};
@@ -146,8 +389,9 @@ mod tests {
         let mut crc_buf = [0_u8; 2];
         crc_buf
             .as_mut_slice()
-            .write_u16::<BigEndian>(crc_code)
+            .write_u16::<BigEndian>(crc_code as u16)
             .unwrap();
+        assert_eq!(&buf[buf.len() - 2..], &crc_buf);
 
         // deserialize
         let x: TestString = from_bytes(&buf).unwrap();
@@ -155,4 +399,279 @@ mod tests {
         let x: TestString = from_bytes_with_checksum(&buf, WrappedCRC::default()).unwrap();
         assert_eq!(&t, &x);
     }
+
+    // checksumがあれば検証し、なければそのまま読む
+    #[test]
+    fn test_maybe_checksum() {
+        use crate::{error::Error, from_bytes_maybe_checksum};
+
+        let t = TestString {
+            string: "123".to_string(),
+            u64: 123,
+        };
+        let buf = to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap();
+        let (x, verified) =
+            from_bytes_maybe_checksum::<TestString, _>(&buf, WrappedCRC::default()).unwrap();
+        assert_eq!(x, t);
+        assert!(verified);
+
+        let mut broken = buf;
+        broken[20] ^= 1;
+        match from_bytes_maybe_checksum::<TestString, _>(&broken, WrappedCRC::default()) {
+            Err(Error::UnmatcheChecksum { .. }) => {}
+            x => unreachable!("{:?}", x),
+        }
+
+        let buf = to_bytes(&t).unwrap();
+        let (x, verified) =
+            from_bytes_maybe_checksum::<TestString, _>(&buf, WrappedCRC::default()).unwrap();
+        assert_eq!(x, t);
+        assert!(!verified);
+    }
+
+    // 末尾以外に置かれたchecksum
+    #[test]
+    fn test_checksum_location() {
+        use super::{CheckSumCalc, ChecksumLocation, ChecksumStatus};
+        use crate::verify_checksum;
+
+        let t = TestString {
+            string: "123".to_string(),
+            u64: 123,
+        };
+        let crc = WrappedCRC::default();
+        let buf = to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap();
+        let last = buf.len() - 4;
+        for location in [
+            ChecksumLocation::LastRecord,
+            ChecksumLocation::Tag(1),
+            ChecksumLocation::Offset(last),
+        ] {
+            assert!(verify_checksum(&buf, &crc, location).is_valid());
+        }
+        assert_eq!(
+            verify_checksum(&buf, &crc, ChecksumLocation::Offset(last - 1)),
+            ChecksumStatus::NotFound
+        );
+        assert_eq!(
+            verify_checksum(&buf, &crc, ChecksumLocation::Tag(2)),
+            ChecksumStatus::NotFound
+        );
+
+        // 先頭のレコードをchecksumとし、それ以降は範囲外
+        let body = to_bytes(&t).unwrap();
+        let mut buf = vec![];
+        buf.extend_from_slice(&body[..16]);
+        buf.push(body[16] + 4);
+        buf.extend_from_slice(&[1, 2, 0, 0]);
+        buf.extend_from_slice(&body[17..]);
+        let value = crc.checksum(&buf[..19]) as u16;
+        BigEndian::write_u16(&mut buf[19..21], value);
+        let status = verify_checksum(&buf, &crc, ChecksumLocation::Tag(1));
+        assert_eq!(
+            status,
+            ChecksumStatus::Valid {
+                value: value as u32,
+                covered: 0..19
+            }
+        );
+        assert_eq!(
+            verify_checksum(&buf, &crc, ChecksumLocation::LastRecord),
+            ChecksumStatus::NotFound
+        );
+        let len = buf.len();
+        buf[len - 1] ^= 1;
+        assert!(verify_checksum(&buf, &crc, ChecksumLocation::Offset(17)).is_valid());
+        buf[0] ^= 1;
+        assert!(matches!(
+            verify_checksum(&buf, &crc, ChecksumLocation::Offset(17)),
+            ChecksumStatus::Unmatched { .. }
+        ));
+    }
+
+    // タグ、幅、範囲を変更したchecksum
+    #[test]
+    fn test_checksum_configurable() {
+        use super::{CheckSumCalc, CheckSumCoverage, CheckSumWidth};
+
+        struct Crc32;
+        impl CheckSumCalc for Crc32 {
+            fn checksum(&self, bytes: &[u8]) -> u32 {
+                crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(bytes)
+            }
+            fn tag(&self) -> u8 {
+                0xf0
+            }
+            fn width(&self) -> CheckSumWidth {
+                CheckSumWidth::U32
+            }
+            fn coverage(&self) -> CheckSumCoverage {
+                CheckSumCoverage::UntilChecksumRecord
+            }
+        }
+
+        struct Sum8;
+        impl CheckSumCalc for Sum8 {
+            fn checksum(&self, bytes: &[u8]) -> u32 {
+                bytes.iter().map(|x| *x as u32).sum()
+            }
+            fn width(&self) -> CheckSumWidth {
+                CheckSumWidth::U8
+            }
+        }
+
+        let t = TestString {
+            string: "123".to_string(),
+            u64: 123,
+        };
+
+        let buf = to_bytes_with_checksum(&t, Crc32).unwrap();
+        assert_eq!(&buf[buf.len() - 6..buf.len() - 4], &[0xf0, 4]);
+        let crc_code = Crc32.checksum(&buf[0..buf.len() - 6]);
+        assert_eq!(BigEndian::read_u32(&buf[buf.len() - 4..]), crc_code);
+        let x: TestString = from_bytes_with_checksum(&buf, Crc32).unwrap();
+        assert_eq!(&t, &x);
+        assert!(from_bytes_with_checksum::<TestString, _>(&buf, WrappedCRC::default()).is_err());
+
+        let buf = to_bytes_with_checksum(&t, Sum8).unwrap();
+        assert_eq!(&buf[buf.len() - 3..buf.len() - 1], &[1, 1]);
+        let x: TestString = from_bytes_with_checksum(&buf, Sum8).unwrap();
+        assert_eq!(&t, &x);
+    }
+
+    #[test]
+    fn test_checksum_lossy() {
+        use super::ChecksumStatus;
+        use crate::from_bytes_with_checksum_lossy;
+
+        let t = TestString {
+            string: "123".to_string(),
+            u64: 123,
+        };
+        let buf = to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap();
+        let (x, status) =
+            from_bytes_with_checksum_lossy::<TestString, _>(&buf, WrappedCRC::default()).unwrap();
+        assert_eq!(&t, &x);
+        let covered = 0..buf.len() - 2;
+        assert_eq!(
+            status,
+            ChecksumStatus::Valid {
+                value: BigEndian::read_u16(&buf[buf.len() - 2..]) as u32,
+                covered,
+            }
+        );
+
+        // 値が壊れていてもデシリアライズ結果を返す
+        let mut broken = buf.clone();
+        let n = broken.len();
+        // UK(16) + L(1) + KL(2)
+        assert_eq!(&broken[19..22], b"123");
+        broken[19] = b'0';
+        let (x, status) =
+            from_bytes_with_checksum_lossy::<TestString, _>(&broken, WrappedCRC::default())
+                .unwrap();
+        assert_eq!(x.string, "023");
+        match status {
+            ChecksumStatus::Unmatched {
+                value,
+                calced,
+                covered,
+            } => {
+                assert_eq!(value, BigEndian::read_u16(&buf[n - 2..]) as u32);
+                assert_ne!(value, calced);
+                assert_eq!(covered, 0..n - 2);
+            }
+            x => unreachable!("{:?}", x),
+        }
+
+        // checksumがないデータ
+        let buf = to_bytes(&t).unwrap();
+        let (x, status) =
+            from_bytes_with_checksum_lossy::<TestString, _>(&buf, WrappedCRC::default()).unwrap();
+        assert_eq!(&t, &x);
+        assert_eq!(status, ChecksumStatus::NotFound);
+    }
+
+    #[test]
+    fn test_crc16_algo() {
+        use super::{CheckSumCalc, Crc16Algo};
+
+        // 各アルゴリズムのcheck値
+        for (algo, expect) in [
+            (Crc16Algo::Iso14443A, 0xbf05),
+            (Crc16Algo::Ccitt, 0x29b1),
+            (Crc16Algo::Kermit, 0x2189),
+            (Crc16Algo::Modbus, 0x4b37),
+        ] {
+            let crc = WrappedCRC::with_algorithm(algo);
+            assert_eq!(crc.checksum(b"123456789"), expect, "{:?}", algo);
+        }
+
+        let t = TestString {
+            string: "123".to_string(),
+            u64: 123,
+        };
+        let sum = WrappedCRC::with_algorithm(Crc16Algo::Misb0601);
+        let buf =
+            to_bytes_with_checksum(&t, WrappedCRC::with_algorithm(Crc16Algo::Misb0601)).unwrap();
+        assert_eq!(
+            BigEndian::read_u16(&buf[buf.len() - 2..]) as u32,
+            sum.checksum(&buf[..buf.len() - 2])
+        );
+        let x: TestString =
+            from_bytes_with_checksum(&buf, WrappedCRC::with_algorithm(Crc16Algo::Misb0601))
+                .unwrap();
+        assert_eq!(&t, &x);
+        assert!(from_bytes_with_checksum::<TestString, _>(&buf, WrappedCRC::default()).is_err());
+    }
+
+    // 範囲を変えたchecksumの書き込みと検証
+    #[test]
+    fn test_checksum_coverage() {
+        use super::{CheckSumCalc, CheckSumCoverage, ChecksumLocation};
+        use crate::{edit::patch_value_with_checksum, verify_checksum, KLVBuilder};
+
+        let t = TestString {
+            string: "123".to_string(),
+            u64: 123,
+        };
+        // 16byteのUniversal Key、1byteのL
+        for (coverage, range) in [
+            (CheckSumCoverage::UntilChecksumLength, 0..34),
+            (CheckSumCoverage::UntilChecksumRecord, 0..32),
+            (CheckSumCoverage::FromLengthUntilChecksumLength, 16..34),
+            (CheckSumCoverage::ContentUntilChecksumLength, 17..34),
+            (CheckSumCoverage::ContentUntilChecksumRecord, 17..32),
+        ] {
+            let crc = WrappedCRC::default().with_coverage(coverage);
+            let buf = to_bytes_with_checksum(&t, &crc).unwrap();
+            assert_eq!(buf.len(), 36);
+            let value = crc.checksum(&buf[range.clone()]) as u16;
+            assert_eq!(BigEndian::read_u16(&buf[34..]), value, "{:?}", coverage);
+            let status = verify_checksum(&buf, &crc, ChecksumLocation::LastRecord);
+            assert_eq!(
+                status,
+                super::ChecksumStatus::Valid {
+                    value: value as u32,
+                    covered: range
+                }
+            );
+            let x: TestString = from_bytes_with_checksum(&buf, &crc).unwrap();
+            assert_eq!(x, t);
+
+            // 他の経路でも同じ範囲を使う
+            let mut builder = KLVBuilder::new(b"TESTDATA00000000").unwrap();
+            builder.push_str(30, "123").push_u64(40, 123);
+            assert_eq!(builder.finish_with_checksum(&crc), buf);
+            let patched = patch_value_with_checksum(&buf, &[30], b"456", &crc).unwrap();
+            assert!(checksum(&patched, &crc).is_ok());
+        }
+
+        // Universal Keyを含まない範囲はキーを変えてもchecksumが変わらない
+        let crc = WrappedCRC::default().with_coverage(CheckSumCoverage::ContentUntilChecksumRecord);
+        let mut buf = to_bytes_with_checksum(&t, &crc).unwrap();
+        buf[0] = b'X';
+        assert!(checksum(&buf, &crc).is_ok());
+        assert!(checksum(&buf, WrappedCRC::default()).is_err());
+    }
 }
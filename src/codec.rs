@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::de::KLVMap;
+use crate::error::Result;
+use crate::LengthOctet;
+
+/// Transform value bytes of a record between the serde representation and the wire format
+///
+/// `encode` receives the bytes written by the serializer and returns the bytes to put on the wire.
+/// `decode` does the reverse before deserialization.
+pub trait ValueCodec {
+    fn encode(&self, value: &[u8]) -> Result<Vec<u8>>;
+    fn decode(&self, value: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// Per-tag codecs applied to the top level records
+///
+/// Example
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{
+///     error::{Error, Result},
+///     from_bytes_with_codecs, to_bytes_with_codecs, CodecRegistry, ValueCodec,
+/// };
+///
+/// // f64 degree <-> i32 scaled to ±90
+/// struct LatitudeCodec;
+///
+/// impl ValueCodec for LatitudeCodec {
+///     fn encode(&self, value: &[u8]) -> Result<Vec<u8>> {
+///         let v = f64::from_be_bytes(value.try_into().map_err(|_| Error::Encode("f64".into()))?);
+///         Ok(((v / 90.0 * i32::MAX as f64) as i32).to_be_bytes().to_vec())
+///     }
+///     fn decode(&self, value: &[u8]) -> Result<Vec<u8>> {
+///         let v = i32::from_be_bytes(value.try_into().map_err(|_| Error::Encode("i32".into()))?);
+///         Ok((v as f64 * 90.0 / i32::MAX as f64).to_be_bytes().to_vec())
+///     }
+/// }
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Position {
+///     #[serde(rename = "13")]
+///     latitude: f64,
+/// }
+///
+/// let mut codecs = CodecRegistry::new();
+/// codecs.codec(13, LatitudeCodec);
+/// let buf = to_bytes_with_codecs(&Position { latitude: 90.0 }, &codecs).unwrap();
+/// assert_eq!(&buf[4..], &[6, 13, 4, 0x7f, 0xff, 0xff, 0xff]);
+/// let x: Position = from_bytes_with_codecs(&buf, &codecs).unwrap();
+/// assert_eq!(x.latitude, 90.0);
+/// ```
+#[derive(Default)]
+pub struct CodecRegistry {
+    codecs: BTreeMap<u8, Box<dyn ValueCodec>>,
+}
+
+impl fmt::Debug for CodecRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CodecRegistry")
+            .field("tags", &self.codecs.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl CodecRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// register codec of the tag, replace when already registered
+    pub fn codec<C: ValueCodec + 'static>(&mut self, tag: u8, codec: C) -> &mut Self {
+        self.codecs.insert(tag, Box::new(codec));
+        self
+    }
+
+    /// codec of the tag
+    pub fn get(&self, tag: u8) -> Option<&dyn ValueCodec> {
+        self.codecs.get(&tag).map(|x| x.as_ref())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codecs.is_empty()
+    }
+
+    // パケットの各レコードにcodecを適用して組み直す
+    pub(crate) fn encode_packet(&self, buf: &[u8]) -> Result<Vec<u8>> {
+        self.transcode(buf, |codec, value| codec.encode(value))
+    }
+
+    pub(crate) fn decode_packet(&self, buf: &[u8]) -> Result<Vec<u8>> {
+        self.transcode(buf, |codec, value| codec.decode(value))
+    }
+
+    fn transcode<F>(&self, buf: &[u8], f: F) -> Result<Vec<u8>>
+    where
+        F: Fn(&dyn ValueCodec, &[u8]) -> Result<Vec<u8>>,
+    {
+        if self.is_empty() {
+            return Ok(buf.to_vec());
+        }
+        let map = KLVMap::try_from_bytes(buf)?;
+        // 同じタグが繰り返されても各レコードの位置に書くため、順に組み直す
+        let mut content = Vec::with_capacity(buf.len());
+        for raw in map.iter() {
+            let value = raw.value.unwrap_or_default();
+            let transcoded;
+            let value = match self.get(raw.key) {
+                Some(codec) => {
+                    transcoded = f(codec, value)?;
+                    transcoded.as_slice()
+                }
+                None => value,
+            };
+            content.push(raw.key);
+            // Vecへの書き込みは失敗しない
+            LengthOctet::length_to_buf(&mut content, value.len()).unwrap();
+            content.extend_from_slice(value);
+        }
+        let universal_key = map.universal_key();
+        let mut out = Vec::with_capacity(
+            universal_key.len() + LengthOctet::encoded_len(content.len()) + content.len(),
+        );
+        out.extend_from_slice(universal_key);
+        LengthOctet::length_to_buf(&mut out, content.len()).unwrap();
+        out.extend_from_slice(&content);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{CodecRegistry, ValueCodec};
+    use crate::error::Result;
+    use crate::{from_bytes, from_bytes_with_codecs, to_bytes, to_bytes_with_codecs};
+
+    // u16をu8へ1/10で詰める
+    struct Div10;
+
+    impl ValueCodec for Div10 {
+        fn encode(&self, value: &[u8]) -> Result<Vec<u8>> {
+            Ok(vec![(u16::from_be_bytes([value[0], value[1]]) / 10) as u8])
+        }
+        fn decode(&self, value: &[u8]) -> Result<Vec<u8>> {
+            Ok((value[0] as u16 * 10).to_be_bytes().to_vec())
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestCodec {
+        #[serde(rename = "10")]
+        scaled: u16,
+        #[serde(rename = "11")]
+        plain: u16,
+    }
+
+    #[test]
+    fn test_codec_roundtrip() {
+        let t = TestCodec {
+            scaled: 2000,
+            plain: 2000,
+        };
+        let mut codecs = CodecRegistry::new();
+        codecs.codec(10, Div10);
+        let buf = to_bytes_with_codecs(&t, &codecs).unwrap();
+        assert_eq!(&buf[4..], &[7, 10, 1, 200, 11, 2, 0x07, 0xd0]);
+        assert_eq!(
+            from_bytes_with_codecs::<TestCodec>(&buf, &codecs).unwrap(),
+            t
+        );
+
+        // codecが空なら通常と同じ
+        let codecs = CodecRegistry::new();
+        let buf = to_bytes_with_codecs(&t, &codecs).unwrap();
+        assert_eq!(buf, to_bytes(&t).unwrap());
+        assert_eq!(from_bytes::<TestCodec>(&buf).unwrap(), t);
+    }
+
+    // 同じタグのレコードはそれぞれの位置で変換される
+    #[test]
+    fn test_codec_repeated_tag() {
+        let mut codecs = CodecRegistry::new();
+        codecs.codec(10, Div10);
+        #[rustfmt::skip]
+        let buf = [
+            b'T', b'E', b'S', b'T', 9,
+            10, 1, 1,
+            11, 1, 2,
+            10, 1, 3,
+        ];
+        #[rustfmt::skip]
+        let expect = [
+            b'T', b'E', b'S', b'T', 11,
+            10, 2, 0, 10,
+            11, 1, 2,
+            10, 2, 0, 30,
+        ];
+        let decoded = codecs.decode_packet(&buf[..]).unwrap();
+        assert_eq!(decoded, expect);
+        assert_eq!(codecs.encode_packet(&decoded).unwrap(), buf);
+    }
+}
@@ -1,24 +1,262 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+
 use byteorder::{BigEndian, ByteOrder};
+use serde::de::value::{BorrowedStrDeserializer, MapDeserializer};
 use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
 use serde::Deserialize;
 
-use crate::error::{Error, Result};
-use crate::{check_universal_key_len, parse_length};
+use crate::checksum::{ChecksumLocation, ChecksumStatus};
+use crate::error::{
+    DecodeErrorKind, DecodeLimit, DecodePath, Error, FieldError, KeyBytes, Result, TagPath,
+};
+use crate::placeholder::Absent;
+use crate::unknown::UNKNOWN_TAGS;
+use crate::{check_universal_key_len, parse_length, KeyWidth, LengthOctet};
 
-struct Deserializer<'de> {
+/// KLV deserializer reading from a byte slice
+///
+/// Use this directly when the deserialization is driven from outside,
+/// such as `DeserializeSeed` or `serde_path_to_error`.
+/// Values borrowed from the input live as long as `'de`.
+///
+/// KLV is not self-describing. `deserialize_any`, used by `#[serde(untagged)]` and generic values,
+/// guesses the type of a field from its length: unit for 0 byte,
+/// unsigned integer for 1, 2, 4 and 8 bytes, or float with [`DeserializerConfig::any_as_float`],
+/// and bytes for the others.
+/// A whole packet is not guessed, use [`crate::from_bytes_to_value`] instead.
+///
+/// Example
+/// ```
+/// use serde::Deserialize;
+/// use serde_klv::KLVDeserializer;
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Packet<'a> {
+///     #[serde(rename = "10")]
+///     name: &'a str,
+/// }
+///
+/// let buf = [b'T', b'E', b'S', b'T', 4, 10, 2, b'h', b'i'];
+/// let mut de = KLVDeserializer::from_slice(&buf);
+/// let x = Packet::deserialize(&mut de).unwrap();
+/// de.end().unwrap();
+/// assert_eq!(x, Packet { name: "hi" });
+/// ```
+pub struct KLVDeserializer<'de> {
     input: &'de [u8],
     position: usize,
     depth: usize,
-    next_len: Vec<(u8, usize)>,
+    next_len: Vec<(u128, usize)>,
+    // 直前に読んだフィールドのL。フィールドの値を直接読む場合のみSomeとなる
+    field_len: Option<usize>,
+    // 読み出し中のレコードのタグ。エラー情報に使う
+    tag: Option<u128>,
+    // 読み出し中のLocal Setを値に持つ親レコードのタグ
+    path: Vec<u128>,
+    // 読み出し中のLocal Setの終端
+    map_end: usize,
+    // UniversalKeyedで与えられたUniversalKey。structの名前より優先する
+    universal_key: Option<&'static [u8]>,
+    // 繰り返しフィールドとして読み出し済みのレコードの開始位置
+    skip: BTreeSet<usize>,
+    // 読み出せないレコードを飛ばして再試行するモード
+    lossy: bool,
+    // 各階層で読み出し中のレコードの範囲
+    records: Vec<Option<(usize, usize)>>,
+    // 読み出せなかったレコードの開始位置と終端
+    ignored: BTreeMap<usize, usize>,
+    // 構造体にないタグのレコードを受け取るコールバック
+    on_unknown_tag: Option<Box<dyn FnMut(&[u128], &'de [u8]) + 'de>>,
+    // 読み出したレコードと列の要素の数
+    record_count: usize,
+    // 欠けたフィールドを仮の値で埋めるLocal Setの終端とパス
+    fill: Option<(usize, Vec<u128>)>,
+    // 仮の値で埋めたフィールドのうちOptionでないもの
+    missing: Vec<&'static str>,
+    // 読み出し中のLocal Setのフィールド名
+    fields: &'static [&'static str],
+    config: DeserializerConfig<'de>,
+    // InternedStrの文字列を共有するinterner
+    interner: Option<&'de crate::intern::StringInterner>,
 }
 
-impl<'de> Deserializer<'de> {
-    pub fn from_bytes(input: &'de [u8]) -> Self {
-        Deserializer {
+impl<'de> KLVDeserializer<'de> {
+    /// deserializer with default config
+    pub fn from_slice(input: &'de [u8]) -> Self {
+        Self::from_slice_with_config(input, DeserializerConfig::default())
+    }
+
+    pub fn from_slice_with_config(input: &'de [u8], config: DeserializerConfig<'de>) -> Self {
+        KLVDeserializer {
             input,
             position: 0,
             depth: 0,
             next_len: vec![],
+            field_len: None,
+            tag: None,
+            path: vec![],
+            map_end: input.len(),
+            universal_key: None,
+            skip: BTreeSet::new(),
+            lossy: false,
+            records: vec![],
+            ignored: BTreeMap::new(),
+            on_unknown_tag: None,
+            record_count: 0,
+            fill: None,
+            missing: vec![],
+            fields: &[],
+            config,
+            interner: None,
+        }
+    }
+
+    /// register a callback receiving records whose tags the struct does not have
+    ///
+    /// The callback is called with the tag path, the last is the unknown tag,
+    /// and the value bytes of the record.
+    pub fn on_unknown_tag<F>(&mut self, f: F) -> &mut Self
+    where
+        F: FnMut(&[u128], &'de [u8]) + 'de,
+    {
+        self.on_unknown_tag = Some(Box::new(f));
+        self
+    }
+
+    /// share the strings of [`crate::intern::InternedStr`] fields through the interner
+    pub fn with_interner(&mut self, interner: &'de crate::intern::StringInterner) -> &mut Self {
+        self.interner = Some(interner);
+        self
+    }
+
+    /// return [`Error::TrailingBytes`] when the input remains after the deserialized value
+    pub fn end(&self) -> Result<()> {
+        if self.input.len() == self.position {
+            Ok(())
+        } else {
+            Err(Error::TrailingBytes {
+                offset: self.position,
+                remaining: self.input.len() - self.position,
+            })
+        }
+    }
+
+    /// input after the deserialized value
+    pub fn remaining(&self) -> &'de [u8] {
+        &self.input[self.position..]
+    }
+}
+
+/// Options of deserializer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeserializerConfig<'a> {
+    /// return [`Error::DuplicateKey`] when the same tag appears twice in a local set
+    pub deny_duplicate_keys: bool,
+    /// return [`Error::LengthLimit`] when a BER length exceeds this value
+    pub max_content_len: usize,
+    /// encoding of the field tags
+    pub key_width: KeyWidth,
+    /// return [`Error::Decode`] with [`DecodeErrorKind::UnorderedTag`]
+    /// when a tag is less than the previous tag in a local set
+    ///
+    /// Repeated records of the same tag are accepted.
+    /// A checksum record placed last with a lower tag is also rejected.
+    pub deny_unordered_tags: bool,
+    /// character set of string values
+    pub string_policy: StringPolicy,
+    /// replace invalid characters of `string_policy` with U+FFFD instead of returning error
+    pub replace_invalid_chars: bool,
+    /// compare the universal key only at the bits set in the mask,
+    /// such as [`crate::UniversalKey::VERSION_MASK`] accepting newer revisions of a standard
+    pub universal_key_mask: Option<[u8; 16]>,
+    /// return [`Error::DecodeLimit`] when local sets nest deeper than this value,
+    /// the top level set is depth 1
+    pub max_depth: usize,
+    /// return [`Error::DecodeLimit`] when the input has more records and sequence elements
+    /// than this value
+    ///
+    /// Limits the time spent on untrusted input of many zero-length records.
+    pub max_records: usize,
+    /// read values of 4 and 8 bytes as `f32` and `f64` instead of unsigned integers
+    /// in `deserialize_any`, see [`KLVDeserializer`]
+    pub any_as_float: bool,
+    /// tags of the field names, see [`crate::SerializerConfig::tag_names`]
+    pub tag_names: &'a [(&'a str, u128)],
+}
+
+/// Character set of string values
+///
+/// Example
+/// ```
+/// use serde::Deserialize;
+/// use serde_klv::{from_bytes_with_config, DeserializerConfig, StringPolicy};
+///
+/// #[derive(Debug, Deserialize)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     name: String,
+/// }
+///
+/// let buf = [b'T', b'E', b'S', b'T', 4, 10, 2, b'A', 0xe9];
+/// let mut config = DeserializerConfig {
+///     string_policy: StringPolicy::Ascii,
+///     ..Default::default()
+/// };
+/// assert!(from_bytes_with_config::<Packet>(&buf, config).is_err());
+///
+/// config.replace_invalid_chars = true;
+/// let x = from_bytes_with_config::<Packet>(&buf, config).unwrap();
+/// assert_eq!(x.name, "A\u{fffd}");
+///
+/// config.string_policy = StringPolicy::Latin1;
+/// let x = from_bytes_with_config::<Packet>(&buf, config).unwrap();
+/// assert_eq!(x.name, "A\u{e9}");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StringPolicy {
+    /// UTF-8
+    #[default]
+    Utf8,
+    /// ISO 646 (ASCII) required by MISB for some tags
+    Ascii,
+    /// ISO 8859-1, every byte is a character of U+0000 to U+00FF
+    Latin1,
+}
+
+impl<'a> DeserializerConfig<'a> {
+    /// default of `max_content_len`, 16 MiB
+    pub const DEFAULT_MAX_CONTENT_LEN: usize = 16 * 1024 * 1024;
+    /// default of `max_depth`
+    pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+    /// read the fields renamed to the names of the table by their tags
+    ///
+    /// Field names not in the table are parsed as tag numbers.
+    /// Returns [`Error::Key`] when a tag does not fit in `key_width`, so set `key_width` first.
+    pub fn tag_names(mut self, names: &'a [(&'a str, u128)]) -> Result<Self> {
+        self.key_width.check_names(names)?;
+        self.tag_names = names;
+        Ok(self)
+    }
+}
+
+impl Default for DeserializerConfig<'_> {
+    fn default() -> Self {
+        Self {
+            deny_duplicate_keys: false,
+            max_content_len: Self::DEFAULT_MAX_CONTENT_LEN,
+            key_width: KeyWidth::default(),
+            deny_unordered_tags: false,
+            string_policy: StringPolicy::default(),
+            replace_invalid_chars: false,
+            universal_key_mask: None,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            max_records: usize::MAX,
+            any_as_float: false,
+            tag_names: &[],
         }
     }
 }
@@ -29,31 +267,417 @@ pub fn from_bytes<'a, T>(s: &'a [u8]) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer::from_bytes(s);
+    from_bytes_with_config(s, DeserializerConfig::default())
+}
+
+/// Deserialize from bytes with config
+///
+/// Example
+/// ```
+/// use serde::Deserialize;
+/// use serde_klv::{error::Error, from_bytes_with_config, DeserializerConfig};
+///
+/// #[derive(Debug, Deserialize)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     u8: u8,
+/// }
+///
+/// let buf = [b'T', b'E', b'S', b'T', 9, 10, 1, 1, 20, 1, 0, 20, 1, 0];
+/// let config = DeserializerConfig {
+///     deny_duplicate_keys: true,
+///     ..Default::default()
+/// };
+/// match from_bytes_with_config::<Packet>(&buf, config) {
+///     Err(Error::DuplicateKey {
+///         tag: 20,
+///         first_offset: 8,
+///         second_offset: 11,
+///         ..
+///     }) => {}
+///     x => panic!("{:?}", x),
+/// }
+/// ```
+pub fn from_bytes_with_config<'a, T>(s: &'a [u8], config: DeserializerConfig<'a>) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = KLVDeserializer::from_slice_with_config(s, config);
     let t = T::deserialize(&mut deserializer)?;
-    if deserializer.input.len() == deserializer.position {
-        Ok(t)
-    } else {
-        Err(Error::ContentLenght)
+    deserializer.end()?;
+    Ok(t)
+}
+
+/// Deserialize from bytes, passing records of unknown tags to the callback
+///
+/// See [`KLVDeserializer::on_unknown_tag`].
+///
+/// Example
+/// ```
+/// use serde::Deserialize;
+/// use serde_klv::from_bytes_with_unknown_tags;
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     u8: u8,
+/// }
+///
+/// let buf = [b'T', b'E', b'S', b'T', 7, 10, 1, 1, 20, 2, 0xab, 0xcd];
+/// let mut unknown = vec![];
+/// let x: Packet = from_bytes_with_unknown_tags(&buf, |path, value| {
+///     unknown.push((path.to_vec(), value.to_vec()))
+/// })
+/// .unwrap();
+/// assert_eq!(x, Packet { u8: 1 });
+/// assert_eq!(unknown, vec![(vec![20], vec![0xab, 0xcd])]);
+/// ```
+pub fn from_bytes_with_unknown_tags<'a, T, F>(s: &'a [u8], f: F) -> Result<T>
+where
+    T: Deserialize<'a>,
+    F: FnMut(&[u128], &'a [u8]) + 'a,
+{
+    let mut deserializer = KLVDeserializer::from_slice(s);
+    deserializer.on_unknown_tag(f);
+    let t = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(t)
+}
+
+/// Deserialize the first packet and return it with the bytes after the packet
+///
+/// Unlike [`from_bytes`], trailing bytes are not an error,
+/// so concatenated packets can be read one by one.
+///
+/// Example
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{from_bytes_partial, to_bytes};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Count {
+///     #[serde(rename = "10")]
+///     count: u8,
+/// }
+///
+/// let mut buf = to_bytes(&Count { count: 1 }).unwrap();
+/// buf.extend(to_bytes(&Count { count: 2 }).unwrap());
+/// let (x, rest) = from_bytes_partial::<Count>(&buf).unwrap();
+/// assert_eq!(x, Count { count: 1 });
+/// let (x, rest) = from_bytes_partial::<Count>(rest).unwrap();
+/// assert_eq!(x, Count { count: 2 });
+/// assert!(rest.is_empty());
+/// ```
+pub fn from_bytes_partial<'a, T>(s: &'a [u8]) -> Result<(T, &'a [u8])>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = KLVDeserializer::from_slice(s);
+    let t = T::deserialize(&mut deserializer)?;
+    Ok((t, deserializer.remaining()))
+}
+
+/// Deserialize from bytes with the universal key of [`crate::UniversalKeyed`] instead of the struct name
+pub fn from_bytes_keyed<'a, T>(s: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a> + crate::UniversalKeyed,
+{
+    from_bytes_keyed_with_config(s, DeserializerConfig::default())
+}
+
+// 設定を指定してUniversalKeyedの型を読む
+pub(crate) fn from_bytes_keyed_with_config<'a, T>(
+    s: &'a [u8],
+    config: DeserializerConfig<'a>,
+) -> Result<T>
+where
+    T: Deserialize<'a> + crate::UniversalKeyed,
+{
+    let mut deserializer = KLVDeserializer::from_slice_with_config(s, config);
+    deserializer.universal_key = Some(T::KEY);
+    let t = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(t)
+}
+
+/// Deserialize from bytes after transforming values of the registered tags
+///
+/// Decoded values are not borrowed from the input, so `T` must own its data.
+pub fn from_bytes_with_codecs<T>(s: &[u8], codecs: &crate::codec::CodecRegistry) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    from_bytes(&codecs.decode_packet(s)?)
+}
+
+/// Deserialize from bytes, skipping records that fail to decode
+///
+/// The struct is returned with the skipped fields left as missing,
+/// so only `Option` or `#[serde(default)]` fields can be skipped.
+/// Skipped records are reported as [`FieldError`].
+/// Error is returned when a required field is skipped.
+///
+/// Example
+/// ```
+/// use serde::Deserialize;
+/// use serde_klv::from_bytes_lossy;
+///
+/// #[derive(Debug, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Packet<'a> {
+///     #[serde(rename = "10")]
+///     u8: u8,
+///     #[serde(rename = "11")]
+///     name: Option<&'a str>,
+/// }
+///
+/// // name is invalid UTF-8
+/// let buf = [b'T', b'E', b'S', b'T', 7, 10, 1, 1, 11, 2, 0xff, 0xfe];
+/// let (x, errors) = from_bytes_lossy::<Packet>(&buf).unwrap();
+/// assert_eq!(x, Packet { u8: 1, name: None });
+/// assert_eq!(errors[0].tag, 11);
+/// assert_eq!(errors[0].offset, 8);
+/// ```
+pub fn from_bytes_lossy<'a, T>(s: &'a [u8]) -> Result<(T, Vec<FieldError>)>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_lossy_with(s, None)
+}
+
+// internerを指定して読めないレコードを飛ばしながら読む
+pub(crate) fn from_bytes_lossy_with<'a, T>(
+    s: &'a [u8],
+    interner: Option<&'a crate::intern::StringInterner>,
+) -> Result<(T, Vec<FieldError>)>
+where
+    T: Deserialize<'a>,
+{
+    let mut ignored = BTreeMap::new();
+    let mut errors = vec![];
+    loop {
+        let mut deserializer = KLVDeserializer::from_slice(s);
+        deserializer.lossy = true;
+        deserializer.ignored = ignored.clone();
+        deserializer.interner = interner;
+        let error = match T::deserialize(&mut deserializer) {
+            Ok(t) => return deserializer.end().map(|_| (t, errors)),
+            Err(e) => e,
+        };
+        // 最も内側で読み出し中のレコードを飛ばして再試行する
+        match deserializer.records.iter().rev().find_map(|x| *x) {
+            Some((start, end)) if !ignored.contains_key(&start) => {
+                ignored.insert(start, end);
+                errors.push(FieldError {
+                    tag: deserializer.key_at(start).map(|x| x.0).unwrap_or_default(),
+                    offset: start,
+                    error,
+                });
+            }
+            _ => return Err(error),
+        }
+    }
+}
+
+/// Deserialize from bytes, reporting all the missing required fields of a local set at once
+///
+/// [`from_bytes`] stops at the first missing field.
+/// This returns [`Error::MissingTags`] with the tags of every field missing
+/// in the local set where the first one is found.
+/// `Option` fields are not reported.
+/// `#[serde(default)]` fields are reported only when a required field of the same local set is missing.
+/// Other errors are returned as [`from_bytes`] does.
+///
+/// Example
+/// ```
+/// use serde::Deserialize;
+/// use serde_klv::{error::Error, from_bytes_strict};
+///
+/// #[derive(Debug, Deserialize)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     a: u8,
+///     #[serde(rename = "11")]
+///     b: u16,
+///     #[serde(rename = "12")]
+///     c: Option<u8>,
+///     #[serde(rename = "13")]
+///     d: String,
+/// }
+///
+/// let buf = [b'T', b'E', b'S', b'T', 3, 10, 1, 1];
+/// match from_bytes_strict::<Packet>(&buf) {
+///     Err(Error::MissingTags { path, tags }) => {
+///         assert!(path.is_empty());
+///         assert_eq!(tags, vec![11, 13]);
+///     }
+///     x => unreachable!("{:?}", x),
+/// }
+/// ```
+pub fn from_bytes_strict<'a, T>(s: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_strict_with_config(s, DeserializerConfig::default())
+}
+
+/// [`from_bytes_strict`] with config
+///
+/// Tags of the missing fields are resolved by [`DeserializerConfig::key_width`]
+/// and [`DeserializerConfig::tag_names`].
+pub fn from_bytes_strict_with_config<'a, T>(
+    s: &'a [u8],
+    config: DeserializerConfig<'a>,
+) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let error = match from_bytes_with_config(s, config) {
+        Err(e) => e,
+        x => return x,
+    };
+    let (end, path) = match &error {
+        Error::Decode {
+            offset,
+            path,
+            kind: DecodeErrorKind::MissingField(_),
+            ..
+        } if !path.is_truncated() => (*offset, path.to_vec()),
+        // 保持しきれない深さのパスは埋める位置を決められない
+        _ => return Err(error),
+    };
+    // 最初に欠けたフィールドのLocal Setで、欠けたフィールドを全て仮の値で埋めて集める
+    // 別のLocal Setで欠けたフィールドによるエラーは報告しない
+    let mut deserializer = KLVDeserializer::from_slice_with_config(s, config);
+    deserializer.fill = Some((end, path.clone()));
+    let _ = T::deserialize(&mut deserializer);
+    let (key_width, names) = (config.key_width, config.tag_names);
+    let tags = deserializer
+        .missing
+        .iter()
+        .map(|x| key_width.parse_named(names, x))
+        .collect::<Option<Vec<_>>>();
+    match tags {
+        Some(tags) if !tags.is_empty() => Err(Error::MissingTags { path, tags }),
+        // タグにならないフィールド名や仮の値で読めない型は最初のエラーを返す
+        _ => Err(error),
     }
 }
 
 pub(crate) fn checksum<C: crate::checksum::CheckSumCalc>(s: &[u8], crc: C) -> Result<()> {
-    use crate::checksum::CHECKSUM_KEY_LENGTH;
-
-    let checksum_offset = s.len() - 4;
-    if &s[checksum_offset..checksum_offset + 2] != CHECKSUM_KEY_LENGTH {
-        return Err(Error::HasNotChecksum);
-    }
-    let crc_value = BigEndian::read_u16(&s[checksum_offset + 2..]);
-    let crc_calced = crc.checksum(&s[0..checksum_offset + 2]);
-    if crc_value != crc_calced {
-        return Err(Error::UnmatcheChecksum {
-            value: crc_value,
-            calced: crc_calced,
-        });
+    match verify_checksum(s, &crc, ChecksumLocation::LastRecord) {
+        ChecksumStatus::Valid { .. } => Ok(()),
+        ChecksumStatus::Unmatched { value, calced, .. } => {
+            Err(Error::UnmatcheChecksum { value, calced })
+        }
+        ChecksumStatus::NotFound => Err(Error::HasNotChecksum),
+    }
+}
+
+/// Verify checksum record found at the location
+///
+/// The record must have 1 byte length equal to [`crate::CheckSumCalc::width`].
+/// Covered bytes are selected by [`crate::CheckSumCalc::coverage`] up to the checksum record,
+/// bytes after the record are not covered.
+///
+/// Example
+/// ```
+/// use serde_klv::{verify_checksum, CheckSumCalc, ChecksumLocation, ChecksumStatus, WrappedCRC};
+///
+/// // checksum record is placed at first
+/// let mut buf = vec![b'T', b'E', b'S', b'T', 7, 1, 2, 0, 0, 10, 1, 1];
+/// let crc = WrappedCRC::default();
+/// let value = crc.checksum(&buf[..7]) as u16;
+/// buf[7..9].copy_from_slice(&value.to_be_bytes());
+///
+/// assert!(verify_checksum(&buf, &crc, ChecksumLocation::Tag(1)).is_valid());
+/// assert!(verify_checksum(&buf, &crc, ChecksumLocation::Offset(5)).is_valid());
+/// assert_eq!(
+///     verify_checksum(&buf, &crc, ChecksumLocation::LastRecord),
+///     ChecksumStatus::NotFound
+/// );
+/// ```
+pub fn verify_checksum<C: crate::checksum::CheckSumCalc>(
+    s: &[u8],
+    crc: &C,
+    location: ChecksumLocation,
+) -> ChecksumStatus {
+    let width = crc.width();
+    // checksumレコードのKの位置を探す
+    let record = match location {
+        ChecksumLocation::LastRecord => s
+            .len()
+            .checked_sub(width.size() + 2)
+            .filter(|x| s[*x] == crc.tag()),
+        ChecksumLocation::Tag(tag) => KLVMap::try_from_bytes(s)
+            .ok()
+            .and_then(|map| map.get(tag).map(|raw| raw.position)),
+        ChecksumLocation::Offset(offset) => Some(offset).filter(|x| s.get(*x) == Some(&crc.tag())),
+    };
+    // Lが1byteで幅と一致し、Vが収まっていること
+    let value_offset = match record {
+        Some(x)
+            if s.get(x + 1).map(|l| *l as usize) == Some(width.size())
+                && x + 2 + width.size() <= s.len() =>
+        {
+            x + 2
+        }
+        _ => return ChecksumStatus::NotFound,
+    };
+    let value = BigEndian::read_uint(&s[value_offset..], width.size()) as u32;
+    // ヘッダを解析できない場合は先頭から数える
+    let (key_len, content_start) = packet_header(s).unwrap_or((0, 0));
+    let covered = crc
+        .coverage()
+        .covered_range_of(key_len, content_start, value_offset);
+    let calced = width.truncate(crc.checksum(&s[covered.clone()]));
+    if value == calced {
+        ChecksumStatus::Valid { value, covered }
+    } else {
+        ChecksumStatus::Unmatched {
+            value,
+            calced,
+            covered,
+        }
     }
-    Ok(())
+}
+
+/// Deserialize from bytes and report checksum status instead of failing on mismatch
+///
+/// Example
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{from_bytes_with_checksum_lossy, to_bytes_with_checksum, WrappedCRC};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     u8: u8,
+/// }
+///
+/// let mut buf = to_bytes_with_checksum(&Packet { u8: 1 }, WrappedCRC::default()).unwrap();
+/// // broken data
+/// buf[7] = 2;
+/// let (x, status) =
+///     from_bytes_with_checksum_lossy::<Packet, _>(&buf, WrappedCRC::default()).unwrap();
+/// assert_eq!(x, Packet { u8: 2 });
+/// assert!(!status.is_valid());
+/// ```
+pub fn from_bytes_with_checksum_lossy<'a, T, C: crate::checksum::CheckSumCalc>(
+    s: &'a [u8],
+    crc: C,
+) -> Result<(T, ChecksumStatus)>
+where
+    T: Deserialize<'a>,
+{
+    let status = verify_checksum(s, &crc, ChecksumLocation::LastRecord);
+    let t = from_bytes(s)?;
+    Ok((t, status))
 }
 
 /// Deserialize from bytes with checksum
@@ -66,88 +690,445 @@ where
     T: Deserialize<'a>,
 {
     checksum(s, crc)?;
-    let mut deserializer = Deserializer::from_bytes(s);
+    let mut deserializer = KLVDeserializer::from_slice(s);
     let t = T::deserialize(&mut deserializer)?;
-    if deserializer.input.len() == deserializer.position {
-        Ok(t)
-    } else {
-        Err(Error::ContentLenght)
+    deserializer.end()?;
+    Ok(t)
+}
+
+/// Deserialize from bytes, verifying checksum only when the packet has it
+///
+/// Returns whether the checksum was verified.
+/// Error is returned when the checksum is present and unmatched.
+///
+/// Example
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{from_bytes_maybe_checksum, to_bytes, to_bytes_with_checksum, WrappedCRC};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     u8: u8,
+/// }
+///
+/// let buf = to_bytes_with_checksum(&Packet { u8: 1 }, WrappedCRC::default()).unwrap();
+/// let (x, verified) =
+///     from_bytes_maybe_checksum::<Packet, _>(&buf, WrappedCRC::default()).unwrap();
+/// assert_eq!(x, Packet { u8: 1 });
+/// assert!(verified);
+///
+/// let buf = to_bytes(&Packet { u8: 1 }).unwrap();
+/// let (x, verified) =
+///     from_bytes_maybe_checksum::<Packet, _>(&buf, WrappedCRC::default()).unwrap();
+/// assert_eq!(x, Packet { u8: 1 });
+/// assert!(!verified);
+/// ```
+pub fn from_bytes_maybe_checksum<'a, T, C: crate::checksum::CheckSumCalc>(
+    s: &'a [u8],
+    crc: C,
+) -> Result<(T, bool)>
+where
+    T: Deserialize<'a>,
+{
+    let verified = match verify_checksum(s, &crc, ChecksumLocation::LastRecord) {
+        ChecksumStatus::Valid { .. } => true,
+        ChecksumStatus::Unmatched { value, calced, .. } => {
+            return Err(Error::UnmatcheChecksum { value, calced })
+        }
+        ChecksumStatus::NotFound => false,
+    };
+    Ok((from_bytes(s)?, verified))
+}
+
+/// Deserialize concatenated packets of the same type
+///
+/// Returns [`Error::TrailingBytes`] when the bytes after the last packet do not start a packet of the type,
+/// and the error of the truncated packet otherwise.
+///
+/// Example
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{from_bytes_multi, to_bytes};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Count {
+///     #[serde(rename = "10")]
+///     count: u16,
+/// }
+///
+/// let mut buf = to_bytes(&Count { count: 1 }).unwrap();
+/// buf.extend(to_bytes(&Count { count: 2 }).unwrap());
+/// let x = from_bytes_multi::<Count>(&buf).unwrap();
+/// assert_eq!(x, vec![Count { count: 1 }, Count { count: 2 }]);
+/// ```
+pub fn from_bytes_multi<'a, T>(s: &'a [u8]) -> Result<Vec<T>>
+where
+    T: Deserialize<'a>,
+{
+    let mut offset = 0;
+    let mut values = vec![];
+    // 前のパケット
+    let mut previous: &[u8] = &[];
+    while offset < s.len() {
+        let rest = &s[offset..];
+        let trailing = Error::TrailingBytes {
+            offset,
+            remaining: rest.len(),
+        };
+        let mut deserializer = KLVDeserializer::from_slice(rest);
+        match T::deserialize(&mut deserializer) {
+            Ok(x) => values.push(x),
+            // 型のUniversal Keyで始まらない残りは欠けたパケットではなく余分なbyteとする
+            Err(Error::UnmatchedKey { .. }) if !values.is_empty() => return Err(trailing),
+            // Universal Keyに満たない残りは前のパケットの先頭と異なる場合のみ余分なbyteとする
+            Err(Error::ContentLenght) if !values.is_empty() && !previous.starts_with(rest) => {
+                return Err(trailing)
+            }
+            Err(e) => return Err(e),
+        }
+        // 読み進められない場合は無限ループになるのでエラーとする
+        if deserializer.position == 0 {
+            return Err(Error::ContentLenght);
+        }
+        previous = &rest[..deserializer.position];
+        offset += deserializer.position;
     }
+    Ok(values)
 }
 
-impl<'de> Deserializer<'de> {}
+/// Split concatenated KLV packets by universal key length and BER length
+///
+/// Example
+/// ```
+/// use serde_klv::split_packets;
+///
+/// let buf = vec![0, 0, 0, 0, 3, 10, 1, 128, 0, 0, 0, 0, 0, 0, 0, 0, 3];
+/// let mut packets = split_packets(&buf, 4);
+/// assert_eq!(packets.next(), Some(&buf[0..8]));
+/// assert_eq!(packets.next(), Some(&buf[8..13]));
+/// assert_eq!(packets.next(), None);
+/// // incomplete packet
+/// assert_eq!(packets.remainder(), &buf[13..]);
+/// ```
+pub fn split_packets(buf: &[u8], key_len: usize) -> Packets<'_> {
+    Packets { buf, key_len }
+}
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
-    type Error = Error;
+/// Iterator of packets created by [`split_packets`]
+#[derive(Debug, Clone)]
+pub struct Packets<'a> {
+    buf: &'a [u8],
+    key_len: usize,
+}
 
-    // 不明な型をParseする場合
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        todo!()
+impl<'a> Packets<'a> {
+    /// bytes not yet split, incomplete packet at the end remains here
+    pub fn remainder(&self) -> &'a [u8] {
+        self.buf
     }
+}
 
-    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        let result = self.input[self.position] != 0;
-        self.position += 1;
-        visitor.visit_bool(result)
-    }
+impl<'a> Iterator for Packets<'a> {
+    type Item = &'a [u8];
 
-    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        let result = self.input[self.position] as i8;
-        self.position += 1;
-        visitor.visit_i8(result)
+    fn next(&mut self) -> Option<Self::Item> {
+        let packet_len = packet_len(
+            self.buf,
+            self.key_len,
+            DeserializerConfig::DEFAULT_MAX_CONTENT_LEN,
+        )
+        .ok()??;
+        let (packet, rest) = self.buf.split_at(packet_len);
+        self.buf = rest;
+        Some(packet)
     }
+}
 
-    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        let result = BigEndian::read_i16(&self.input[self.position..]);
-        self.position += 2;
-        visitor.visit_i16(result)
+// 先頭のパケットの長さを求める。パケットが揃っていない場合はNone
+pub(crate) fn packet_len(
+    buf: &[u8],
+    key_len: usize,
+    max_content_len: usize,
+) -> Result<Option<usize>> {
+    if buf.len() <= key_len {
+        return Ok(None);
+    }
+    let length_len = match LengthOctet::from_u8(buf[key_len]) {
+        LengthOctet::Long(x) => 1 + x as usize,
+        _ => 1,
+    };
+    if buf.len() < key_len + length_len {
+        return Ok(None);
     }
+    let (length_len, content_len) =
+        parse_length(&buf[key_len..]).map_err(Error::UnsupportedLength)?;
+    if content_len > max_content_len {
+        return Err(Error::LengthLimit {
+            offset: key_len,
+            length: content_len,
+            limit: max_content_len,
+        });
+    }
+    let packet_len = key_len + length_len + content_len;
+    if packet_len > buf.len() {
+        Ok(None)
+    } else {
+        Ok(Some(packet_len))
+    }
+}
 
-    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        let result = BigEndian::read_i32(&self.input[self.position..]);
-        self.position += 4;
-        visitor.visit_i32(result)
+impl<'de> KLVDeserializer<'de> {
+    // 現在位置とタグを付与したエラー
+    fn error(&self, kind: DecodeErrorKind) -> Error {
+        Error::Decode {
+            offset: self.position,
+            tag: self.tag,
+            path: self.tag_path(self.tag),
+            kind,
+        }
     }
 
-    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        let result = BigEndian::read_i64(&self.input[self.position..]);
-        self.position += 8;
-        visitor.visit_i64(result)
+    // 欠けたフィールドのエラーにLocal Setの終端位置とパスを付与する
+    fn missing_field_error(&self, e: Error, end: usize) -> Error {
+        match e {
+            Error::MissingField(field) => Error::Decode {
+                offset: end,
+                tag: self.path.last().copied(),
+                path: self.path[..].into(),
+                kind: DecodeErrorKind::MissingField(field),
+            },
+            e => e,
+        }
     }
 
-    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
-    where
-        V: Visitor<'de>,
-    {
-        let result = self.input[self.position];
-        self.position += 1;
-        visitor.visit_u8(result)
+    // 設定されたマスクでUniversalKeyを比較する
+    fn universal_key_matches(&self, expect: &[u8], key: &[u8]) -> bool {
+        match &self.config.universal_key_mask {
+            Some(mask) => crate::key::masked_eq(expect, key, mask),
+            None => expect == key,
+        }
     }
 
-    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    // 設定された文字集合で文字列を読む。入力を借用できない場合のみ確保する
+    fn decode_str(&self, b: &'de [u8]) -> Result<Cow<'de, str>> {
+        let replace = self.config.replace_invalid_chars;
+        match self.config.string_policy {
+            StringPolicy::Utf8 => match std::str::from_utf8(b) {
+                Ok(s) => Ok(Cow::Borrowed(s)),
+                Err(_) if replace => Ok(String::from_utf8_lossy(b)),
+                Err(_) => Err(self.error(DecodeErrorKind::InvalidString)),
+            },
+            StringPolicy::Ascii => match b.iter().find(|x| !x.is_ascii()) {
+                // ASCIIはそのままUTF-8として扱える
+                None => Ok(Cow::Borrowed(std::str::from_utf8(b).unwrap())),
+                Some(_) if replace => Ok(Cow::Owned(
+                    b.iter()
+                        .map(|x| if x.is_ascii() { *x as char } else { '\u{fffd}' })
+                        .collect(),
+                )),
+                Some(x) => Err(self.error(DecodeErrorKind::NonAscii(*x))),
+            },
+            StringPolicy::Latin1 => {
+                if b.is_ascii() {
+                    Ok(Cow::Borrowed(std::str::from_utf8(b).unwrap()))
+                } else {
+                    Ok(Cow::Owned(b.iter().map(|x| *x as char).collect()))
+                }
+            }
+        }
+    }
+
+    // 親レコードから読み出し中のレコードまでのタグ
+    fn tag_path(&self, tag: Option<u128>) -> DecodePath {
+        let mut path = DecodePath::from(&self.path[..]);
+        if let Some(tag) = tag {
+            path.push(tag);
+        }
+        path
+    }
+
+    // 読み出し中の構造体のフィールドのうち名前の表でtagになるもの
+    fn named_field(&self, tag: u128) -> Option<&'static str> {
+        let (names, key_width) = (self.config.tag_names, self.config.key_width);
+        self.fields.iter().copied().find(|x| {
+            names.iter().any(|(name, _)| name == x) && key_width.parse_named(names, x) == Some(tag)
+        })
+    }
+
+    // positionのタグを設定された幅で読み、タグとそのbyte数を返す
+    fn key_at(&self, position: usize) -> Result<(u128, usize)> {
+        self.config
+            .key_width
+            .decode(self.input.get(position..).unwrap_or_default())
+            .ok_or_else(|| Error::Decode {
+                offset: position,
+                tag: None,
+                path: self.path[..].into(),
+                kind: DecodeErrorKind::InvalidKey,
+            })
+    }
+
+    // 範囲を確認してlenバイト読み進める
+    fn read_bytes(&mut self, len: usize) -> Result<&'de [u8]> {
+        let remains = self.input.len().saturating_sub(self.position);
+        if len > remains {
+            return Err(self.error(DecodeErrorKind::UnexpectedEnd {
+                expected: len,
+                actual: remains,
+            }));
+        }
+        let input = self.input;
+        let b = &input[self.position..self.position + len];
+        self.position += len;
+        Ok(b)
+    }
+
+    // 整数型の読み出し長を決める
+    // フィールドの値として読む場合はLを採用し、型の幅より短いエンコードを許容する
+    fn value_width(&mut self, width: usize) -> Result<usize> {
+        match self.field_len.take() {
+            None => Ok(width),
+            Some(len) if len == 0 || len > width => Err(self.error(DecodeErrorKind::TypeLength {
+                expected: width,
+                actual: len,
+            })),
+            Some(len) => Ok(len),
+        }
+    }
+
+    // 符号なし整数をゼロ拡張して読む
+    fn read_uint(&mut self, width: usize) -> Result<u64> {
+        let len = self.value_width(width)?;
+        Ok(BigEndian::read_uint(self.read_bytes(len)?, len))
+    }
+
+    // 符号付き整数を符号拡張して読む
+    fn read_int(&mut self, width: usize) -> Result<i64> {
+        let len = self.value_width(width)?;
+        Ok(BigEndian::read_int(self.read_bytes(len)?, len))
+    }
+
+    // 16byteの整数は拡張して読む
+    fn read_uint128(&mut self) -> Result<u128> {
+        let len = self.value_width(16)?;
+        Ok(BigEndian::read_uint128(self.read_bytes(len)?, len))
+    }
+
+    fn read_int128(&mut self) -> Result<i128> {
+        let len = self.value_width(16)?;
+        Ok(BigEndian::read_int128(self.read_bytes(len)?, len))
+    }
+
+    // BERのLを読む
+    fn read_length(&mut self) -> Result<usize> {
+        let (length_len, content_len) =
+            parse_length(&self.input[self.position.min(self.input.len())..])
+                .map_err(|e| self.error(DecodeErrorKind::Length(e)))?;
+        if content_len > self.config.max_content_len {
+            return Err(Error::LengthLimit {
+                offset: self.position,
+                length: content_len,
+                limit: self.config.max_content_len,
+            });
+        }
+        self.position += length_len;
+        Ok(content_len)
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for &'a mut KLVDeserializer<'de> {
+    type Error = Error;
+
+    // 不明な型をParseする場合
+    // 型の情報がないためLから推測する
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.depth == 0 {
+            return Err(Error::Unsupported(
+                "packet can not be self-describing, deserialize into struct or use from_bytes_to_value"
+                    .to_string(),
+            ));
+        }
+        let (_key, len) = *self.next_len.last().ok_or(Error::NeedKey)?;
+        let as_float = self.config.any_as_float;
+        match len {
+            0 => visitor.visit_unit(),
+            1 => self.deserialize_u8(visitor),
+            2 => self.deserialize_u16(visitor),
+            4 if as_float => self.deserialize_f32(visitor),
+            4 => self.deserialize_u32(visitor),
+            8 if as_float => self.deserialize_f64(visitor),
+            8 => self.deserialize_u64(visitor),
+            _ => self.deserialize_bytes(visitor),
+        }
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.read_bytes(1)?[0] != 0;
+        visitor.visit_bool(result)
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.read_int(1)? as i8;
+        visitor.visit_i8(result)
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.read_int(2)? as i16;
+        visitor.visit_i16(result)
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.read_int(4)? as i32;
+        visitor.visit_i32(result)
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.read_int(8)?;
+        visitor.visit_i64(result)
+    }
+
+    fn deserialize_i128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.read_int128()?;
+        visitor.visit_i128(result)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.read_uint(1)? as u8;
+        visitor.visit_u8(result)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let result = BigEndian::read_u16(&self.input[self.position..]);
-        self.position += 2;
+        let result = self.read_uint(2)? as u16;
         visitor.visit_u16(result)
     }
 
@@ -155,8 +1136,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let result = BigEndian::read_u32(&self.input[self.position..]);
-        self.position += 4;
+        let result = self.read_uint(4)? as u32;
         visitor.visit_u32(result)
     }
 
@@ -164,17 +1144,23 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let result = BigEndian::read_u64(&self.input[self.position..]);
-        self.position += 8;
+        let result = self.read_uint(8)?;
         visitor.visit_u64(result)
     }
 
+    fn deserialize_u128<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let result = self.read_uint128()?;
+        visitor.visit_u128(result)
+    }
+
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let result = BigEndian::read_f32(&self.input[self.position..]);
-        self.position += 4;
+        let result = BigEndian::read_f32(self.read_bytes(4)?);
         visitor.visit_f32(result)
     }
 
@@ -182,8 +1168,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let result = BigEndian::read_f64(&self.input[self.position..]);
-        self.position += 8;
+        let result = BigEndian::read_f64(self.read_bytes(8)?);
         visitor.visit_f64(result)
     }
 
@@ -192,17 +1177,22 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         let (_key, len) = self.next_len.pop().ok_or(Error::NeedKey)?;
-        let s = std::str::from_utf8(&self.input[self.position..self.position + len])
-            .map_err(|_e| Error::ExpectedString)?;
-        self.position += len;
-        visitor.visit_borrowed_str(s)
+        let b = self.read_bytes(len)?;
+        match self.decode_str(b)? {
+            Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+            Cow::Owned(s) => visitor.visit_string(s),
+        }
     }
 
+    // 所有を要求された場合はコピーを渡す
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_str(visitor)
+        let (_key, len) = self.next_len.pop().ok_or(Error::NeedKey)?;
+        let b = self.read_bytes(len)?;
+        let s = self.decode_str(b)?;
+        visitor.visit_string(s.into_owned())
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
@@ -210,8 +1200,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         let (_key, len) = self.next_len.pop().ok_or(Error::NeedKey)?;
-        let b = &self.input[self.position..self.position + len];
-        self.position += len;
+        let b = self.read_bytes(len)?;
         visitor.visit_borrowed_bytes(b)
     }
 
@@ -220,8 +1209,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         let (_key, len) = self.next_len.pop().ok_or(Error::NeedKey)?;
-        let b = &self.input[self.position..self.position + len];
-        self.position += len;
+        let b = self.read_bytes(len)?;
         visitor.visit_byte_buf(Vec::from(b))
     }
 
@@ -251,10 +1239,20 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         self.deserialize_unit(visitor)
     }
 
-    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        if name == crate::repeated::REPEATED {
+            let access = RepeatedAccess::new(self)?;
+            return visitor.visit_seq(access);
+        }
+        if let (crate::intern::INTERNED, Some(interner)) = (name, self.interner) {
+            let (_key, len) = self.next_len.pop().ok_or(Error::NeedKey)?;
+            let b = self.read_bytes(len)?;
+            let s = interner.intern(&self.decode_str(b)?);
+            return crate::intern::visit_interned(visitor, s);
+        }
         visitor.visit_newtype_struct(self)
     }
 
@@ -262,73 +1260,110 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        // TopLevelの列は同じUniversalKeyのパケットが連続したものとして読む
+        if self.depth == 0 && self.next_len.is_empty() {
+            return visitor.visit_seq(PacketAccess { de: self });
+        }
         // ある長さまでシリアライズを続ける
+        // 要素は型の幅で読むのでフィールドのLは使わない
+        self.field_len = None;
         let (_key, len) = self.next_len.last().ok_or(Error::NeedKey)?;
         visitor.visit_seq(KLVVisitor::new(self, self.position + len))
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        // 要素数分を型の幅で読み、読んだ長さがLと一致するか確認する
+        self.field_len = None;
+        let (_key, content_len) = *self.next_len.last().ok_or(Error::NeedKey)?;
+        let start = self.position;
+        let end = start + content_len;
+        let value = visitor.visit_seq(TupleAccess {
+            de: &mut *self,
+            end,
+            remaining: len,
+        })?;
+        if self.position != end {
+            return Err(self.error(DecodeErrorKind::TypeLength {
+                expected: self.position - start,
+                actual: content_len,
+            }));
+        }
+        Ok(value)
     }
 
     // Tuple structs look just like sequences in JSON.
     fn deserialize_tuple_struct<V>(
         self,
         _name: &'static str,
-        _len: usize,
+        len: usize,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        self.deserialize_seq(visitor)
+        self.deserialize_tuple(len, visitor)
     }
 
     fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        Err(Error::Unsupported("map is not supported".to_string()))
     }
 
+    // TopLevelのenumはUniversalKeyと一致する名前のvariantとして読む
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
-        _variants: &'static [&'static str],
-        _visitor: V,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        if self.depth != 0 {
+            return Err(Error::Unsupported(format!(
+                "enum {} is supported only as packet",
+                name
+            )));
+        }
+        let input = self.input.get(self.position..).unwrap_or_default();
+        // 短いUniversal Keyが長いものの先頭と一致する場合は長い方を選ぶ
+        let variant = variants
+            .iter()
+            .rev()
+            .filter(|x| {
+                let key = x.as_bytes();
+                check_universal_key_len(key).is_ok()
+                    && input.len() >= key.len()
+                    && self.universal_key_matches(key, &input[..key.len()])
+            })
+            .max_by_key(|x| x.len())
+            .ok_or_else(|| Error::UnmatchedKey {
+                expected: &[],
+                found: KeyBytes::new(input),
+            })?;
+        visitor.visit_enum(PacketEnumAccess { de: self, variant })
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let (_key, len) = self.next_len.last().ok_or(Error::NeedKey)?;
-        let v = BigEndian::read_u32(&self.input[self.position..]);
-        let c = std::char::from_u32(v);
-        if let Some(x) = c {
-            self.position += len;
-            visitor.visit_char(x)
-        } else {
-            Err(Error::Message(format!(
-                "unexpected char {} {}",
-                self.input[self.position],
-                self.input[self.position + 1]
-            )))
+        let v = BigEndian::read_u32(self.read_bytes(4)?);
+        match std::char::from_u32(v) {
+            Some(x) => visitor.visit_char(x),
+            None => Err(self.error(DecodeErrorKind::InvalidChar(v))),
         }
     }
 
     fn deserialize_struct<V>(
         self,
         name: &'static str,
-        _fields: &'static [&'static str],
+        fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
@@ -336,28 +1371,49 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     {
         // 0階層目のみUniversalKeyが存在する
         // それより深い階層は構造体定義にのみ依存するためUniverslkeyを必要としない
-        if self.position == 0 {
-            let key_len = check_universal_key_len(name)?;
-            if self.input.len() <= key_len {
+        if self.depth == 0 {
+            let expect = self.universal_key.unwrap_or(name.as_bytes());
+            let key_len = check_universal_key_len(expect)?;
+            if self.input.len() <= self.position + key_len {
                 return Err(Error::ContentLenght);
             }
             let key = &self.input[self.position..self.position + key_len];
-            let (length_len, content_len) = parse_length(&self.input[self.position + key_len..])
-                .map_err(Error::UnsupportedLength)?;
-            if name.as_bytes() != key {
-                return Err(Error::Key(format!(
-                    "Universal key is unmatched get {:02x?}, expect {:02x?}",
-                    name.as_bytes(),
-                    key
-                )));
-            }
-            self.position = key_len + length_len;
+            if !self.universal_key_matches(expect, key) {
+                return Err(Error::UnmatchedKey {
+                    expected: expect,
+                    found: KeyBytes::new(key),
+                });
+            }
+            self.position += key_len;
+            let content_len = self.read_length()?;
+            trace!(
+                offset = self.position,
+                length = content_len,
+                "universal key {:02x?}",
+                expect
+            );
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("packet", key = ?expect).entered();
             self.depth += 1;
-            visitor.visit_map(KLVVisitor::new(self, self.position + content_len))
+            let end = self.position + content_len;
+            visitor
+                .visit_map(KLVVisitor::local_set(self, end, fields)?)
+                .map_err(|e| self.missing_field_error(e, end))
         } else {
             self.depth += 1;
-            let (_key, len) = self.next_len.last().ok_or(Error::NeedKey)?;
-            visitor.visit_map(KLVVisitor::new(self, self.position + len))
+            self.field_len = None;
+            let (key, len) = *self.next_len.last().ok_or(Error::NeedKey)?;
+            // 入れ子の中ではこのレコードのタグを親としてパスに積む
+            self.path.push(key);
+            #[cfg(feature = "tracing")]
+            let _span = tracing::trace_span!("local_set", tag = key).entered();
+            let end = self.position + len;
+            let value = visitor
+                .visit_map(KLVVisitor::local_set(self, end, fields)?)
+                .map_err(|e| self.missing_field_error(e, end))?;
+            self.path.pop();
+            self.tag = Some(key);
+            Ok(value)
         }
     }
 
@@ -366,13 +1422,25 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         // jsonの場合はdeserialize_strへ飛んでいる
-        let v = self.input[self.position];
-        let (length_len, content_len) =
-            parse_length(&self.input[self.position + 1..]).map_err(Error::UnsupportedLength)?;
-        self.position += 1 + length_len;
+        let (v, key_len) = self.key_at(self.position)?;
+        self.position += key_len;
+        self.tag = Some(v);
+        trace!(tag = v, offset = self.position - key_len, "key");
+        let content_len = self.read_length()?;
+        trace!(
+            tag = v,
+            offset = self.position,
+            length = content_len,
+            "length"
+        );
         // 不定長データstructやstringなどの読み出し範囲として記録
         self.next_len.push((v, content_len));
-        visitor.visit_string(v.to_string())
+        self.field_len = Some(content_len);
+        // 名前の表にあるフィールドはその名前で渡す
+        match self.named_field(v) {
+            Some(name) => visitor.visit_str(name),
+            None => visitor.visit_str(self.config.key_width.name(v).as_str()),
+        }
     }
 
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
@@ -380,20 +1448,147 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         // デシリアライズ先がない場合はデータを無視する
-        let (_key, len) = self.next_len.last().ok_or(Error::NeedKey)?;
-        self.position += len;
+        let (key, len) = *self.next_len.last().ok_or(Error::NeedKey)?;
+        let value = self.read_bytes(len)?;
+        if let Some(mut f) = self.on_unknown_tag.take() {
+            f(&self.tag_path(Some(key)), value);
+            self.on_unknown_tag = Some(f);
+        }
         visitor.visit_unit()
     }
 }
 
 struct KLVVisitor<'a, 'de: 'a> {
-    de: &'a mut Deserializer<'de>,
+    de: &'a mut KLVDeserializer<'de>,
     len: usize,
+    // 重複を検出する場合のみ、読み出したタグと位置を記録する
+    seen: Option<BTreeMap<u128, usize>>,
+    // lossyモードでrecordsにこの階層を積んだか
+    recording: bool,
+    // 構造体のフィールド名
+    fields: &'static [&'static str],
+    // UnknownTagsのフィールドを持つ構造体のフィールド名
+    known: Option<&'static [&'static str]>,
+    // フィールドにない不明なタグのレコード
+    unknown: Vec<(u128, &'de [u8])>,
+    // UnknownTagsのフィールドのKeyを返し、値の読み出し待ち
+    unknown_pending: bool,
+    // 順序を検査する場合に直前に読んだタグ
+    last_tag: Option<u128>,
+    // 欠けたフィールドを埋める場合に読み出したタグ
+    read: Vec<u128>,
+    // 仮の値で埋めるフィールドの残りと、その値の読み出し待ち
+    absent: Option<Vec<&'static str>>,
+    absent_pending: Option<&'static str>,
 }
 
 impl<'a, 'de> KLVVisitor<'a, 'de> {
-    fn new(de: &'a mut Deserializer<'de>, len: usize) -> Self {
-        Self { de, len }
+    fn new(de: &'a mut KLVDeserializer<'de>, len: usize) -> Self {
+        let seen = de.config.deny_duplicate_keys.then(BTreeMap::new);
+        Self {
+            de,
+            len,
+            seen,
+            recording: false,
+            fields: &[],
+            known: None,
+            unknown: vec![],
+            unknown_pending: false,
+            last_tag: None,
+            read: vec![],
+            absent: None,
+            absent_pending: None,
+        }
+    }
+
+    // Local Setを読む。入れ子の深さはパスに積んだ親レコードの数から求める
+    fn local_set(
+        de: &'a mut KLVDeserializer<'de>,
+        len: usize,
+        fields: &'static [&'static str],
+    ) -> Result<Self> {
+        let limit = de.config.max_depth;
+        if de.path.len() + 1 > limit {
+            return Err(Error::DecodeLimit {
+                offset: de.position,
+                limit: DecodeLimit::Depth(limit),
+            });
+        }
+        Ok(Self::new(de, len).with_fields(fields))
+    }
+
+    // レコードまたは列の要素を1つ読む毎に数える
+    fn count_record(&mut self) -> Result<()> {
+        let limit = self.de.config.max_records;
+        if self.de.record_count >= limit {
+            return Err(Error::DecodeLimit {
+                offset: self.de.position,
+                limit: DecodeLimit::Records(limit),
+            });
+        }
+        self.de.record_count += 1;
+        Ok(())
+    }
+
+    // UnknownTagsのフィールドがある場合は不明なタグのレコードを集める
+    fn with_fields(mut self, fields: &'static [&'static str]) -> Self {
+        self.fields = fields;
+        if fields.contains(&UNKNOWN_TAGS) {
+            self.known = Some(fields);
+        }
+        self
+    }
+
+    // このLocal Setで次に仮の値で埋めるフィールド
+    // 初回に読み出されなかったフィールドを集める
+    fn next_absent(&mut self) -> Option<&'static str> {
+        if self.absent.is_none() {
+            let target = match &self.de.fill {
+                Some((end, path)) => *end == self.len && *path == self.de.path,
+                None => false,
+            };
+            if !target {
+                return None;
+            }
+            let (key_width, names) = (self.de.config.key_width, self.de.config.tag_names);
+            let read = &self.read;
+            self.absent = Some(
+                self.fields
+                    .iter()
+                    .rev()
+                    .copied()
+                    .filter(|x| *x != UNKNOWN_TAGS)
+                    .filter(|x| match key_width.parse_named(names, x) {
+                        Some(tag) => !read.contains(&tag),
+                        None => true,
+                    })
+                    .collect(),
+            );
+        }
+        self.absent.as_mut().and_then(|x| x.pop())
+    }
+
+    // 読み出すレコードの範囲を記録する。Lが読めない場合はこの階層の終端までとする
+    fn record(&mut self) {
+        if !self.recording {
+            self.de.records.push(None);
+            self.recording = true;
+        }
+        let start = self.de.position;
+        let end = self
+            .de
+            .key_at(start)
+            .ok()
+            .and_then(|(_, key_len)| {
+                parse_length(&self.de.input[start + key_len..])
+                    .ok()
+                    .map(|(length_len, content_len)| start + key_len + length_len + content_len)
+            })
+            .unwrap_or(self.len)
+            .min(self.len);
+        if let Some(x) = self.de.records.last_mut() {
+            *x = Some((start, end));
+        }
     }
 }
 
@@ -404,9 +1599,97 @@ impl<'de, 'a> MapAccess<'de> for KLVVisitor<'a, 'de> {
     where
         K: DeserializeSeed<'de>,
     {
-        if self.de.position >= self.len {
-            return Ok(None);
+        loop {
+            if self.de.position >= self.len {
+                if self.recording {
+                    self.de.records.pop();
+                    self.recording = false;
+                }
+                // 終端で集めたレコードをUnknownTagsのフィールドとして返す
+                if self.known.take().is_some() {
+                    self.unknown_pending = true;
+                    let key = BorrowedStrDeserializer::<Error>::new(UNKNOWN_TAGS);
+                    return seed.deserialize(key).map(Some);
+                }
+                // 欠けたフィールドを仮の値で埋める
+                if let Some(field) = self.next_absent() {
+                    self.absent_pending = Some(field);
+                    let key = BorrowedStrDeserializer::<Error>::new(field);
+                    return seed.deserialize(key).map(Some);
+                }
+                return Ok(None);
+            }
+            self.count_record()?;
+            // 読み飛ばすレコードも含めてタグが昇順であることを確認する
+            if self.de.config.deny_unordered_tags {
+                let offset = self.de.position;
+                let (tag, _) = self.de.key_at(offset)?;
+                if let Some(previous) = self.last_tag.filter(|x| *x > tag) {
+                    return Err(Error::Decode {
+                        offset,
+                        tag: Some(tag),
+                        path: self.de.tag_path(Some(tag)),
+                        kind: DecodeErrorKind::UnorderedTag { previous },
+                    });
+                }
+                self.last_tag = Some(tag);
+            }
+            // 繰り返しフィールドとして読み出し済みのレコードは飛ばす
+            if self.de.skip.remove(&self.de.position) {
+                trace!(offset = self.de.position, "skip repeated record");
+                self.de.position += self.de.key_at(self.de.position)?.1;
+                let len = self.de.read_length()?;
+                self.de.position += len;
+                continue;
+            }
+            // 読み出せなかったレコードは飛ばす
+            if let Some(end) = self.de.ignored.get(&self.de.position) {
+                trace!(offset = self.de.position, end = *end, "skip ignored record");
+                self.de.position = *end;
+                continue;
+            }
+            // フィールドにないタグのレコードは読み出さずに集める
+            if let Some(known) = self.known {
+                let key_width = self.de.config.key_width;
+                let (tag, key_len) = self.de.key_at(self.de.position)?;
+                let names = self.de.config.tag_names;
+                if !known
+                    .iter()
+                    .any(|x| key_width.parse_named(names, x) == Some(tag))
+                {
+                    trace!(tag = tag, offset = self.de.position, "unknown record");
+                    self.de.tag = Some(tag);
+                    self.de.position += key_len;
+                    let len = self.de.read_length()?;
+                    let value = self.de.read_bytes(len)?;
+                    self.unknown.push((tag, value));
+                    continue;
+                }
+            }
+            break;
+        }
+        if self.de.lossy {
+            self.record();
         }
+        if let Some(seen) = self.seen.as_mut() {
+            let offset = self.de.position;
+            if let Ok((tag, _)) = self.de.key_at(offset) {
+                if let Some(first_offset) = seen.insert(tag, offset) {
+                    return Err(Error::DuplicateKey {
+                        tag,
+                        path: self.de.tag_path(Some(tag)),
+                        first_offset,
+                        second_offset: offset,
+                    });
+                }
+            }
+        }
+        if self.de.fill.is_some() {
+            if let Ok((tag, _)) = self.de.key_at(self.de.position) {
+                self.read.push(tag);
+            }
+        }
+        self.de.fields = self.fields;
         seed.deserialize(&mut *self.de).map(Some)
     }
 
@@ -414,17 +1697,125 @@ impl<'de, 'a> MapAccess<'de> for KLVVisitor<'a, 'de> {
     where
         V: DeserializeSeed<'de>,
     {
+        if std::mem::take(&mut self.unknown_pending) {
+            let records = std::mem::take(&mut self.unknown);
+            return seed.deserialize(MapDeserializer::<_, Error>::new(records.into_iter()));
+        }
+        if let Some(field) = self.absent_pending.take() {
+            let mut missing = false;
+            let v = seed.deserialize(Absent(&mut missing))?;
+            if missing {
+                self.de.missing.push(field);
+            }
+            return Ok(v);
+        }
         // >=ではないのはunitのような長さ0のデータが末尾に来る場合に
         // positionがValueの位置ではなくlenを超えた次のKeyに来るため
         if self.de.position > self.len {
             return Err(Error::ExpectedMapEnd);
         }
-        let v = seed.deserialize(&mut *self.de)?;
+        // Deserialize実装からのエラーにレコードの情報を付与する
+        trace!(
+            tag = self.de.tag,
+            offset = self.de.position,
+            length = self.de.next_len.last().map(|x| x.1),
+            "value"
+        );
+        let map_end = std::mem::replace(&mut self.de.map_end, self.len);
+        let v = seed.deserialize(&mut *self.de).map_err(|e| match e {
+            Error::Message(msg) => self.de.error(DecodeErrorKind::Custom(msg)),
+            e => e,
+        })?;
+        self.de.map_end = map_end;
         self.de.next_len.pop();
         Ok(v)
     }
 }
 
+// 同じLocal Set内の同じタグのレコードを順に読み出す
+struct RepeatedAccess<'a, 'de: 'a> {
+    de: &'a mut KLVDeserializer<'de>,
+    tag: u128,
+    // next_lenのフィールド分を除いた深さ
+    base: usize,
+    // 最初のレコードの長さ
+    first_len: usize,
+    // 最初のレコードを読み終えた位置。最後にここへ戻る
+    resume: Option<usize>,
+    // 次に探索するレコードの位置
+    cursor: usize,
+}
+
+impl<'a, 'de> RepeatedAccess<'a, 'de> {
+    fn new(de: &'a mut KLVDeserializer<'de>) -> Result<Self> {
+        let (tag, first_len) = *de.next_len.last().ok_or(Error::NeedKey)?;
+        let base = de.next_len.len() - 1;
+        let cursor = de.position + first_len;
+        Ok(Self {
+            de,
+            tag,
+            base,
+            first_len,
+            resume: None,
+            cursor,
+        })
+    }
+
+    // 次のレコードを探してValueの位置と長さを返す
+    fn find_next(&mut self) -> Result<Option<(usize, usize)>> {
+        while self.cursor < self.de.map_end {
+            let start = self.cursor;
+            let (key, key_len) = self.de.key_at(start)?;
+            self.de.position = start + key_len;
+            let len = self.de.read_length()?;
+            let value_start = self.de.position;
+            self.cursor = value_start + len;
+            if key == self.tag && self.de.skip.insert(start) {
+                return Ok(Some((value_start, len)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn set_field(&mut self, len: usize) {
+        self.de.next_len.truncate(self.base);
+        self.de.next_len.push((self.tag, len));
+        self.de.field_len = Some(len);
+        self.de.tag = Some(self.tag);
+    }
+}
+
+impl<'de, 'a> SeqAccess<'de> for RepeatedAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.resume.is_none() {
+            self.set_field(self.first_len);
+            let v = seed.deserialize(&mut *self.de)?;
+            self.resume = Some(self.de.position);
+            return Ok(Some(v));
+        }
+        match self.find_next()? {
+            Some((value_start, len)) => {
+                self.de.position = value_start;
+                self.set_field(len);
+                seed.deserialize(&mut *self.de).map(Some)
+            }
+            None => {
+                // 最初のレコードの直後から通常の読み出しを続ける
+                if let Some(resume) = self.resume {
+                    self.de.position = resume;
+                }
+                self.set_field(self.first_len);
+                Ok(None)
+            }
+        }
+    }
+}
+
 impl<'de, 'a> SeqAccess<'de> for KLVVisitor<'a, 'de> {
     type Error = Error;
 
@@ -438,81 +1829,507 @@ impl<'de, 'a> SeqAccess<'de> for KLVVisitor<'a, 'de> {
             x if x > self.len => return Err(Error::ExpectedSeqEnd),
             _ => unreachable!(),
         }
+        self.count_record()?;
         seed.deserialize(&mut *self.de).map(Some)
     }
+
+    // 要素の型が分からないため残りのbyte数は要素数にならない
+    fn size_hint(&self) -> Option<usize> {
+        None
+    }
 }
 
-/// Parse unknown KLVdata
-///
-/// Example
-/// ```
-/// use serde_klv::KLVMap;
-///
-/// let buf = vec![0,0,0,0,3,10,1,128];
-/// let x = KLVMap::try_from_bytes(&buf).unwrap();
-/// assert_eq!(x.universal_key(), "\0\0\0\0".as_bytes());
-/// assert_eq!(x.content_len(), 3);
-/// assert_eq!(x.iter().len(), 1);
-/// ```
-#[derive(Debug)]
-pub struct KLVMap<'m> {
-    universal_key: &'m [u8],
-    content_len: usize,
-    values: Vec<KLVRaw<'m>>,
+// UniversalKeyで選んだvariantの値をパケットとして読む
+struct PacketEnumAccess<'a, 'de: 'a> {
+    de: &'a mut KLVDeserializer<'de>,
+    variant: &'static str,
 }
 
-impl<'m> KLVMap<'m> {
-    /// parse from bytes
-    pub fn try_from_bytes(buf: &'m [u8]) -> Result<Self> {
-        let buf_len = buf.len();
-        // key長探索
-        let uk_len = Self::find_universal_key(buf)?;
-        let universal_key = &buf[0..uk_len];
-        let (length_len, content_len) =
-            parse_length(&buf[uk_len..]).map_err(Error::UnsupportedLength)?;
-        let mut position = uk_len + length_len;
-        let mut values = vec![];
-        while position < buf_len {
-            let (length_len, content_len) =
-                parse_length(&buf[position + 1..]).map_err(Error::UnsupportedLength)?;
-            values.push(KLVRaw::from(
-                buf[position],
-                position,
-                content_len,
-                &buf[position + 1 + length_len..],
-            ));
-            position += 1 + length_len + content_len;
-        }
+impl<'a, 'de> PacketEnumAccess<'a, 'de> {
+    // variant名をUniversalKeyとして中の構造体を読む
+    fn with_universal_key<T, F>(self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut KLVDeserializer<'de>) -> Result<T>,
+    {
+        let universal_key = self.de.universal_key.replace(self.variant.as_bytes());
+        let result = f(&mut *self.de);
+        self.de.universal_key = universal_key;
+        result
+    }
+}
 
-        Ok(Self {
-            universal_key,
-            content_len,
-            values,
-        })
+impl<'de, 'a> de::EnumAccess<'de> for PacketEnumAccess<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let value = seed.deserialize(BorrowedStrDeserializer::<Error>::new(self.variant))?;
+        Ok((value, self))
     }
+}
 
-    /// get universal key
-    pub fn universal_key(&'m self) -> &'m [u8] {
-        self.universal_key
+impl<'de, 'a> de::VariantAccess<'de> for PacketEnumAccess<'a, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Err(Error::Unsupported(
+            "unit variant can not be a packet".to_string(),
+        ))
     }
-    /// get content length
-    pub fn content_len(&'m self) -> usize {
-        self.content_len
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        self.with_universal_key(|de| seed.deserialize(de))
     }
-    /// iterate KLV records
-    pub fn iter(&'m self) -> std::slice::Iter<KLVRaw<'m>> {
-        self.values.iter()
+
+    fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::Unsupported(
+            "tuple variant can not be a packet".to_string(),
+        ))
     }
 
-    // データからUniversalKeyの長さを取り出す
-    fn find_universal_key(buf: &'m [u8]) -> Result<usize> {
-        let buf_len = buf.len();
-        for l in [1, 2, 4, 16] {
-            // バッファの長さが想定する長さより短い
-            if l >= buf_len {
-                break;
-            }
-            let (lenght_len, content_len) =
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let variant = self.variant;
+        self.with_universal_key(|de| {
+            de::Deserializer::deserialize_struct(de, variant, fields, visitor)
+        })
+    }
+}
+
+// 連続したパケットを順に読み出す
+struct PacketAccess<'a, 'de: 'a> {
+    de: &'a mut KLVDeserializer<'de>,
+}
+
+impl<'de, 'a> SeqAccess<'de> for PacketAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.de.position >= self.de.input.len() {
+            return Ok(None);
+        }
+        // パケット毎にTopLevelから読み直す
+        let start = self.de.position;
+        self.de.depth = 0;
+        self.de.tag = None;
+        self.de.map_end = self.de.input.len();
+        let v = seed.deserialize(&mut *self.de)?;
+        self.de.depth = 0;
+        // 読み進められない場合は無限ループになるのでエラーとする
+        if self.de.position == start {
+            return Err(Error::ContentLenght);
+        }
+        Ok(Some(v))
+    }
+}
+
+// 固定長の要素をremaining個読み出す
+struct TupleAccess<'a, 'de: 'a> {
+    de: &'a mut KLVDeserializer<'de>,
+    end: usize,
+    remaining: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for TupleAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        // Lを読み切った場合は要素数の不足としてVisitorがエラーにする
+        if self.remaining == 0 || self.de.position >= self.end {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+/// Parse many packets into [`KLVMap`] for throughput
+///
+/// Records are parsed into a scratch buffer shared by all packets,
+/// then each map allocates its records once at the exact size.
+///
+/// Example
+/// ```
+/// use serde_klv::decode_batch;
+///
+/// let a = [b'T', b'E', b'S', b'T', 3, 10, 1, 1];
+/// let b = [b'T', b'E', b'S', b'T', 6, 10, 1, 2, 11, 1, 3];
+/// let maps = decode_batch(&[&a, &b, &a[..5]]);
+/// assert_eq!(maps[0].as_ref().unwrap().get_u8(10), Some(1));
+/// assert_eq!(maps[1].as_ref().unwrap().iter().len(), 2);
+/// assert!(maps[2].is_err());
+/// ```
+pub fn decode_batch<'m>(packets: &[&'m [u8]]) -> Vec<Result<KLVMap<'m>>> {
+    let mut scratch = vec![];
+    packets
+        .iter()
+        .map(|&buf| {
+            scratch.clear();
+            let (uk_len, content_len) = KLVMap::parse_records(
+                buf,
+                DeserializerConfig::DEFAULT_MAX_CONTENT_LEN,
+                &mut scratch,
+            )?;
+            Ok(KLVMap {
+                universal_key: &buf[0..uk_len],
+                content_len,
+                values: scratch.drain(..).collect(),
+                has_checksum: false,
+            })
+        })
+        .collect()
+}
+
+/// Parse unknown KLVdata
+///
+/// Example
+/// ```
+/// use serde_klv::KLVMap;
+///
+/// let buf = vec![0,0,0,0,3,10,1,128];
+/// let x = KLVMap::try_from_bytes(&buf).unwrap();
+/// assert_eq!(x.universal_key(), "\0\0\0\0".as_bytes());
+/// assert_eq!(x.content_len(), 3);
+/// assert_eq!(x.iter().len(), 1);
+/// ```
+#[derive(Debug)]
+pub struct KLVMap<'m> {
+    pub(crate) universal_key: &'m [u8],
+    pub(crate) content_len: usize,
+    pub(crate) values: Vec<KLVRaw<'m>>,
+    // checksumを検証して読んだか
+    pub(crate) has_checksum: bool,
+}
+
+impl<'m> KLVMap<'m> {
+    /// parse from bytes
+    pub fn try_from_bytes(buf: &'m [u8]) -> Result<Self> {
+        Self::try_from_bytes_with_limit(buf, DeserializerConfig::DEFAULT_MAX_CONTENT_LEN)
+    }
+
+    /// parse from bytes after verifying the checksum record at the end
+    ///
+    /// Return [`Error::HasNotChecksum`] when the packet has not the checksum record of `crc`,
+    /// like [`crate::from_bytes_with_checksum`].
+    /// The checksum record remains in the records.
+    ///
+    /// Example
+    /// ```
+    /// use serde_klv::{KLVMap, WrappedCRC};
+    ///
+    /// let buf = [b'T', b'E', b'S', b'T', 3, 10, 1, 1];
+    /// assert!(KLVMap::try_from_bytes_with_checksum(&buf, WrappedCRC::default()).is_err());
+    /// assert!(!KLVMap::try_from_bytes(&buf).unwrap().has_checksum());
+    /// ```
+    pub fn try_from_bytes_with_checksum<C: crate::checksum::CheckSumCalc>(
+        buf: &'m [u8],
+        crc: C,
+    ) -> Result<Self> {
+        checksum(buf, crc)?;
+        let mut map = Self::try_from_bytes(buf)?;
+        map.has_checksum = true;
+        Ok(map)
+    }
+
+    /// parse from bytes, reject BER length exceeding `max_content_len`
+    pub fn try_from_bytes_with_limit(buf: &'m [u8], max_content_len: usize) -> Result<Self> {
+        let mut values = vec![];
+        let (uk_len, content_len) = Self::parse_records(buf, max_content_len, &mut values)?;
+        Ok(Self {
+            universal_key: &buf[0..uk_len],
+            content_len,
+            values,
+            has_checksum: false,
+        })
+    }
+
+    // レコードをvaluesに追加し、UniversalKeyの長さとLを返す
+    fn parse_records(
+        buf: &'m [u8],
+        max_content_len: usize,
+        values: &mut Vec<KLVRaw<'m>>,
+    ) -> Result<(usize, usize)> {
+        let buf_len = buf.len();
+        // key長探索
+        let uk_len = Self::find_universal_key(buf)?;
+        let (length_len, content_len) =
+            parse_length(&buf[uk_len..]).map_err(Error::UnsupportedLength)?;
+        if content_len > max_content_len {
+            return Err(Error::LengthLimit {
+                offset: uk_len,
+                length: content_len,
+                limit: max_content_len,
+            });
+        }
+        let mut position = uk_len + length_len;
+        while position < buf_len {
+            let key = buf[position];
+            let (length_len, content_len) =
+                parse_length(&buf[position + 1..]).map_err(|e| Error::Decode {
+                    offset: position + 1,
+                    tag: Some(key.into()),
+                    path: DecodePath::from(&[u128::from(key)][..]),
+                    kind: DecodeErrorKind::Length(e),
+                })?;
+            if content_len > max_content_len {
+                return Err(Error::LengthLimit {
+                    offset: position + 1,
+                    length: content_len,
+                    limit: max_content_len,
+                });
+            }
+            let remains = buf_len - (position + 1 + length_len);
+            if content_len > remains {
+                return Err(Error::Decode {
+                    offset: position + 1 + length_len,
+                    tag: Some(key.into()),
+                    path: DecodePath::from(&[u128::from(key)][..]),
+                    kind: DecodeErrorKind::UnexpectedEnd {
+                        expected: content_len,
+                        actual: remains,
+                    },
+                });
+            }
+            let mut raw = KLVRaw::from(
+                buf[position],
+                position,
+                content_len,
+                &buf[position + 1 + length_len..],
+            );
+            raw.length_len = length_len;
+            values.push(raw);
+            position += 1 + length_len + content_len;
+        }
+        Ok((uk_len, content_len))
+    }
+
+    /// get universal key
+    pub fn universal_key(&'m self) -> &'m [u8] {
+        self.universal_key
+    }
+    /// find the standard of the universal key from [`crate::UniversalKey::KNOWN`]
+    pub fn detect_standard(&self) -> Option<crate::UniversalKey> {
+        crate::UniversalKey::lookup(self.universal_key)
+    }
+    /// get content length
+    pub fn content_len(&'m self) -> usize {
+        self.content_len
+    }
+    /// whether the checksum record was verified by [`KLVMap::try_from_bytes_with_checksum`]
+    pub fn has_checksum(&self) -> bool {
+        self.has_checksum
+    }
+    /// copy the records into [`KLVMapOwned`] not borrowing the input
+    pub fn to_owned(&self) -> KLVMapOwned {
+        KLVMapOwned {
+            universal_key: self.universal_key.to_vec(),
+            content_len: self.content_len,
+            values: self.values.iter().map(|x| x.to_owned()).collect(),
+            has_checksum: self.has_checksum,
+        }
+    }
+    /// iterate KLV records
+    pub fn iter(&'m self) -> std::slice::Iter<KLVRaw<'m>> {
+        self.values.iter()
+    }
+
+    /// first record of the tag
+    pub fn get(&self, tag: u8) -> Option<&KLVRaw<'m>> {
+        self.values.iter().find(|x| x.key == tag)
+    }
+    /// first record at the tag path, descending into nested local sets
+    ///
+    /// `position` of a nested record is also the offset from the head of the packet,
+    /// so [`KLVRaw::as_slice_of`] takes the same buffer as the top level records.
+    ///
+    /// Example
+    /// ```
+    /// use serde_klv::KLVMap;
+    ///
+    /// // tag 48 holds a local set of tag 1 and 4
+    /// let buf = [b'T', b'E', b'S', b'T', 8, 48, 6, 1, 1, 1, 4, 1, 3];
+    /// let map = KLVMap::try_from_bytes(&buf).unwrap();
+    /// assert_eq!(map.get_path(&[48, 4]).and_then(|x| x.as_u8()), Some(3));
+    /// assert!(map.get_path(&[48, 2]).is_none());
+    /// ```
+    pub fn get_path(&self, path: &[u8]) -> Option<KLVRaw<'m>> {
+        let (first, rest) = path.split_first()?;
+        let mut raw = self.get(*first)?.clone();
+        for tag in rest {
+            // 親の値からの位置をパケット先頭からの位置に直す
+            let base = raw.value_offset();
+            raw = parse_local_set(raw.value.unwrap_or_default())?
+                .into_iter()
+                .find(|x| x.key == *tag)?;
+            raw.position += base;
+        }
+        Some(raw)
+    }
+    /// value bytes of the tag, empty slice for zero-length record
+    pub fn get_bytes(&self, tag: u8) -> Option<&'m [u8]> {
+        self.get(tag).map(|x| x.value.unwrap_or_default())
+    }
+    /// value of the tag as UTF-8 string
+    pub fn get_str(&self, tag: u8) -> Option<&'m str> {
+        self.get(tag)?.as_str()
+    }
+    pub fn get_bool(&self, tag: u8) -> Option<bool> {
+        self.get(tag)?.as_bool()
+    }
+    pub fn get_u8(&self, tag: u8) -> Option<u8> {
+        self.get(tag)?.as_u8()
+    }
+    pub fn get_u16(&self, tag: u8) -> Option<u16> {
+        self.get(tag)?.as_u16()
+    }
+    pub fn get_u32(&self, tag: u8) -> Option<u32> {
+        self.get(tag)?.as_u32()
+    }
+    pub fn get_u64(&self, tag: u8) -> Option<u64> {
+        self.get(tag)?.as_u64()
+    }
+    pub fn get_i8(&self, tag: u8) -> Option<i8> {
+        self.get(tag)?.as_i8()
+    }
+    pub fn get_i16(&self, tag: u8) -> Option<i16> {
+        self.get(tag)?.as_i16()
+    }
+    pub fn get_i32(&self, tag: u8) -> Option<i32> {
+        self.get(tag)?.as_i32()
+    }
+    pub fn get_i64(&self, tag: u8) -> Option<i64> {
+        self.get(tag)?.as_i64()
+    }
+    pub fn get_f32(&self, tag: u8) -> Option<f32> {
+        self.get(tag)?.as_f32()
+    }
+    pub fn get_f64(&self, tag: u8) -> Option<f64> {
+        self.get(tag)?.as_f64()
+    }
+
+    /// deserialize the parsed records into `T` without parsing the bytes again
+    ///
+    /// Each record is deserialized from its own value bytes,
+    /// so fields with [`crate::repeated`] receive only one record.
+    ///
+    /// Example
+    /// ```
+    /// use serde::Deserialize;
+    /// use serde_klv::KLVMap;
+    ///
+    /// #[derive(Debug, Deserialize, PartialEq)]
+    /// #[serde(rename = "TEST")]
+    /// struct Packet<'a> {
+    ///     #[serde(rename = "10")]
+    ///     u8: u8,
+    ///     #[serde(rename = "11")]
+    ///     name: &'a str,
+    /// }
+    ///
+    /// let buf = [b'T', b'E', b'S', b'T', 8, 10, 1, 1, 11, 3, b'K', b'L', b'V'];
+    /// let map = KLVMap::try_from_bytes(&buf).unwrap();
+    /// assert_eq!(map.detect_standard(), None);
+    /// let x: Packet = map.deserialize_into().unwrap();
+    /// assert_eq!(x, Packet { u8: 1, name: "KLV" });
+    /// ```
+    pub fn deserialize_into<T>(&self) -> Result<T>
+    where
+        T: Deserialize<'m>,
+    {
+        T::deserialize(MapDeserializer { map: self })
+    }
+
+    /// append record at last
+    pub fn push(&mut self, key: u8, value: &'m [u8]) {
+        self.values.push(KLVRaw::from(key, 0, value.len(), value));
+        self.reindex();
+    }
+
+    /// remove first record of the key
+    pub fn remove(&mut self, key: u8) -> Option<KLVRaw<'m>> {
+        let index = self.values.iter().position(|x| x.key == key)?;
+        let raw = self.values.remove(index);
+        self.reindex();
+        Some(raw)
+    }
+
+    /// replace value of the first record of the key, or append when not exists
+    pub fn set(&mut self, key: u8, value: &'m [u8]) {
+        match self.values.iter_mut().find(|x| x.key == key) {
+            Some(raw) => *raw = KLVRaw::from(key, raw.position, value.len(), value),
+            None => self.values.push(KLVRaw::from(key, 0, value.len(), value)),
+        }
+        self.reindex();
+    }
+
+    /// encode to bytes
+    ///
+    /// Lengths are re-emitted in the shortest BER form.
+    /// Checksum record is written as is, so recalculate it when edited.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(
+            self.universal_key.len()
+                + LengthOctet::encoded_len(self.content_len)
+                + self.content_len,
+        );
+        buf.extend_from_slice(self.universal_key);
+        // Vecへの書き込みは失敗しない
+        LengthOctet::length_to_buf(&mut buf, self.content_len).unwrap();
+        for raw in self.values.iter() {
+            buf.push(raw.key);
+            LengthOctet::length_to_buf(&mut buf, raw.length).unwrap();
+            if let Some(value) = raw.value {
+                buf.extend_from_slice(value);
+            }
+        }
+        buf
+    }
+
+    // 編集後のcontent_lenとto_bytesで出力した場合のpositionを再計算する
+    fn reindex(&mut self) {
+        let mut content_len = 0;
+        for raw in self.values.iter_mut() {
+            raw.position = content_len;
+            raw.length_len = LengthOctet::encoded_len(raw.length);
+            content_len += raw.total_len();
+        }
+        let offset = self.universal_key.len() + LengthOctet::encoded_len(content_len);
+        for raw in self.values.iter_mut() {
+            raw.position += offset;
+        }
+        self.content_len = content_len;
+    }
+
+    // データからUniversalKeyの長さを取り出す
+    fn find_universal_key(buf: &'m [u8]) -> Result<usize> {
+        let buf_len = buf.len();
+        for l in [1, 2, 4, 16] {
+            // バッファの長さが想定する長さより短い
+            if l >= buf_len {
+                break;
+            }
+            let (lenght_len, content_len) =
                 parse_length(&buf[l..]).map_err(Error::UnsupportedLength)?;
             if buf_len == l + lenght_len + content_len {
                 return Ok(l);
@@ -522,13 +2339,154 @@ impl<'m> KLVMap<'m> {
     }
 }
 
+// 解析済みのKLVMapをstructとして読み出す
+struct MapDeserializer<'a, 'm> {
+    map: &'a KLVMap<'m>,
+}
+
+impl<'de, 'a> de::Deserializer<'de> for MapDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        Err(Error::Unsupported(
+            "KLVMap can deserialize only into struct".to_string(),
+        ))
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        check_universal_key_len(name.as_bytes())?;
+        if name.as_bytes() != self.map.universal_key {
+            return Err(Error::UnmatchedKey {
+                expected: name.as_bytes(),
+                found: KeyBytes::new(self.map.universal_key),
+            });
+        }
+        visitor.visit_map(RecordAccess {
+            records: self.map.values.iter(),
+            current: None,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+// KLVMapのレコードを順にフィールドとして渡す
+struct RecordAccess<'a, 'm> {
+    records: std::slice::Iter<'a, KLVRaw<'m>>,
+    current: Option<&'a KLVRaw<'m>>,
+}
+
+impl<'de, 'a> MapAccess<'de> for RecordAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        self.current = self.records.next();
+        match self.current {
+            Some(raw) => seed
+                .deserialize(de::IntoDeserializer::<Error>::into_deserializer(
+                    raw.key.to_string(),
+                ))
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let raw = self.current.take().ok_or(Error::NeedKey)?;
+        // レコードのVだけを入力として、フィールドを読む状態から始める
+        let mut deserializer = KLVDeserializer::from_slice(raw.value.unwrap_or_default());
+        deserializer.depth = 1;
+        deserializer.next_len.push((raw.key.into(), raw.length));
+        deserializer.field_len = Some(raw.length);
+        deserializer.tag = Some(raw.key.into());
+        let v = seed.deserialize(&mut deserializer).map_err(|e| match e {
+            Error::Message(msg) => deserializer.error(DecodeErrorKind::Custom(msg)),
+            e => e,
+        });
+        // エラーの位置をパケット先頭からの位置に直す
+        let base = raw.value_offset();
+        v.map_err(|e| match e {
+            Error::Decode {
+                offset,
+                tag,
+                path,
+                kind,
+            } => Error::Decode {
+                offset: base + offset,
+                tag,
+                path,
+                kind,
+            },
+            e => e,
+        })
+    }
+}
+
 /// Single KLV Record
-#[derive(Debug)]
+///
+/// `position` is the byte offset of the tag from the head of the packet,
+/// including the universal key, also for nested records of [`KLVMap::get_path`].
+/// The record occupies `position..position + total_len()` of the input,
+/// and its value `value_offset()..position + total_len()`.
+/// After editing [`KLVMap`], offsets refer to the bytes of [`KLVMap::to_bytes`].
+///
+/// `length_len` keeps the size of the length octets in the input,
+/// so that records in a redundant long form map back to their bytes.
+/// The struct is `#[non_exhaustive]` to add such metadata,
+/// build it with [`KLVRaw::from`] instead of a struct literal.
+///
+/// Example
+/// ```
+/// use serde_klv::KLVMap;
+///
+/// // length of tag 11 in redundant long form
+/// let buf = [b'T', b'E', b'S', b'T', 8, 10, 1, 1, 11, 0x81, 2, b'a', b'b'];
+/// let map = KLVMap::try_from_bytes(&buf).unwrap();
+/// let raw = map.get(11).unwrap();
+/// assert_eq!(raw.position, 8);
+/// assert_eq!(raw.value_offset(), 11);
+/// assert_eq!(raw.total_len(), 5);
+/// assert_eq!(raw.as_slice_of(&buf), Some(&buf[8..]));
+/// ```
+#[derive(Debug, Clone)]
+#[non_exhaustive]
 pub struct KLVRaw<'m> {
     pub key: u8,
     pub position: usize,
     pub length: usize,
     pub value: Option<&'m [u8]>,
+    /// byte size of the length octets, the shortest form of `length` when built by [`KLVRaw::from`]
+    ///
+    /// A size too short for `length` is read as the shortest form.
+    pub length_len: usize,
 }
 
 impl<'m> KLVRaw<'m> {
@@ -539,6 +2497,7 @@ impl<'m> KLVRaw<'m> {
                 position,
                 length,
                 value: Some(&value[..length]),
+                length_len: LengthOctet::encoded_len(length),
             }
         } else {
             Self {
@@ -546,7 +2505,1860 @@ impl<'m> KLVRaw<'m> {
                 position,
                 length,
                 value: None,
+                length_len: LengthOctet::encoded_len(length),
             }
         }
     }
+
+    /// byte offset of the value, next to the length octets
+    pub fn value_offset(&self) -> usize {
+        self.position + 1 + length_octets(self.length_len, self.length)
+    }
+
+    /// byte size of the record with the tag and the length octets
+    pub fn total_len(&self) -> usize {
+        1 + length_octets(self.length_len, self.length) + self.length
+    }
+
+    /// bytes of the record in the buffer parsed into it, None when the buffer is shorter
+    pub fn as_slice_of<'b>(&self, buf: &'b [u8]) -> Option<&'b [u8]> {
+        buf.get(self.position..self.position + self.total_len())
+    }
+
+    /// copy the value into [`KLVRawOwned`]
+    pub fn to_owned(&self) -> KLVRawOwned {
+        KLVRawOwned {
+            key: self.key,
+            position: self.position,
+            length: self.length,
+            value: self.value.map(|x| x.to_vec()),
+            length_len: self.length_len,
+        }
+    }
+
+    // デシリアライザと同様に型の幅より短い整数は拡張して読む
+    fn int_bytes(&self, width: usize) -> Option<&'m [u8]> {
+        self.value.filter(|x| x.len() <= width)
+    }
+
+    /// value as integer, extended when shorter than the type. None when empty or longer
+    pub fn as_u8(&self) -> Option<u8> {
+        self.int_bytes(1)
+            .map(|x| BigEndian::read_uint(x, x.len()) as u8)
+    }
+    pub fn as_u16(&self) -> Option<u16> {
+        self.int_bytes(2)
+            .map(|x| BigEndian::read_uint(x, x.len()) as u16)
+    }
+    pub fn as_u32(&self) -> Option<u32> {
+        self.int_bytes(4)
+            .map(|x| BigEndian::read_uint(x, x.len()) as u32)
+    }
+    pub fn as_u64(&self) -> Option<u64> {
+        self.int_bytes(8).map(|x| BigEndian::read_uint(x, x.len()))
+    }
+    pub fn as_i8(&self) -> Option<i8> {
+        self.int_bytes(1)
+            .map(|x| BigEndian::read_int(x, x.len()) as i8)
+    }
+    pub fn as_i16(&self) -> Option<i16> {
+        self.int_bytes(2)
+            .map(|x| BigEndian::read_int(x, x.len()) as i16)
+    }
+    pub fn as_i32(&self) -> Option<i32> {
+        self.int_bytes(4)
+            .map(|x| BigEndian::read_int(x, x.len()) as i32)
+    }
+    pub fn as_i64(&self) -> Option<i64> {
+        self.int_bytes(8).map(|x| BigEndian::read_int(x, x.len()))
+    }
+    /// value as UTF-8 string, None when invalid
+    pub fn as_str(&self) -> Option<&'m str> {
+        std::str::from_utf8(self.value.unwrap_or_default()).ok()
+    }
+    /// value as bool, None unless the length is 1
+    pub fn as_bool(&self) -> Option<bool> {
+        self.as_u8().filter(|_| self.length == 1).map(|x| x != 0)
+    }
+    /// value as f32, None unless the length is 4
+    pub fn as_f32(&self) -> Option<f32> {
+        self.value.filter(|x| x.len() == 4).map(BigEndian::read_f32)
+    }
+    /// value as f64, None unless the length is 8
+    pub fn as_f64(&self) -> Option<f64> {
+        self.value.filter(|x| x.len() == 8).map(BigEndian::read_f64)
+    }
+}
+
+/// [`KLVMap`] owning the records, to send parsed packets to another thread or store them
+///
+/// Getters are available through [`KLVMapOwned::as_map`].
+///
+/// Example
+/// ```
+/// use serde_klv::{KLVMap, KLVMapOwned};
+///
+/// let buf = vec![b'T', b'E', b'S', b'T', 3, 10, 1, 128];
+/// let owned: KLVMapOwned = KLVMap::try_from_bytes(&buf).unwrap().to_owned();
+/// drop(buf);
+/// let handle = std::thread::spawn(move || owned.as_map().get_u8(10));
+/// assert_eq!(handle.join().unwrap(), Some(128));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KLVMapOwned {
+    universal_key: Vec<u8>,
+    content_len: usize,
+    values: Vec<KLVRawOwned>,
+    has_checksum: bool,
+}
+
+impl KLVMapOwned {
+    /// get universal key
+    pub fn universal_key(&self) -> &[u8] {
+        &self.universal_key
+    }
+    /// get content length
+    pub fn content_len(&self) -> usize {
+        self.content_len
+    }
+    /// whether the checksum record was verified on parse
+    pub fn has_checksum(&self) -> bool {
+        self.has_checksum
+    }
+    /// iterate KLV records
+    pub fn iter(&self) -> std::slice::Iter<KLVRawOwned> {
+        self.values.iter()
+    }
+    /// borrow as [`KLVMap`] for the getters, edit and deserialize
+    pub fn as_map(&self) -> KLVMap<'_> {
+        KLVMap {
+            universal_key: &self.universal_key,
+            content_len: self.content_len,
+            values: self.values.iter().map(|x| x.as_raw()).collect(),
+            has_checksum: self.has_checksum,
+        }
+    }
+    /// replace value of the first record at the tag path, or append it to the innermost local set
+    ///
+    /// The parent records are re-encoded and the other records are kept as they are.
+    /// Returns [`Error::Key`] when a parent is not found or is not a local set.
+    /// The verified checksum record, see [`KLVMapOwned::has_checksum`], no longer matches the edited content,
+    /// so it is removed and `has_checksum` becomes false.
+    /// Recompute it by [`crate::edit::patch_value_with_checksum`] or serializing with the checksum.
+    ///
+    /// Example
+    /// ```
+    /// use serde_klv::KLVMap;
+    ///
+    /// let buf = [b'T', b'E', b'S', b'T', 8, 48, 6, 1, 1, 1, 4, 1, 3];
+    /// let mut map = KLVMap::try_from_bytes(&buf).unwrap().to_owned();
+    /// map.set_path(&[48, 1], &[5]).unwrap();
+    /// map.set_path(&[48, 6], b"US").unwrap();
+    /// assert_eq!(map.as_map().get_path(&[48, 1]).and_then(|x| x.as_u8()), Some(5));
+    /// assert_eq!(map.as_map().get_path(&[48, 6]).and_then(|x| x.as_str()), Some("US"));
+    /// assert!(map.set_path(&[49, 1], &[5]).is_err());
+    /// ```
+    pub fn set_path(&mut self, path: &[u8], value: &[u8]) -> Result<()> {
+        let (first, rest) = path
+            .split_first()
+            .ok_or_else(|| Error::Key("empty tag path".to_string()))?;
+        let value = if rest.is_empty() {
+            value.to_vec()
+        } else {
+            let parent = self
+                .values
+                .iter()
+                .find(|x| x.key == *first)
+                .ok_or_else(|| Error::Key(format!("tag {} is not found", first)))?;
+            set_in_local_set(parent.value.as_deref().unwrap_or_default(), path, 1, value)?
+        };
+        let owned = {
+            let mut map = self.as_map();
+            // 検証済みのchecksumは末尾のレコードで、書き換えると一致しなくなるため除く
+            if map.has_checksum {
+                map.values.pop();
+                map.has_checksum = false;
+            }
+            map.set(*first, &value);
+            map.to_owned()
+        };
+        *self = owned;
+        Ok(())
+    }
+}
+
+// path[depth]のレコードをLocal Setのbuf内で書き換え、Local Set全体を返す
+fn set_in_local_set(buf: &[u8], path: &[u8], depth: usize, value: &[u8]) -> Result<Vec<u8>> {
+    let records = parse_local_set(buf).ok_or_else(|| {
+        Error::Key(format!(
+            "tag {} is not a local set",
+            TagPath(&path[..depth])
+        ))
+    })?;
+    let tag = path[depth];
+    let value = if depth + 1 == path.len() {
+        Cow::Borrowed(value)
+    } else {
+        let parent = records
+            .iter()
+            .find(|x| x.key == tag)
+            .ok_or_else(|| Error::Key(format!("tag {} is not found", TagPath(&path[..=depth]))))?;
+        Cow::Owned(set_in_local_set(
+            parent.value.unwrap_or_default(),
+            path,
+            depth + 1,
+            value,
+        )?)
+    };
+    let mut out = Vec::with_capacity(buf.len() + value.len());
+    let mut replaced = false;
+    for raw in records.iter() {
+        if !replaced && raw.key == tag {
+            out.push(tag);
+            // Vecへの書き込みは失敗しない
+            LengthOctet::length_to_buf(&mut out, value.len()).unwrap();
+            out.extend_from_slice(&value);
+            replaced = true;
+        } else {
+            // 編集しないレコードは入力のLの形式のまま書く
+            out.extend_from_slice(&buf[raw.position..raw.position + raw.total_len()]);
+        }
+    }
+    if !replaced {
+        out.push(tag);
+        LengthOctet::length_to_buf(&mut out, value.len()).unwrap();
+        out.extend_from_slice(&value);
+    }
+    Ok(out)
+}
+
+// Lのbyte数。lengthを表せない場合は最短の形式とする
+fn length_octets(length_len: usize, length: usize) -> usize {
+    length_len.max(LengthOctet::encoded_len(length))
+}
+
+/// [`KLVRaw`] owning the value
+///
+/// Build it with [`KLVRawOwned::from`] or [`KLVRaw::to_owned`], see [`KLVRaw`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct KLVRawOwned {
+    pub key: u8,
+    pub position: usize,
+    pub length: usize,
+    pub value: Option<Vec<u8>>,
+    /// byte size of the length octets, see [`KLVRaw::length_len`]
+    pub length_len: usize,
+}
+
+impl KLVRawOwned {
+    /// record of the value with the length octets in the shortest form
+    pub fn from(key: u8, position: usize, value: Vec<u8>) -> Self {
+        let length = value.len();
+        Self {
+            key,
+            position,
+            length,
+            value: (length > 0).then_some(value),
+            length_len: LengthOctet::encoded_len(length),
+        }
+    }
+
+    /// borrow as [`KLVRaw`] for the getters
+    pub fn as_raw(&self) -> KLVRaw<'_> {
+        KLVRaw {
+            key: self.key,
+            position: self.position,
+            length: self.length,
+            value: self.value.as_deref(),
+            length_len: self.length_len,
+        }
+    }
+}
+
+// Universal Keyの長さと最初のレコードの位置を返す
+pub(crate) fn packet_header(buf: &[u8]) -> Option<(usize, usize)> {
+    let uk_len = KLVMap::find_universal_key(buf).ok()?;
+    let (length_len, _) = parse_length(&buf[uk_len..]).ok()?;
+    Some((uk_len, uk_len + length_len))
+}
+
+// 値全体がレコードに分解できる場合のみLocal Setとみなす
+// positionは値の先頭からの位置とする
+pub(crate) fn parse_local_set(buf: &[u8]) -> Option<Vec<KLVRaw>> {
+    let mut position = 0;
+    let mut values = vec![];
+    while position < buf.len() {
+        let key = buf[position];
+        let (length_len, content_len) = parse_length(&buf[position + 1..]).ok()?;
+        let start = position + 1 + length_len;
+        let end = start.checked_add(content_len).filter(|x| *x <= buf.len())?;
+        let mut raw = KLVRaw::from(key, position, content_len, &buf[start..]);
+        raw.length_len = length_len;
+        values.push(raw);
+        position = end;
+    }
+    Some(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::error::{DecodeErrorKind, DecodeLimit, Error};
+    use crate::{
+        from_bytes, from_bytes_lossy, from_bytes_multi, from_bytes_partial, from_bytes_strict,
+        from_bytes_strict_with_config, from_bytes_with_checksum, from_bytes_with_config,
+        from_bytes_with_unknown_tags, split_packets, to_bytes, to_bytes_with_checksum,
+        to_bytes_with_config, DeserializerConfig, KLVDeserializer, KLVMap, KLVRawOwned, KeyWidth,
+        SerializerConfig, WrappedCRC,
+    };
+
+    // Lが型の幅より短い場合は拡張して読む
+    #[test]
+    fn test_deserialize_short_integer() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestInteger {
+            #[serde(rename = "10")]
+            u32: u32,
+            #[serde(rename = "11")]
+            i32: i32,
+            #[serde(rename = "12")]
+            u64: Option<u64>,
+            #[serde(rename = "13")]
+            i16: i16,
+        }
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 16,
+            10, 2, 0x01, 0xc9,
+            11, 1, 0xff,
+            12, 3, 0x01, 0x00, 0x00,
+            13, 2, 0x80, 0x00,
+        ];
+        let x = from_bytes::<TestInteger>(&buf).unwrap();
+        assert_eq!(
+            x,
+            TestInteger {
+                u32: 0x01c9,
+                i32: -1,
+                u64: Some(0x010000),
+                i16: i16::MIN,
+            }
+        );
+        // 同じ型であれば固定長でシリアライズしたものも読める
+        let buf = to_bytes(&x).unwrap();
+        assert_eq!(from_bytes::<TestInteger>(&buf).unwrap(), x);
+    }
+
+    // Lが型の幅を超える場合はエラー
+    #[test]
+    fn test_deserialize_long_integer_error() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestInteger {
+            #[serde(rename = "10")]
+            u16: u16,
+        }
+        let buf = vec![b'T', b'E', b'S', b'T', 6, 10, 4, 0, 0, 1, 0];
+        match from_bytes::<TestInteger>(&buf) {
+            Err(Error::Decode {
+                offset: 7,
+                tag: Some(10),
+                kind:
+                    DecodeErrorKind::TypeLength {
+                        expected: 2,
+                        actual: 4,
+                    },
+                ..
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_128bit_integer() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestInteger {
+            #[serde(rename = "10")]
+            u128: u128,
+            #[serde(rename = "11")]
+            i128: i128,
+        }
+        let x = TestInteger {
+            u128: 0x0123_4567_89ab_cdef_fedc_ba98_7654_3210,
+            i128: i128::MIN,
+        };
+        let buf = to_bytes(&x).unwrap();
+        // UK(4) + L(1) + (K(1) + L(1) + V(16)) * 2
+        assert_eq!(buf.len(), 41);
+        assert_eq!(&buf[7..9], &[0x01, 0x23]);
+        assert_eq!(from_bytes::<TestInteger>(&buf).unwrap(), x);
+
+        // 短いエンコードは拡張して読む
+        let buf = vec![b'T', b'E', b'S', b'T', 7, 10, 2, 0x01, 0x00, 11, 1, 0xfe];
+        assert_eq!(
+            from_bytes::<TestInteger>(&buf).unwrap(),
+            TestInteger {
+                u128: 256,
+                i128: -2
+            }
+        );
+    }
+
+    #[test]
+    fn test_deny_duplicate_keys() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestParent {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11")]
+            child: TestChild,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "1")]
+            u8: u8,
+        }
+        let config = DeserializerConfig {
+            deny_duplicate_keys: true,
+            ..Default::default()
+        };
+        // 同じタグでも階層が異なれば重複ではない
+        let buf = to_bytes(&TestParent {
+            u8: 1,
+            child: TestChild { u8: 2 },
+        })
+        .unwrap();
+        assert!(from_bytes_with_config::<TestParent>(&buf, config).is_ok());
+
+        // 入れ子の中での未知のタグの重複
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 14,
+            10, 1, 1,
+            11, 9, 1, 1, 2, 5, 1, 0, 5, 1, 0,
+        ];
+        match from_bytes_with_config::<TestParent>(&buf, config) {
+            Err(Error::DuplicateKey {
+                tag: 5,
+                path,
+                first_offset: 13,
+                second_offset: 16,
+            }) => assert_eq!(path, vec![11, 5]),
+            x => unreachable!("{:?}", x),
+        }
+        // 既定では読み飛ばす
+        assert_eq!(
+            from_bytes::<TestParent>(&buf).unwrap(),
+            TestParent {
+                u8: 1,
+                child: TestChild { u8: 2 },
+            }
+        );
+    }
+
+    // 固定長配列は要素数と型の幅からLを確認する
+    #[test]
+    fn test_fixed_size_array() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestArray {
+            #[serde(rename = "10")]
+            designator: [u8; 16],
+            #[serde(rename = "11")]
+            quaternion: [f32; 4],
+            #[serde(rename = "12")]
+            u8: u8,
+        }
+        let t = TestArray {
+            designator: *b"0123456789abcdef",
+            quaternion: [1.0, 0.0, -0.5, 0.25],
+            u8: 3,
+        };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(&buf[4..7], &[39, 10, 16]);
+        assert_eq!(&buf[23..25], &[11, 16]);
+        assert_eq!(from_bytes::<TestArray>(&buf).unwrap(), t);
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestShort {
+            #[serde(rename = "10")]
+            bytes: [u8; 4],
+            #[serde(rename = "12")]
+            u8: u8,
+        }
+        let buf = vec![b'T', b'E', b'S', b'T', 9, 10, 4, 1, 2, 3, 4, 12, 1, 3];
+        assert_eq!(
+            from_bytes::<TestShort>(&buf).unwrap(),
+            TestShort {
+                bytes: [1, 2, 3, 4],
+                u8: 3
+            }
+        );
+        // Lが要素数x型の幅より長い
+        let buf = vec![b'T', b'E', b'S', b'T', 10, 10, 5, 1, 2, 3, 4, 5, 12, 1, 3];
+        match from_bytes::<TestShort>(&buf) {
+            Err(Error::Decode {
+                tag: Some(10),
+                kind:
+                    DecodeErrorKind::TypeLength {
+                        expected: 4,
+                        actual: 5,
+                    },
+                ..
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+        // Lが短い
+        let buf = vec![b'T', b'E', b'S', b'T', 8, 10, 3, 1, 2, 3, 12, 1, 3];
+        assert!(from_bytes::<TestShort>(&buf).is_err());
+    }
+
+    // 入れ子のエラーは親からのタグのパスを持つ
+    #[test]
+    fn test_error_path() {
+        #[derive(Debug, Deserialize)]
+        struct TestChild<'a> {
+            #[serde(rename = "3")]
+            str: &'a str,
+        }
+        #[derive(Debug, Deserialize)]
+        #[serde(rename = "TEST")]
+        struct TestParent<'a> {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "48", borrow)]
+            child: TestChild<'a>,
+        }
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 8,
+            10, 1, 1,
+            48, 3, 3, 1, 0xff,
+        ];
+        let err = from_bytes::<TestParent>(&buf).unwrap_err();
+        match &err {
+            Error::Decode {
+                tag: Some(3),
+                path,
+                kind: DecodeErrorKind::InvalidString,
+                ..
+            } => assert_eq!(path, &vec![48, 3]),
+            x => unreachable!("{:?}", x),
+        }
+        assert!(err.to_string().ends_with("in tag 48/3"));
+
+        // 入れ子を抜けた後は親のタグに戻る
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 10,
+            48, 3, 3, 1, b'a',
+            10, 2, 0, 1,
+        ];
+        match from_bytes::<TestParent>(&buf) {
+            Err(Error::Decode {
+                tag: Some(10),
+                path,
+                kind: DecodeErrorKind::TypeLength { .. },
+                ..
+            }) => assert_eq!(path, vec![10]),
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    // defaultのあるフィールドは欠けていても読める
+    #[test]
+    fn test_missing_field() {
+        #[derive(Debug, Default, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "1")]
+            a: u8,
+            #[serde(rename = "2", default)]
+            b: u8,
+        }
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestDefault {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11", default)]
+            u16: u16,
+            #[serde(rename = "12", default = "default_name")]
+            name: String,
+            #[serde(rename = "13", default)]
+            child: TestChild,
+        }
+        fn default_name() -> String {
+            "none".to_string()
+        }
+
+        let buf = vec![b'T', b'E', b'S', b'T', 3, 10, 1, 5];
+        assert_eq!(
+            from_bytes::<TestDefault>(&buf).unwrap(),
+            TestDefault {
+                u8: 5,
+                u16: 0,
+                name: "none".to_string(),
+                child: TestChild::default(),
+            }
+        );
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 8,
+            10, 1, 5,
+            13, 3, 1, 1, 7,
+        ];
+        assert_eq!(
+            from_bytes::<TestDefault>(&buf).unwrap().child,
+            TestChild { a: 7, b: 0 }
+        );
+
+        // defaultのないフィールドはLocal Setの終端の位置で報告する
+        let buf = vec![b'T', b'E', b'S', b'T', 4, 11, 2, 0, 5];
+        match from_bytes::<TestDefault>(&buf) {
+            Err(Error::Decode {
+                offset: 9,
+                tag: None,
+                path,
+                kind: DecodeErrorKind::MissingField("10"),
+            }) => assert!(path.is_empty()),
+            x => unreachable!("{:?}", x),
+        }
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 8,
+            10, 1, 5,
+            13, 3, 2, 1, 7,
+        ];
+        let err = from_bytes::<TestDefault>(&buf).unwrap_err();
+        match &err {
+            Error::Decode {
+                offset: 13,
+                tag: Some(13),
+                path,
+                kind: DecodeErrorKind::MissingField("1"),
+            } => assert_eq!(path, &vec![13]),
+            x => unreachable!("{:?}", x),
+        }
+        assert_eq!(err.to_string(), "missing field `1` at offset 13 in tag 13");
+
+        // KLVMapからの読み出しも同じ位置を報告する
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert!(matches!(
+            map.deserialize_into::<TestDefault>(),
+            Err(Error::Decode {
+                offset: 13,
+                kind: DecodeErrorKind::MissingField("1"),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_from_bytes_strict() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct TestChild<'a> {
+            #[serde(rename = "2")]
+            name: &'a str,
+            #[serde(rename = "3")]
+            none: Option<u8>,
+            #[serde(rename = "4")]
+            pair: (u8, f32),
+        }
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestStrict<'a> {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11", default)]
+            u16: u16,
+            #[serde(rename = "12", borrow)]
+            child: TestChild<'a>,
+            #[serde(rename = "13")]
+            bytes: &'a [u8],
+        }
+
+        // 欠けがなければfrom_bytesと同じ
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 19,
+            10, 1, 1,
+            12, 10, 2, 1, b'a', 4, 5, 5, 0x3f, 0x80, 0, 0,
+            13, 2, 1, 2,
+        ];
+        assert_eq!(
+            from_bytes_strict::<TestStrict>(&buf).unwrap(),
+            TestStrict {
+                u8: 1,
+                u16: 0,
+                child: TestChild {
+                    name: "a",
+                    none: None,
+                    pair: (5, 1.0),
+                },
+                bytes: &[1, 2],
+            }
+        );
+
+        // 入れ子のLocal Setで欠けたフィールドを全て報告する
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 7,
+            10, 1, 1,
+            12, 2, 3, 0,
+        ];
+        let err = from_bytes_strict::<TestStrict>(&buf).unwrap_err();
+        match &err {
+            Error::MissingTags { path, tags } => {
+                assert_eq!(path, &vec![12]);
+                assert_eq!(tags, &vec![2, 4]);
+            }
+            x => unreachable!("{:?}", x),
+        }
+        assert_eq!(err.to_string(), "missing tags [2, 4] in tag 12");
+        assert!(matches!(
+            from_bytes::<TestStrict>(&buf),
+            Err(Error::Decode {
+                kind: DecodeErrorKind::MissingField("2"),
+                ..
+            })
+        ));
+
+        // 最上位で欠けたフィールド。欠けた構造体は仮の値で埋めて続ける
+        let buf = vec![b'T', b'E', b'S', b'T', 4, 11, 2, 0, 1];
+        match from_bytes_strict::<TestStrict>(&buf) {
+            Err(Error::MissingTags { path, tags }) => {
+                assert!(path.is_empty());
+                assert_eq!(tags, vec![10, 12, 13]);
+            }
+            x => unreachable!("{:?}", x),
+        }
+
+        // 他のエラーはそのまま返す
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 8,
+            10, 1, 1,
+            12, 3, 2, 1, 0xff,
+        ];
+        assert!(matches!(
+            from_bytes_strict::<TestStrict>(&buf),
+            Err(Error::Decode {
+                kind: DecodeErrorKind::InvalidString,
+                ..
+            })
+        ));
+
+        #[derive(Debug, Deserialize)]
+        enum TestMode {
+            A,
+        }
+        #[derive(Debug, Deserialize)]
+        #[serde(rename = "TEST")]
+        struct TestEnumField {
+            #[serde(rename = "1")]
+            _mode: TestMode,
+            #[serde(rename = "2")]
+            _u8: u8,
+        }
+        match from_bytes_strict::<TestEnumField>(&[b'T', b'E', b'S', b'T', 0]) {
+            Err(Error::MissingTags { tags, .. }) => assert_eq!(tags, vec![1, 2]),
+            x => unreachable!("{:?}", x),
+        }
+
+        // 名前の表とKeyの幅からタグを求める
+        #[derive(Debug, Deserialize)]
+        #[serde(rename = "TEST")]
+        struct TestNamed {
+            #[serde(rename = "altitude")]
+            _altitude: u16,
+            #[serde(rename = "300")]
+            _heading: u8,
+            #[serde(rename = "301")]
+            _speed: Option<u8>,
+        }
+        let config = DeserializerConfig {
+            key_width: KeyWidth::U16,
+            ..Default::default()
+        }
+        .tag_names(&[("altitude", 0x0102)])
+        .unwrap();
+        let buf = [b'T', b'E', b'S', b'T', 4, 0x01, 0x2d, 1, 5];
+        match from_bytes_strict_with_config::<TestNamed>(&buf, config) {
+            Err(Error::MissingTags { path, tags }) => {
+                assert!(path.is_empty());
+                assert_eq!(tags, vec![0x0102, 300]);
+            }
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_deserialize_any() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(untagged)]
+        enum TestAny<'a> {
+            Unit(()),
+            U8(u8),
+            U16(u16),
+            U32(u32),
+            U64(u64),
+            F64(f64),
+            Bytes(&'a [u8]),
+        }
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestPacket<'a> {
+            #[serde(rename = "10", borrow)]
+            unit: TestAny<'a>,
+            #[serde(rename = "11", borrow)]
+            u8: TestAny<'a>,
+            #[serde(rename = "12", borrow)]
+            u16: TestAny<'a>,
+            #[serde(rename = "13", borrow)]
+            u32: TestAny<'a>,
+            #[serde(rename = "14", borrow)]
+            u64: TestAny<'a>,
+            #[serde(rename = "15", borrow)]
+            bytes: TestAny<'a>,
+        }
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 30,
+            10, 0,
+            11, 1, 1,
+            12, 2, 1, 0,
+            13, 4, 0x3f, 0x80, 0, 0,
+            14, 8, 0x3f, 0xf0, 0, 0, 0, 0, 0, 0,
+            15, 3, 1, 2, 3,
+        ];
+        let x: TestPacket = from_bytes(&buf).unwrap();
+        assert_eq!(
+            x,
+            TestPacket {
+                unit: TestAny::Unit(()),
+                u8: TestAny::U8(1),
+                u16: TestAny::U16(0x0100),
+                u32: TestAny::U32(0x3f80_0000),
+                u64: TestAny::U64(0x3ff0_0000_0000_0000),
+                bytes: TestAny::Bytes(&[1, 2, 3]),
+            }
+        );
+
+        let config = DeserializerConfig {
+            any_as_float: true,
+            ..Default::default()
+        };
+        let x: TestPacket = from_bytes_with_config(&buf, config).unwrap();
+        assert_eq!(x.u32, TestAny::F64(1.0));
+        assert_eq!(x.u64, TestAny::F64(1.0));
+
+        // パケット全体は推測しない
+        assert!(matches!(
+            from_bytes::<TestAny>(&buf),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_serde_path_to_error() {
+        #[derive(Debug, Deserialize)]
+        struct TestChild<'a> {
+            #[serde(rename = "3")]
+            str: &'a str,
+        }
+        #[derive(Debug, Deserialize)]
+        #[serde(rename = "TEST")]
+        struct TestParent<'a> {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "48", borrow)]
+            child: TestChild<'a>,
+        }
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 8,
+            10, 1, 1,
+            48, 3, 3, 1, 0xff,
+        ];
+        let mut de = KLVDeserializer::from_slice(&buf);
+        let err = serde_path_to_error::deserialize::<_, TestParent>(&mut de).unwrap_err();
+        // パスはフィールドのタグで表される
+        assert_eq!(err.path().to_string(), "48.3");
+        assert!(matches!(
+            err.inner(),
+            Error::Decode {
+                kind: DecodeErrorKind::InvalidString,
+                ..
+            }
+        ));
+        assert!(err.inner().to_string().contains("at offset"));
+
+        // 全てのエラーが内容を表示する
+        assert_eq!(Error::NeedKey.to_string(), "value has no key");
+        assert_eq!(
+            Error::UnmatcheChecksum {
+                value: 0x1234,
+                calced: 0xabcd
+            }
+            .to_string(),
+            "checksum mismatch, packet has 0x1234 but calculated 0xabcd"
+        );
+    }
+
+    // Cowはborrow指定時は入力を借用し、それ以外はコピーする
+    #[test]
+    fn test_deserialize_cow() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestCow<'a> {
+            #[serde(rename = "10", borrow)]
+            str: Cow<'a, str>,
+            #[serde(rename = "11")]
+            owned_str: Cow<'a, str>,
+            #[serde(rename = "12", borrow)]
+            bytes: Cow<'a, [u8]>,
+            #[serde(rename = "13")]
+            owned_bytes: Cow<'a, [u8]>,
+            #[serde(rename = "14")]
+            string: String,
+        }
+        let t = TestCow {
+            str: Cow::Borrowed("borrowed"),
+            owned_str: Cow::Owned("owned".to_string()),
+            bytes: Cow::Borrowed(&[1, 2, 3]),
+            owned_bytes: Cow::Owned(vec![4, 5]),
+            string: "string".to_string(),
+        };
+        let buf = to_bytes(&t).unwrap();
+        let x = from_bytes::<TestCow>(&buf).unwrap();
+        assert_eq!(x, t);
+        assert!(matches!(x.str, Cow::Borrowed(_)));
+        assert!(matches!(x.owned_str, Cow::Owned(_)));
+        assert!(matches!(x.bytes, Cow::Borrowed(_)));
+        assert!(matches!(x.owned_bytes, Cow::Owned(_)));
+    }
+
+    #[test]
+    fn test_length_limit() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestLimit<'a> {
+            #[serde(rename = "10")]
+            str: &'a str,
+        }
+        // 8byteのLで巨大な長さを宣言する
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 0x88, 0, 0, 0x10, 0, 0, 0, 0, 0,
+            10, 1, b'a',
+        ];
+        match from_bytes::<TestLimit>(&buf) {
+            Err(Error::LengthLimit {
+                offset: 4,
+                length: 0x1000_0000_0000,
+                limit: DeserializerConfig::DEFAULT_MAX_CONTENT_LEN,
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+        assert!(matches!(
+            KLVMap::try_from_bytes(&buf),
+            Err(Error::LengthLimit { offset: 4, .. })
+        ));
+        assert_eq!(split_packets(&buf, 4).next(), None);
+
+        // 上限は設定できる
+        let buf = to_bytes(&TestLimit { str: "abcd" }).unwrap();
+        let config = DeserializerConfig {
+            max_content_len: 4,
+            ..Default::default()
+        };
+        match from_bytes_with_config::<TestLimit>(&buf, config) {
+            Err(Error::LengthLimit {
+                offset: 4,
+                length: 6,
+                limit: 4,
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+        let config = DeserializerConfig {
+            max_content_len: 6,
+            ..Default::default()
+        };
+        assert!(from_bytes_with_config::<TestLimit>(&buf, config).is_ok());
+        assert!(matches!(
+            KLVMap::try_from_bytes_with_limit(&buf, 5),
+            Err(Error::LengthLimit { offset: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_limit() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestNode {
+            #[serde(rename = "1")]
+            child: Option<Box<TestNode>>,
+        }
+        let mut t = TestNode { child: None };
+        for _ in 0..4 {
+            t = TestNode {
+                child: Some(Box::new(t)),
+            };
+        }
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(from_bytes::<TestNode>(&buf).unwrap(), t);
+
+        // 最上位を含めて5階層のLocal Set
+        let mut config = DeserializerConfig {
+            max_depth: 4,
+            ..Default::default()
+        };
+        match from_bytes_with_config::<TestNode>(&buf, config) {
+            Err(Error::DecodeLimit {
+                limit: DecodeLimit::Depth(4),
+                ..
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+        config.max_depth = 5;
+        assert_eq!(from_bytes_with_config::<TestNode>(&buf, config).unwrap(), t);
+
+        // 長さ0の不明なレコードも数える
+        let mut buf = vec![b'T', b'E', b'S', b'T', 22];
+        for _ in 0..10 {
+            buf.extend_from_slice(&[2, 0]);
+        }
+        buf.extend_from_slice(&[1, 0]);
+        let config = DeserializerConfig {
+            max_records: 10,
+            ..Default::default()
+        };
+        match from_bytes_with_config::<TestNode>(&buf, config) {
+            Err(Error::DecodeLimit {
+                offset: 25,
+                limit: DecodeLimit::Records(10),
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+        let config = DeserializerConfig {
+            max_records: 11,
+            ..Default::default()
+        };
+        assert_eq!(
+            from_bytes_with_config::<TestNode>(&buf, config).unwrap(),
+            TestNode { child: None }
+        );
+    }
+
+    #[test]
+    fn test_klvmap_get() {
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 26,
+            10, 2, 0x01, 0xc9,
+            11, 1, 0xff,
+            12, 4, b'K', b'L', b'V', 0xe3,
+            13, 4, 0x3f, 0x80, 0x00, 0x00,
+            14, 0,
+            15, 3, b'K', b'L', b'V',
+        ];
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert_eq!(map.get(10).map(|x| x.position), Some(5));
+        assert!(map.get(99).is_none());
+        assert_eq!(map.get_u16(10), Some(0x01c9));
+        assert_eq!(map.get_u64(10), Some(0x01c9));
+        assert_eq!(map.get_u8(10), None);
+        assert_eq!(map.get_u8(11), Some(0xff));
+        assert_eq!(map.get_i32(11), Some(-1));
+        assert_eq!(map.get_bool(11), Some(true));
+        assert_eq!(map.get_str(12), None);
+        assert_eq!(map.get_str(15), Some("KLV"));
+        assert_eq!(map.get_f32(13), Some(1.0));
+        assert_eq!(map.get_f64(13), None);
+        assert_eq!(map.get_bytes(14), Some(&[][..]));
+        assert_eq!(map.get_u32(14), None);
+        assert_eq!(map.get_str(14), Some(""));
+    }
+
+    #[test]
+    fn test_from_bytes_lossy() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct TestInner<'a> {
+            #[serde(rename = "1")]
+            name: &'a str,
+        }
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestLossy<'a> {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11")]
+            name: Option<&'a str>,
+            #[serde(rename = "12", borrow)]
+            inner: Option<TestInner<'a>>,
+            #[serde(rename = "13")]
+            u16: Option<u16>,
+        }
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 17,
+            10, 1, 1,
+            11, 2, 0xff, 0xfe,
+            12, 4, 1, 2, 0xc3, 0x28,
+            13, 2, 0x01, 0x02,
+        ];
+        assert!(from_bytes::<TestLossy>(&buf).is_err());
+        let (x, errors) = from_bytes_lossy::<TestLossy>(&buf).unwrap();
+        assert_eq!(
+            x,
+            TestLossy {
+                u8: 1,
+                name: None,
+                inner: None,
+                u16: Some(0x0102),
+            }
+        );
+        assert_eq!(
+            errors.iter().map(|x| (x.tag, x.offset)).collect::<Vec<_>>(),
+            vec![(11, 8), (1, 14), (12, 12)]
+        );
+
+        // 必須フィールドは飛ばせない
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 3,
+            10, 1, 1,
+        ];
+        let (x, errors) = from_bytes_lossy::<TestLossy>(&buf).unwrap();
+        assert_eq!(x.u8, 1);
+        assert!(errors.is_empty());
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 4,
+            10, 2, 1, 1,
+        ];
+        assert!(from_bytes_lossy::<TestLossy>(&buf).is_err());
+    }
+
+    #[test]
+    fn test_klv_deserializer() {
+        use serde::de::DeserializeSeed;
+        use std::marker::PhantomData;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestSeed<'a> {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11")]
+            str: &'a str,
+        }
+        let t = TestSeed { u8: 1, str: "abc" };
+        let buf = to_bytes(&t).unwrap();
+        let mut de = KLVDeserializer::from_slice(&buf);
+        let x: TestSeed = PhantomData.deserialize(&mut de).unwrap();
+        de.end().unwrap();
+        assert_eq!(x, t);
+        // 入力から借用したまま返す
+        assert_eq!(x.str.as_ptr(), buf[buf.len() - 3..].as_ptr());
+
+        // 残りがある
+        let mut trailing = buf.clone();
+        trailing.push(0);
+        let mut de =
+            KLVDeserializer::from_slice_with_config(&trailing, DeserializerConfig::default());
+        TestSeed::deserialize(&mut de).unwrap();
+        assert_eq!(de.remaining(), &[0]);
+        match de.end() {
+            Err(Error::TrailingBytes {
+                offset,
+                remaining: 1,
+            }) if offset == buf.len() => {}
+            x => unreachable!("{:?}", x),
+        }
+        match from_bytes::<TestSeed>(&trailing) {
+            Err(Error::TrailingBytes { remaining: 1, .. }) => {}
+            x => unreachable!("{:?}", x),
+        }
+        let (x, rest) = from_bytes_partial::<TestSeed>(&trailing).unwrap();
+        assert_eq!(x, t);
+        assert_eq!(rest, &[0]);
+    }
+
+    #[test]
+    fn test_klvmap_deserialize_into() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild<'a> {
+            #[serde(rename = "1")]
+            name: &'a str,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestInto<'a> {
+            #[serde(rename = "10")]
+            u16: u16,
+            #[serde(rename = "11")]
+            none: Option<u8>,
+            #[serde(rename = "12", borrow)]
+            child: TestChild<'a>,
+        }
+        let t = TestInto {
+            u16: 0x0102,
+            none: None,
+            child: TestChild { name: "KLV" },
+        };
+        let buf = to_bytes(&t).unwrap();
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert_eq!(map.deserialize_into::<TestInto>().unwrap(), t);
+
+        // 編集後のレコードも読める
+        let mut map = KLVMap::try_from_bytes(&buf).unwrap();
+        map.set(10, &[3]);
+        assert_eq!(map.deserialize_into::<TestInto>().unwrap().u16, 3);
+
+        // エラーの位置はfrom_bytesと同じくパケット先頭から数える
+        let mut broken = buf.clone();
+        let len = broken.len();
+        broken[len - 1] = 0xff;
+        let expected = match from_bytes::<TestInto>(&broken) {
+            Err(Error::Decode { offset, .. }) => offset,
+            x => unreachable!("{:?}", x),
+        };
+        let map = KLVMap::try_from_bytes(&broken).unwrap();
+        match map.deserialize_into::<TestInto>() {
+            Err(Error::Decode {
+                offset,
+                path,
+                kind: DecodeErrorKind::InvalidString,
+                ..
+            }) => {
+                assert_eq!(offset, expected);
+                assert_eq!(path, vec![12, 1]);
+            }
+            x => unreachable!("{:?}", x),
+        }
+
+        // UniversalKeyが異なる
+        #[derive(Debug, Deserialize)]
+        #[serde(rename = "ABCD")]
+        struct TestOther {}
+        match map.deserialize_into::<TestOther>() {
+            Err(Error::UnmatchedKey { expected, found }) => {
+                assert_eq!(expected, b"ABCD");
+                assert_eq!(found.as_bytes(), b"TEST");
+            }
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_klvmap_checksum() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestChecksum {
+            #[serde(rename = "10")]
+            u16: u16,
+        }
+        let t = TestChecksum { u16: 0x0102 };
+        let buf = to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap();
+        let map = KLVMap::try_from_bytes_with_checksum(&buf, WrappedCRC::default()).unwrap();
+        assert!(map.has_checksum());
+        assert_eq!(map.get_u16(10), Some(0x0102));
+        assert_eq!(map.iter().len(), 2);
+        assert!(!KLVMap::try_from_bytes(&buf).unwrap().has_checksum());
+
+        // checksumが一致しない
+        let mut broken = buf.clone();
+        broken[7] = 0xff;
+        assert!(matches!(
+            KLVMap::try_from_bytes_with_checksum(&broken, WrappedCRC::default()),
+            Err(Error::UnmatcheChecksum { .. })
+        ));
+
+        // checksumレコードがない
+        let buf = to_bytes(&t).unwrap();
+        assert!(matches!(
+            KLVMap::try_from_bytes_with_checksum(&buf, WrappedCRC::default()),
+            Err(Error::HasNotChecksum)
+        ));
+    }
+
+    #[test]
+    fn test_klvraw_offsets() {
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 0x82, 0, 13,
+            10, 0x84, 0, 0, 0, 1, 1,
+            11, 0,
+            12, 2, b'a', b'b',
+        ];
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        let ranges: Vec<_> = map
+            .iter()
+            .map(|x| (x.position, x.value_offset(), x.total_len()))
+            .collect();
+        assert_eq!(ranges, vec![(7, 13, 7), (14, 16, 2), (16, 18, 4)]);
+        for raw in map.iter() {
+            let record = raw.as_slice_of(&buf).unwrap();
+            assert_eq!(record[0], raw.key);
+            assert_eq!(
+                &buf[raw.value_offset()..raw.position + raw.total_len()],
+                raw.value.unwrap_or_default()
+            );
+        }
+        assert_eq!(map.get(12).unwrap().as_slice_of(&buf[..19]), None);
+
+        // 所有した場合も同じ位置を返す
+        let owned = map.to_owned();
+        assert_eq!(owned.as_map().get(10).unwrap().value_offset(), 13);
+
+        // 構築したレコードやlengthを書き換えたレコードはLを最短の形式とする
+        let raw = KLVRawOwned::from(12, 16, vec![b'a', b'b']);
+        assert_eq!(raw.as_raw().as_slice_of(&buf), Some(&buf[16..]));
+        let mut raw = map.get(11).unwrap().clone();
+        raw.length = 200;
+        assert_eq!((raw.value_offset(), raw.total_len()), (17, 203));
+
+        // 編集後はto_bytesの出力での位置となる
+        let mut map = KLVMap::try_from_bytes(&buf).unwrap();
+        map.set(11, &[1]);
+        let edited = map.to_bytes();
+        let raw = map.get(12).unwrap();
+        assert_eq!((raw.position, raw.value_offset()), (11, 13));
+        assert_eq!(raw.as_slice_of(&edited), Some(&[12, 2, b'a', b'b'][..]));
+    }
+
+    #[test]
+    fn test_klvmap_owned() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestOwned {
+            #[serde(rename = "10")]
+            u16: u16,
+            #[serde(rename = "11")]
+            none: Option<u8>,
+            #[serde(rename = "12")]
+            str: String,
+        }
+        let t = TestOwned {
+            u16: 0x0102,
+            none: None,
+            str: "KLV".to_string(),
+        };
+        let buf = to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap();
+        let map = KLVMap::try_from_bytes_with_checksum(&buf, WrappedCRC::default()).unwrap();
+        let owned = map.to_owned();
+        drop(map);
+        drop(buf);
+
+        // 別スレッドへ渡して読み出す
+        let handle = std::thread::spawn(move || {
+            let map = owned.as_map();
+            assert!(map.has_checksum());
+            assert_eq!(map.universal_key(), b"TEST");
+            assert_eq!(map.get_u16(10), Some(0x0102));
+            assert_eq!(map.get(11).unwrap().value, None);
+            assert_eq!(map.get_str(12), Some("KLV"));
+            let x = map.deserialize_into::<TestOwned>().unwrap();
+            let raw = owned.iter().nth(2).unwrap();
+            assert_eq!(raw.value.as_deref(), Some(&b"KLV"[..]));
+            assert_eq!(raw.as_raw().as_str(), Some("KLV"));
+            (x, map.to_bytes())
+        });
+        let (x, bytes) = handle.join().unwrap();
+        assert_eq!(x, t);
+        assert_eq!(
+            bytes,
+            to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_klvmap_edit() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestEdit<'a> {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11", skip_serializing_if = "Option::is_none")]
+            str: Option<&'a str>,
+            #[serde(rename = "12", skip_serializing_if = "Option::is_none")]
+            u16: Option<u16>,
+        }
+        let t = TestEdit {
+            u8: 1,
+            str: Some("before"),
+            u16: None,
+        };
+        let buf = to_bytes(&t).unwrap();
+
+        // 変更しなければ同じバイト列になる
+        let mut map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert_eq!(map.to_bytes(), buf);
+
+        let long_str = "a".repeat(200);
+        map.set(10, &[2]);
+        map.set(11, long_str.as_bytes());
+        map.push(12, &[1, 0]);
+        let edited = map.to_bytes();
+        let x = from_bytes::<TestEdit>(&edited).unwrap();
+        assert_eq!(
+            x,
+            TestEdit {
+                u8: 2,
+                str: Some(long_str.as_str()),
+                u16: Some(256),
+            }
+        );
+        // positionは出力したバイト列の位置を指す
+        let reparsed = KLVMap::try_from_bytes(&edited).unwrap();
+        for (a, b) in map.iter().zip(reparsed.iter()) {
+            assert_eq!(a.position, b.position);
+        }
+        assert_eq!(map.content_len(), reparsed.content_len());
+
+        let removed = map.remove(11).unwrap();
+        assert_eq!(removed.value, Some(long_str.as_bytes()));
+        assert!(map.remove(11).is_none());
+        let x = from_bytes::<TestEdit>(&map.to_bytes()).unwrap();
+        assert_eq!(
+            x,
+            TestEdit {
+                u8: 2,
+                str: None,
+                u16: Some(256),
+            }
+        );
+    }
+
+    #[test]
+    fn test_klvmap_path() {
+        #[rustfmt::skip]
+        let buf = [
+            b'T', b'E', b'S', b'T', 18,
+            2, 1, 7,
+            // tag 48にネストしたLocal Set、tag 3の値もLocal Setとして読める
+            48, 0x81, 12,
+                1, 1, 0x01,
+                3, 4, 10, 2, 0x12, 0x34,
+                4, 1, 0x02,
+        ];
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert_eq!(map.get_path(&[2]).and_then(|x| x.as_u8()), Some(7));
+        let raw = map.get_path(&[48, 4]).unwrap();
+        assert_eq!(raw.position, 20);
+        assert_eq!(raw.as_u8(), Some(2));
+        assert_eq!(raw.as_slice_of(&buf), Some(&[4, 1, 0x02][..]));
+        let raw = map.get_path(&[48, 3, 10]).unwrap();
+        assert_eq!((raw.position, raw.value_offset()), (16, 18));
+        assert_eq!(
+            map.get_path(&[48, 3, 10]).and_then(|x| x.as_u16()),
+            Some(0x1234)
+        );
+        assert!(map.get_path(&[]).is_none());
+        assert!(map.get_path(&[48, 5]).is_none());
+        assert!(map.get_path(&[2, 7]).is_none());
+
+        // 分類を書き換え、他のレコードは入力のまま残す
+        let mut owned = map.to_owned();
+        owned.set_path(&[48, 1], &[0x05]).unwrap();
+        owned.set_path(&[48, 3, 10], &[0x56]).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(owned.as_map().to_bytes(), [
+            b'T', b'E', b'S', b'T', 16,
+            2, 1, 7,
+            48, 11,
+                1, 1, 0x05,
+                3, 3, 10, 1, 0x56,
+                4, 1, 0x02,
+        ]);
+        assert_eq!(
+            owned.as_map().get_path(&[48, 1]).and_then(|x| x.as_u8()),
+            Some(5)
+        );
+
+        assert!(matches!(owned.set_path(&[], &[1]), Err(Error::Key(_))));
+        assert!(matches!(owned.set_path(&[49, 1], &[1]), Err(Error::Key(_))));
+        match owned.set_path(&[48, 5, 1], &[1]) {
+            Err(Error::Key(msg)) => assert_eq!(msg, "tag 48/5 is not found"),
+            x => unreachable!("{:?}", x),
+        }
+
+        // 書き換えるとchecksumのレコードを除く
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestChecksum {
+            #[serde(rename = "10")]
+            u16: u16,
+        }
+        let buf = to_bytes_with_checksum(&TestChecksum { u16: 1 }, WrappedCRC::default()).unwrap();
+        let map = KLVMap::try_from_bytes_with_checksum(&buf, WrappedCRC::default()).unwrap();
+        let mut owned = map.to_owned();
+        owned.set_path(&[10], &[0x01, 0x02]).unwrap();
+        assert!(!owned.has_checksum());
+        let edited = owned.as_map().to_bytes();
+        assert_eq!(edited, [b'T', b'E', b'S', b'T', 4, 10, 2, 0x01, 0x02]);
+        assert_eq!(
+            from_bytes::<TestChecksum>(&edited).unwrap(),
+            TestChecksum { u16: 0x0102 }
+        );
+        assert!(matches!(
+            from_bytes_with_checksum::<TestChecksum, _>(&edited, WrappedCRC::default()),
+            Err(Error::HasNotChecksum)
+        ));
+    }
+
+    #[test]
+    fn test_split_packets() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TESTDATA00000000")]
+        struct TestPacket<'a> {
+            #[serde(rename = "10")]
+            u16: u16,
+            #[serde(rename = "11")]
+            str: &'a str,
+        }
+        let long_str = "b".repeat(300);
+        let t = vec![
+            TestPacket { u16: 1, str: "a" },
+            TestPacket {
+                u16: 2,
+                str: &long_str,
+            },
+            TestPacket { u16: 3, str: "" },
+        ];
+        let mut buf = vec![];
+        for x in t.iter() {
+            buf.extend(to_bytes(x).unwrap());
+        }
+
+        let x = from_bytes_multi::<TestPacket>(&buf).unwrap();
+        assert_eq!(x, t);
+
+        // 末尾が欠けたデータは分割されずに残る
+        let truncated = &buf[..buf.len() - 1];
+        let mut packets = split_packets(truncated, 16);
+        for expect in t.iter().take(2) {
+            let packet = packets.next().unwrap();
+            assert_eq!(&from_bytes::<TestPacket>(packet).unwrap(), expect);
+        }
+        assert!(packets.next().is_none());
+        assert_eq!(packets.remainder().len(), 16 + 1 + 6 - 1);
+        assert!(from_bytes_multi::<TestPacket>(truncated).is_err());
+
+        // Universal Keyの途中で欠けたパケット
+        let mut truncated = buf.clone();
+        truncated.extend_from_slice(b"TESTDATA");
+        assert!(matches!(
+            from_bytes_multi::<TestPacket>(&truncated),
+            Err(Error::ContentLenght)
+        ));
+
+        // パケットでない末尾のbyte
+        for garbage in [&[0xff, 0xff][..], &[0; 20][..]] {
+            let mut trailing = buf.clone();
+            trailing.extend_from_slice(garbage);
+            match from_bytes_multi::<TestPacket>(&trailing) {
+                Err(Error::TrailingBytes { offset, remaining }) => {
+                    assert_eq!((offset, remaining), (buf.len(), garbage.len()));
+                }
+                x => unreachable!("{:?}", x),
+            }
+        }
+    }
+
+    // 壊れたデータはpanicせずに位置とタグを含むエラーを返す
+    #[test]
+    fn test_decode_error_context() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestContext<'a> {
+            #[serde(rename = "10")]
+            u16: u16,
+            #[serde(rename = "11")]
+            str: &'a str,
+        }
+        let buf = to_bytes(&TestContext { u16: 1, str: "abc" }).unwrap();
+        // 4(UK) + 1(L) + 4(tag 10) + 2(tag 11 KL)
+        assert_eq!(&buf[9..11], &[11, 3]);
+
+        // Lが残りのデータより長い
+        let mut broken = buf.clone();
+        broken[10] = 10;
+        match from_bytes::<TestContext>(&broken) {
+            Err(Error::Decode {
+                offset: 11,
+                tag: Some(11),
+                kind:
+                    DecodeErrorKind::UnexpectedEnd {
+                        expected: 10,
+                        actual: 3,
+                    },
+                ..
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+        assert!(KLVMap::try_from_bytes(&broken).is_err());
+
+        // UTF-8ではない文字列
+        let mut broken = buf.clone();
+        broken[12] = 0xff;
+        match from_bytes::<TestContext>(&broken) {
+            Err(Error::Decode {
+                tag: Some(11),
+                kind: DecodeErrorKind::InvalidString,
+                ..
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+
+        // 未対応のLength
+        let mut broken = buf;
+        broken[10] = 0x85;
+        let err = from_bytes::<TestContext>(&broken).unwrap_err();
+        assert!(err.to_string().contains("in tag 11"), "{}", err);
+    }
+
+    // 入れ子のLocal Setの不明なタグは親のタグを含むパスで通知する
+    #[test]
+    fn test_unknown_tags() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "1")]
+            x: u8,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestParent {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11")]
+            child: TestChild,
+        }
+
+        #[rustfmt::skip]
+        let buf = [
+            b'T', b'E', b'S', b'T', 14,
+            10, 1, 1,
+            11, 6, 1, 1, 2, 2, 3, 4,
+            12, 1, 5,
+        ];
+        let mut unknown = vec![];
+        let x: TestParent = from_bytes_with_unknown_tags(&buf, |path, value| {
+            unknown.push((path.to_vec(), value.to_vec()))
+        })
+        .unwrap();
+        assert_eq!(
+            x,
+            TestParent {
+                u8: 1,
+                child: TestChild { x: 1 }
+            }
+        );
+        assert_eq!(
+            unknown,
+            vec![(vec![11, 2], vec![3, 4]), (vec![12], vec![5])]
+        );
+    }
+
+    // serde_bytesを付けないbyte列のフィールドも同じ形式で読み書きできる
+    #[test]
+    fn test_plain_bytes() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestPlain<'a> {
+            #[serde(rename = "10")]
+            slice: &'a [u8],
+            #[serde(rename = "11")]
+            vec: Vec<u8>,
+            #[serde(rename = "12")]
+            cow: Cow<'a, [u8]>,
+            #[serde(rename = "13")]
+            option: Option<Vec<u8>>,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestSerdeBytes<'a> {
+            #[serde(rename = "10", with = "serde_bytes")]
+            slice: &'a [u8],
+            #[serde(rename = "11", with = "serde_bytes")]
+            vec: Vec<u8>,
+            #[serde(rename = "12", with = "serde_bytes")]
+            cow: Cow<'a, [u8]>,
+            #[serde(rename = "13", with = "serde_bytes")]
+            option: Option<Vec<u8>>,
+        }
+        let t = TestPlain {
+            slice: &[1, 2, 3],
+            vec: vec![4, 5],
+            cow: Cow::Borrowed(&[6]),
+            option: Some(vec![7, 8, 9, 10]),
+        };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(
+            &buf[4..],
+            &[18, 10, 3, 1, 2, 3, 11, 2, 4, 5, 12, 1, 6, 13, 4, 7, 8, 9, 10]
+        );
+        let x = from_bytes::<TestPlain>(&buf).unwrap();
+        assert_eq!(x, t);
+
+        let y = from_bytes::<TestSerdeBytes>(&buf).unwrap();
+        assert_eq!(y.slice, t.slice);
+        assert_eq!(y.option, t.option);
+        let z = TestSerdeBytes {
+            slice: t.slice,
+            vec: t.vec.clone(),
+            cow: t.cow.clone(),
+            option: t.option.clone(),
+        };
+        assert_eq!(to_bytes(&z).unwrap(), buf);
+    }
+
+    // TopLevelのenumはUniversalKeyでvariantを選ぶ
+    #[test]
+    fn test_packet_enum() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "XXXX")]
+        struct TestA {
+            #[serde(rename = "10")]
+            u8: u8,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum TestPacket {
+            #[serde(rename = "TEST")]
+            A(TestA),
+            #[serde(rename = "AB")]
+            B {
+                #[serde(rename = "1")]
+                x: u8,
+            },
+        }
+        let t = vec![
+            TestPacket::A(TestA { u8: 1 }),
+            TestPacket::B { x: 2 },
+            TestPacket::A(TestA { u8: 3 }),
+        ];
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(
+            buf,
+            vec![
+                b'T', b'E', b'S', b'T', 3, 10, 1, 1,
+                b'A', b'B', 3, 1, 1, 2,
+                b'T', b'E', b'S', b'T', 3, 10, 1, 3,
+            ]
+        );
+        assert_eq!(from_bytes::<Vec<TestPacket>>(&buf).unwrap(), t);
+        assert_eq!(
+            from_bytes::<TestPacket>(&buf[8..14]).unwrap(),
+            TestPacket::B { x: 2 }
+        );
+
+        // どのvariantにも一致しない
+        match from_bytes::<TestPacket>(&[b'C', b'D', 0]) {
+            Err(Error::UnmatchedKey { expected, found }) => {
+                assert!(expected.is_empty());
+                assert_eq!(found.as_bytes(), &[b'C', b'D', 0]);
+            }
+            x => unreachable!("{:?}", x),
+        }
+
+        // 宣言順によらず最も長く一致するUniversal Keyを選ぶ
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum TestPrefix {
+            #[serde(rename = "TE")]
+            Short {
+                #[serde(rename = "1")]
+                x: u8,
+            },
+            #[serde(rename = "TEST")]
+            Long {
+                #[serde(rename = "1")]
+                x: u8,
+            },
+        }
+        for t in [TestPrefix::Short { x: 1 }, TestPrefix::Long { x: 2 }] {
+            let buf = to_bytes(&t).unwrap();
+            assert_eq!(from_bytes::<TestPrefix>(&buf).unwrap(), t);
+        }
+    }
+
+    // 名前の表は入れ子のLocal Setにも使われ、表にない名前は数値として読む
+    #[test]
+    fn test_tag_names() {
+        const TAGS: &[(&str, u128)] = &[("latitude", 13), ("x", 1)];
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "x")]
+            x: u8,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestParent {
+            #[serde(rename = "latitude")]
+            latitude: u8,
+            #[serde(rename = "11")]
+            child: TestChild,
+        }
+
+        let t = TestParent {
+            latitude: 2,
+            child: TestChild { x: 3 },
+        };
+        let buf =
+            to_bytes_with_config(&t, SerializerConfig::default().tag_names(TAGS).unwrap()).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(
+            buf,
+            vec![
+                b'T', b'E', b'S', b'T', 6,
+                13, 1, 2,
+                11, 3, 1, 1, 3,
+            ]
+        );
+        let config = DeserializerConfig::default().tag_names(TAGS).unwrap();
+        assert_eq!(
+            from_bytes_with_config::<TestParent>(&buf, config).unwrap(),
+            t
+        );
+
+        // 表がなければ名前をタグとして書けない
+        assert!(to_bytes(&t).is_err());
+
+        // 実行時に読み込んだ表
+        let loaded = vec![("latitude".to_string(), 13), ("x".to_string(), 1)];
+        let names: Vec<(&str, u128)> = loaded.iter().map(|(x, tag)| (x.as_str(), *tag)).collect();
+        let config = DeserializerConfig::default().tag_names(&names).unwrap();
+        assert_eq!(
+            from_bytes_with_config::<TestParent>(&buf, config).unwrap(),
+            t
+        );
+
+        // Keyの幅に収まらないタグ
+        let names = [("latitude", 256)];
+        assert!(matches!(
+            DeserializerConfig::default().tag_names(&names),
+            Err(Error::Key(_))
+        ));
+        assert!(matches!(
+            SerializerConfig::default().tag_names(&names),
+            Err(Error::Key(_))
+        ));
+        let config = DeserializerConfig {
+            key_width: KeyWidth::U16,
+            ..Default::default()
+        };
+        assert!(config.tag_names(&names).is_ok());
+    }
 }
@@ -0,0 +1,191 @@
+//! Delta encoding of packets against a previous value
+//!
+//! [`to_bytes_delta`] writes only the tags whose records changed.
+//! Tags removed from the previous value are listed in the value of the tag
+//! [`DELTA_REMOVED_TAGS`] record, one byte per tag, and [`apply_delta`] removes them from the base.
+
+use std::collections::BTreeSet;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::de::{from_bytes, KLVMap};
+use crate::error::{Error, Result};
+use crate::ser::to_bytes;
+
+/// Tag of the record listing the removed tags in a delta packet
+///
+/// Local sets of MISB standards do not use tag 0,
+/// so the delta of a struct using tag 0 returns [`Error::Key`].
+pub const DELTA_REMOVED_TAGS: u8 = 0;
+
+/// Serialize only the records changed from the previous value
+///
+/// Records of a tag are written when their values differ from `previous`,
+/// so the fields of `T` should be `Option` to deserialize the delta packet by itself.
+/// A field changed to `None` is written as zero-length record.
+/// Tags written in `previous` but not in `current`, such as a repeated field becoming empty
+/// or a field omitted by `skip_serializing_if`, are listed in the [`DELTA_REMOVED_TAGS`] record.
+///
+/// Example
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{apply_delta, from_bytes, to_bytes_delta};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Telemetry {
+///     #[serde(rename = "10")]
+///     altitude: Option<u16>,
+///     #[serde(rename = "11")]
+///     speed: Option<u8>,
+///     #[serde(rename = "12", skip_serializing_if = "Option::is_none")]
+///     heading: Option<u16>,
+/// }
+///
+/// let previous = Telemetry { altitude: Some(100), speed: Some(5), heading: Some(90) };
+/// let current = Telemetry { altitude: Some(100), speed: None, heading: None };
+/// let delta = to_bytes_delta(&current, &previous).unwrap();
+/// assert_eq!(&delta[4..], &[5, 11, 0, 0, 1, 12]);
+///
+/// let x = from_bytes::<Telemetry>(&delta).unwrap();
+/// assert_eq!(x, Telemetry { altitude: None, speed: None, heading: None });
+/// assert_eq!(apply_delta(&previous, &delta).unwrap(), current);
+/// ```
+pub fn to_bytes_delta<T>(current: &T, previous: &T) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let current_buf = to_bytes(current)?;
+    let previous_buf = to_bytes(previous)?;
+    // 削除したタグの一覧はmapより長く生存させる
+    let removed: Vec<u8>;
+    let mut map = KLVMap::try_from_bytes(&current_buf)?;
+    let base = KLVMap::try_from_bytes(&previous_buf)?;
+    check_universal_key(&base, &map)?;
+    check_removed_tag(&map)?;
+    check_removed_tag(&base)?;
+    let tags: BTreeSet<u8> = map.values.iter().map(|x| x.key).collect();
+    for tag in tags.iter() {
+        // 同じタグのレコードがすべて一致する場合のみ取り除く
+        if records(&map, *tag) == records(&base, *tag) {
+            while map.remove(*tag).is_some() {}
+        }
+    }
+    let base_tags: BTreeSet<u8> = base.values.iter().map(|x| x.key).collect();
+    removed = base_tags.difference(&tags).copied().collect();
+    if !removed.is_empty() {
+        map.push(DELTA_REMOVED_TAGS, &removed);
+    }
+    Ok(map.to_bytes())
+}
+
+/// Apply the packet of [`to_bytes_delta`] to the base value
+///
+/// Every record of the tags in `packet` replaces the records of the same tag in `base`,
+/// and the tags listed in the [`DELTA_REMOVED_TAGS`] record are removed from `base`.
+pub fn apply_delta<T>(base: &T, packet: &[u8]) -> Result<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let base_buf = to_bytes(base)?;
+    let mut map = KLVMap::try_from_bytes(&base_buf)?;
+    let delta = KLVMap::try_from_bytes(packet)?;
+    check_universal_key(&map, &delta)?;
+    let mut tags: BTreeSet<u8> = delta.values.iter().map(|x| x.key).collect();
+    for raw in delta.values.iter().filter(|x| x.key == DELTA_REMOVED_TAGS) {
+        tags.extend(raw.value.unwrap_or_default());
+    }
+    for tag in tags {
+        while map.remove(tag).is_some() {}
+    }
+    for raw in delta.values.iter().filter(|x| x.key != DELTA_REMOVED_TAGS) {
+        map.push(raw.key, raw.value.unwrap_or_default());
+    }
+    from_bytes(&map.to_bytes())
+}
+
+fn check_universal_key(base: &KLVMap, delta: &KLVMap) -> Result<()> {
+    if base.universal_key == delta.universal_key {
+        Ok(())
+    } else {
+        Err(Error::Key(format!(
+            "Universal key is unmatched get {:02x?}, expect {:02x?}",
+            delta.universal_key, base.universal_key
+        )))
+    }
+}
+
+// 削除したタグの一覧と同じタグは使えない
+fn check_removed_tag(map: &KLVMap) -> Result<()> {
+    match map.values.iter().any(|x| x.key == DELTA_REMOVED_TAGS) {
+        true => Err(Error::Key(format!(
+            "tag {} is reserved for the removed tags of delta",
+            DELTA_REMOVED_TAGS
+        ))),
+        false => Ok(()),
+    }
+}
+
+// タグのレコードの値を出現順に並べる
+fn records<'m>(map: &KLVMap<'m>, tag: u8) -> Vec<&'m [u8]> {
+    map.values
+        .iter()
+        .filter(|x| x.key == tag)
+        .map(|x| x.value.unwrap_or_default())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{apply_delta, to_bytes, to_bytes_delta};
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestDelta {
+        #[serde(rename = "10")]
+        name: Option<String>,
+        #[serde(rename = "11", with = "crate::repeated", default)]
+        ids: Vec<u8>,
+        #[serde(rename = "12")]
+        count: Option<u32>,
+    }
+
+    #[test]
+    fn test_delta() {
+        let base = TestDelta {
+            name: Some("a".to_string()),
+            ids: vec![1, 2],
+            count: Some(1),
+        };
+
+        // 変化がない場合はレコードを持たない
+        let delta = to_bytes_delta(&base, &base).unwrap();
+        assert_eq!(&delta[4..], &[0]);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), base);
+
+        // 繰り返しフィールドは一部が変化した場合もすべてのレコードを書き込む
+        let mut current = base.clone();
+        current.ids = vec![1, 3, 4];
+        let delta = to_bytes_delta(&current, &base).unwrap();
+        assert_eq!(&delta[4..], &[9, 11, 1, 1, 11, 1, 3, 11, 1, 4]);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), current);
+
+        // 繰り返しフィールドが空になった場合は削除したタグとして書き込む
+        let mut current = base.clone();
+        current.ids = vec![];
+        let delta = to_bytes_delta(&current, &base).unwrap();
+        assert_eq!(&delta[4..], &[3, 0, 1, 11]);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), current);
+        // 空から戻す場合は通常のレコード
+        let delta = to_bytes_delta(&base, &current).unwrap();
+        assert_eq!(&delta[4..], &[6, 11, 1, 1, 11, 1, 2]);
+        assert_eq!(apply_delta(&current, &delta).unwrap(), base);
+
+        // 異なるUniversal Keyのパケットは適用できない
+        let mut other = to_bytes(&current).unwrap();
+        other[0] = b'X';
+        assert!(apply_delta(&base, &other).is_err());
+    }
+}
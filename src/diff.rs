@@ -0,0 +1,324 @@
+//! Record level comparison of parsed packets
+//!
+//! [`diff`] reports the tags added, removed or changed from one packet to another,
+//! for golden-file tests of encoders.
+//! When a tag appears more than once, records are compared in order of appearance.
+//! [`diff_with_schema`] also decodes the values by [`FieldType`] of the schema
+//! and compares nested local sets record by record.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde_klv::diff::{diff_with_schema, Difference};
+//! use serde_klv::schema::{FieldType, Schema};
+//! use serde_klv::KLVMap;
+//!
+//! let mut schema = Schema::new(b"TEST").unwrap();
+//! schema.field(10, "speed", FieldType::U16).field(11, "name", FieldType::String);
+//!
+//! let golden = [b'T', b'E', b'S', b'T', 4, 10, 2, 0, 100];
+//! let actual = [b'T', b'E', b'S', b'T', 8, 10, 2, 0, 101, 11, 2, b'a', b'b'];
+//! let golden = KLVMap::try_from_bytes(&golden).unwrap();
+//! let actual = KLVMap::try_from_bytes(&actual).unwrap();
+//! let differences = diff_with_schema(&golden, &actual, &schema);
+//! assert_eq!(differences.len(), 2);
+//! assert!(matches!(&differences[0], Difference::Changed { path, .. } if path == &[10]));
+//! assert_eq!(differences[0].to_string(), "tag 10 changed: 100 [00, 64] -> 101 [00, 65]");
+//! assert_eq!(differences[1].to_string(), "tag 11 added: \"ab\" [61, 62]");
+//! ```
+
+use std::fmt::{self, Display};
+
+use crate::de::{parse_local_set, KLVMap, KLVRaw};
+use crate::error::TagPath;
+use crate::schema::{FieldType, Schema};
+
+/// Difference of a record found by [`diff`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Difference {
+    /// universal keys of the packets differ
+    UniversalKey { before: Vec<u8>, after: Vec<u8> },
+    /// record appears only in the second packet
+    Added { path: Vec<u8>, after: FieldValue },
+    /// record appears only in the first packet
+    Removed { path: Vec<u8>, before: FieldValue },
+    /// value of the record differs
+    Changed {
+        path: Vec<u8>,
+        before: FieldValue,
+        after: FieldValue,
+    },
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::UniversalKey { before, after } => write!(
+                f,
+                "universal key changed: {:02x?} -> {:02x?}",
+                before, after
+            ),
+            Difference::Added { path, after } => {
+                write!(f, "tag {} added: {}", TagPath(path), after)
+            }
+            Difference::Removed { path, before } => {
+                write!(f, "tag {} removed: {}", TagPath(path), before)
+            }
+            Difference::Changed {
+                path,
+                before,
+                after,
+            } => write!(f, "tag {} changed: {} -> {}", TagPath(path), before, after),
+        }
+    }
+}
+
+/// Value bytes of a record and its representation decoded by the schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldValue {
+    pub bytes: Vec<u8>,
+    /// value formatted by [`FieldType`], None without schema or when it can not be read as the type
+    pub decoded: Option<String>,
+}
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.decoded {
+            Some(decoded) => write!(f, "{} {:02x?}", decoded, self.bytes),
+            None => write!(f, "{:02x?}", self.bytes),
+        }
+    }
+}
+
+/// Compare the records of the packets
+///
+/// Differences are ordered by the first appearance of the tag in `before`,
+/// then the tags only in `after`.
+pub fn diff(before: &KLVMap, after: &KLVMap) -> Vec<Difference> {
+    diff_packet(before, after, None)
+}
+
+/// Compare the records like [`diff`], decoding the values by the schema
+pub fn diff_with_schema(before: &KLVMap, after: &KLVMap, schema: &Schema) -> Vec<Difference> {
+    diff_packet(before, after, Some(schema))
+}
+
+fn diff_packet(before: &KLVMap, after: &KLVMap, schema: Option<&Schema>) -> Vec<Difference> {
+    let mut differences = vec![];
+    if before.universal_key != after.universal_key {
+        differences.push(Difference::UniversalKey {
+            before: before.universal_key.to_vec(),
+            after: after.universal_key.to_vec(),
+        });
+    }
+    diff_set(&before.values, &after.values, schema, &[], &mut differences);
+    differences
+}
+
+fn diff_set(
+    before: &[KLVRaw],
+    after: &[KLVRaw],
+    schema: Option<&Schema>,
+    path: &[u8],
+    differences: &mut Vec<Difference>,
+) {
+    let mut tags: Vec<u8> = vec![];
+    for raw in before.iter().chain(after.iter()) {
+        if !tags.contains(&raw.key) {
+            tags.push(raw.key);
+        }
+    }
+    for tag in tags {
+        let path = [path, &[tag]].concat();
+        let ty = schema.and_then(|x| x.by_tag(tag)).map(|x| &x.ty);
+        let mut before = before.iter().filter(|x| x.key == tag);
+        let mut after = after.iter().filter(|x| x.key == tag);
+        // 同じタグのレコードは出現順に対応させる
+        loop {
+            match (before.next(), after.next()) {
+                (Some(b), Some(a)) => diff_record(b, a, ty, &path, differences),
+                (Some(b), None) => differences.push(Difference::Removed {
+                    path: path.clone(),
+                    before: field_value(b, ty),
+                }),
+                (None, Some(a)) => differences.push(Difference::Added {
+                    path: path.clone(),
+                    after: field_value(a, ty),
+                }),
+                (None, None) => break,
+            }
+        }
+    }
+}
+
+fn diff_record(
+    before: &KLVRaw,
+    after: &KLVRaw,
+    ty: Option<&FieldType>,
+    path: &[u8],
+    differences: &mut Vec<Difference>,
+) {
+    if before.value == after.value {
+        return;
+    }
+    // 入れ子のLocal Setはレコード毎に比較する
+    if let Some(FieldType::Set(schema)) = ty {
+        let nested = (
+            parse_local_set(before.value.unwrap_or_default()),
+            parse_local_set(after.value.unwrap_or_default()),
+        );
+        if let (Some(b), Some(a)) = nested {
+            diff_set(&b, &a, Some(schema), path, differences);
+            return;
+        }
+    }
+    differences.push(Difference::Changed {
+        path: path.to_vec(),
+        before: field_value(before, ty),
+        after: field_value(after, ty),
+    });
+}
+
+fn field_value(raw: &KLVRaw, ty: Option<&FieldType>) -> FieldValue {
+    FieldValue {
+        bytes: raw.value.unwrap_or_default().to_vec(),
+        decoded: ty.and_then(|ty| decode(raw, ty)),
+    }
+}
+
+fn decode(raw: &KLVRaw, ty: &FieldType) -> Option<String> {
+    match ty {
+        FieldType::Bool => raw.as_bool().map(|x| x.to_string()),
+        FieldType::U8 => raw.as_u8().map(|x| x.to_string()),
+        FieldType::U16 => raw.as_u16().map(|x| x.to_string()),
+        FieldType::U32 => raw.as_u32().map(|x| x.to_string()),
+        FieldType::U64 => raw.as_u64().map(|x| x.to_string()),
+        FieldType::I8 => raw.as_i8().map(|x| x.to_string()),
+        FieldType::I16 => raw.as_i16().map(|x| x.to_string()),
+        FieldType::I32 => raw.as_i32().map(|x| x.to_string()),
+        FieldType::I64 => raw.as_i64().map(|x| x.to_string()),
+        FieldType::F32 => raw.as_f32().map(|x| x.to_string()),
+        FieldType::F64 => raw.as_f64().map(|x| x.to_string()),
+        FieldType::String => raw.as_str().map(|x| format!("{:?}", x)),
+        FieldType::Bytes | FieldType::Set(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::{diff, diff_with_schema, Difference, FieldValue};
+    use crate::schema::{FieldType, Schema};
+    use crate::{to_bytes, KLVMap};
+
+    #[derive(Debug, Clone, Serialize)]
+    #[serde(rename = "TEST")]
+    struct TestDiff {
+        #[serde(rename = "10")]
+        i16: i16,
+        #[serde(rename = "11", with = "crate::repeated")]
+        ids: Vec<u8>,
+        #[serde(rename = "12")]
+        child: TestChild,
+    }
+
+    #[derive(Debug, Clone, Serialize)]
+    struct TestChild {
+        #[serde(rename = "1")]
+        f32: f32,
+        #[serde(rename = "2")]
+        str: String,
+    }
+
+    fn value(bytes: &[u8], decoded: Option<&str>) -> FieldValue {
+        FieldValue {
+            bytes: bytes.to_vec(),
+            decoded: decoded.map(|x| x.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let before = TestDiff {
+            i16: -1,
+            ids: vec![1, 2],
+            child: TestChild {
+                f32: 0.5,
+                str: "a".to_string(),
+            },
+        };
+        let mut after = before.clone();
+        after.ids = vec![1, 3, 4];
+        after.child.str = "b".to_string();
+        let before_buf = to_bytes(&before).unwrap();
+        let after_buf = to_bytes(&after).unwrap();
+        let before_map = KLVMap::try_from_bytes(&before_buf).unwrap();
+        let after_map = KLVMap::try_from_bytes(&after_buf).unwrap();
+
+        assert_eq!(diff(&before_map, &before_map), vec![]);
+
+        // スキーマがなければ入れ子のLocal Setも値として比較する
+        let differences = diff(&before_map, &after_map);
+        assert_eq!(
+            differences,
+            vec![
+                Difference::Changed {
+                    path: vec![11],
+                    before: value(&[2], None),
+                    after: value(&[3], None),
+                },
+                Difference::Added {
+                    path: vec![11],
+                    after: value(&[4], None),
+                },
+                Difference::Changed {
+                    path: vec![12],
+                    before: value(&[1, 4, 0x3f, 0, 0, 0, 2, 1, b'a'], None),
+                    after: value(&[1, 4, 0x3f, 0, 0, 0, 2, 1, b'b'], None),
+                },
+            ]
+        );
+
+        let mut child = Schema::nested();
+        child
+            .field(1, "f32", FieldType::F32)
+            .field(2, "str", FieldType::String);
+        let mut schema = Schema::new(b"TEST").unwrap();
+        schema
+            .field(10, "i16", FieldType::I16)
+            .field(11, "ids", FieldType::U8)
+            .field(12, "child", FieldType::Set(child));
+        let differences = diff_with_schema(&after_map, &before_map, &schema);
+        assert_eq!(
+            differences,
+            vec![
+                Difference::Changed {
+                    path: vec![11],
+                    before: value(&[3], Some("3")),
+                    after: value(&[2], Some("2")),
+                },
+                Difference::Removed {
+                    path: vec![11],
+                    before: value(&[4], Some("4")),
+                },
+                Difference::Changed {
+                    path: vec![12, 2],
+                    before: value(b"b", Some("\"b\"")),
+                    after: value(b"a", Some("\"a\"")),
+                },
+            ]
+        );
+        assert_eq!(differences[1].to_string(), "tag 11 removed: 4 [04]");
+
+        let mut other = before_buf.clone();
+        other[0] = b'X';
+        let other_map = KLVMap::try_from_bytes(&other).unwrap();
+        let differences = diff(&before_map, &other_map);
+        assert_eq!(
+            differences[0].to_string(),
+            "universal key changed: [54, 45, 53, 54] -> [58, 45, 53, 54]"
+        );
+        assert_eq!(differences.len(), 1);
+    }
+}
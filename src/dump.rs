@@ -0,0 +1,156 @@
+use std::fmt::{self, Display, Write};
+
+use crate::de::{parse_local_set, KLVMap, KLVRaw};
+
+// 1行に表示する値のbyte数
+const BYTES_PER_LINE: usize = 16;
+// タグとLの列の幅。値の2行目以降はこの分だけ字下げする
+const HEAD_WIDTH: usize = 12;
+// 入れ子の階層毎の字下げ
+const INDENT: usize = 2;
+
+impl<'m> Display for KLVRaw<'m> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_record(f, self, 0, 0)
+    }
+}
+
+impl<'m> Display for KLVMap<'m> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_dump(f, 0)
+    }
+}
+
+impl<'m> KLVMap<'m> {
+    /// dump like `Display`, expanding values readable as nested local set up to `depth` levels
+    ///
+    /// Any value that splits exactly into records is expanded,
+    /// so a string may be shown as local set by chance.
+    ///
+    /// Example
+    /// ```
+    /// use serde_klv::KLVMap;
+    ///
+    /// let buf = [b'T', b'E', b'S', b'T', 8, 10, 1, 1, 48, 3, 3, 1, b'a'];
+    /// let map = KLVMap::try_from_bytes(&buf).unwrap();
+    /// let dump = map.to_debug_string(1);
+    /// assert!(dump.starts_with("UK 54 45 53 54 |TEST| L=8\n"));
+    /// assert!(dump.ends_with("     3     1  61                                               |a|"));
+    /// ```
+    pub fn to_debug_string(&self, depth: usize) -> String {
+        let mut s = String::new();
+        // Stringへの書き込みは失敗しない
+        self.write_dump(&mut s, depth).unwrap();
+        s
+    }
+
+    fn write_dump(&self, w: &mut dyn Write, depth: usize) -> fmt::Result {
+        write!(w, "UK ")?;
+        write_bytes(w, self.universal_key)?;
+        write!(w, " L={}", self.content_len)?;
+        if let Some(known) = self.detect_standard() {
+            write!(w, " ({})", known.name)?;
+        }
+        for raw in self.values.iter() {
+            writeln!(w)?;
+            write_record(w, raw, 0, depth)?;
+        }
+        Ok(())
+    }
+}
+
+// 1レコードをタグ、L、値の16進とASCIIの列で書き出す
+fn write_record(w: &mut dyn Write, raw: &KLVRaw, indent: usize, depth: usize) -> fmt::Result {
+    let value = raw.value.unwrap_or_default();
+    let mut lines = value.chunks(BYTES_PER_LINE);
+    write!(w, "{:indent$}{:>4} {:>5}  ", "", raw.key, raw.length)?;
+    write_line(w, lines.next().unwrap_or_default())?;
+    for line in lines {
+        write!(w, "\n{:width$}", "", width = indent + HEAD_WIDTH)?;
+        write_line(w, line)?;
+    }
+    if depth > 0 {
+        for nested in parse_local_set(value).unwrap_or_default() {
+            writeln!(w)?;
+            write_record(w, &nested, indent + INDENT, depth - 1)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_line(w: &mut dyn Write, bytes: &[u8]) -> fmt::Result {
+    let hex = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ");
+    write!(w, "{:<width$}  ", hex, width = BYTES_PER_LINE * 3 - 1)?;
+    write_ascii(w, bytes)
+}
+
+fn write_bytes(w: &mut dyn Write, bytes: &[u8]) -> fmt::Result {
+    for (i, b) in bytes.iter().enumerate() {
+        if i > 0 {
+            w.write_char(' ')?;
+        }
+        write!(w, "{:02x}", b)?;
+    }
+    w.write_char(' ')?;
+    write_ascii(w, bytes)
+}
+
+// 表示できない文字は.に置き換える
+fn write_ascii(w: &mut dyn Write, bytes: &[u8]) -> fmt::Result {
+    w.write_char('|')?;
+    for b in bytes {
+        match b {
+            0x20..=0x7e => w.write_char(*b as char)?,
+            _ => w.write_char('.')?,
+        }
+    }
+    w.write_char('|')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::KLVMap;
+
+    #[test]
+    fn test_dump() {
+        #[rustfmt::skip]
+        let buf = [
+            b'T', b'E', b'S', b'T', 36,
+            10, 1, 1,
+            11, 3, b'K', b'L', b'V',
+            48, 6, 3, 1, 0xff, 4, 1, 2,
+            12, 18, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, b'x', b'y',
+        ];
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        let expect = [
+            "UK 54 45 53 54 |TEST| L=36",
+            "  10     1  01                                               |.|",
+            "  11     3  4b 4c 56                                         |KLV|",
+            "  48     6  03 01 ff 04 01 02                                |......|",
+            "  12    18  00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f  |................|",
+            "            78 79                                            |xy|",
+        ];
+        assert_eq!(map.to_string(), expect.join("\n"));
+        assert_eq!(map.to_debug_string(0), map.to_string());
+        assert_eq!(map.iter().nth(1).unwrap().to_string(), expect[2]);
+
+        // 入れ子を展開する
+        let dump = map.to_debug_string(1);
+        let lines = dump.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 8);
+        assert_eq!(
+            lines[4],
+            "     3     1  ff                                               |.|"
+        );
+        assert_eq!(
+            lines[5],
+            "     4     1  02                                               |.|"
+        );
+        // 1byteの値はLocal Setとして読めない
+        assert_eq!(lines[1], expect[1]);
+    }
+}
@@ -0,0 +1,518 @@
+//! In-place edit of encoded packets
+//!
+//! Relay nodes often rewrite only one tag, such as the timestamp, of packets they forward.
+//! [`patch_value`] replaces the value of a record without deserializing the packet,
+//! and recomputes the lengths of the packet and the local sets holding the record.
+//! Other records are copied as they are, including tags unknown to the relay.
+//!
+//! Length octets keep their original form when the new length fits in,
+//! so the offsets of following records change only by the size difference of the value.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::edit::{patch_value, patch_value_with_checksum};
+//! use serde_klv::{from_bytes, from_bytes_with_checksum, to_bytes, to_bytes_with_checksum, WrappedCRC};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "2")]
+//!     ts: u64,
+//!     #[serde(rename = "3")]
+//!     name: String,
+//!     #[serde(rename = "70")]
+//!     child: Child,
+//! }
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! struct Child {
+//!     #[serde(rename = "10")]
+//!     name: String,
+//! }
+//!
+//! let t = Packet {
+//!     ts: 1,
+//!     name: "a".to_string(),
+//!     child: Child { name: "b".to_string() },
+//! };
+//! let buf = to_bytes(&t).unwrap();
+//! let buf = patch_value(&buf, &[2], &2_u64.to_be_bytes()).unwrap();
+//! // nested record by the path of tags
+//! let buf = patch_value(&buf, &[70, 10], b"child").unwrap();
+//! let x = from_bytes::<Packet>(&buf).unwrap();
+//! assert_eq!(x.ts, 2);
+//! assert_eq!(x.child.name, "child");
+//!
+//! let buf = to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap();
+//! let buf = patch_value_with_checksum(&buf, &[3], b"abc", &WrappedCRC::default()).unwrap();
+//! let x: Packet = from_bytes_with_checksum(&buf, WrappedCRC::default()).unwrap();
+//! assert_eq!(x.name, "abc");
+//! ```
+//!
+//! [`filter_tags`] removes records by the path of tags, to sanitize packets before dissemination.
+//! The predicate returns [`TagFilter::Descend`] to filter the records of a nested local set.
+//!
+//! ```rust
+//! use serde_klv::edit::{filter_tags, TagFilter};
+//! use serde_klv::KLVMap;
+//!
+//! #[rustfmt::skip]
+//! let buf = [
+//!     b'T', b'E', b'S', b'T', 14,
+//!     2, 1, 1,
+//!     3, 1, 2,
+//!     70, 6, 10, 1, 3, 11, 1, 4,
+//! ];
+//! let filtered = filter_tags(&buf, |path| match path {
+//!     [3] => TagFilter::Remove,
+//!     [70] => TagFilter::Descend,
+//!     [70, 11] => TagFilter::Remove,
+//!     _ => TagFilter::Keep,
+//! })
+//! .unwrap();
+//! assert_eq!(&filtered[4..], &[8, 2, 1, 1, 70, 3, 10, 1, 3]);
+//!
+//! // bool is accepted for top level records
+//! let filtered = filter_tags(&buf, |path| path != [70]).unwrap();
+//! let map = KLVMap::try_from_bytes(&filtered).unwrap();
+//! assert!(map.get(70).is_none());
+//! ```
+
+use std::ops::Range;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::checksum::CheckSumCalc;
+use crate::de::{packet_header, KLVMap};
+use crate::error::{DecodeErrorKind, DecodePath, Error, Result};
+use crate::{parse_length, LengthOctet};
+
+/// Replace the value of the first record found by the path of tags
+///
+/// `path` is the tags from the top level record to the record to replace,
+/// `&[2]` for a top level tag.
+pub fn patch_value(buf: &[u8], path: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+    if path.is_empty() {
+        return Err(Error::Key("path of the record is empty".to_string()));
+    }
+    let (uk_len, content) = packet_content(buf)?;
+    let patched = patch_set(&buf[content.clone()], content.start, path, 0, value)?;
+    write_packet(buf, uk_len, &patched)
+}
+
+/// Replace the value like [`patch_value`] and recompute the checksum record at the end
+///
+/// Return [`Error::HasNotChecksum`] if the packet has not the checksum record of `crc`.
+pub fn patch_value_with_checksum<C: CheckSumCalc>(
+    buf: &[u8],
+    path: &[u8],
+    value: &[u8],
+    crc: &C,
+) -> Result<Vec<u8>> {
+    let mut out = patch_value(buf, path, value)?;
+    checksum_record(&out, 0..out.len(), crc)?;
+    update_checksum(&mut out, crc);
+    Ok(out)
+}
+
+/// Action of [`filter_tags`] on a record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagFilter {
+    /// keep the record as it is
+    Keep,
+    /// remove the record
+    Remove,
+    /// keep the record and filter the records of its value as a local set
+    Descend,
+}
+
+impl From<bool> for TagFilter {
+    fn from(keep: bool) -> Self {
+        if keep {
+            Self::Keep
+        } else {
+            Self::Remove
+        }
+    }
+}
+
+/// Remove the records rejected by the predicate and recompute the lengths
+///
+/// The predicate is called with the path of tags from the top level record,
+/// and returns [`TagFilter`] or `bool` to keep the record.
+/// Kept records and their length octets are copied as they are.
+pub fn filter_tags<F, R>(buf: &[u8], mut keep: F) -> Result<Vec<u8>>
+where
+    F: FnMut(&[u8]) -> R,
+    R: Into<TagFilter>,
+{
+    let (uk_len, content) = packet_content(buf)?;
+    let filtered = filter_set(&buf[content.clone()], content.start, &mut vec![], &mut keep)?;
+    write_packet(buf, uk_len, &filtered)
+}
+
+/// Filter the records like [`filter_tags`] and recompute the checksum record at the end
+///
+/// The checksum record is not passed to the predicate and always kept.
+/// Return [`Error::HasNotChecksum`] if the packet has not the checksum record of `crc`.
+pub fn filter_tags_with_checksum<C, F, R>(buf: &[u8], crc: &C, mut keep: F) -> Result<Vec<u8>>
+where
+    C: CheckSumCalc,
+    F: FnMut(&[u8]) -> R,
+    R: Into<TagFilter>,
+{
+    let (uk_len, content) = packet_content(buf)?;
+    let record = checksum_record(buf, content.clone(), crc)?;
+    let mut filtered = filter_set(
+        &buf[content.start..record],
+        content.start,
+        &mut vec![],
+        &mut keep,
+    )?;
+    filtered.extend_from_slice(&buf[record..content.end]);
+    let mut out = write_packet(buf, uk_len, &filtered)?;
+    update_checksum(&mut out, crc);
+    Ok(out)
+}
+
+// Universal Keyの長さと内容の範囲を返す
+fn packet_content(buf: &[u8]) -> Result<(usize, Range<usize>)> {
+    let uk_len = KLVMap::try_from_bytes(buf)?.universal_key.len();
+    let (length_len, content_len) =
+        parse_length(&buf[uk_len..]).map_err(Error::UnsupportedLength)?;
+    let start = uk_len + length_len;
+    Ok((uk_len, start..start + content_len))
+}
+
+// 入力のUniversal KeyとLの形式で内容を書く
+fn write_packet(buf: &[u8], uk_len: usize, content: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(uk_len + 9 + content.len());
+    out.extend_from_slice(&buf[..uk_len]);
+    write_length(&mut out, buf[uk_len], content.len())?;
+    out.extend_from_slice(content);
+    Ok(out)
+}
+
+// 内容の末尾のchecksumレコードのKの位置
+fn checksum_record<C: CheckSumCalc>(buf: &[u8], content: Range<usize>, crc: &C) -> Result<usize> {
+    let width = crc.width().size();
+    content
+        .end
+        .checked_sub(width + 2)
+        .filter(|x| *x >= content.start && buf[*x] == crc.tag() && buf[x + 1] as usize == width)
+        .ok_or(Error::HasNotChecksum)
+}
+
+// 末尾のchecksumレコードの値を再計算する
+fn update_checksum<C: CheckSumCalc>(out: &mut [u8], crc: &C) {
+    let width = crc.width().size();
+    let value_offset = out.len() - width;
+    // 書き出したパケットのヘッダは必ず解析できる
+    let (key_len, content_start) = packet_header(out).unwrap_or((0, 0));
+    let covered = crc
+        .coverage()
+        .covered_range_of(key_len, content_start, value_offset);
+    let calced = crc.width().truncate(crc.checksum(&out[covered]));
+    BigEndian::write_uint(&mut out[value_offset..], calced as u64, width);
+}
+
+// ローカルセットの内容から残すレコードだけを書き出す
+// pathは親レコードのタグで、呼び出し中に現在のタグを積む
+fn filter_set<F, R>(set: &[u8], offset: usize, path: &mut Vec<u8>, keep: &mut F) -> Result<Vec<u8>>
+where
+    F: FnMut(&[u8]) -> R,
+    R: Into<TagFilter>,
+{
+    let mut out = Vec::with_capacity(set.len());
+    let mut position = 0;
+    while position < set.len() {
+        let (start, end) = read_record(set, offset, position, path)?;
+        path.push(set[position]);
+        match keep(path.as_slice()).into() {
+            TagFilter::Keep => out.extend_from_slice(&set[position..end]),
+            TagFilter::Remove => {}
+            TagFilter::Descend => {
+                let filtered = filter_set(&set[start..end], offset + start, path, keep)?;
+                out.push(set[position]);
+                write_length(&mut out, set[position + 1], filtered.len())?;
+                out.extend_from_slice(&filtered);
+            }
+        }
+        path.pop();
+        position = end;
+    }
+    Ok(out)
+}
+
+// positionのレコードの値の範囲を返す。parentsは親レコードのタグ
+fn read_record(
+    set: &[u8],
+    offset: usize,
+    position: usize,
+    parents: &[u8],
+) -> Result<(usize, usize)> {
+    let key = set[position];
+    let (length_len, content_len) =
+        parse_length(&set[position + 1..]).map_err(|e| Error::Decode {
+            offset: offset + position + 1,
+            tag: Some(key.into()),
+            path: path_of(parents, key),
+            kind: DecodeErrorKind::Length(e),
+        })?;
+    let start = position + 1 + length_len;
+    let remains = set.len() - start;
+    if content_len > remains {
+        return Err(Error::Decode {
+            offset: offset + start,
+            tag: Some(key.into()),
+            path: path_of(parents, key),
+            kind: DecodeErrorKind::UnexpectedEnd {
+                expected: content_len,
+                actual: remains,
+            },
+        });
+    }
+    Ok((start, start + content_len))
+}
+
+// ローカルセットの内容からpathのレコードを探して置き換えた内容を返す
+// offsetは入力の先頭からのsetの位置
+fn patch_set(
+    set: &[u8],
+    offset: usize,
+    path: &[u8],
+    depth: usize,
+    value: &[u8],
+) -> Result<Vec<u8>> {
+    let tag = path[depth];
+    let mut position = 0;
+    while position < set.len() {
+        let key = set[position];
+        let (start, end) = read_record(set, offset, position, &path[..depth])?;
+        if key != tag {
+            position = end;
+            continue;
+        }
+        let patched = if depth + 1 == path.len() {
+            value.to_vec()
+        } else {
+            patch_set(&set[start..end], offset + start, path, depth + 1, value)?
+        };
+        let mut out = Vec::with_capacity(set.len() + patched.len());
+        out.extend_from_slice(&set[..=position]);
+        write_length(&mut out, set[position + 1], patched.len())?;
+        out.extend_from_slice(&patched);
+        out.extend_from_slice(&set[end..]);
+        return Ok(out);
+    }
+    Err(Error::Key(format!(
+        "record of {} is not found",
+        crate::error::TagPath(&path[..=depth])
+    )))
+}
+
+// 元の長さの形式に収まる場合はその形式で書く
+fn write_length(out: &mut Vec<u8>, first_octet: u8, size: usize) -> Result<()> {
+    match LengthOctet::from_u8(first_octet) {
+        LengthOctet::Long(octets) if octets == 8 || (size as u64) < 1 << (8 * octets as u32) => {
+            LengthOctet::long_to_buf(out, size, octets)
+        }
+        _ => LengthOctet::length_to_buf(out, size),
+    }
+    .map_err(Error::IO)?;
+    Ok(())
+}
+
+fn path_of(parents: &[u8], tag: u8) -> DecodePath {
+    let mut path = DecodePath::default();
+    for x in parents.iter().chain(std::iter::once(&tag)) {
+        path.push(*x as u128);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{
+        filter_tags, filter_tags_with_checksum, patch_value, patch_value_with_checksum, TagFilter,
+    };
+    use crate::error::Error;
+    use crate::{
+        from_bytes, from_bytes_with_checksum, to_bytes, to_bytes_with_config, LengthForm,
+        SerializerConfig, WrappedCRC,
+    };
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestEdit {
+        #[serde(rename = "10")]
+        u8: u8,
+        #[serde(rename = "11")]
+        child: TestChild,
+        #[serde(rename = "12")]
+        str: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+    struct TestChild {
+        #[serde(rename = "20")]
+        str: String,
+        #[serde(rename = "21")]
+        u16: u16,
+    }
+
+    fn sample() -> TestEdit {
+        TestEdit {
+            u8: 1,
+            child: TestChild {
+                str: "a".to_string(),
+                u16: 2,
+            },
+            str: "b".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_patch_value() {
+        let t = sample();
+        let buf = to_bytes(&t).unwrap();
+
+        // 入れ子のレコードを長くすると外側の長さが長形式になる
+        let long = "x".repeat(200);
+        let patched = patch_value(&buf, &[11, 20], long.as_bytes()).unwrap();
+        let x = from_bytes::<TestEdit>(&patched).unwrap();
+        let mut expect = t.clone();
+        expect.child.str = long;
+        assert_eq!(x, expect);
+        assert_eq!(patched[4], 0x81);
+
+        // 短くすると短形式に戻らず元の形式のまま
+        let patched = patch_value(&patched, &[11, 20], b"c").unwrap();
+        let x = from_bytes::<TestEdit>(&patched).unwrap();
+        assert_eq!(x.child.str, "c");
+        assert_eq!(patched.len(), buf.len() + 3);
+
+        let patched = patch_value(&buf, &[12], b"d").unwrap();
+        assert_eq!(patched.len(), buf.len());
+        assert_eq!(from_bytes::<TestEdit>(&patched).unwrap().str, "d");
+
+        // 後続のバイトを持つバッファはパケットとして扱えない
+        let mut trailing = buf.clone();
+        trailing.push(0xff);
+        assert!(patch_value(&trailing, &[12], b"d").is_err());
+
+        // 存在しないタグ
+        assert!(patch_value(&buf, &[13], b"").is_err());
+        assert!(patch_value(&buf, &[11, 10], b"").is_err());
+        assert!(patch_value(&buf, &[], b"").is_err());
+    }
+
+    #[test]
+    fn test_patch_keeps_length_form() {
+        let t = sample();
+        let config = SerializerConfig {
+            length_form: LengthForm::ForceLong(2),
+            ..Default::default()
+        };
+        let buf = to_bytes_with_config(&t, config).unwrap();
+        let patched = patch_value(&buf, &[10], &[3]).unwrap();
+        assert_eq!(patched.len(), buf.len());
+        assert_eq!(from_bytes::<TestEdit>(&patched).unwrap().u8, 3);
+    }
+
+    #[test]
+    fn test_patch_checksum() {
+        let t = sample();
+        let buf = crate::to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap();
+        let patched =
+            patch_value_with_checksum(&buf, &[11, 21], &[0, 5], &WrappedCRC::default()).unwrap();
+        let x: TestEdit = from_bytes_with_checksum(&patched, WrappedCRC::default()).unwrap();
+        assert_eq!(x.child.u16, 5);
+
+        // checksumを再計算しない場合は不一致になる
+        let patched = patch_value(&buf, &[11, 21], &[0, 5]).unwrap();
+        assert!(from_bytes_with_checksum::<TestEdit, _>(&patched, WrappedCRC::default()).is_err());
+
+        // checksumレコードがない
+        let buf = to_bytes(&t).unwrap();
+        assert!(patch_value_with_checksum(&buf, &[10], &[2], &WrappedCRC::default()).is_err());
+    }
+
+    #[test]
+    fn test_filter_tags() {
+        let t = sample();
+        let config = SerializerConfig {
+            length_form: LengthForm::ForceLong(2),
+            ..Default::default()
+        };
+        let buf = to_bytes_with_config(&t, config).unwrap();
+
+        let mut paths = vec![];
+        let filtered = filter_tags(&buf, |path| {
+            paths.push(path.to_vec());
+            match path {
+                [11] => TagFilter::Descend,
+                [11, 20] | [12] => TagFilter::Remove,
+                _ => TagFilter::Keep,
+            }
+        })
+        .unwrap();
+        assert_eq!(
+            paths,
+            [vec![10], vec![11], vec![11, 20], vec![11, 21], vec![12]]
+        );
+        // 残したレコードとLの形式はそのまま
+        #[rustfmt::skip]
+        assert_eq!(&filtered[4..], &[
+            0x82, 0, 15,
+            10, 0x82, 0, 1, 1,
+            11, 0x82, 0, 6,
+                21, 0x82, 0, 2, 0, 2,
+        ]);
+
+        // すべて残せば入力と同じ
+        assert_eq!(filter_tags(&buf, |_| true).unwrap(), buf);
+        let filtered = filter_tags(&buf, |_| false).unwrap();
+        assert_eq!(&filtered[4..], &[0x82, 0, 0]);
+
+        // Local Setでない値は辿れない
+        match filter_tags(&buf, |path| match path {
+            [12] => TagFilter::Descend,
+            _ => TagFilter::Keep,
+        }) {
+            Err(Error::Decode { path, .. }) => assert_eq!(path, vec![12, 98]),
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_filter_checksum() {
+        let t = sample();
+        let crc = WrappedCRC::default();
+        let buf = crate::to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap();
+
+        let mut paths = vec![];
+        let filtered = filter_tags_with_checksum(&buf, &crc, |path| {
+            paths.push(path.to_vec());
+            path != [10]
+        })
+        .unwrap();
+        // checksumレコードは判定しない
+        assert_eq!(paths, [vec![10], vec![11], vec![12]]);
+        assert_eq!(filtered.len(), buf.len() - 3);
+        let map =
+            crate::KLVMap::try_from_bytes_with_checksum(&filtered, WrappedCRC::default()).unwrap();
+        assert!(map.get(10).is_none());
+        assert_eq!(map.get_str(12), Some("b"));
+
+        let buf = to_bytes(&t).unwrap();
+        assert!(matches!(
+            filter_tags_with_checksum(&buf, &crc, |_| true),
+            Err(Error::HasNotChecksum)
+        ));
+    }
+}
@@ -4,32 +4,38 @@ use serde::{de, ser};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-// This is a bare-bones implementation. A real library would provide additional
-// information in its error type, for example the line and column at which the
-// error occurred, the byte offset into the input, or the current key being
-// processed.
+/// Error of KLV serialization and deserialization
+///
+/// New variants may be added in minor releases.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum Error {
-    // GeneralError
+    /// custom message from Serialize or Deserialize implementation
     Message(String),
-    // Key Error
-    // related Universal Keys or VER Encoding key
+    /// invalid universal key or field tag
     Key(String),
-    // Unsupported Length, define by BER encoding rules
-    UnsupportedLength(String),
-    // write bytes
+    /// universal key of the input does not match the type
+    UnmatchedKey {
+        /// universal key of the struct, empty for a packet enum and [`crate::PacketRegistry`]
+        expected: &'static [u8],
+        /// leading bytes of the input
+        found: KeyBytes,
+    },
+    /// unsupported length octets, defined by BER encoding rules
+    UnsupportedLength(LengthError),
+    /// failed to read or write bytes
     IO(std::io::Error),
-    // byte encoding
+    /// value can not be encoded
     Encode(String),
-    // unmatch type length
+    /// length does not match the type width
     TypeLength(String),
-    // content length
-    // must has 16 byte or more
+    /// input ends before the declared length
     ContentLenght,
-    // string encoding error
+    /// value is not a string
     ExpectedString,
-    // unmatch length between length and content
+    /// local set does not end at the declared length
     ExpectedMapEnd,
+    /// sequence does not end at the declared length
     ExpectedSeqEnd,
     /// Can not serialize when has not key
     NeedKey,
@@ -37,12 +43,351 @@ pub enum Error {
     Unsupported(String),
     /// Has not checksum field
     HasNotChecksum,
+    /// checksum of the packet does not match
     UnmatcheChecksum {
-        value: u16,
-        calced: u16,
+        /// checksum written in the packet
+        value: u32,
+        /// checksum calculated from the packet
+        calced: u32,
+    },
+    /// Failed to decode the record
+    Decode {
+        /// byte offset in the input where decoding stopped
+        offset: usize,
+        /// tag of the record being decoded, None for packet header
+        tag: Option<u128>,
+        /// tags from the top level record to the record being decoded
+        path: DecodePath,
+        kind: DecodeErrorKind,
+    },
+    /// Same tag appears twice in a local set
+    DuplicateKey {
+        tag: u128,
+        /// tags from the top level record to the duplicated record
+        path: DecodePath,
+        /// byte offset of the first record
+        first_offset: usize,
+        /// byte offset of the duplicated record
+        second_offset: usize,
+    },
+    /// BER length exceeds the configured limit
+    LengthLimit {
+        /// byte offset of the length octets
+        offset: usize,
+        length: usize,
+        limit: usize,
+    },
+    /// Packet does not fit in the size limit
+    PacketTooLarge { size: usize, limit: usize },
+    /// input remains after the deserialized packet
+    TrailingBytes {
+        /// byte offset of the end of the packet
+        offset: usize,
+        /// number of the remaining bytes
+        remaining: usize,
+    },
+    /// input exceeds the nesting depth or the record count of the deserializer config
+    DecodeLimit {
+        /// byte offset where the limit is exceeded
+        offset: usize,
+        limit: DecodeLimit,
+    },
+    /// required field is not in the packet
+    ///
+    /// [`crate::from_bytes`] reports it as [`Error::Decode`] with [`DecodeErrorKind::MissingField`].
+    MissingField(&'static str),
+    /// required fields are not in the local set, returned by [`crate::from_bytes_strict`]
+    ///
+    /// Tags are `u128` to hold the tags of every [`crate::KeyWidth`],
+    /// and the path tells which nested local set lacks them.
+    MissingTags {
+        /// tags from the top level record to the local set, empty for the packet
+        path: Vec<u128>,
+        tags: Vec<u128>,
     },
 }
 
+/// Tags from the top level record to a record, held without heap allocation
+///
+/// The first [`DecodePath::CAPACITY`] tags are kept,
+/// [`DecodePath::depth`] counts the tags of deeper records as well.
+///
+/// Example
+/// ```
+/// use serde_klv::error::DecodePath;
+///
+/// let path = DecodePath::from(&[48, 3][..]);
+/// assert_eq!(path, vec![48, 3]);
+/// assert_eq!(path.to_string(), "48/3");
+///
+/// let path = DecodePath::from(&[1, 2, 3, 4, 5, 6][..]);
+/// assert_eq!(path.as_slice(), &[1, 2, 3, 4]);
+/// assert_eq!(path.depth(), 6);
+/// assert_eq!(path.to_string(), "1/2/3/4/...");
+/// ```
+#[derive(Clone, Copy, Default)]
+pub struct DecodePath {
+    tags: [u128; DecodePath::CAPACITY],
+    depth: usize,
+}
+
+impl DecodePath {
+    /// number of the tags kept from the top level
+    pub const CAPACITY: usize = 4;
+
+    /// kept tags
+    pub fn as_slice(&self) -> &[u128] {
+        &self.tags[..self.depth.min(Self::CAPACITY)]
+    }
+
+    /// number of the tags including those not kept
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// true when tags deeper than [`DecodePath::CAPACITY`] are not kept
+    pub fn is_truncated(&self) -> bool {
+        self.depth > Self::CAPACITY
+    }
+
+    pub(crate) fn push(&mut self, tag: u128) {
+        if let Some(x) = self.tags.get_mut(self.depth) {
+            *x = tag;
+        }
+        self.depth += 1;
+    }
+}
+
+impl From<&[u128]> for DecodePath {
+    fn from(tags: &[u128]) -> Self {
+        let mut path = Self::default();
+        for tag in tags {
+            path.push(*tag);
+        }
+        path
+    }
+}
+
+impl std::ops::Deref for DecodePath {
+    type Target = [u128];
+
+    fn deref(&self) -> &[u128] {
+        self.as_slice()
+    }
+}
+
+impl PartialEq for DecodePath {
+    fn eq(&self, other: &Self) -> bool {
+        self.depth == other.depth && self.as_slice() == other.as_slice()
+    }
+}
+
+impl Eq for DecodePath {}
+
+impl PartialEq<[u128]> for DecodePath {
+    fn eq(&self, other: &[u128]) -> bool {
+        self.depth == other.len() && self.as_slice() == &other[..self.as_slice().len()]
+    }
+}
+
+impl PartialEq<Vec<u128>> for DecodePath {
+    fn eq(&self, other: &Vec<u128>) -> bool {
+        self == other.as_slice()
+    }
+}
+
+impl fmt::Debug for DecodePath {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.debug_list().entries(self.as_slice()).finish()?;
+        if self.is_truncated() {
+            write!(formatter, " (depth {})", self.depth)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for DecodePath {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", TagPath(self.as_slice()))?;
+        if self.is_truncated() {
+            formatter.write_str("/...")?;
+        }
+        Ok(())
+    }
+}
+
+/// Leading bytes of the input compared with universal keys, held without heap allocation
+///
+/// Holds up to 16 bytes, the length of the SMPTE universal key.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct KeyBytes {
+    buf: [u8; 16],
+    len: usize,
+}
+
+impl KeyBytes {
+    pub(crate) fn new(input: &[u8]) -> Self {
+        let len = input.len().min(16);
+        let mut buf = [0; 16];
+        buf[..len].copy_from_slice(&input[..len]);
+        Self { buf, len }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl fmt::Debug for KeyBytes {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{:02x?}", self.as_bytes())
+    }
+}
+
+// タグのパスを 48/3 の形式で表示する
+pub(crate) struct TagPath<'a, T>(pub(crate) &'a [T]);
+
+impl<'a, T: Display> Display for TagPath<'a, T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for (i, tag) in self.0.iter().enumerate() {
+            if i > 0 {
+                formatter.write_str("/")?;
+            }
+            write!(formatter, "{}", tag)?;
+        }
+        Ok(())
+    }
+}
+
+/// Record skipped by [`crate::from_bytes_lossy`]
+#[derive(Debug)]
+pub struct FieldError {
+    pub tag: u128,
+    /// byte offset of the record in the input
+    pub offset: usize,
+    pub error: Error,
+}
+
+/// Limit exceeded by [`Error::DecodeLimit`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeLimit {
+    /// nesting depth of local sets
+    Depth(usize),
+    /// number of records and sequence elements
+    Records(usize),
+}
+
+impl Display for DecodeLimit {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeLimit::Depth(limit) => write!(formatter, "nesting depth limit {}", limit),
+            DecodeLimit::Records(limit) => write!(formatter, "record count limit {}", limit),
+        }
+    }
+}
+
+/// Reason of [`Error::UnsupportedLength`] and [`DecodeErrorKind::Length`]
+///
+/// Holds only numbers, so parsing the length does not allocate on error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LengthError {
+    /// no length octet
+    Empty,
+    /// input ends in the long form length octets
+    Truncated { expected: usize, actual: usize },
+    /// number of subsequent octets is not one of {1,2,3,4,8}
+    UnsupportedOctets(u8),
+    /// length can not be written in the number of subsequent octets
+    Overflow { length: usize, octets: u8 },
+    /// indefinite form, not supported by KLV
+    Indefinite,
+    /// reserved octet 0xff
+    Reserved,
+}
+
+impl Display for LengthError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LengthError::Empty => formatter.write_str("length octet is empty"),
+            LengthError::Truncated { expected, actual } => write!(
+                formatter,
+                "length octets need {} bytes, got {}",
+                expected, actual
+            ),
+            LengthError::UnsupportedOctets(x) => write!(
+                formatter,
+                "Unsupported length [{}], supported only {{1,2,3,4,8}}",
+                x
+            ),
+            LengthError::Overflow { length, octets } => write!(
+                formatter,
+                "length {} does not fit in {} octets",
+                length, octets
+            ),
+            LengthError::Indefinite => formatter.write_str("length is indefinite"),
+            LengthError::Reserved => formatter.write_str("Reserved octet"),
+        }
+    }
+}
+
+impl std::error::Error for LengthError {}
+
+/// Reason of [`Error::Decode`]
+#[derive(Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodeErrorKind {
+    /// input ends before the value
+    UnexpectedEnd { expected: usize, actual: usize },
+    /// length of the record does not fit the type width
+    TypeLength { expected: usize, actual: usize },
+    /// unsupported BER length octets
+    Length(LengthError),
+    /// tag does not match the configured key width
+    InvalidKey,
+    /// value is not UTF-8 string
+    InvalidString,
+    /// string has the byte out of ISO 646
+    NonAscii(u8),
+    /// value is not unicode scalar value
+    InvalidChar(u32),
+    /// tag is less than the previous tag in the local set
+    UnorderedTag { previous: u128 },
+    /// local set ends without the field that has no default, named by its tag
+    MissingField(&'static str),
+    /// error from Deserialize implementation
+    Custom(String),
+}
+
+impl Display for DecodeErrorKind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeErrorKind::UnexpectedEnd { expected, actual } => write!(
+                formatter,
+                "unexpected end of input, expected {} bytes but remains {}",
+                expected, actual
+            ),
+            DecodeErrorKind::TypeLength { expected, actual } => write!(
+                formatter,
+                "length {} does not fit type width {}",
+                actual, expected
+            ),
+            DecodeErrorKind::Length(e) => write!(formatter, "{}", e),
+            DecodeErrorKind::InvalidKey => formatter.write_str("invalid or truncated tag"),
+            DecodeErrorKind::InvalidString => formatter.write_str("invalid UTF-8 string"),
+            DecodeErrorKind::NonAscii(v) => {
+                write!(formatter, "non-ASCII byte {:#04x} in string", v)
+            }
+            DecodeErrorKind::InvalidChar(v) => write!(formatter, "invalid char {:#x}", v),
+            DecodeErrorKind::UnorderedTag { previous } => {
+                write!(formatter, "tag is less than the previous tag {}", previous)
+            }
+            DecodeErrorKind::MissingField(field) => write!(formatter, "missing field `{}`", field),
+            DecodeErrorKind::Custom(msg) => formatter.write_str(msg),
+        }
+    }
+}
+
 impl ser::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
         Error::Message(msg.to_string())
@@ -53,6 +398,10 @@ impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
         Error::Message(msg.to_string())
     }
+
+    fn missing_field(field: &'static str) -> Self {
+        Error::MissingField(field)
+    }
 }
 
 impl Display for Error {
@@ -60,10 +409,99 @@ impl Display for Error {
         match self {
             Error::Message(msg) => formatter.write_str(msg),
             Error::ContentLenght => formatter.write_str("unexpected end of input or less"),
-            /* and so forth */
-            _ => formatter.write_str("unexpected error"),
+            Error::Decode {
+                offset,
+                tag: Some(_),
+                path,
+                kind,
+            } => write!(formatter, "{} at offset {} in tag {}", kind, offset, path),
+            Error::Decode {
+                offset,
+                tag: None,
+                kind,
+                ..
+            } => write!(formatter, "{} at offset {}", kind, offset),
+            Error::DuplicateKey {
+                path,
+                first_offset,
+                second_offset,
+                ..
+            } => write!(
+                formatter,
+                "duplicate tag {} at offset {}, first at offset {}",
+                path, second_offset, first_offset
+            ),
+            Error::LengthLimit {
+                offset,
+                length,
+                limit,
+            } => write!(
+                formatter,
+                "length {} at offset {} exceeds the limit {}",
+                length, offset, limit
+            ),
+            Error::PacketTooLarge { size, limit } => write!(
+                formatter,
+                "packet size {} exceeds the limit {}",
+                size, limit
+            ),
+            Error::TrailingBytes { offset, remaining } => write!(
+                formatter,
+                "{} bytes remain after the packet at offset {}",
+                remaining, offset
+            ),
+            Error::DecodeLimit { offset, limit } => {
+                write!(
+                    formatter,
+                    "input exceeds the {} at offset {}",
+                    limit, offset
+                )
+            }
+            Error::MissingField(field) => write!(formatter, "missing field `{}`", field),
+            Error::MissingTags { path, tags } if path.is_empty() => {
+                write!(formatter, "missing tags {:?}", tags)
+            }
+            Error::MissingTags { path, tags } => {
+                write!(
+                    formatter,
+                    "missing tags {:?} in tag {}",
+                    tags,
+                    TagPath(path)
+                )
+            }
+            Error::Key(msg) => write!(formatter, "key error: {}", msg),
+            Error::UnmatchedKey { expected, found } if expected.is_empty() => {
+                write!(formatter, "universal key {:?} is not expected", found)
+            }
+            Error::UnmatchedKey { expected, found } => write!(
+                formatter,
+                "universal key {:?} does not match {:02x?}",
+                found, expected
+            ),
+            Error::UnsupportedLength(e) => write!(formatter, "unsupported length: {}", e),
+            Error::IO(e) => write!(formatter, "io error: {}", e),
+            Error::Encode(msg) => write!(formatter, "encode error: {}", msg),
+            Error::TypeLength(msg) => write!(formatter, "type length error: {}", msg),
+            Error::ExpectedString => formatter.write_str("expected string"),
+            Error::ExpectedMapEnd => formatter.write_str("expected end of local set"),
+            Error::ExpectedSeqEnd => formatter.write_str("expected end of sequence"),
+            Error::NeedKey => formatter.write_str("value has no key"),
+            Error::Unsupported(msg) => write!(formatter, "unsupported: {}", msg),
+            Error::HasNotChecksum => formatter.write_str("packet has no checksum"),
+            Error::UnmatcheChecksum { value, calced } => write!(
+                formatter,
+                "checksum mismatch, packet has {:#x} but calculated {:#x}",
+                value, calced
+            ),
         }
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! Flat file archive of KLV packets
+//!
+//! Packets are appended as is, so the file without timestamps is the plain concatenation
+//! readable by [`crate::split_packets`].
+//! With timestamps, each packet is prefixed by the receive time as 8 byte big-endian
+//! microseconds from UNIX epoch.
+//!
+//! Example
+//!
+//! ```rust
+//! use std::time::{Duration, SystemTime};
+//!
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::fileio::{KLVFileReader, KLVFileWriter};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Count {
+//!     #[serde(rename = "10")]
+//!     count: u16,
+//! }
+//!
+//! let ts = SystemTime::UNIX_EPOCH + Duration::from_secs(1_663_000_000);
+//! let mut writer = KLVFileWriter::with_timestamps(vec![]);
+//! writer.write_at(&Count { count: 1 }, ts).unwrap();
+//! writer.write_at(&Count { count: 2 }, ts).unwrap();
+//! let file = writer.into_inner();
+//!
+//! let mut reader = KLVFileReader::with_timestamps(file.as_slice(), 4);
+//! assert_eq!(reader.read::<Count>().unwrap(), Some((Some(ts), Count { count: 1 })));
+//! let entry = reader.read_packet().unwrap().unwrap();
+//! assert_eq!(entry.packet, &[b'T', b'E', b'S', b'T', 4, 10, 2, 0, 2]);
+//! assert!(reader.read_packet().unwrap().is_none());
+//! ```
+
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, SystemTime};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::{Error, Result};
+use crate::{from_bytes, parse_length, to_bytes, DeserializerConfig, LengthOctet};
+
+// 受信時刻の長さ
+const TIMESTAMP_LEN: usize = 8;
+
+/// Append KLV packets to file
+pub struct KLVFileWriter<W> {
+    writer: W,
+    timestamps: bool,
+}
+
+impl<W: Write> KLVFileWriter<W> {
+    /// writer of plain concatenated packets
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            timestamps: false,
+        }
+    }
+
+    /// writer prefixing each packet by the receive time
+    pub fn with_timestamps(writer: W) -> Self {
+        Self {
+            writer,
+            timestamps: true,
+        }
+    }
+
+    /// append packet bytes received now
+    pub fn write_packet(&mut self, packet: &[u8]) -> Result<()> {
+        self.write_packet_at(packet, SystemTime::now())
+    }
+
+    /// append packet bytes received at the time
+    ///
+    /// The time is dropped when the writer has no timestamps.
+    pub fn write_packet_at(&mut self, packet: &[u8], time: SystemTime) -> Result<()> {
+        if self.timestamps {
+            let micros = time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_err(|e| Error::Encode(format!("timestamp before UNIX epoch. {e}")))?
+                .as_micros();
+            let micros = u64::try_from(micros)
+                .map_err(|_| Error::Encode("timestamp overflow u64 microseconds".to_string()))?;
+            self.writer
+                .write_all(&micros.to_be_bytes())
+                .map_err(Error::IO)?;
+        }
+        self.writer.write_all(packet).map_err(Error::IO)
+    }
+
+    /// serialize and append packet received now
+    pub fn write<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        self.write_packet(&to_bytes(value)?)
+    }
+
+    /// serialize and append packet received at the time
+    pub fn write_at<T: Serialize>(&mut self, value: &T, time: SystemTime) -> Result<()> {
+        self.write_packet_at(&to_bytes(value)?, time)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush().map_err(Error::IO)
+    }
+
+    /// unwrap inner writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Packet read from file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KLVFileEntry {
+    /// receive time, None when the file has no timestamps
+    pub timestamp: Option<SystemTime>,
+    pub packet: Vec<u8>,
+}
+
+/// Read KLV packets from file
+pub struct KLVFileReader<R> {
+    reader: R,
+    key_len: usize,
+    timestamps: bool,
+    // 宣言された長さがこれを超える場合は読み込まずエラーとする
+    max_content_len: usize,
+}
+
+impl<R: Read> KLVFileReader<R> {
+    /// reader of plain concatenated packets with universal key length
+    pub fn new(reader: R, key_len: usize) -> Self {
+        Self {
+            reader,
+            key_len,
+            timestamps: false,
+            max_content_len: DeserializerConfig::DEFAULT_MAX_CONTENT_LEN,
+        }
+    }
+
+    /// reader of packets prefixed by the receive time
+    pub fn with_timestamps(reader: R, key_len: usize) -> Self {
+        Self {
+            timestamps: true,
+            ..Self::new(reader, key_len)
+        }
+    }
+
+    /// set limit of the packet content length
+    pub fn with_max_content_len(mut self, max_content_len: usize) -> Self {
+        self.max_content_len = max_content_len;
+        self
+    }
+
+    /// read next packet bytes
+    ///
+    /// Return `None` when file ends at entry boundary.
+    pub fn read_packet(&mut self) -> Result<Option<KLVFileEntry>> {
+        let mut timestamp = None;
+        if self.timestamps {
+            let mut buf = [0_u8; TIMESTAMP_LEN];
+            if !self.fill(&mut buf)? {
+                return Ok(None);
+            }
+            let micros = u64::from_be_bytes(buf);
+            timestamp = Some(SystemTime::UNIX_EPOCH + Duration::from_micros(micros));
+        }
+        // UKとLの先頭を読み、Lのoctet数を確定させてから残りを読む
+        let mut packet = vec![0; self.key_len + 1];
+        if !self.fill(&mut packet)? {
+            return match timestamp {
+                Some(_) => Err(Error::ContentLenght),
+                None => Ok(None),
+            };
+        }
+        if let LengthOctet::Long(x) = LengthOctet::from_u8(packet[self.key_len]) {
+            let start = packet.len();
+            packet.resize(start + x as usize, 0);
+            self.fill_exact(&mut packet[start..])?;
+        }
+        let (length_len, content_len) =
+            parse_length(&packet[self.key_len..]).map_err(Error::UnsupportedLength)?;
+        if content_len > self.max_content_len {
+            return Err(Error::LengthLimit {
+                offset: self.key_len,
+                length: content_len,
+                limit: self.max_content_len,
+            });
+        }
+        let start = self.key_len + length_len;
+        packet.resize(start + content_len, 0);
+        self.fill_exact(&mut packet[start..])?;
+        Ok(Some(KLVFileEntry { timestamp, packet }))
+    }
+
+    /// read next packet and deserialize
+    pub fn read<T: DeserializeOwned>(&mut self) -> Result<Option<(Option<SystemTime>, T)>> {
+        match self.read_packet()? {
+            Some(entry) => Ok(Some((entry.timestamp, from_bytes(&entry.packet)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// unwrap inner reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // bufを埋める。1byteも読めずに終端に達した場合はfalse
+    fn fill(&mut self, buf: &mut [u8]) -> Result<bool> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.reader.read(&mut buf[read..]) {
+                Ok(0) if read == 0 => return Ok(false),
+                // エントリの途中で終端に達した
+                Ok(0) => return Err(Error::ContentLenght),
+                Ok(n) => read += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(Error::IO(e)),
+            }
+        }
+        Ok(true)
+    }
+
+    fn fill_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        if buf.is_empty() || self.fill(buf)? {
+            Ok(())
+        } else {
+            Err(Error::ContentLenght)
+        }
+    }
+}
+
+impl<R: Read> Iterator for KLVFileReader<R> {
+    type Item = Result<KLVFileEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_packet().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{KLVFileReader, KLVFileWriter};
+    use crate::error::Error;
+    use crate::{split_packets, to_bytes};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TESTDATA00000000")]
+    struct TestFile {
+        #[serde(rename = "10")]
+        u32: u32,
+        #[serde(rename = "11")]
+        string: String,
+    }
+
+    fn samples() -> Vec<TestFile> {
+        // 長形式のLを含める
+        (0..3)
+            .map(|i| TestFile {
+                u32: i,
+                string: "x".repeat(i as usize * 100),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_file_plain() {
+        let t = samples();
+        let mut writer = KLVFileWriter::new(vec![]);
+        for x in t.iter() {
+            writer.write(x).unwrap();
+        }
+        let file = writer.into_inner();
+        let mut expect = vec![];
+        for x in t.iter() {
+            expect.extend(to_bytes(x).unwrap());
+        }
+        assert_eq!(file, expect);
+        assert_eq!(split_packets(&file, 16).count(), 3);
+
+        let entries = KLVFileReader::new(file.as_slice(), 16)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(entries.iter().all(|x| x.timestamp.is_none()));
+
+        let mut reader = KLVFileReader::new(file.as_slice(), 16);
+        for expect in t.iter() {
+            let (ts, x) = reader.read::<TestFile>().unwrap().unwrap();
+            assert_eq!(ts, None);
+            assert_eq!(&x, expect);
+        }
+        assert!(reader.read::<TestFile>().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_file_timestamps() {
+        let t = samples();
+        let base = SystemTime::UNIX_EPOCH + Duration::from_micros(1_663_000_000_123_456);
+        let mut writer = KLVFileWriter::with_timestamps(vec![]);
+        for (i, x) in t.iter().enumerate() {
+            writer
+                .write_at(x, base + Duration::from_millis(i as u64))
+                .unwrap();
+        }
+        let file = writer.into_inner();
+        assert_eq!(&file[..8], &1_663_000_000_123_456_u64.to_be_bytes());
+
+        let mut reader = KLVFileReader::with_timestamps(file.as_slice(), 16);
+        for (i, expect) in t.iter().enumerate() {
+            let (ts, x) = reader.read::<TestFile>().unwrap().unwrap();
+            assert_eq!(ts, Some(base + Duration::from_millis(i as u64)));
+            assert_eq!(&x, expect);
+        }
+        assert!(reader.read_packet().unwrap().is_none());
+
+        // エントリの途中で終わる
+        for len in [4, 8, 20, file.len() - 1] {
+            let reader = KLVFileReader::with_timestamps(&file[..len], 16);
+            match reader.collect::<Result<Vec<_>, _>>() {
+                Err(Error::ContentLenght) => {}
+                x => unreachable!("{} {:?}", len, x),
+            }
+        }
+
+        // 宣言された長さで判定する
+        let mut reader =
+            KLVFileReader::with_timestamps(file.as_slice(), 16).with_max_content_len(7);
+        match reader.read_packet() {
+            Err(Error::LengthLimit { limit: 7, .. }) => {}
+            x => unreachable!("{:?}", x),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Presence flag encoded as zero-length record
+//!
+//! Some local sets tell that a flag is set by the record of the tag with no value,
+//! and that it is unset by the absence of the record.
+//! [`Flag`] writes the set flag as zero-length record and reads the presence as set.
+//! The field needs `default` to read the absence as unset,
+//! and `skip_serializing_if = "Flag::is_unset"` to omit the unset flag.
+//! Serializing an unset flag returns error, since zero-length record means set.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{flag::Flag, from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "10", default, skip_serializing_if = "Flag::is_unset")]
+//!     recording: Flag,
+//!     #[serde(rename = "11", default, skip_serializing_if = "Flag::is_unset")]
+//!     fault: Flag,
+//! }
+//!
+//! let t = Packet { recording: Flag::new(true), fault: Flag::new(false) };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(&buf[4..], &[2, 10, 0]);
+//! let x = from_bytes::<Packet>(&buf).unwrap();
+//! assert!(x.recording.is_set());
+//! assert!(x.fault.is_unset());
+//! ```
+
+use std::fmt;
+
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Flag set by the presence of the record
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Flag(bool);
+
+impl Flag {
+    pub fn new(set: bool) -> Self {
+        Self(set)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0
+    }
+
+    /// for `skip_serializing_if`
+    pub fn is_unset(&self) -> bool {
+        !self.0
+    }
+}
+
+impl From<bool> for Flag {
+    fn from(set: bool) -> Self {
+        Self(set)
+    }
+}
+
+impl From<Flag> for bool {
+    fn from(flag: Flag) -> Self {
+        flag.0
+    }
+}
+
+impl Serialize for Flag {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if !self.0 {
+            return Err(ser::Error::custom(
+                "unset Flag must be skipped by skip_serializing_if = \"Flag::is_unset\"",
+            ));
+        }
+        serializer.serialize_unit()
+    }
+}
+
+impl<'de> Deserialize<'de> for Flag {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(FlagVisitor)
+    }
+}
+
+struct FlagVisitor;
+
+impl<'de> de::Visitor<'de> for FlagVisitor {
+    type Value = Flag;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("zero-length value")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if !v.is_empty() {
+            return Err(de::Error::invalid_length(v.len(), &self));
+        }
+        Ok(Flag(true))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Flag(true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::Flag;
+    use crate::error::{DecodeErrorKind, Error};
+    use crate::{from_bytes, to_bytes, to_bytes_with_config, SerializerConfig};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestFlag {
+        #[serde(rename = "10", default, skip_serializing_if = "Flag::is_unset")]
+        a: Flag,
+        #[serde(rename = "11")]
+        u8: u8,
+        #[serde(rename = "12", default, skip_serializing_if = "Flag::is_unset")]
+        b: Flag,
+    }
+
+    #[test]
+    fn test_flag() {
+        let t = TestFlag {
+            a: Flag::new(true),
+            u8: 1,
+            b: Flag::new(true),
+        };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(&buf[4..], &[7, 10, 0, 11, 1, 1, 12, 0]);
+        assert_eq!(from_bytes::<TestFlag>(&buf).unwrap(), t);
+
+        let t = TestFlag {
+            a: false.into(),
+            u8: 1,
+            b: true.into(),
+        };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(&buf[4..], &[5, 11, 1, 1, 12, 0]);
+        let x = from_bytes::<TestFlag>(&buf).unwrap();
+        assert_eq!(x, t);
+        assert!(!bool::from(x.a));
+
+        // 値を持つレコードはFlagではない
+        let buf = [b'T', b'E', b'S', b'T', 6, 10, 1, 1, 11, 1, 1];
+        match from_bytes::<TestFlag>(&buf) {
+            Err(Error::Decode {
+                kind: DecodeErrorKind::Custom(_),
+                path,
+                ..
+            }) => assert_eq!(path, vec![10]),
+            x => unreachable!("{:?}", x),
+        }
+
+        // 省略しないFlagは書き込めない
+        #[derive(Serialize)]
+        #[serde(rename = "TEST")]
+        struct TestUnskipped {
+            #[serde(rename = "10")]
+            a: Flag,
+        }
+        let t = TestUnskipped { a: Flag::default() };
+        assert!(to_bytes(&t).is_err());
+        let config = SerializerConfig {
+            none_as_absent: true,
+            ..Default::default()
+        };
+        assert!(to_bytes_with_config(&t, config).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+//! Floating point values mapped to integers by MISB ST 1201 IMAPB
+//!
+//! `Imap::new(min, max, len)` maps `min..=max` onto `len` bytes unsigned integer
+//! with the power-of-two scale of IMAPB.
+//! Values out of the range and NaN are rejected on serialize.
+//!
+//! Use it from a `with` module, or `#[klv(imap(min, max, len))]` of the `derive` feature.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! mod latitude {
+//!     use serde_klv::imap::Imap;
+//!
+//!     const IMAP: Imap = Imap::new(-90.0, 90.0, 4);
+//!
+//!     pub fn serialize<S: serde::Serializer>(v: &f64, s: S) -> Result<S::Ok, S::Error> {
+//!         IMAP.serialize(v, s)
+//!     }
+//!     pub fn deserialize<'de, D: serde::Deserializer<'de>>(d: D) -> Result<f64, D::Error> {
+//!         IMAP.deserialize(d)
+//!     }
+//! }
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Position {
+//!     #[serde(rename = "13", with = "latitude")]
+//!     latitude: f64,
+//! }
+//!
+//! let t = Position { latitude: 45.0 };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(&buf[4..], &[6, 13, 4, 0x43, 0x80, 0x00, 0x00]);
+//! assert_eq!(from_bytes::<Position>(&buf).unwrap(), t);
+//! assert!(to_bytes(&Position { latitude: 90.5 }).is_err());
+//! ```
+
+use std::fmt;
+
+use serde::{
+    de::{self, Visitor},
+    ser, Deserializer, Serializer,
+};
+
+use crate::scaled::ScaledFloat;
+
+/// IMAPB mapping of `min..=max` onto `len` bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Imap {
+    pub min: f64,
+    pub max: f64,
+    /// number of bytes, 1 to 8
+    pub len: usize,
+}
+
+impl Imap {
+    pub const fn new(min: f64, max: f64, len: usize) -> Self {
+        Self { min, max, len }
+    }
+
+    // IMAPBのスケールとゼロのオフセット
+    // スケールは2の冪なので逆変換は除算で誤差なく行える
+    fn params(&self) -> (f64, f64) {
+        let b_pow = (self.max - self.min).log2().ceil() as i32;
+        let d_pow = 8 * self.len as i32 - 1;
+        let scale = 2_f64.powi(d_pow - b_pow);
+        let offset = if self.min < 0.0 && self.max > 0.0 {
+            scale * self.min - (scale * self.min).floor()
+        } else {
+            0.0
+        };
+        (scale, offset)
+    }
+
+    /// mapped integer, None when the value is out of the range or NaN
+    pub fn encode(&self, value: f64) -> Option<u64> {
+        if !(self.min..=self.max).contains(&value) {
+            return None;
+        }
+        let (scale, offset) = self.params();
+        Some((scale * (value - self.min) + offset).trunc() as u64)
+    }
+
+    /// value of the mapped integer
+    pub fn decode(&self, value: u64) -> f64 {
+        let (scale, offset) = self.params();
+        (value as f64 - offset) / scale + self.min
+    }
+
+    pub fn serialize<T, S>(&self, value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: ScaledFloat,
+        S: Serializer,
+    {
+        if !(1..=8).contains(&self.len) {
+            return Err(ser::Error::custom(format!(
+                "IMAP length {} is not supported, expected 1 to 8",
+                self.len
+            )));
+        }
+        let v = value.to_f64();
+        let mapped = self.encode(v).ok_or_else(|| {
+            ser::Error::custom(format!(
+                "{} is out of IMAP range {}..={}",
+                v, self.min, self.max
+            ))
+        })?;
+        serializer.serialize_bytes(&mapped.to_be_bytes()[8 - self.len..])
+    }
+
+    pub fn deserialize<'de, T, D>(&self, deserializer: D) -> Result<T, D::Error>
+    where
+        T: ScaledFloat,
+        D: Deserializer<'de>,
+    {
+        let mapped = deserializer.deserialize_bytes(ImapVisitor(self.len))?;
+        Ok(T::from_f64(self.decode(mapped)))
+    }
+}
+
+// 長さが一致するbig-endianの整数を読む
+struct ImapVisitor(usize);
+
+impl<'de> Visitor<'de> for ImapVisitor {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{} bytes of IMAP value", self.0)
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if v.len() != self.0 || v.len() > 8 {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        Ok(v.iter().fold(0_u64, |acc, b| (acc << 8) | *b as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Imap;
+
+    #[test]
+    fn test_imap() {
+        let imap = Imap::new(-90.0, 90.0, 4);
+        for (v, expect) in [
+            (-90.0, 0),
+            (0.0, 0x2d00_0000),
+            (45.0, 0x4380_0000),
+            (90.0, 0x5a00_0000),
+        ] {
+            assert_eq!(imap.encode(v), Some(expect));
+            assert_eq!(imap.decode(expect), v);
+        }
+        assert_eq!(imap.encode(90.1), None);
+        assert_eq!(imap.encode(f64::NAN), None);
+
+        // 0が整数にならない範囲ではオフセットで0を表せるようにする
+        let imap = Imap::new(-0.3, 1.0, 2);
+        let zero = imap.encode(0.0).unwrap();
+        assert_eq!(imap.decode(zero), 0.0);
+
+        // 丸めは切り捨て
+        let imap = Imap::new(0.0, 10.0, 1);
+        assert_eq!(imap.encode(10.0), Some(80));
+        assert_eq!(imap.encode(0.9), Some(7));
+        assert_eq!(imap.decode(7), 0.875);
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive() {
+        use serde::{Deserialize, Serialize};
+
+        use crate::{from_bytes, klv, to_bytes};
+
+        #[klv(key = "TEST")]
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Position {
+            #[klv(tag = 13, imap(-90.0, 90.0, 4))]
+            latitude: f64,
+            #[klv(tag = 14, scaled(i32, 10_000_000))]
+            longitude: f64,
+            #[klv(tag = 15, varlen)]
+            count: u64,
+            #[klv(tag = 16)]
+            #[serde(skip_serializing_if = "Option::is_none")]
+            mode: Option<u8>,
+            #[klv(unknown)]
+            extra: crate::unknown::UnknownTags,
+        }
+
+        let t = Position {
+            latitude: 45.0,
+            longitude: 135.0,
+            count: 300,
+            mode: None,
+            extra: Default::default(),
+        };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(&buf[..4], b"TEST");
+        assert_eq!(
+            &buf[4..],
+            &[17, 13, 4, 0x43, 0x80, 0x00, 0x00, 14, 4, 0x50, 0x77, 0x5d, 0x80, 15, 2, 0x01, 0x2c]
+        );
+        assert_eq!(from_bytes::<Position>(&buf).unwrap(), t);
+    }
+}
@@ -0,0 +1,328 @@
+//! Interned strings shared across packets
+//!
+//! String tags such as the sensor name repeat the same value in every packet of a stream.
+//! A field of [`InternedStr`] deserialized by a [`KLVDeserializer`] with a [`StringInterner`]
+//! shares one `Arc<str>` for the same string, instead of allocating a `String` per packet.
+//! Without an interner each value allocates its own `Arc<str>`.
+//!
+//! The interner is set by [`KLVDeserializer::with_interner`],
+//! so it combines with [`DeserializerConfig`] and [`crate::verify_checksum`],
+//! and [`from_bytes_lossy_with_interner`] skips records as [`crate::from_bytes_lossy`] does.
+//!
+//! Example
+//!
+//! ```rust
+//! use std::sync::Arc;
+//!
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::intern::{from_bytes_with_interner, InternedStr, StringInterner};
+//! use serde_klv::{to_bytes, DeserializerConfig};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "11")]
+//!     sensor: InternedStr,
+//! }
+//!
+//! let buf = to_bytes(&Packet { sensor: InternedStr::from("EO Nose") }).unwrap();
+//! let interner = StringInterner::new();
+//! let config = DeserializerConfig::default();
+//! let a: Packet = from_bytes_with_interner(&buf, config, &interner).unwrap();
+//! let b: Packet = from_bytes_with_interner(&buf, config, &interner).unwrap();
+//! assert_eq!(&*a.sensor, "EO Nose");
+//! assert!(Arc::ptr_eq(a.sensor.as_arc(), b.sensor.as_arc()));
+//! assert_eq!(interner.len(), 1);
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::de::from_bytes_lossy_with;
+use crate::error::{FieldError, Result};
+use crate::{DeserializerConfig, KLVDeserializer};
+
+pub(crate) const INTERNED: &str = "$serde_klv::intern";
+
+thread_local! {
+    // KLVDeserializerが共有した文字列をVisitorへ渡すための1回限りの受け渡し場所
+    // internerはKLVDeserializerが持ち、ここには呼び出し中の1つだけを置く
+    static HANDOFF: RefCell<Option<Arc<str>>> = RefCell::new(None);
+}
+
+/// Cache of `Arc<str>` keyed by the string
+///
+/// The cache stops adding strings at the capacity,
+/// so untrusted input of many distinct strings does not grow it without limit.
+#[derive(Debug, Clone)]
+pub struct StringInterner {
+    strings: RefCell<HashSet<Arc<str>>>,
+    capacity: usize,
+}
+
+impl Default for StringInterner {
+    fn default() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl StringInterner {
+    /// default of the number of cached strings
+    pub const DEFAULT_CAPACITY: usize = 1024;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// interner caching up to `capacity` strings
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            strings: RefCell::new(HashSet::new()),
+            capacity,
+        }
+    }
+
+    /// shared string of the value, allocate a new one when not cached
+    pub fn intern(&self, s: &str) -> Arc<str> {
+        let mut strings = self.strings.borrow_mut();
+        if let Some(x) = strings.get(s) {
+            return x.clone();
+        }
+        let x: Arc<str> = Arc::from(s);
+        if strings.len() < self.capacity {
+            strings.insert(x.clone());
+        }
+        x
+    }
+
+    /// number of cached strings
+    pub fn len(&self) -> usize {
+        self.strings.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.borrow().is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.strings.get_mut().clear();
+    }
+}
+
+/// Deserialize from bytes with config, sharing the strings of [`InternedStr`] fields through the interner
+pub fn from_bytes_with_interner<'a, T>(
+    s: &'a [u8],
+    config: DeserializerConfig<'a>,
+    interner: &'a StringInterner,
+) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = KLVDeserializer::from_slice_with_config(s, config);
+    deserializer.with_interner(interner);
+    let t = T::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(t)
+}
+
+/// [`crate::from_bytes_lossy`] sharing the strings of [`InternedStr`] fields through the interner
+pub fn from_bytes_lossy_with_interner<'a, T>(
+    s: &'a [u8],
+    interner: &'a StringInterner,
+) -> Result<(T, Vec<FieldError>)>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_lossy_with(s, Some(interner))
+}
+
+// 共有した文字列をVisitorに渡す
+pub(crate) fn visit_interned<'de, V, E>(visitor: V, s: Arc<str>) -> std::result::Result<V::Value, E>
+where
+    V: de::Visitor<'de>,
+    E: de::Error,
+{
+    HANDOFF.with(|x| x.replace(Some(s.clone())));
+    let result = visitor.visit_str(&s);
+    HANDOFF.with(|x| x.take());
+    result
+}
+
+/// String field shared by [`StringInterner`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InternedStr(Arc<str>);
+
+impl InternedStr {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn as_arc(&self) -> &Arc<str> {
+        &self.0
+    }
+}
+
+impl Deref for InternedStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for InternedStr {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+impl From<&str> for InternedStr {
+    fn from(s: &str) -> Self {
+        Self(Arc::from(s))
+    }
+}
+
+impl From<Arc<str>> for InternedStr {
+    fn from(s: Arc<str>) -> Self {
+        Self(s)
+    }
+}
+
+impl Serialize for InternedStr {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for InternedStr {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_newtype_struct(INTERNED, InternedStrVisitor)
+    }
+}
+
+struct InternedStrVisitor;
+
+impl<'de> de::Visitor<'de> for InternedStrVisitor {
+    type Value = InternedStr;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string")
+    }
+
+    fn visit_newtype_struct<D>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(self)
+    }
+
+    // KLVDeserializerが共有した文字列であればそのまま使う
+    fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let shared = HANDOFF.with(|x| x.borrow_mut().take());
+        match shared {
+            Some(x) if std::ptr::eq(x.as_ptr(), v.as_ptr()) && x.len() == v.len() => {
+                Ok(InternedStr(x))
+            }
+            _ => Ok(InternedStr(Arc::from(v))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{
+        from_bytes_lossy_with_interner, from_bytes_with_interner, InternedStr, StringInterner,
+    };
+    use crate::{from_bytes, to_bytes, DeserializerConfig, KLVDeserializer};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestIntern {
+        #[serde(rename = "10")]
+        name: InternedStr,
+        #[serde(rename = "11")]
+        other: InternedStr,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename = "TEST")]
+    struct TestLossy {
+        #[serde(rename = "10")]
+        name: InternedStr,
+        #[serde(rename = "11")]
+        other: Option<InternedStr>,
+    }
+
+    #[test]
+    fn test_interner() {
+        let t = TestIntern {
+            name: InternedStr::from("sensor"),
+            other: InternedStr::from("sensor"),
+        };
+        let buf = to_bytes(&t).unwrap();
+
+        let interner = StringInterner::new();
+        let config = DeserializerConfig::default();
+        let x: TestIntern = from_bytes_with_interner(&buf, config, &interner).unwrap();
+        assert_eq!(x, t);
+        assert!(Arc::ptr_eq(x.name.as_arc(), x.other.as_arc()));
+        assert_eq!(interner.len(), 1);
+
+        // internerを使わなければ個別に確保する
+        let y: TestIntern = from_bytes(&buf).unwrap();
+        assert_eq!(y, t);
+        assert!(!Arc::ptr_eq(y.name.as_arc(), y.other.as_arc()));
+
+        // 上限を超えた文字列はキャッシュしない
+        let mut interner = StringInterner::with_capacity(1);
+        let t = TestIntern {
+            name: InternedStr::from("a"),
+            other: InternedStr::from("b"),
+        };
+        let buf = to_bytes(&t).unwrap();
+        let x: TestIntern = from_bytes_with_interner(&buf, config, &interner).unwrap();
+        assert_eq!(x, t);
+        assert_eq!(interner.len(), 1);
+        assert_eq!(&*interner.intern("a"), "a");
+        assert!(Arc::ptr_eq(&interner.intern("a"), x.name.as_arc()));
+        interner.clear();
+        assert!(interner.is_empty());
+
+        // Deserializerに設定したinternerは呼び出し元が持ち続ける
+        let interner = StringInterner::new();
+        interner.intern("a");
+        let mut de = KLVDeserializer::from_slice(&buf);
+        de.with_interner(&interner);
+        let x = TestIntern::deserialize(&mut de).unwrap();
+        de.end().unwrap();
+        assert_eq!(interner.len(), 2);
+        assert!(Arc::ptr_eq(&interner.intern("b"), x.other.as_arc()));
+        assert!(from_bytes_with_interner::<TestIntern>(&buf[..5], config, &interner).is_err());
+        assert_eq!(interner.len(), 2);
+
+        // 読み出せないレコードを飛ばす場合も共有する
+        let buf = [b'T', b'E', b'S', b'T', 7, 10, 1, b'a', 11, 2, 0xff, 0xfe];
+        let (x, errors) = from_bytes_lossy_with_interner::<TestLossy>(&buf, &interner).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(x.other.is_none());
+        assert!(Arc::ptr_eq(&interner.intern("a"), x.name.as_arc()));
+    }
+}
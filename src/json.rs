@@ -0,0 +1,413 @@
+//! Convert between KLV packet and JSON by [`Schema`]
+//!
+//! The schema names the tags and declares the types of their values.
+//! Records of tags not in the schema are written with the tag number as the key
+//! and the value bytes as an array, and read back in the same form.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde_json::json;
+//! use serde_klv::json::{json_to_klv, klv_to_json};
+//! use serde_klv::schema::{FieldType, Schema};
+//! use serde_klv::KLVMap;
+//!
+//! let mut position = Schema::nested();
+//! position.field(1, "latitude", FieldType::F64);
+//! let mut schema = Schema::new(b"TEST").unwrap();
+//! schema
+//!     .field(10, "speed", FieldType::U16)
+//!     .field(11, "name", FieldType::String)
+//!     .field(12, "position", FieldType::Set(position));
+//!
+//! let value = json!({
+//!     "speed": 300,
+//!     "name": "KLV",
+//!     "position": { "latitude": 35.5 },
+//! });
+//! let buf = json_to_klv(&value, &schema).unwrap();
+//! let map = KLVMap::try_from_bytes(&buf).unwrap();
+//! assert_eq!(map.get_u16(10), Some(300));
+//! assert_eq!(klv_to_json(&map, &schema).unwrap(), value);
+//! ```
+
+use serde_json::{Map, Number, Value};
+
+use crate::de::{parse_local_set, KLVMap, KLVRaw};
+use crate::error::{Error, Result, TagPath};
+use crate::schema::{FieldType, Schema};
+use crate::{KLVBuilder, KeyWidth};
+
+/// Convert parsed packet to JSON object
+///
+/// When a tag appears more than once, the first record is used.
+/// Zero-length records of number and bool are `null`.
+pub fn klv_to_json(map: &KLVMap, schema: &Schema) -> Result<Value> {
+    klv_to_json_with_names(map, schema, &[])
+}
+
+/// [`klv_to_json`] writing the tags not in the schema by the names of the table
+///
+/// The table is the same form as [`crate::DeserializerConfig::tag_names`]
+/// and is shared by all local sets.
+///
+/// Example
+///
+/// ```rust
+/// use serde_json::json;
+/// use serde_klv::json::{json_to_klv_with_names, klv_to_json_with_names};
+/// use serde_klv::schema::{FieldType, Schema};
+/// use serde_klv::KLVMap;
+///
+/// let names = vec![("heading".to_string(), 5)];
+/// let names: Vec<(&str, u128)> = names.iter().map(|(x, tag)| (x.as_str(), *tag)).collect();
+/// let mut schema = Schema::new(b"TEST").unwrap();
+/// schema.field(10, "speed", FieldType::U16);
+///
+/// let buf = [b'T', b'E', b'S', b'T', 7, 10, 2, 1, 44, 5, 1, 9];
+/// let map = KLVMap::try_from_bytes(&buf).unwrap();
+/// let value = klv_to_json_with_names(&map, &schema, &names).unwrap();
+/// assert_eq!(value, json!({ "speed": 300, "heading": [9] }));
+/// assert_eq!(json_to_klv_with_names(&value, &schema, &names).unwrap(), buf);
+/// ```
+pub fn klv_to_json_with_names(
+    map: &KLVMap,
+    schema: &Schema,
+    names: &[(&str, u128)],
+) -> Result<Value> {
+    records_to_json(map.iter(), schema, names, &[])
+}
+
+/// Encode JSON object to packet
+///
+/// Records are written in the order of the schema, then the tags not in the schema.
+/// `null` fields are omitted.
+pub fn json_to_klv(value: &Value, schema: &Schema) -> Result<Vec<u8>> {
+    json_to_klv_with_names(value, schema, &[])
+}
+
+/// [`json_to_klv`] reading the fields not in the schema by the names of the table
+///
+/// Fields neither in the schema nor in the table are parsed as tag numbers,
+/// see [`klv_to_json_with_names`].
+pub fn json_to_klv_with_names(
+    value: &Value,
+    schema: &Schema,
+    names: &[(&str, u128)],
+) -> Result<Vec<u8>> {
+    let mut builder = KLVBuilder::new(&schema.universal_key)?;
+    json_to_records(value, schema, names, &mut builder, &[])?;
+    Ok(builder.finish())
+}
+
+fn records_to_json<'a, 'm: 'a>(
+    records: impl Iterator<Item = &'a KLVRaw<'m>>,
+    schema: &Schema,
+    names: &[(&str, u128)],
+    path: &[u128],
+) -> Result<Value> {
+    let mut object = Map::new();
+    for raw in records {
+        let (name, value) = match schema.by_tag(raw.key) {
+            Some(spec) => {
+                let path = [path, &[raw.key.into()]].concat();
+                (
+                    spec.name.clone(),
+                    field_to_json(raw, &spec.ty, names, &path)?,
+                )
+            }
+            // スキーマにないタグは表の名前かタグ番号をキーとしてbyte列のまま出力する
+            None => (
+                match names.iter().find(|(_, tag)| *tag == raw.key.into()) {
+                    Some((name, _)) => name.to_string(),
+                    None => raw.key.to_string(),
+                },
+                bytes_to_json(raw.value.unwrap_or_default()),
+            ),
+        };
+        object.entry(name).or_insert(value);
+    }
+    Ok(Value::Object(object))
+}
+
+fn field_to_json(
+    raw: &KLVRaw,
+    ty: &FieldType,
+    names: &[(&str, u128)],
+    path: &[u128],
+) -> Result<Value> {
+    use FieldType::*;
+    // 長さ0は数値とboolの場合のみnullとする
+    if raw.value.is_none() && !matches!(ty, String | Bytes | Set(_)) {
+        return Ok(Value::Null);
+    }
+    let float = |v: f64| Number::from_f64(v).map_or(Value::Null, Value::Number);
+    let value = match ty {
+        Bool => raw.as_bool().map(Value::Bool),
+        U8 => raw.as_u8().map(Value::from),
+        U16 => raw.as_u16().map(Value::from),
+        U32 => raw.as_u32().map(Value::from),
+        U64 => raw.as_u64().map(Value::from),
+        I8 => raw.as_i8().map(Value::from),
+        I16 => raw.as_i16().map(Value::from),
+        I32 => raw.as_i32().map(Value::from),
+        I64 => raw.as_i64().map(Value::from),
+        F32 => raw.as_f32().map(|v| float(v as f64)),
+        F64 => raw.as_f64().map(float),
+        String => raw.as_str().map(Value::from),
+        Bytes => Some(bytes_to_json(raw.value.unwrap_or_default())),
+        Set(schema) => match parse_local_set(raw.value.unwrap_or_default()) {
+            Some(records) => Some(records_to_json(records.iter(), schema, names, path)?),
+            None => None,
+        },
+    };
+    value.ok_or_else(|| {
+        Error::TypeLength(format!(
+            "tag {} has {} bytes which is not {}",
+            TagPath(path),
+            raw.length,
+            ty.name()
+        ))
+    })
+}
+
+fn json_to_records(
+    value: &Value,
+    schema: &Schema,
+    names: &[(&str, u128)],
+    builder: &mut KLVBuilder,
+    path: &[u128],
+) -> Result<()> {
+    let object = value.as_object().ok_or_else(|| {
+        Error::Encode(format!(
+            "expected JSON object for local set {}",
+            TagPath(path)
+        ))
+    })?;
+    for spec in schema.specs() {
+        match object.get(&spec.name) {
+            None | Some(Value::Null) => {}
+            Some(v) => {
+                let path = [path, &[spec.tag.into()]].concat();
+                field_to_klv(v, spec.tag, &spec.ty, names, builder, &path)?;
+            }
+        }
+    }
+    for (key, v) in object.iter() {
+        if schema.by_name(key).is_some() {
+            continue;
+        }
+        let tag = KeyWidth::U8
+            .parse_named(names, key)
+            .and_then(|x| u8::try_from(x).ok())
+            .ok_or_else(|| Error::Encode(format!("unknown field {} in {}", key, TagPath(path))))?;
+        let bytes = json_to_bytes(v)
+            .ok_or_else(|| Error::Encode(format!("field {} expects array of bytes", key)))?;
+        builder.push_bytes(tag, &bytes);
+    }
+    Ok(())
+}
+
+fn field_to_klv(
+    v: &Value,
+    tag: u8,
+    ty: &FieldType,
+    names: &[(&str, u128)],
+    builder: &mut KLVBuilder,
+    path: &[u128],
+) -> Result<()> {
+    use FieldType::*;
+    let uint = || v.as_u64();
+    let int = || v.as_i64();
+    let pushed = match ty {
+        Bool => v.as_bool().map(|x| builder.push_bool(tag, x)),
+        U8 => uint()
+            .and_then(|x| u8::try_from(x).ok())
+            .map(|x| builder.push_u8(tag, x)),
+        U16 => uint()
+            .and_then(|x| u16::try_from(x).ok())
+            .map(|x| builder.push_u16(tag, x)),
+        U32 => uint()
+            .and_then(|x| u32::try_from(x).ok())
+            .map(|x| builder.push_u32(tag, x)),
+        U64 => uint().map(|x| builder.push_u64(tag, x)),
+        I8 => int()
+            .and_then(|x| i8::try_from(x).ok())
+            .map(|x| builder.push_i8(tag, x)),
+        I16 => int()
+            .and_then(|x| i16::try_from(x).ok())
+            .map(|x| builder.push_i16(tag, x)),
+        I32 => int()
+            .and_then(|x| i32::try_from(x).ok())
+            .map(|x| builder.push_i32(tag, x)),
+        I64 => int().map(|x| builder.push_i64(tag, x)),
+        F32 => v.as_f64().map(|x| builder.push_f32(tag, x as f32)),
+        F64 => v.as_f64().map(|x| builder.push_f64(tag, x)),
+        String => v.as_str().map(|x| builder.push_str(tag, x)),
+        Bytes => json_to_bytes(v).map(|x| builder.push_bytes(tag, &x)),
+        Set(schema) => {
+            let mut nested = KLVBuilder::nested();
+            json_to_records(v, schema, names, &mut nested, path)?;
+            Some(builder.push_nested(tag, &nested))
+        }
+    };
+    match pushed {
+        Some(_) => Ok(()),
+        None => Err(Error::Encode(format!(
+            "tag {} expects {} but got {}",
+            TagPath(path),
+            ty.name(),
+            v
+        ))),
+    }
+}
+
+fn bytes_to_json(bytes: &[u8]) -> Value {
+    Value::Array(bytes.iter().map(|b| Value::from(*b)).collect())
+}
+
+fn json_to_bytes(v: &Value) -> Option<Vec<u8>> {
+    v.as_array()?
+        .iter()
+        .map(|x| x.as_u64().and_then(|x| u8::try_from(x).ok()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use serde_json::json;
+
+    use super::{json_to_klv, json_to_klv_with_names, klv_to_json, klv_to_json_with_names};
+    use crate::error::Error;
+    use crate::schema::{FieldType, Schema};
+    use crate::{to_bytes, KLVMap};
+
+    #[derive(Debug, Serialize)]
+    #[serde(rename = "TEST")]
+    struct TestJson<'a> {
+        #[serde(rename = "10")]
+        u16: u16,
+        #[serde(rename = "11")]
+        i8: i8,
+        #[serde(rename = "12")]
+        str: &'a str,
+        #[serde(rename = "13")]
+        child: TestChild,
+        #[serde(rename = "14")]
+        none: Option<u32>,
+        #[serde(rename = "99")]
+        unknown: u8,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct TestChild {
+        #[serde(rename = "1")]
+        flag: bool,
+        #[serde(rename = "2")]
+        f32: f32,
+    }
+
+    fn schema() -> Schema {
+        let mut child = Schema::nested();
+        child
+            .field(1, "flag", FieldType::Bool)
+            .field(2, "f32", FieldType::F32);
+        let mut schema = Schema::new(b"TEST").unwrap();
+        schema
+            .field(10, "u16", FieldType::U16)
+            .field(11, "i8", FieldType::I8)
+            .field(12, "str", FieldType::String)
+            .field(13, "child", FieldType::Set(child))
+            .field(14, "none", FieldType::U32);
+        schema
+    }
+
+    #[test]
+    fn test_json() {
+        let t = TestJson {
+            u16: 1000,
+            i8: -1,
+            str: "abc",
+            child: TestChild {
+                flag: true,
+                f32: 0.5,
+            },
+            none: None,
+            unknown: 7,
+        };
+        let buf = to_bytes(&t).unwrap();
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        let value = klv_to_json(&map, &schema()).unwrap();
+        let expect = json!({
+            "u16": 1000,
+            "i8": -1,
+            "str": "abc",
+            "child": { "flag": true, "f32": 0.5 },
+            "none": null,
+            "99": [7],
+        });
+        assert_eq!(value, expect);
+
+        // nullは書き出さないためNoneのレコードだけが消える
+        let x = json_to_klv(&value, &schema()).unwrap();
+        let mut expect = buf.clone();
+        let none = [14, 0];
+        let at = expect.windows(2).position(|x| x == none).unwrap();
+        expect.drain(at..at + 2);
+        expect[4] -= 2;
+        assert_eq!(x, expect);
+    }
+
+    #[test]
+    fn test_json_names() {
+        let names = [("extra", 99), ("wide", 300)];
+        let value = json!({ "u16": 1, "child": { "flag": true, "extra": [2] }, "extra": [1] });
+        let buf = json_to_klv_with_names(&value, &schema(), &names).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(
+            &buf[4..],
+            &[
+                15,
+                10, 2, 0, 1,
+                13, 6, 1, 1, 1, 99, 1, 2,
+                99, 1, 1,
+            ]
+        );
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert_eq!(
+            klv_to_json_with_names(&map, &schema(), &names).unwrap(),
+            value
+        );
+
+        // 1byteのタグに収まらない名前
+        let value = json!({ "wide": [1] });
+        assert!(matches!(
+            json_to_klv_with_names(&value, &schema(), &names),
+            Err(Error::Encode(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_error() {
+        // 範囲外
+        let value = json!({ "u16": 70000 });
+        assert!(matches!(
+            json_to_klv(&value, &schema()),
+            Err(Error::Encode(_))
+        ));
+        // スキーマにない名前
+        let value = json!({ "unknown": 1 });
+        assert!(matches!(
+            json_to_klv(&value, &schema()),
+            Err(Error::Encode(_))
+        ));
+        // 型の幅に収まらない
+        let buf = [b'T', b'E', b'S', b'T', 5, 11, 3, 0, 0, 1];
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        match klv_to_json(&map, &schema()) {
+            Err(Error::TypeLength(msg)) => assert_eq!(msg, "tag 11 has 3 bytes which is not i8"),
+            x => unreachable!("{:?}", x),
+        }
+    }
+}
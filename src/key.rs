@@ -0,0 +1,421 @@
+use std::fmt::{self, Write};
+
+use crate::error::{Error, Result};
+
+/// Universal key of a known SMPTE/MISB local set
+///
+/// Example
+/// ```
+/// use serde_klv::{KLVMap, UniversalKey};
+///
+/// let mut buf = UniversalKey::UAS_DATALINK_LS.key.to_vec();
+/// buf.extend_from_slice(&[3, 65, 1, 17]);
+/// let map = KLVMap::try_from_bytes(&buf).unwrap();
+/// assert_eq!(map.detect_standard(), Some(UniversalKey::UAS_DATALINK_LS));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UniversalKey {
+    /// name of the standard
+    pub name: &'static str,
+    pub key: &'static [u8],
+}
+
+impl UniversalKey {
+    /// MISB ST 0601 UAS Datalink Local Set
+    pub const UAS_DATALINK_LS: Self = Self {
+        name: "MISB ST 0601 UAS Datalink Local Set",
+        key: b"\x06\x0e\x2b\x34\x02\x0b\x01\x01\x0e\x01\x03\x01\x01\x00\x00\x00",
+    };
+    /// MISB ST 0102 Security Metadata Local Set
+    pub const SECURITY_LS: Self = Self {
+        name: "MISB ST 0102 Security Local Set",
+        key: b"\x06\x0e\x2b\x34\x02\x03\x01\x01\x0e\x01\x03\x03\x02\x00\x00\x00",
+    };
+    /// MISB ST 0903 Video Moving Target Indicator Local Set
+    pub const VMTI_LS: Self = Self {
+        name: "MISB ST 0903 VMTI Local Set",
+        key: b"\x06\x0e\x2b\x34\x02\x0b\x01\x01\x0e\x01\x03\x03\x06\x00\x00\x00",
+    };
+    /// MISB ST 0806 Remote Video Terminal Local Set
+    pub const RVT_LS: Self = Self {
+        name: "MISB ST 0806 RVT Local Set",
+        key: b"\x06\x0e\x2b\x34\x02\x0b\x01\x01\x0e\x01\x03\x01\x02\x00\x00\x00",
+    };
+
+    /// registry of the known keys
+    pub const KNOWN: &'static [Self] = &[
+        Self::UAS_DATALINK_LS,
+        Self::SECURITY_LS,
+        Self::VMTI_LS,
+        Self::RVT_LS,
+    ];
+
+    /// mask of 16 byte universal label ignoring the version byte (the 8th byte)
+    pub const VERSION_MASK: [u8; 16] = [
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        0xff,
+    ];
+
+    /// find known key matches the bytes
+    pub fn lookup(key: &[u8]) -> Option<Self> {
+        Self::KNOWN.iter().find(|x| x.key == key).copied()
+    }
+
+    /// compare the key with the bits set in the mask
+    ///
+    /// The mask is applied from the head of the key, bytes beyond the mask are compared exactly.
+    ///
+    /// Example
+    /// ```
+    /// use serde_klv::UniversalKey;
+    ///
+    /// let mut newer = UniversalKey::UAS_DATALINK_LS.key.to_vec();
+    /// newer[7] = 0x02;
+    /// assert!(UniversalKey::UAS_DATALINK_LS.matches_masked(&newer, &UniversalKey::VERSION_MASK));
+    /// assert!(!UniversalKey::UAS_DATALINK_LS.matches_masked(&newer, &[]));
+    /// ```
+    pub fn matches_masked(&self, other: &[u8], mask: &[u8]) -> bool {
+        masked_eq(self.key, other, mask)
+    }
+}
+
+// マスクのbitのみ比較する。マスクより後ろのbyteは完全に一致しなければならない
+pub(crate) fn masked_eq(a: &[u8], b: &[u8], mask: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b.iter())
+            .enumerate()
+            .all(|(i, (x, y))| (x ^ y) & mask.get(i).copied().unwrap_or(0xff) == 0)
+}
+
+/// Universal key given by type instead of `#[serde(rename)]` of the struct
+///
+/// Used by [`crate::to_bytes_keyed`] and [`crate::from_bytes_keyed`],
+/// so the key can contain any bytes.
+///
+/// Example
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{from_bytes_keyed, to_bytes_keyed, UniversalKeyed};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     u8: u8,
+/// }
+///
+/// impl UniversalKeyed for Packet {
+///     const KEY: &'static [u8] = &[0xff, 0x80, 0x00, 0x01];
+/// }
+///
+/// let buf = to_bytes_keyed(&Packet { u8: 1 }).unwrap();
+/// assert_eq!(&buf, &[0xff, 0x80, 0x00, 0x01, 3, 10, 1, 1]);
+/// assert_eq!(from_bytes_keyed::<Packet>(&buf).unwrap(), Packet { u8: 1 });
+/// ```
+pub trait UniversalKeyed {
+    const KEY: &'static [u8];
+}
+
+/// Encoding of the tag of each field
+///
+/// Field names are the tag in decimal, or dot separated upper-case hex such as
+/// `"06.0E.2B.34.01.01.01.01.07.02.01.01.01.05.00.00"` for [`KeyWidth::Bytes16`].
+/// Only the serializer and the deserializer follow this,
+/// [`crate::KLVMap`], [`crate::value`] and the codecs read 1 byte tags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyWidth {
+    /// 1 byte tag of local set
+    #[default]
+    U8,
+    /// 2 byte big-endian tag
+    U16,
+    /// 4 byte big-endian tag
+    U32,
+    /// 16 byte key of universal set
+    Bytes16,
+    /// BER-OID encoded tag up to u64, 7 bits per byte with the continuation bit
+    BerOid,
+}
+
+impl KeyWidth {
+    // BER-OIDはu64までとするため最大10byte
+    const BER_OID_MAX_LEN: usize = 10;
+
+    // 名前の表にあるフィールドはそのタグとし、なければ数値として読む
+    pub(crate) fn parse_named(&self, names: &[(&str, u128)], name: &str) -> Option<u128> {
+        match names.iter().find(|(x, _)| *x == name) {
+            Some((_, tag)) => Some(*tag),
+            None => self.parse(name),
+        }
+    }
+
+    // 名前の表のタグがすべて幅に収まるか確認する
+    pub(crate) fn check_names(&self, names: &[(&str, u128)]) -> Result<()> {
+        match names.iter().find(|(_, tag)| !self.fits(*tag)) {
+            Some((name, tag)) => Err(Error::Key(format!(
+                "tag {} of {} does not fit in {:?}",
+                tag, name, self
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn fits(&self, tag: u128) -> bool {
+        match self {
+            KeyWidth::U8 => tag <= u8::MAX.into(),
+            KeyWidth::U16 => tag <= u16::MAX.into(),
+            KeyWidth::U32 => tag <= u32::MAX.into(),
+            KeyWidth::BerOid => tag <= u64::MAX.into(),
+            KeyWidth::Bytes16 => true,
+        }
+    }
+
+    // フィールド名からタグを読む
+    pub(crate) fn parse(&self, name: &str) -> Option<u128> {
+        match self {
+            KeyWidth::U8 => name.parse::<u8>().ok().map(u128::from),
+            KeyWidth::U16 => name.parse::<u16>().ok().map(u128::from),
+            KeyWidth::U32 => name.parse::<u32>().ok().map(u128::from),
+            KeyWidth::BerOid => name.parse::<u64>().ok().map(u128::from),
+            KeyWidth::Bytes16 => {
+                let mut bytes = [0_u8; 16];
+                let mut parts = name.split('.');
+                for b in bytes.iter_mut() {
+                    let part = parts.next()?;
+                    if part.len() != 2 {
+                        return None;
+                    }
+                    *b = u8::from_str_radix(part, 16).ok()?;
+                }
+                parts.next().is_none().then_some(u128::from_be_bytes(bytes))
+            }
+        }
+    }
+
+    // デシリアライズ時にvisitorへ渡すフィールド名
+    pub(crate) fn name(&self, tag: u128) -> TagName {
+        let mut name = TagName {
+            buf: [0; TagName::MAX_LEN],
+            len: 0,
+        };
+        // バッファは最長の名前に足りるため書き込みは失敗しない
+        let _ = match self {
+            KeyWidth::Bytes16 => tag.to_be_bytes().iter().enumerate().try_for_each(|(i, b)| {
+                if i == 0 {
+                    write!(name, "{:02X}", b)
+                } else {
+                    write!(name, ".{:02X}", b)
+                }
+            }),
+            _ => write!(name, "{}", tag),
+        };
+        name
+    }
+
+    // タグを書き込む。幅に収まらない場合はNone
+    pub(crate) fn encode(&self, tag: u128, buf: &mut Vec<u8>) -> Option<()> {
+        match self {
+            KeyWidth::U8 => buf.push(u8::try_from(tag).ok()?),
+            KeyWidth::U16 => buf.extend_from_slice(&u16::try_from(tag).ok()?.to_be_bytes()),
+            KeyWidth::U32 => buf.extend_from_slice(&u32::try_from(tag).ok()?.to_be_bytes()),
+            KeyWidth::Bytes16 => buf.extend_from_slice(&tag.to_be_bytes()),
+            KeyWidth::BerOid => {
+                let tag = u64::try_from(tag).ok()?;
+                let start = buf.len();
+                // 下位7bitから詰めて最後に反転する
+                buf.push((tag & 0x7f) as u8);
+                let mut rest = tag >> 7;
+                while rest > 0 {
+                    buf.push((rest & 0x7f) as u8 | 0x80);
+                    rest >>= 7;
+                }
+                buf[start..].reverse();
+            }
+        }
+        Some(())
+    }
+
+    // 先頭のタグを読み、タグとそのbyte数を返す
+    pub(crate) fn decode(&self, buf: &[u8]) -> Option<(u128, usize)> {
+        let fixed = |len: usize| {
+            let bytes = buf.get(..len)?;
+            let tag = bytes.iter().fold(0_u128, |acc, b| acc << 8 | *b as u128);
+            Some((tag, len))
+        };
+        match self {
+            KeyWidth::U8 => fixed(1),
+            KeyWidth::U16 => fixed(2),
+            KeyWidth::U32 => fixed(4),
+            KeyWidth::Bytes16 => fixed(16),
+            KeyWidth::BerOid => {
+                let mut tag = 0_u64;
+                for (i, b) in buf.iter().take(Self::BER_OID_MAX_LEN).enumerate() {
+                    if tag > u64::MAX >> 7 {
+                        return None;
+                    }
+                    tag = tag << 7 | (b & 0x7f) as u64;
+                    if b & 0x80 == 0 {
+                        return Some((tag as u128, i + 1));
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+// タグの名前をヒープに確保せずに保持する
+pub(crate) struct TagName {
+    buf: [u8; TagName::MAX_LEN],
+    len: usize,
+}
+
+impl TagName {
+    // Bytes16の"XX.XX. ... .XX"が最長
+    const MAX_LEN: usize = 47;
+
+    pub(crate) fn as_str(&self) -> &str {
+        std::str::from_utf8(&self.buf[..self.len]).unwrap_or_default()
+    }
+}
+
+impl fmt::Write for TagName {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{KeyWidth, UniversalKey, UniversalKeyed};
+    use crate::{
+        from_bytes, from_bytes_keyed, from_bytes_with_config, to_bytes, to_bytes_keyed,
+        DeserializerConfig, KLVMap,
+    };
+
+    #[test]
+    fn test_lookup() {
+        for known in UniversalKey::KNOWN {
+            assert_eq!(known.key.len(), 16);
+            assert_eq!(UniversalKey::lookup(known.key), Some(*known));
+        }
+        assert_eq!(UniversalKey::lookup(b"TEST"), None);
+
+        let buf = [b'T', b'E', b'S', b'T', 3, 10, 1, 1];
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert_eq!(map.detect_standard(), None);
+    }
+    // 名前はparseで同じタグに戻る
+    #[test]
+    fn test_tag_name() {
+        let tag = u128::from_be_bytes([
+            0x06, 0x0e, 0x2b, 0x34, 0x01, 0x01, 0x01, 0x01, 0x07, 0x02, 0x01, 0x01, 0x01, 0x05,
+            0x00, 0x00,
+        ]);
+        let name = KeyWidth::Bytes16.name(tag);
+        assert_eq!(
+            name.as_str(),
+            "06.0E.2B.34.01.01.01.01.07.02.01.01.01.05.00.00"
+        );
+        assert_eq!(KeyWidth::Bytes16.parse(name.as_str()), Some(tag));
+        assert_eq!(KeyWidth::U32.name(u32::MAX.into()).as_str(), "4294967295");
+        assert_eq!(
+            KeyWidth::BerOid.name(u128::MAX).as_str(),
+            u128::MAX.to_string()
+        );
+    }
+
+    #[test]
+    fn test_universal_keyed() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "1")]
+            u8: u8,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestKeyed {
+            #[serde(rename = "2")]
+            u16: u16,
+            #[serde(rename = "3")]
+            child: TestChild,
+        }
+        impl UniversalKeyed for TestKeyed {
+            const KEY: &'static [u8] = UniversalKey::UAS_DATALINK_LS.key;
+        }
+
+        let t = TestKeyed {
+            u16: 1,
+            child: TestChild { u8: 2 },
+        };
+        let buf = to_bytes_keyed(&t).unwrap();
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert_eq!(map.detect_standard(), Some(UniversalKey::UAS_DATALINK_LS));
+        assert_eq!(from_bytes_keyed::<TestKeyed>(&buf).unwrap(), t);
+
+        // structの名前はUniversalKeyとして使えない
+        assert!(to_bytes(&t).is_err());
+        assert!(from_bytes::<TestKeyed>(&buf).is_err());
+
+        // 異なるUniversalKey
+        let mut broken = buf;
+        broken[15] = 0xff;
+        assert!(from_bytes_keyed::<TestKeyed>(&broken).is_err());
+    }
+
+    #[test]
+    fn test_matches_masked() {
+        let key = UniversalKey::UAS_DATALINK_LS;
+        assert!(key.matches_masked(key.key, &[]));
+        assert!(!key.matches_masked(&key.key[..15], &UniversalKey::VERSION_MASK));
+        let mut other = key.key.to_vec();
+        other[7] = 0xff;
+        assert!(key.matches_masked(&other, &UniversalKey::VERSION_MASK));
+        // マスクより後ろのbyteは完全に一致しなければならない
+        assert!(!key.matches_masked(&other, &[0xff; 4]));
+        assert!(key.matches_masked(&other, &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0]));
+        other[15] = 0x01;
+        assert!(!key.matches_masked(&other, &UniversalKey::VERSION_MASK));
+        let mut mask = UniversalKey::VERSION_MASK;
+        mask[15] = 0xfe;
+        assert!(key.matches_masked(&other, &mask));
+    }
+
+    #[test]
+    fn test_universal_key_mask() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "\x06\x0e\x2b\x34\x02\x0b\x01\x01\x0e\x01\x03\x01\x01\x00\x00\x00")]
+        struct TestMask {
+            #[serde(rename = "2")]
+            u16: u16,
+        }
+
+        let t = TestMask { u16: 1 };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(&buf[..16], UniversalKey::UAS_DATALINK_LS.key);
+
+        // バージョンのbyteのみ異なる場合はマスクを設定すると読める
+        let mut newer = buf.clone();
+        newer[7] = 0x02;
+        assert!(from_bytes::<TestMask>(&newer).is_err());
+        let config = DeserializerConfig {
+            universal_key_mask: Some(UniversalKey::VERSION_MASK),
+            ..Default::default()
+        };
+        assert_eq!(
+            from_bytes_with_config::<TestMask>(&newer, config).unwrap(),
+            t
+        );
+
+        let mut broken = buf;
+        broken[15] = 0xff;
+        assert!(from_bytes_with_config::<TestMask>(&broken, config).is_err());
+    }
+}
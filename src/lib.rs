@@ -61,31 +61,171 @@
 //! let x: TestStruct = from_bytes_with_checksum(&buf, WrappedCRC::default()).unwrap();
 //! assert_eq!(&t, &x);
 //! ```
+//!
+//! ## Features
+//!
+//! Checksum API such as [`to_bytes_with_checksum`] and [`WrappedCRC`] is always available.
+//!
+//! - `derive`: `klv` attribute macro of tag metadata and `MaxEncodedLen` derive macro
+//! - `json`: schema based conversion between [`KLVMap`] and JSON
+//! - `mpegts`: MPEG-2 TS carriage helpers
+//! - `net`: fragmented packets over UDP
+//! - `rvt`: MISB ST 0806 local sets
+//! - `st0102`, `uasdls`: MISB ST 0102 and ST 0601 local sets
+//! - `st1108`: MISB ST 1108 local set
+//! - `testkit`: MISB sample packets and assertions for tests
+//! - `tokio`: asynchronous packet reader
+//! - `tracing`: trace events of reads and writes
+//!
+//! ## Packet enum
+//!
+//! An enum of newtype or struct variants renamed to universal keys is a packet of any of them.
+//! The variant is selected by the universal key on deserialize,
+//! and the variant name is written as the universal key on serialize.
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! struct Position {
+//!     #[serde(rename = "10")]
+//!     x: u8,
+//! }
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! enum Packet {
+//!     #[serde(rename = "POS0")]
+//!     Position(Position),
+//!     #[serde(rename = "STAT")]
+//!     Status {
+//!         #[serde(rename = "1")]
+//!         code: u8,
+//!     },
+//! }
+//!
+//! let buf = to_bytes(&Packet::Status { code: 2 }).unwrap();
+//! assert_eq!(&buf[..4], b"STAT");
+//! assert_eq!(from_bytes::<Packet>(&buf).unwrap(), Packet::Status { code: 2 });
+//! ```
 
 use std::fmt::Debug;
 
 use byteorder::ByteOrder;
 
+use crate::error::LengthError;
+
+// tracing featureが有効な場合のみtraceイベントを出力する
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+pub mod ascii;
+pub mod bits;
+pub mod bounded;
+mod builder;
 mod checksum;
+mod codec;
 mod de;
+mod delta;
+pub mod diff;
+mod dump;
+pub mod edit;
 pub mod error;
+pub mod fileio;
+pub mod flag;
+pub mod imap;
+pub mod intern;
+mod key;
+pub mod lint;
+pub mod pack;
+mod placeholder;
+mod raw;
+mod registry;
+pub mod repeated;
+pub mod scaled;
+pub mod schema;
+pub mod sentinel;
 mod ser;
+pub mod size;
+pub mod st0603;
+pub mod stats;
+pub mod timestamps;
+pub mod unknown;
+pub mod value;
+pub mod varlen;
 
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "mpegts")]
+pub mod mpegts;
+#[cfg(feature = "net")]
+pub mod net;
+#[cfg(feature = "rvt")]
+pub mod rvt;
+#[cfg(feature = "st0102")]
+pub mod st0102;
+#[cfg(feature = "st1108")]
+pub mod st1108;
+#[cfg(feature = "tokio")]
+pub mod stream;
+#[cfg(feature = "testkit")]
+pub mod testkit;
 #[cfg(feature = "uasdls")]
 pub mod uasdls;
 
-pub use checksum::{CheckSumCalc, WrappedCRC};
-pub use de::{from_bytes, from_bytes_with_checksum, KLVMap, KLVRaw};
-pub use ser::{to_bytes, to_bytes_with_checksum};
+pub use builder::KLVBuilder;
+pub use checksum::{
+    CheckSumCalc, CheckSumCoverage, CheckSumWidth, ChecksumLocation, ChecksumStatus, Crc16Algo,
+    WrappedCRC,
+};
+pub use codec::{CodecRegistry, ValueCodec};
+pub use de::{
+    decode_batch, from_bytes, from_bytes_keyed, from_bytes_lossy, from_bytes_maybe_checksum,
+    from_bytes_multi, from_bytes_partial, from_bytes_strict, from_bytes_strict_with_config,
+    from_bytes_with_checksum, from_bytes_with_checksum_lossy, from_bytes_with_codecs,
+    from_bytes_with_config, from_bytes_with_unknown_tags, split_packets, verify_checksum,
+    DeserializerConfig, KLVDeserializer, KLVMap, KLVMapOwned, KLVRaw, KLVRawOwned, Packets,
+    StringPolicy,
+};
+pub use delta::{apply_delta, to_bytes_delta, DELTA_REMOVED_TAGS};
+pub use key::{KeyWidth, UniversalKey, UniversalKeyed};
+pub use lint::{validate_type, validate_type_with};
+pub use raw::RawKLV;
+pub use registry::{from_bytes_auto, DecodedPacket, PacketRegistry};
+pub use ser::{
+    to_bytes, to_bytes_into, to_bytes_keyed, to_bytes_with_capacity, to_bytes_with_checksum,
+    to_bytes_with_codecs, to_bytes_with_config, to_bytes_with_limit, LengthForm, SerializeBuffer,
+    SerializerConfig, TimestampSource,
+};
+pub use size::MaxEncodedLen;
+pub use value::from_bytes_to_value;
+
+#[cfg(feature = "derive")]
+pub use serde_klv_derive::{klv, MaxEncodedLen};
+
+// deriveで生成されるコードの`::serde_klv`をクレート内のテストから参照する
+#[cfg(all(test, feature = "derive"))]
+extern crate self as serde_klv;
 
 type LengthByteSize = usize;
 type ContentByteSize = usize;
 
 /// parse length rule by BER
-pub fn parse_length(buf: &[u8]) -> Result<(LengthByteSize, ContentByteSize), String> {
+pub fn parse_length(buf: &[u8]) -> Result<(LengthByteSize, ContentByteSize), LengthError> {
     use byteorder::BigEndian;
+    if buf.is_empty() {
+        return Err(LengthError::Empty);
+    }
     match LengthOctet::from_u8(buf[0]) {
         LengthOctet::Short(x) => Ok((1, x as usize)),
+        LengthOctet::Long(x) if buf.len() <= x as usize => Err(LengthError::Truncated {
+            expected: x as usize + 1,
+            actual: buf.len(),
+        }),
         LengthOctet::Long(x) => match x {
             1 => Ok((2, buf[1] as usize)),
             2 => Ok((3, BigEndian::read_u16(&buf[1..3]) as usize)),
@@ -98,16 +238,51 @@ pub fn parse_length(buf: &[u8]) -> Result<(LengthByteSize, ContentByteSize), Str
             }
             4 => Ok((5, BigEndian::read_u32(&buf[1..5]) as usize)),
             8 => Ok((9, BigEndian::read_u64(&buf[1..9]) as usize)),
-            x => Err(format!(
-                "Unsupported length [{}], supported only {{1,2,3,4,8}}",
-                x
-            )),
+            x => Err(LengthError::UnsupportedOctets(x)),
         },
-        LengthOctet::Indefinite => Err("length is Indefinete".to_string()),
-        LengthOctet::Reserved => Err("Reserved octet".to_string()),
+        LengthOctet::Indefinite => Err(LengthError::Indefinite),
+        LengthOctet::Reserved => Err(LengthError::Reserved),
     }
 }
 
+/// write length by BER in the form of the policy, return the number of written bytes
+///
+/// Example
+///
+/// ```rust
+/// use serde_klv::{encode_length, parse_length, LengthForm};
+///
+/// let mut buf = vec![];
+/// encode_length(&mut buf, 70000, LengthForm::Compact).unwrap();
+/// assert_eq!(buf, [0x83, 0x01, 0x11, 0x70]);
+/// assert_eq!(parse_length(&buf).unwrap(), (4, 70000));
+///
+/// let mut buf = vec![];
+/// encode_length(&mut buf, 70000, LengthForm::Minimal).unwrap();
+/// assert_eq!(buf, [0x84, 0, 0x01, 0x11, 0x70]);
+/// assert!(encode_length(&mut buf, 70000, LengthForm::ForceLong(2)).is_err());
+/// ```
+pub fn encode_length(
+    buf: &mut dyn std::io::Write,
+    len: usize,
+    policy: LengthForm,
+) -> Result<usize, error::Error> {
+    // 長形式の制約はio::Errorにせずそのまま返す
+    if let LengthForm::ForceLong(x) = policy {
+        LengthOctet::check_long(len, x).map_err(error::Error::UnsupportedLength)?;
+    }
+    match policy {
+        LengthForm::Minimal => LengthOctet::length_to_buf(buf, len),
+        // uint24に収まる長さのみ3byteの長形式で書く
+        LengthForm::Compact if len > u16::MAX as usize && len < 1 << 24 => {
+            LengthOctet::long_to_buf(buf, len, 3)
+        }
+        LengthForm::Compact => LengthOctet::length_to_buf(buf, len),
+        LengthForm::ForceLong(x) => LengthOctet::long_to_buf(buf, len, x),
+    }
+    .map_err(error::Error::IO)
+}
+
 /// LengthはBERの仕様に従う
 #[derive(Debug, PartialEq, Eq)]
 enum LengthOctet {
@@ -136,6 +311,41 @@ impl LengthOctet {
         }
     }
 
+    /// number of bytes written by `length_to_buf`
+    pub fn encoded_len(size: usize) -> usize {
+        size::ber_len(size)
+    }
+
+    /// check the length can be written by long form with the number of subsequent octets
+    pub fn check_long(size: usize, octets: u8) -> Result<(), LengthError> {
+        if !matches!(octets, 1 | 2 | 3 | 4 | 8) {
+            return Err(LengthError::UnsupportedOctets(octets));
+        }
+        if octets < 8 && size as u64 >= 1 << (8 * octets as u32) {
+            return Err(LengthError::Overflow {
+                length: size,
+                octets,
+            });
+        }
+        Ok(())
+    }
+
+    /// write length by long form with the number of subsequent octets
+    pub fn long_to_buf(
+        buf: &mut dyn std::io::Write,
+        size: usize,
+        octets: u8,
+    ) -> std::io::Result<usize> {
+        use byteorder::BigEndian;
+        // 詳細はcheck_longで得る。ここではメッセージを作らない
+        Self::check_long(size, octets)
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        let mut r = [0_u8; 9];
+        r[0] = Self::FIRST_BIT | octets;
+        BigEndian::write_uint(&mut r[1..], size as u64, octets as usize);
+        buf.write(&r[..1 + octets as usize])
+    }
+
     pub fn length_to_buf(buf: &mut dyn std::io::Write, size: usize) -> std::io::Result<usize> {
         use byteorder::BigEndian;
         if size <= 127 {
@@ -146,20 +356,25 @@ impl LengthOctet {
             let mut r = [0b1000_0010, 0, 0];
             BigEndian::write_u16(&mut r[1..], size as u16);
             buf.write(&r)
-        } else {
+        } else if size as u64 <= u32::MAX as u64 {
             let mut r = [0b1000_0100, 0, 0, 0, 0];
             BigEndian::write_u32(&mut r[1..], size as u32);
             buf.write(&r)
+        } else {
+            // 4byteに収まらない長さは8byteの長形式で書く
+            let mut r = [0b1000_1000, 0, 0, 0, 0, 0, 0, 0, 0];
+            BigEndian::write_u64(&mut r[1..], size as u64);
+            buf.write(&r)
         }
     }
 }
 
-fn check_universal_key_len(name: &str) -> Result<usize, error::Error> {
-    match name.len() {
-        1 | 2 | 4 | 16 => Ok(name.len()),
+fn check_universal_key_len(key: &[u8]) -> Result<usize, error::Error> {
+    match key.len() {
+        1 | 2 | 4 | 16 => Ok(key.len()),
         _ => Err(error::Error::Key(format!(
-            "universal key support length only {{1,2,4,16}} got {}",
-            name
+            "universal key support length only {{1,2,4,16}} got {:02x?}",
+            key
         ))),
     }
 }
@@ -167,7 +382,8 @@ fn check_universal_key_len(name: &str) -> Result<usize, error::Error> {
 #[cfg(test)]
 mod tests {
 
-    use crate::{parse_length, LengthOctet};
+    use crate::error::LengthError;
+    use crate::{encode_length, parse_length, LengthForm, LengthOctet};
 
     #[test]
     fn test_length_octets() {
@@ -248,4 +464,101 @@ mod tests {
             verify_length(&buf, expected_length, expected_content_length);
         }
     }
+
+    #[test]
+    fn test_length_to_buf() {
+        let cases = [
+            (127, 1),
+            (128, 2),
+            (256, 3),
+            (65536, 5),
+            (u32::MAX as u64, 5),
+            (u32::MAX as u64 + 1, 9),
+            (u64::MAX >> 1, 9),
+        ];
+        for (size, expected_length) in cases {
+            // usizeに収まらない環境では試さない
+            let Ok(size) = usize::try_from(size) else {
+                continue;
+            };
+            let mut buf = vec![];
+            let n = LengthOctet::length_to_buf(&mut buf, size).unwrap();
+            assert_eq!(n, expected_length);
+            assert_eq!(LengthOctet::encoded_len(size), expected_length);
+            verify_length(&buf, expected_length, size);
+        }
+    }
+
+    #[test]
+    fn test_encode_length() {
+        let cases = [
+            (127, LengthForm::Compact, vec![127]),
+            (255, LengthForm::Compact, vec![0x81, 0xff]),
+            (65535, LengthForm::Compact, vec![0x82, 0xff, 0xff]),
+            (65536, LengthForm::Compact, vec![0x83, 1, 0, 0]),
+            (0xff_ffff, LengthForm::Compact, vec![0x83, 0xff, 0xff, 0xff]),
+            (0x100_0000, LengthForm::Compact, vec![0x84, 1, 0, 0, 0]),
+            (65536, LengthForm::Minimal, vec![0x84, 0, 1, 0, 0]),
+            (1, LengthForm::ForceLong(3), vec![0x83, 0, 0, 1]),
+            (
+                1,
+                LengthForm::ForceLong(8),
+                vec![0x88, 0, 0, 0, 0, 0, 0, 0, 1],
+            ),
+        ];
+        for (size, policy, expected) in cases {
+            let mut buf = vec![];
+            let n = encode_length(&mut buf, size, policy).unwrap();
+            assert_eq!(buf, expected, "{} {:?}", size, policy);
+            verify_length(&buf, n, size);
+        }
+
+        // 指定の形式に収まらない長さと未対応の形式
+        let mut buf = vec![];
+        for (size, policy, expected) in [
+            (
+                256,
+                LengthForm::ForceLong(1),
+                LengthError::Overflow {
+                    length: 256,
+                    octets: 1,
+                },
+            ),
+            (
+                1,
+                LengthForm::ForceLong(5),
+                LengthError::UnsupportedOctets(5),
+            ),
+        ] {
+            assert!(matches!(
+                encode_length(&mut buf, size, policy),
+                Err(crate::error::Error::UnsupportedLength(e)) if e == expected
+            ));
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_parse_length_error() {
+        let cases: [(&[u8], LengthError); 5] = [
+            (&[], LengthError::Empty),
+            (
+                &[0x82, 1],
+                LengthError::Truncated {
+                    expected: 3,
+                    actual: 2,
+                },
+            ),
+            (&[0x85, 0, 0, 0, 0, 1], LengthError::UnsupportedOctets(5)),
+            (&[0x80], LengthError::Indefinite),
+            (&[0xff], LengthError::Reserved),
+        ];
+        for (buf, expected) in cases {
+            assert_eq!(parse_length(buf), Err(expected), "{:02x?}", buf);
+        }
+        assert_eq!(
+            LengthError::UnsupportedOctets(5).to_string(),
+            "Unsupported length [5], supported only {1,2,3,4,8}"
+        );
+    }
 }
@@ -0,0 +1,691 @@
+//! Check of struct definitions without a value
+//!
+//! [`validate_type`] runs `Deserialize` of the type against a probe instead of a packet,
+//! and reports the definitions that fail on every packet, such as a field without a tag number.
+//! Call it from a test of the downstream crate to find misconfigured structs in CI.
+//!
+//! - universal key of the packet struct is not 1, 2, 4 or 16 bytes
+//! - field name is not a tag of the key width, nor a name of [`DeserializerConfig::tag_names`]
+//! - two fields of a local set have the same tag
+//! - type can not be encoded, such as a map or an enum in a local set
+//!
+//! [`validate_type`] checks with the default config, and [`validate_type_with`] with the given one.
+//! The probe gives zero or empty values to the fields.
+//! A `Deserialize` implementation rejecting them stops the check at the field,
+//! and the issues found until then are returned.
+//! A struct nested in itself, or deeper than [`DeserializerConfig::max_depth`],
+//! is not checked again.
+//!
+//! Example
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//!
+//! use serde::Deserialize;
+//! use serde_klv::lint::{validate_type, SchemaIssue};
+//!
+//! #[derive(Deserialize)]
+//! #[serde(rename = "TEST")]
+//! struct Valid<'a> {
+//!     #[serde(rename = "10")]
+//!     u8: u8,
+//!     #[serde(rename = "11", borrow)]
+//!     name: Option<&'a str>,
+//! }
+//! assert_eq!(validate_type::<Valid>(), Ok(()));
+//!
+//! #[derive(Deserialize)]
+//! #[serde(rename = "TEST")]
+//! struct Invalid {
+//!     #[serde(rename = "10")]
+//!     u8: u8,
+//!     speed: u16,
+//!     #[serde(rename = "12")]
+//!     names: HashMap<String, u8>,
+//! }
+//! assert_eq!(
+//!     validate_type::<Invalid>(),
+//!     Err(vec![
+//!         SchemaIssue::InvalidTag { path: vec![], field: "speed" },
+//!         SchemaIssue::UnsupportedType { path: vec![12], ty: "map" },
+//!     ])
+//! );
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+
+use serde::de::value::BorrowedStrDeserializer;
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+
+use crate::check_universal_key_len;
+use crate::error::{Error, Result, TagPath};
+use crate::placeholder::Placeholder;
+use crate::repeated::REPEATED;
+use crate::unknown::UNKNOWN_TAGS;
+use crate::DeserializerConfig;
+
+/// Problem of a struct definition found by [`validate_type`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaIssue {
+    /// universal key of the packet is not 1, 2, 4 or 16 bytes
+    InvalidUniversalKey { name: &'static str },
+    /// field name of the local set at `path` is not a tag number
+    InvalidTag {
+        path: Vec<u128>,
+        field: &'static str,
+    },
+    /// two fields of the local set have the same tag
+    DuplicateTag {
+        path: Vec<u128>,
+        first: &'static str,
+        second: &'static str,
+    },
+    /// type of the value at `path` can not be encoded
+    UnsupportedType { path: Vec<u128>, ty: &'static str },
+}
+
+impl Display for SchemaIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaIssue::InvalidUniversalKey { name } => write!(
+                f,
+                "universal key {:02x?} is not 1, 2, 4 or 16 bytes",
+                name.as_bytes()
+            ),
+            SchemaIssue::InvalidTag { path, field } if path.is_empty() => {
+                write!(f, "field {:?} is not a tag number", field)
+            }
+            SchemaIssue::InvalidTag { path, field } => write!(
+                f,
+                "field {:?} in tag {} is not a tag number",
+                field,
+                TagPath(path)
+            ),
+            SchemaIssue::DuplicateTag {
+                path,
+                first,
+                second,
+            } => write!(
+                f,
+                "tag {} is used by {:?} and {:?}",
+                TagPath(path),
+                first,
+                second
+            ),
+            SchemaIssue::UnsupportedType { path, ty } if path.is_empty() => {
+                write!(f, "packet of {} is not supported", ty)
+            }
+            SchemaIssue::UnsupportedType { path, ty } => {
+                write!(f, "{} of tag {} is not supported", ty, TagPath(path))
+            }
+        }
+    }
+}
+
+/// Check the definition of the packet type without a value
+///
+/// Returns the issues in order of the fields, nested ones follow their parent.
+pub fn validate_type<'de, T: Deserialize<'de>>() -> std::result::Result<(), Vec<SchemaIssue>> {
+    validate_type_with::<T>(&DeserializerConfig::default())
+}
+
+/// Check the definition of the packet type for the key width and tag names of the config
+///
+/// Example
+///
+/// ```rust
+/// use serde::Deserialize;
+/// use serde_klv::lint::validate_type_with;
+/// use serde_klv::{DeserializerConfig, KeyWidth};
+///
+/// #[derive(Deserialize)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "300")]
+///     u8: u8,
+///     #[serde(rename = "altitude")]
+///     altitude: u16,
+/// }
+///
+/// let config = DeserializerConfig {
+///     key_width: KeyWidth::U16,
+///     ..Default::default()
+/// }
+/// .tag_names(&[("altitude", 301)])
+/// .unwrap();
+/// assert_eq!(validate_type_with::<Packet>(&config), Ok(()));
+/// ```
+pub fn validate_type_with<'de, T: Deserialize<'de>>(
+    config: &DeserializerConfig,
+) -> std::result::Result<(), Vec<SchemaIssue>> {
+    let mut issues = vec![];
+    // 走査を止めたエラーは報告済みか、型の実装によるもの
+    let _ = T::deserialize(Probe {
+        issues: &mut issues,
+        config,
+        path: vec![],
+        structs: vec![],
+        depth: 0,
+        field: "",
+    });
+    if issues.is_empty() {
+        Ok(())
+    } else {
+        Err(issues)
+    }
+}
+
+// 型の要求に応じて0や空の値を返し、定義の問題を記録する
+struct Probe<'a> {
+    issues: &'a mut Vec<SchemaIssue>,
+    config: &'a DeserializerConfig<'a>,
+    // 値を読んでいるフィールドのタグ
+    path: Vec<u128>,
+    // 読んでいる構造体の名前。自身を含む構造体の再帰を止める
+    structs: Vec<&'static str>,
+    // 0はパケット
+    depth: usize,
+    field: &'static str,
+}
+
+impl<'a> Probe<'a> {
+    fn reborrow(&mut self, path: Vec<u128>, depth: usize, field: &'static str) -> Probe<'_> {
+        Probe {
+            issues: &mut *self.issues,
+            config: self.config,
+            path,
+            structs: self.structs.clone(),
+            depth,
+            field,
+        }
+    }
+
+    // 名前の表と設定されたKeyの幅でタグを求める
+    fn tag(&self, field: &str) -> Option<u128> {
+        self.config
+            .key_width
+            .parse_named(self.config.tag_names, field)
+    }
+
+    fn unsupported(&mut self, ty: &'static str) {
+        self.issues.push(SchemaIssue::UnsupportedType {
+            path: self.path.clone(),
+            ty,
+        });
+    }
+
+    // パケットは構造体でなければならない
+    fn value(&mut self, ty: &'static str) -> Result<()> {
+        if self.depth == 0 {
+            self.unsupported(ty);
+            return Err(abort());
+        }
+        Ok(())
+    }
+
+    fn check_fields(&mut self, fields: &'static [&'static str]) {
+        let mut tags = BTreeMap::new();
+        for field in fields.iter().copied().filter(|x| *x != UNKNOWN_TAGS) {
+            let tag = match self.tag(field) {
+                Some(tag) => tag,
+                None => {
+                    self.issues.push(SchemaIssue::InvalidTag {
+                        path: self.path.clone(),
+                        field,
+                    });
+                    continue;
+                }
+            };
+            if let Some(first) = tags.insert(tag, field) {
+                self.issues.push(SchemaIssue::DuplicateTag {
+                    path: [self.path.as_slice(), &[tag]].concat(),
+                    first,
+                    second: field,
+                });
+            }
+        }
+    }
+}
+
+const NO_FIELDS: &[&str] = &[];
+
+fn abort() -> Error {
+    Error::Unsupported("type can not be validated further".to_string())
+}
+
+macro_rules! probe_value {
+    ($($method:ident => $visit:ident($value:expr), $ty:expr;)*) => {
+        $(
+            fn $method<V>(mut self, visitor: V) -> Result<V::Value>
+            where
+                V: Visitor<'de>,
+            {
+                self.value($ty)?;
+                visitor.$visit($value)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Probe<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // Local Set内では長さから型を決めて読める
+        self.value("self-describing type")?;
+        visitor.visit_unit()
+    }
+
+    probe_value! {
+        deserialize_bool => visit_bool(false), "bool";
+        deserialize_i8 => visit_i64(0), "i8";
+        deserialize_i16 => visit_i64(0), "i16";
+        deserialize_i32 => visit_i64(0), "i32";
+        deserialize_i64 => visit_i64(0), "i64";
+        deserialize_i128 => visit_i128(0), "i128";
+        deserialize_u8 => visit_u64(0), "u8";
+        deserialize_u16 => visit_u64(0), "u16";
+        deserialize_u32 => visit_u64(0), "u32";
+        deserialize_u64 => visit_u64(0), "u64";
+        deserialize_u128 => visit_u128(0), "u128";
+        deserialize_f32 => visit_f64(0.0), "f32";
+        deserialize_f64 => visit_f64(0.0), "f64";
+        deserialize_char => visit_char('\0'), "char";
+        deserialize_str => visit_borrowed_str(""), "str";
+        deserialize_string => visit_borrowed_str(""), "string";
+        deserialize_bytes => visit_borrowed_bytes(&[]), "bytes";
+        deserialize_byte_buf => visit_borrowed_bytes(&[]), "bytes";
+        deserialize_unit => visit_unit(), "unit";
+        deserialize_identifier => visit_borrowed_str(""), "identifier";
+        deserialize_ignored_any => visit_unit(), "ignored value";
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit_struct<V>(mut self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.value("unit struct")?;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // 繰り返しフィールドは列として読む
+        if name == REPEATED {
+            return self.deserialize_seq(visitor);
+        }
+        visitor.visit_newtype_struct(self)
+    }
+
+    // TopLevelの列はパケットの列として要素を調べる
+    fn deserialize_seq<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Elements {
+            probe: &mut self,
+            remaining: 1,
+        })
+    }
+
+    fn deserialize_tuple<V>(mut self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(Elements {
+            probe: &mut self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V>(mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // UnknownTagsのみmapとして読める
+        if self.depth == 0 || self.field != UNKNOWN_TAGS {
+            self.unsupported("map");
+        }
+        visitor.visit_map(Fields {
+            probe: &mut self,
+            fields: NO_FIELDS.iter(),
+            current: "",
+        })
+    }
+
+    fn deserialize_struct<V>(
+        mut self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // 確認済みの構造体は仮の値で埋める
+        if self.structs.contains(&name) || self.structs.len() >= self.config.max_depth {
+            return de::Deserializer::deserialize_struct(Placeholder, name, fields, visitor);
+        }
+        self.structs.push(name);
+        if self.depth == 0 && check_universal_key_len(name.as_bytes()).is_err() {
+            self.issues.push(SchemaIssue::InvalidUniversalKey { name });
+        }
+        self.check_fields(fields);
+        visitor.visit_map(Fields {
+            probe: &mut self,
+            fields: fields.iter(),
+            current: "",
+        })
+    }
+
+    fn deserialize_enum<V>(
+        mut self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // TopLevelのenumはvariant名をUniversalKeyとする
+        if self.depth != 0 {
+            self.unsupported("enum");
+        }
+        for name in variants.iter().copied() {
+            if check_universal_key_len(name.as_bytes()).is_err() {
+                self.issues.push(SchemaIssue::InvalidUniversalKey { name });
+            }
+        }
+        Err(abort())
+    }
+}
+
+// 構造体のフィールドを順に渡す
+struct Fields<'a, 'b> {
+    probe: &'b mut Probe<'a>,
+    fields: std::slice::Iter<'static, &'static str>,
+    current: &'static str,
+}
+
+impl<'de, 'a, 'b> MapAccess<'de> for Fields<'a, 'b> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.fields.next() {
+            Some(&field) => {
+                self.current = field;
+                seed.deserialize(BorrowedStrDeserializer::new(field))
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let mut path = self.probe.path.clone();
+        // タグでないフィールドは親のパスで報告する
+        path.extend(self.probe.tag(self.current));
+        let depth = self.probe.depth + 1;
+        seed.deserialize(self.probe.reborrow(path, depth, self.current))
+    }
+}
+
+// 列の要素を決めた数だけ渡す
+struct Elements<'a, 'b> {
+    probe: &'b mut Probe<'a>,
+    remaining: usize,
+}
+
+impl<'de, 'a, 'b> SeqAccess<'de> for Elements<'a, 'b> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let (path, depth, field) = (self.probe.path.clone(), self.probe.depth, self.probe.field);
+        seed.deserialize(self.probe.reborrow(path, depth, field))
+            .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{validate_type, validate_type_with, SchemaIssue};
+    use crate::unknown::UnknownTags;
+    use crate::{DeserializerConfig, KeyWidth};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[serde(rename = "TESTDATA00000000")]
+    struct TestValid<'a> {
+        #[serde(rename = "10")]
+        u8: u8,
+        #[serde(rename = "11")]
+        str: &'a str,
+        #[serde(rename = "12")]
+        bytes: Vec<u8>,
+        #[serde(rename = "13")]
+        child: Option<TestChild>,
+        #[serde(
+            rename = "14",
+            with = "crate::repeated",
+            default,
+            skip_serializing_if = "Vec::is_empty"
+        )]
+        children: Vec<TestChild>,
+        #[serde(rename = "15")]
+        pair: (u16, i32),
+        #[serde(rename = "$unknown_tags", default)]
+        unknown: UnknownTags,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TestChild {
+        #[serde(rename = "20")]
+        f64: f64,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    enum TestMode {
+        A,
+        B,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[serde(rename = "BAD")]
+    struct TestInvalid {
+        #[serde(rename = "10")]
+        u8: u8,
+        #[serde(rename = "010")]
+        u16: u16,
+        #[serde(rename = "256")]
+        large: u8,
+        #[serde(rename = "11")]
+        child: TestInvalidChild,
+        #[serde(rename = "12")]
+        map: HashMap<String, u8>,
+        #[serde(rename = "13")]
+        mode: TestMode,
+        // enumで走査が止まる
+        name: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TestInvalidChild {
+        #[serde(rename = "20")]
+        u8: u8,
+        #[serde(rename = "020")]
+        u16: u16,
+        tag: u8,
+    }
+
+    #[test]
+    fn test_validate_type() {
+        assert_eq!(validate_type::<TestValid>(), Ok(()));
+        assert_eq!(validate_type::<Vec<TestValid>>(), Ok(()));
+
+        let issues = validate_type::<TestInvalid>().unwrap_err();
+        assert_eq!(
+            issues,
+            vec![
+                SchemaIssue::InvalidUniversalKey { name: "BAD" },
+                SchemaIssue::DuplicateTag {
+                    path: vec![10],
+                    first: "10",
+                    second: "010"
+                },
+                SchemaIssue::InvalidTag {
+                    path: vec![],
+                    field: "256"
+                },
+                SchemaIssue::InvalidTag {
+                    path: vec![],
+                    field: "name"
+                },
+                SchemaIssue::DuplicateTag {
+                    path: vec![11, 20],
+                    first: "20",
+                    second: "020"
+                },
+                SchemaIssue::InvalidTag {
+                    path: vec![11],
+                    field: "tag"
+                },
+                SchemaIssue::UnsupportedType {
+                    path: vec![12],
+                    ty: "map"
+                },
+                SchemaIssue::UnsupportedType {
+                    path: vec![13],
+                    ty: "enum"
+                },
+            ]
+        );
+        assert_eq!(
+            issues[1].to_string(),
+            "tag 10 is used by \"10\" and \"010\""
+        );
+        assert_eq!(
+            issues[5].to_string(),
+            "field \"tag\" in tag 11 is not a tag number"
+        );
+
+        // パケットは構造体でなければならない
+        assert_eq!(
+            validate_type::<u8>(),
+            Err(vec![SchemaIssue::UnsupportedType {
+                path: vec![],
+                ty: "u8"
+            }])
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[serde(rename = "TEST")]
+    struct TestRecursive {
+        #[serde(rename = "10")]
+        u8: u8,
+        #[serde(rename = "11")]
+        child: Option<Box<TestRecursive>>,
+        #[serde(rename = "12", with = "crate::repeated", default)]
+        nodes: Vec<TestNode>,
+        name: String,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TestNode {
+        #[serde(rename = "20", with = "crate::repeated", default)]
+        nodes: Vec<TestNode>,
+        tag: u8,
+    }
+
+    #[test]
+    fn test_validate_recursive_type() {
+        // 自身を含む構造体は一度だけ調べ、後のフィールドも続けて調べる
+        assert_eq!(
+            validate_type::<TestRecursive>(),
+            Err(vec![
+                SchemaIssue::InvalidTag {
+                    path: vec![],
+                    field: "name"
+                },
+                SchemaIssue::InvalidTag {
+                    path: vec![12],
+                    field: "tag"
+                },
+            ])
+        );
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    #[serde(rename = "TEST")]
+    struct TestNamed {
+        #[serde(rename = "300")]
+        u8: u8,
+        #[serde(rename = "altitude")]
+        altitude: u16,
+        #[serde(rename = "speed")]
+        speed: u8,
+    }
+
+    #[test]
+    fn test_validate_type_with() {
+        // 既定の設定では名前もU8に収まらないタグも読めない
+        assert_eq!(validate_type::<TestNamed>().unwrap_err().len(), 3);
+
+        let config = DeserializerConfig {
+            key_width: KeyWidth::U16,
+            ..Default::default()
+        }
+        .tag_names(&[("altitude", 300), ("speed", 301)])
+        .unwrap();
+        assert_eq!(
+            validate_type_with::<TestNamed>(&config),
+            Err(vec![SchemaIssue::DuplicateTag {
+                path: vec![300],
+                first: "300",
+                second: "altitude"
+            }])
+        );
+    }
+}
@@ -0,0 +1,432 @@
+//! Carriage of KLV packets in MPEG-2 TS by MISB ST 1402
+//!
+//! - Synchronous: packets wrapped in Metadata AU cells of a metadata stream PES
+//!   (`stream_id` 0xFC) with the PTS of the video frame.
+//! - Asynchronous: packets as the payload of a private stream 1 PES
+//!   (`stream_id` 0xBD) without PTS.
+//!
+//! PES packets are written with `data_alignment_indicator`,
+//! so the payload starts with an AU cell or a KLV packet.
+//! [`wrap_ts`] splits a PES into 188-byte TS packets of a PID,
+//! stuffing the adaptation field of the last packet to fill it.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::mpegts::{unwrap_async, unwrap_sync, wrap_async, wrap_sync, AuCellHeader};
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "10")]
+//!     u8: u8,
+//! }
+//!
+//! let packet = to_bytes(&Packet { u8: 1 }).unwrap();
+//! let pes = wrap_sync(&AuCellHeader::new(0, 7), 900_000, &packet).unwrap();
+//! let (pts, cells) = unwrap_sync(&pes).unwrap();
+//! assert_eq!(pts, 900_000);
+//! assert_eq!(cells[0].0.sequence_number, 7);
+//! assert_eq!(from_bytes::<Packet>(cells[0].1).unwrap(), Packet { u8: 1 });
+//!
+//! let pes = wrap_async(&packet).unwrap();
+//! assert_eq!(unwrap_async(&pes).unwrap(), &packet[..]);
+//! ```
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::error::{Error, Result};
+
+/// `stream_id` of metadata stream for synchronous carriage
+pub const METADATA_STREAM_ID: u8 = 0xFC;
+/// `stream_id` of private stream 1 for asynchronous carriage
+pub const PRIVATE_STREAM_1_ID: u8 = 0xBD;
+
+const START_CODE: [u8; 3] = [0x00, 0x00, 0x01];
+
+/// byte size of a TS packet
+pub const TS_PACKET_LEN: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+const TS_HEADER_LEN: usize = 4;
+const TS_PAYLOAD_LEN: usize = TS_PACKET_LEN - TS_HEADER_LEN;
+
+/// Position of the cell in the access unit, `cell_fragment_indication`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellFragment {
+    /// the cell holds whole of the packet
+    Complete,
+    First,
+    Middle,
+    Last,
+}
+
+impl CellFragment {
+    fn to_bits(self) -> u8 {
+        match self {
+            CellFragment::Complete => 0b11,
+            CellFragment::First => 0b10,
+            CellFragment::Last => 0b01,
+            CellFragment::Middle => 0b00,
+        }
+    }
+    fn from_bits(b: u8) -> Self {
+        match b & 0b11 {
+            0b11 => CellFragment::Complete,
+            0b10 => CellFragment::First,
+            0b01 => CellFragment::Last,
+            _ => CellFragment::Middle,
+        }
+    }
+}
+
+/// Header of Metadata AU cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuCellHeader {
+    /// `metadata_service_id` declared in the metadata descriptor
+    pub service_id: u8,
+    /// incremented by one for each cell of the service, modulo 256
+    pub sequence_number: u8,
+    pub fragment: CellFragment,
+    pub decoder_config: bool,
+    pub random_access: bool,
+}
+
+impl AuCellHeader {
+    /// byte size of the header
+    pub const LEN: usize = 5;
+
+    /// header of a complete cell with random access point
+    pub fn new(service_id: u8, sequence_number: u8) -> Self {
+        Self {
+            service_id,
+            sequence_number,
+            fragment: CellFragment::Complete,
+            decoder_config: false,
+            random_access: true,
+        }
+    }
+}
+
+/// Metadata AU cell holding the packet
+pub fn wrap_au_cell(header: &AuCellHeader, packet: &[u8]) -> Result<Vec<u8>> {
+    let len = u16::try_from(packet.len()).map_err(|_| {
+        Error::Encode(format!(
+            "AU cell data length {} exceeds {}",
+            packet.len(),
+            u16::MAX
+        ))
+    })?;
+    let mut buf = Vec::with_capacity(AuCellHeader::LEN + packet.len());
+    // 下位4bitはreserved
+    let flags = (header.fragment.to_bits() << 6)
+        | ((header.decoder_config as u8) << 5)
+        | ((header.random_access as u8) << 4)
+        | 0x0f;
+    buf.extend_from_slice(&[header.service_id, header.sequence_number, flags]);
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(packet);
+    Ok(buf)
+}
+
+/// Split consecutive Metadata AU cells into headers and data
+pub fn unwrap_au_cells(buf: &[u8]) -> Result<Vec<(AuCellHeader, &[u8])>> {
+    let mut cells = vec![];
+    let mut rest = buf;
+    while !rest.is_empty() {
+        if rest.len() < AuCellHeader::LEN {
+            return Err(Error::ContentLenght);
+        }
+        let len = BigEndian::read_u16(&rest[3..5]) as usize;
+        let end = AuCellHeader::LEN + len;
+        if rest.len() < end {
+            return Err(Error::ContentLenght);
+        }
+        let header = AuCellHeader {
+            service_id: rest[0],
+            sequence_number: rest[1],
+            fragment: CellFragment::from_bits(rest[2] >> 6),
+            decoder_config: rest[2] & 0x20 != 0,
+            random_access: rest[2] & 0x10 != 0,
+        };
+        cells.push((header, &rest[AuCellHeader::LEN..end]));
+        rest = &rest[end..];
+    }
+    Ok(cells)
+}
+
+/// PES packet parsed by [`unwrap_pes`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pes<'a> {
+    pub stream_id: u8,
+    /// 33bit PTS in 90kHz clock
+    pub pts: Option<u64>,
+    pub payload: &'a [u8],
+}
+
+/// PES packet with optional PTS
+pub fn wrap_pes(stream_id: u8, pts: Option<u64>, payload: &[u8]) -> Result<Vec<u8>> {
+    let header_data_len = if pts.is_some() { 5 } else { 0 };
+    // PES_packet_lengthはこのフィールドより後ろのbyte数
+    let packet_len = 3 + header_data_len + payload.len();
+    let packet_len = u16::try_from(packet_len).map_err(|_| {
+        Error::Encode(format!(
+            "PES packet length {} exceeds {}",
+            packet_len,
+            u16::MAX
+        ))
+    })?;
+    let mut buf = Vec::with_capacity(6 + packet_len as usize);
+    buf.extend_from_slice(&START_CODE);
+    buf.push(stream_id);
+    buf.extend_from_slice(&packet_len.to_be_bytes());
+    // '10' + data_alignment_indicator
+    buf.push(0x84);
+    buf.push(if pts.is_some() { 0x80 } else { 0x00 });
+    buf.push(header_data_len as u8);
+    if let Some(pts) = pts {
+        let pts = pts & 0x1_ffff_ffff;
+        buf.extend_from_slice(&[
+            0x21 | ((pts >> 29) & 0x0e) as u8,
+            (pts >> 22) as u8,
+            ((pts >> 14) & 0xfe) as u8 | 1,
+            (pts >> 7) as u8,
+            ((pts << 1) & 0xfe) as u8 | 1,
+        ]);
+    }
+    buf.extend_from_slice(payload);
+    Ok(buf)
+}
+
+/// Parse PES packet and return its payload
+///
+/// Trailing bytes after `PES_packet_length` are ignored.
+/// `PES_packet_length` of 0 takes the rest of the input as the payload.
+pub fn unwrap_pes(buf: &[u8]) -> Result<Pes<'_>> {
+    if buf.len() < 9 {
+        return Err(Error::ContentLenght);
+    }
+    if buf[..3] != START_CODE {
+        return Err(Error::Key(format!(
+            "PES start code is unmatched get {:02x?}",
+            &buf[..3]
+        )));
+    }
+    let stream_id = buf[3];
+    let packet_len = BigEndian::read_u16(&buf[4..6]) as usize;
+    let end = if packet_len == 0 {
+        buf.len()
+    } else {
+        6 + packet_len
+    };
+    let header_end = 9 + buf[8] as usize;
+    if buf.len() < end || end < header_end {
+        return Err(Error::ContentLenght);
+    }
+    let pts = if buf[7] & 0x80 != 0 {
+        if header_end < 14 {
+            return Err(Error::ContentLenght);
+        }
+        let p = &buf[9..14];
+        Some(
+            ((p[0] as u64 >> 1) & 0x07) << 30
+                | (p[1] as u64) << 22
+                | (p[2] as u64 >> 1) << 15
+                | (p[3] as u64) << 7
+                | (p[4] as u64 >> 1),
+        )
+    } else {
+        None
+    };
+    Ok(Pes {
+        stream_id,
+        pts,
+        payload: &buf[header_end..end],
+    })
+}
+
+/// Metadata stream PES of synchronous carriage holding the packet in one AU cell
+pub fn wrap_sync(header: &AuCellHeader, pts: u64, packet: &[u8]) -> Result<Vec<u8>> {
+    wrap_pes(
+        METADATA_STREAM_ID,
+        Some(pts),
+        &wrap_au_cell(header, packet)?,
+    )
+}
+
+/// PTS and AU cells of a synchronous carriage PES
+pub fn unwrap_sync(buf: &[u8]) -> Result<(u64, Vec<(AuCellHeader, &[u8])>)> {
+    let pes = unwrap_pes(buf)?;
+    if pes.stream_id != METADATA_STREAM_ID {
+        return Err(Error::Key(format!(
+            "stream_id is unmatched get {:#04x}, expect {:#04x}",
+            pes.stream_id, METADATA_STREAM_ID
+        )));
+    }
+    let pts = pes.pts.ok_or(Error::ContentLenght)?;
+    Ok((pts, unwrap_au_cells(pes.payload)?))
+}
+
+/// Private stream 1 PES of asynchronous carriage
+pub fn wrap_async(packet: &[u8]) -> Result<Vec<u8>> {
+    wrap_pes(PRIVATE_STREAM_1_ID, None, packet)
+}
+
+/// KLV bytes of an asynchronous carriage PES
+pub fn unwrap_async(buf: &[u8]) -> Result<&[u8]> {
+    let pes = unwrap_pes(buf)?;
+    if pes.stream_id != PRIVATE_STREAM_1_ID {
+        return Err(Error::Key(format!(
+            "stream_id is unmatched get {:#04x}, expect {:#04x}",
+            pes.stream_id, PRIVATE_STREAM_1_ID
+        )));
+    }
+    Ok(pes.payload)
+}
+
+/// Split the PES into TS packets of the PID
+///
+/// The first packet has `payload_unit_start_indicator`.
+/// The last packet is filled to 188 bytes by stuffing bytes of the adaptation field.
+/// `continuity_counter` is the counter of the first packet
+/// and is updated to the counter of the next packet of the PID.
+pub fn wrap_ts(pid: u16, continuity_counter: &mut u8, pes: &[u8]) -> Result<Vec<u8>> {
+    if pid > 0x1fff {
+        return Err(Error::Encode(format!("PID {:#x} exceeds 13 bits", pid)));
+    }
+    let count = (pes.len() + TS_PAYLOAD_LEN - 1) / TS_PAYLOAD_LEN;
+    let mut buf = Vec::with_capacity(count * TS_PACKET_LEN);
+    for (i, chunk) in pes.chunks(TS_PAYLOAD_LEN).enumerate() {
+        let start = if i == 0 { 0x40 } else { 0x00 };
+        let stuffing = TS_PAYLOAD_LEN - chunk.len();
+        // adaptation_field_control: 01 payloadのみ, 11 adaptation fieldとpayload
+        let control = if stuffing > 0 { 0x30 } else { 0x10 };
+        buf.extend_from_slice(&[
+            TS_SYNC_BYTE,
+            start | (pid >> 8) as u8,
+            pid as u8,
+            control | (*continuity_counter & 0x0f),
+        ]);
+        *continuity_counter = (*continuity_counter + 1) & 0x0f;
+        if stuffing > 0 {
+            // adaptation_field_lengthの1byteのみ、またはflagsと0xffの詰め物を続ける
+            buf.push((stuffing - 1) as u8);
+            if stuffing > 1 {
+                buf.push(0x00);
+                buf.resize(buf.len() + stuffing - 2, 0xff);
+            }
+        }
+        buf.extend_from_slice(chunk);
+    }
+    Ok(buf)
+}
+
+/// Concatenate the payloads of TS packets of the PID
+///
+/// Packets of other PIDs are skipped and adaptation fields are removed.
+pub fn unwrap_ts(pid: u16, buf: &[u8]) -> Result<Vec<u8>> {
+    if buf.len() % TS_PACKET_LEN != 0 {
+        return Err(Error::ContentLenght);
+    }
+    let mut payload = vec![];
+    for packet in buf.chunks(TS_PACKET_LEN) {
+        if packet[0] != TS_SYNC_BYTE {
+            return Err(Error::Key(format!(
+                "TS sync byte is unmatched get {:#04x}",
+                packet[0]
+            )));
+        }
+        if BigEndian::read_u16(&packet[1..3]) & 0x1fff != pid {
+            continue;
+        }
+        let control = (packet[3] >> 4) & 0b11;
+        let start = if control & 0b10 != 0 {
+            TS_HEADER_LEN + 1 + packet[TS_HEADER_LEN] as usize
+        } else {
+            TS_HEADER_LEN
+        };
+        if start > TS_PACKET_LEN {
+            return Err(Error::ContentLenght);
+        }
+        if control & 0b01 != 0 {
+            payload.extend_from_slice(&packet[start..]);
+        }
+    }
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        unwrap_async, unwrap_au_cells, unwrap_pes, unwrap_ts, wrap_async, wrap_au_cell, wrap_pes,
+        wrap_ts, AuCellHeader, CellFragment, TS_PACKET_LEN,
+    };
+
+    #[test]
+    fn test_pes_pts() {
+        for pts in [0, 1, 0x1234_5678, 0x1_ffff_ffff] {
+            let buf = wrap_pes(0xfc, Some(pts), &[1, 2, 3]).unwrap();
+            assert_eq!(buf.len(), 6 + 3 + 5 + 3);
+            // マーカービット
+            assert_eq!(buf[9] & 0xf1, 0x21);
+            assert_eq!(buf[11] & 1, 1);
+            assert_eq!(buf[13] & 1, 1);
+            let pes = unwrap_pes(&buf).unwrap();
+            assert_eq!(pes.pts, Some(pts));
+            assert_eq!(pes.payload, &[1, 2, 3]);
+        }
+        assert!(unwrap_pes(&[0, 0, 2, 0xbd, 0, 3, 0x84, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_au_cells() {
+        let mut header = AuCellHeader::new(1, 255);
+        header.fragment = CellFragment::First;
+        header.random_access = false;
+        let mut buf = wrap_au_cell(&header, &[1, 2]).unwrap();
+        assert_eq!(&buf, &[1, 255, 0b1000_1111, 0, 2, 1, 2]);
+        buf.extend(wrap_au_cell(&AuCellHeader::new(1, 0), &[3]).unwrap());
+        let cells = unwrap_au_cells(&buf).unwrap();
+        assert_eq!(cells.len(), 2);
+        assert_eq!(cells[0], (header, &[1_u8, 2][..]));
+        assert_eq!(cells[1].0.fragment, CellFragment::Complete);
+        assert_eq!(cells[1].1, &[3]);
+
+        // 途中で途切れたセル
+        assert!(unwrap_au_cells(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_ts_packets() {
+        // 最後のTSパケットのpayloadが184byte、183byte、1byteとなる場合
+        for len in [184 - 9, 184 * 2 - 9 - 1, 184 + 1 - 9] {
+            let packet = vec![0xa5; len];
+            let pes = wrap_async(&packet).unwrap();
+            let mut counter = 15;
+            let ts = wrap_ts(0x101, &mut counter, &pes).unwrap();
+            assert_eq!(ts.len() % TS_PACKET_LEN, 0, "{}", len);
+            let count = ts.len() / TS_PACKET_LEN;
+            assert_eq!(counter, (15 + count as u8) & 0x0f);
+            for (i, x) in ts.chunks(TS_PACKET_LEN).enumerate() {
+                assert_eq!(x[0], 0x47);
+                assert_eq!(x[1] & 0x40 != 0, i == 0);
+                assert_eq!(x[3] & 0x0f, (15 + i as u8) & 0x0f);
+            }
+            let unwrapped = unwrap_ts(0x101, &ts).unwrap();
+            assert_eq!(unwrapped, pes);
+            assert_eq!(unwrap_async(&unwrapped).unwrap(), &packet[..]);
+            // 他のPIDは読み飛ばす
+            assert!(unwrap_ts(0x102, &ts).unwrap().is_empty());
+        }
+
+        // 183byteの場合はadaptation_field_lengthの1byteのみ
+        let ts = wrap_ts(0x101, &mut 0, &[0; 183]).unwrap();
+        assert_eq!(&ts[3..5], &[0x30, 0]);
+        let ts = wrap_ts(0x101, &mut 0, &[0; 180]).unwrap();
+        assert_eq!(&ts[3..8], &[0x30, 3, 0, 0xff, 0xff]);
+
+        assert!(wrap_ts(0x2000, &mut 0, &[0]).is_err());
+        assert!(unwrap_ts(0x101, &[0x47; 187]).is_err());
+    }
+}
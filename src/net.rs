@@ -0,0 +1,321 @@
+//! KLV packets over UDP for ground-segment testing tools
+//!
+//! Each packet is split into datagrams fitting the MTU,
+//! and every datagram starts with the sequence header of 8 bytes.
+//!
+//! | bytes | field |
+//! |-------|-------|
+//! | 0..4  | sequence number of the packet, u32 big-endian |
+//! | 4..6  | index of the fragment, u16 big-endian |
+//! | 6..8  | number of the fragments, u16 big-endian |
+//!
+//! The receiver reassembles one packet at a time.
+//! A packet is dropped when a fragment is lost or a fragment of a newer packet arrives first,
+//! and fragments of older packets are ignored.
+//!
+//! Example
+//!
+//! ```rust
+//! use std::net::UdpSocket;
+//!
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::net::{KlvUdpReceiver, KlvUdpSender};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "10")]
+//!     name: String,
+//! }
+//!
+//! let rx = UdpSocket::bind("127.0.0.1:0").unwrap();
+//! let tx = UdpSocket::bind("127.0.0.1:0").unwrap();
+//! tx.connect(rx.local_addr().unwrap()).unwrap();
+//!
+//! let mut sender = KlvUdpSender::new(tx).with_mtu(64).unwrap();
+//! let mut receiver = KlvUdpReceiver::new(rx);
+//! let t = Packet { name: "x".repeat(200) };
+//! sender.send(&t).unwrap();
+//! assert_eq!(receiver.recv::<Packet>().unwrap(), t);
+//! ```
+
+use std::net::UdpSocket;
+
+use byteorder::{BigEndian, ByteOrder};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::{Error, Result};
+use crate::{from_bytes, to_bytes};
+
+/// byte size of the sequence header
+pub const HEADER_LEN: usize = 8;
+
+/// UDP payload size of Ethernet MTU 1500 over IPv4
+pub const DEFAULT_MTU: usize = 1472;
+
+// 受信バッファはUDPの最大長とする
+const MAX_DATAGRAM_LEN: usize = 65536;
+
+/// Send KLV packets to the connected socket
+#[derive(Debug)]
+pub struct KlvUdpSender {
+    socket: UdpSocket,
+    mtu: usize,
+    sequence: u32,
+}
+
+impl KlvUdpSender {
+    /// sender to the socket connected to the receiver, with [`DEFAULT_MTU`]
+    pub fn new(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            mtu: DEFAULT_MTU,
+            sequence: 0,
+        }
+    }
+
+    /// set the maximum datagram size including the header
+    pub fn with_mtu(mut self, mtu: usize) -> Result<Self> {
+        if mtu <= HEADER_LEN {
+            return Err(Error::Encode(format!(
+                "MTU {} does not exceed the header length {}",
+                mtu, HEADER_LEN
+            )));
+        }
+        self.mtu = mtu;
+        Ok(self)
+    }
+
+    /// serialize and send the packet, return its sequence number
+    pub fn send<T: Serialize>(&mut self, value: &T) -> Result<u32> {
+        self.send_packet(&to_bytes(value)?)
+    }
+
+    /// send the packet bytes, return its sequence number
+    pub fn send_packet(&mut self, packet: &[u8]) -> Result<u32> {
+        let sequence = self.sequence;
+        for datagram in fragment(sequence, packet, self.mtu)? {
+            self.socket.send(&datagram).map_err(Error::IO)?;
+        }
+        self.sequence = sequence.wrapping_add(1);
+        Ok(sequence)
+    }
+
+    /// unwrap inner socket
+    pub fn into_inner(self) -> UdpSocket {
+        self.socket
+    }
+}
+
+/// Receive KLV packets sent by [`KlvUdpSender`]
+#[derive(Debug)]
+pub struct KlvUdpReceiver {
+    socket: UdpSocket,
+    buf: Vec<u8>,
+    reassembler: Reassembler,
+}
+
+impl KlvUdpReceiver {
+    pub fn new(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            buf: vec![0; MAX_DATAGRAM_LEN],
+            reassembler: Reassembler::default(),
+        }
+    }
+
+    /// block until a packet is reassembled, return its sequence number and bytes
+    pub fn recv_packet(&mut self) -> Result<(u32, Vec<u8>)> {
+        loop {
+            let n = self.socket.recv(&mut self.buf).map_err(Error::IO)?;
+            if let Some(x) = self.reassembler.push(&self.buf[..n]) {
+                return Ok(x);
+            }
+        }
+    }
+
+    /// block until a packet is reassembled and deserialize
+    pub fn recv<T: DeserializeOwned>(&mut self) -> Result<T> {
+        let (_, packet) = self.recv_packet()?;
+        from_bytes(&packet)
+    }
+
+    /// number of packets dropped by lost or malformed datagrams
+    pub fn dropped(&self) -> u64 {
+        self.reassembler.dropped
+    }
+
+    /// unwrap inner socket
+    pub fn into_inner(self) -> UdpSocket {
+        self.socket
+    }
+}
+
+// パケットをヘッダ付きのデータグラムに分割する
+fn fragment(sequence: u32, packet: &[u8], mtu: usize) -> Result<Vec<Vec<u8>>> {
+    let payload_len = mtu - HEADER_LEN;
+    let count = (packet.len().max(1) + payload_len - 1) / payload_len;
+    let count = u16::try_from(count).map_err(|_| Error::PacketTooLarge {
+        size: packet.len(),
+        limit: payload_len * u16::MAX as usize,
+    })?;
+    let mut datagrams = Vec::with_capacity(count as usize);
+    for index in 0..count {
+        let start = index as usize * payload_len;
+        let end = (start + payload_len).min(packet.len());
+        let mut datagram = vec![0; HEADER_LEN];
+        BigEndian::write_u32(&mut datagram[0..4], sequence);
+        BigEndian::write_u16(&mut datagram[4..6], index);
+        BigEndian::write_u16(&mut datagram[6..8], count);
+        datagram.extend_from_slice(&packet[start..end]);
+        datagrams.push(datagram);
+    }
+    Ok(datagrams)
+}
+
+// 1パケット分の断片を集める
+#[derive(Debug, Default)]
+struct Reassembler {
+    // 最後に受け取ったパケットの番号
+    latest: Option<u32>,
+    // 組み立て中の断片。組み立て済みまたは破棄した場合は空
+    fragments: Vec<Option<Vec<u8>>>,
+    dropped: u64,
+}
+
+impl Reassembler {
+    // 全ての断片が揃ったらパケットを返す
+    fn push(&mut self, datagram: &[u8]) -> Option<(u32, Vec<u8>)> {
+        if datagram.len() < HEADER_LEN {
+            self.dropped += 1;
+            return None;
+        }
+        let sequence = BigEndian::read_u32(&datagram[0..4]);
+        let index = BigEndian::read_u16(&datagram[4..6]) as usize;
+        let count = BigEndian::read_u16(&datagram[6..8]) as usize;
+        if index >= count {
+            self.dropped += 1;
+            return None;
+        }
+        match self.latest {
+            Some(latest) if latest == sequence => {
+                // 組み立て済みのパケットの重複
+                if self.fragments.is_empty() {
+                    return None;
+                }
+                if self.fragments.len() != count {
+                    self.dropped += 1;
+                    self.fragments.clear();
+                    return None;
+                }
+            }
+            // 古いパケットの断片は無視する
+            Some(latest) if (sequence.wrapping_sub(latest) as i32) < 0 => return None,
+            _ => {
+                // 組み立て中のパケットは欠けたものとして捨てる
+                if !self.fragments.is_empty() {
+                    self.dropped += 1;
+                }
+                self.latest = Some(sequence);
+                self.fragments = vec![None; count];
+            }
+        }
+        self.fragments[index] = Some(datagram[HEADER_LEN..].to_vec());
+        if self.fragments.iter().any(|x| x.is_none()) {
+            return None;
+        }
+        let packet = std::mem::take(&mut self.fragments)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect();
+        Some((sequence, packet))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{fragment, KlvUdpReceiver, KlvUdpSender, Reassembler, HEADER_LEN};
+    use crate::to_bytes;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestNet {
+        #[serde(rename = "10")]
+        u16: u16,
+        #[serde(rename = "11")]
+        str: String,
+    }
+
+    #[test]
+    fn test_fragment() {
+        let packet = (0..=255).collect::<Vec<u8>>();
+        let datagrams = fragment(7, &packet, 108).unwrap();
+        assert_eq!(datagrams.len(), 3);
+        assert_eq!(&datagrams[2][..HEADER_LEN], &[0, 0, 0, 7, 0, 2, 0, 3]);
+        assert_eq!(datagrams[2].len(), HEADER_LEN + 56);
+
+        // 順序が入れ替わっても組み立てられる
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(&datagrams[2]), None);
+        assert_eq!(reassembler.push(&datagrams[0]), None);
+        assert_eq!(reassembler.push(&datagrams[1]), Some((7, packet.clone())));
+
+        // 欠けたパケットは次のパケットの断片で捨てる
+        let next = fragment(8, &packet, 108).unwrap();
+        assert_eq!(reassembler.push(&next[0]), None);
+        let newer = fragment(9, &packet[..10], 108).unwrap();
+        assert_eq!(newer.len(), 1);
+        assert_eq!(
+            reassembler.push(&newer[0]),
+            Some((9, packet[..10].to_vec()))
+        );
+        assert_eq!(reassembler.dropped, 1);
+
+        // 古い断片と壊れたデータグラムは無視する
+        assert_eq!(reassembler.push(&next[1]), None);
+        assert_eq!(reassembler.push(&[0, 0, 0, 10, 0, 1, 0, 1]), None);
+        assert_eq!(reassembler.push(&[0, 0]), None);
+        assert_eq!(reassembler.dropped, 3);
+
+        // 空のパケットも1つのデータグラムになる
+        assert_eq!(fragment(0, &[], 9).unwrap().len(), 1);
+        assert!(fragment(0, &packet, 9).is_ok());
+        assert!(fragment(0, &[0; 70000], 9).is_err());
+    }
+
+    #[test]
+    fn test_udp_loopback() {
+        let rx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        rx.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let tx = UdpSocket::bind("127.0.0.1:0").unwrap();
+        tx.connect(rx.local_addr().unwrap()).unwrap();
+
+        let mut sender = KlvUdpSender::new(tx).with_mtu(100).unwrap();
+        let mut receiver = KlvUdpReceiver::new(rx);
+        for i in 0..3 {
+            let t = TestNet {
+                u16: i,
+                str: "x".repeat(i as usize * 150),
+            };
+            assert_eq!(sender.send(&t).unwrap(), i as u32);
+            let (sequence, packet) = receiver.recv_packet().unwrap();
+            assert_eq!(sequence, i as u32);
+            assert_eq!(packet, to_bytes(&t).unwrap());
+        }
+        sender
+            .send(&TestNet {
+                u16: 3,
+                str: String::new(),
+            })
+            .unwrap();
+        assert_eq!(receiver.recv::<TestNet>().unwrap().u16, 3);
+        assert_eq!(receiver.dropped(), 0);
+        assert!(KlvUdpSender::new(sender.into_inner()).with_mtu(8).is_err());
+    }
+}
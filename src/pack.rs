@@ -0,0 +1,768 @@
+//! Fields of a struct concatenated in one value
+//!
+//! MISB defines the defined-length pack and the truncation pack,
+//! whose value is the fields back-to-back without tag and length.
+//! `#[serde(with = "serde_klv::pack")]` writes the struct in the field as such a pack.
+//!
+//! - integers, floats and bool are big-endian of the type width
+//! - `&str`, `String` and bytes take the rest of the value, so only the last field can be
+//! - `Option` fields at the end are truncated when `None`, and read as `None` when the value ends
+//! - nested structs, tuples and arrays are flattened in order
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! struct Location {
+//!     latitude: i32,
+//!     longitude: i32,
+//!     altitude: Option<u16>,
+//! }
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "10", with = "serde_klv::pack")]
+//!     location: Location,
+//! }
+//!
+//! let t = Packet {
+//!     location: Location { latitude: 1, longitude: -1, altitude: None },
+//! };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(&buf[4..], &[10, 10, 8, 0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff]);
+//! assert_eq!(from_bytes::<Packet>(&buf).unwrap(), t);
+//! ```
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, DeserializeSeed, SeqAccess, Visitor};
+use serde::ser::{self, Impossible, SerializeSeq, SerializeStruct, SerializeTuple};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::{Error, Result};
+
+/// serialize the value as pack in the field
+pub fn serialize<T, S>(value: &T, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    let buf = to_pack(value).map_err(ser::Error::custom)?;
+    serializer.serialize_bytes(&buf)
+}
+
+/// deserialize the value from pack in the field
+pub fn deserialize<'de, T, D>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_bytes(PackVisitor(PhantomData))
+}
+
+/// Serialize the fields back-to-back
+pub fn to_pack<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut serializer = PackSerializer {
+        output: vec![],
+        closed: None,
+    };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+/// Deserialize the fields from the whole bytes
+pub fn from_pack<'a, T: Deserialize<'a>>(s: &'a [u8]) -> Result<T> {
+    let mut deserializer = PackDeserializer { input: s };
+    let t = T::deserialize(&mut deserializer)?;
+    if !deserializer.input.is_empty() {
+        return Err(Error::Message(format!(
+            "{} bytes remain after the pack",
+            deserializer.input.len()
+        )));
+    }
+    Ok(t)
+}
+
+struct PackVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for PackVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("fields without tag and length")
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> std::result::Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        from_pack(v).map_err(de::Error::custom)
+    }
+}
+
+struct PackSerializer {
+    output: Vec<u8>,
+    // これ以上フィールドを書けない理由
+    closed: Option<&'static str>,
+}
+
+impl PackSerializer {
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        if let Some(reason) = self.closed {
+            return Err(Error::Encode(format!("field after {} in pack", reason)));
+        }
+        self.output.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+fn unsupported<T>(what: &str) -> Result<T> {
+    Err(Error::Unsupported(format!(
+        "{} is not supported in pack",
+        what
+    )))
+}
+
+impl<'a> Serializer for &'a mut PackSerializer {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = Impossible<(), Error>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write(&[v as u8])
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.write(&[v])
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.write(&v.to_be_bytes())
+    }
+
+    fn serialize_char(self, _v: char) -> Result<()> {
+        unsupported("char")
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    // 可変長の値は残り全てを占める
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write(v)?;
+        self.closed = Some("variable-length field");
+        Ok(())
+    }
+
+    // 末尾のNoneは書き込まずに切り詰める
+    fn serialize_none(self) -> Result<()> {
+        if self.closed.is_none() {
+            self.closed = Some("truncated field");
+        }
+        Ok(())
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<()> {
+        unsupported("enum")
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        unsupported("enum")
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        unsupported("enum")
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        unsupported("map")
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        unsupported("enum")
+    }
+}
+
+impl<'a> SerializeSeq for &'a mut PackSerializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeTuple for &'a mut PackSerializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for &'a mut PackSerializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeStruct for &'a mut PackSerializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct PackDeserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> PackDeserializer<'de> {
+    fn take<const N: usize>(&mut self, what: &str) -> Result<[u8; N]> {
+        if self.input.len() < N {
+            return Err(Error::Message(format!(
+                "pack ends before {}, expected {} bytes but remains {}",
+                what,
+                N,
+                self.input.len()
+            )));
+        }
+        let (head, rest) = self.input.split_at(N);
+        self.input = rest;
+        let mut buf = [0_u8; N];
+        buf.copy_from_slice(head);
+        Ok(buf)
+    }
+
+    fn take_rest(&mut self) -> &'de [u8] {
+        std::mem::take(&mut self.input)
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for &'a mut PackDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        unsupported("deserialize_any")
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_bool(self.take::<1>("bool")?[0] != 0)
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i8(i8::from_be_bytes(self.take("i8")?))
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i16(i16::from_be_bytes(self.take("i16")?))
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i32(i32::from_be_bytes(self.take("i32")?))
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_i64(i64::from_be_bytes(self.take("i64")?))
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u8(self.take::<1>("u8")?[0])
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u16(u16::from_be_bytes(self.take("u16")?))
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u32(u32::from_be_bytes(self.take("u32")?))
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_u64(u64::from_be_bytes(self.take("u64")?))
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_f32(f32::from_be_bytes(self.take("f32")?))
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_f64(f64::from_be_bytes(self.take("f64")?))
+    }
+
+    fn deserialize_char<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        unsupported("char")
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let s = std::str::from_utf8(self.take_rest()).map_err(|_| Error::ExpectedString)?;
+        visitor.visit_borrowed_str(s)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(self.take_rest())
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    // 切り詰められたフィールドはNoneとする
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        if self.input.is_empty() {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // 長さのない列は残り全てを読む
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(PackAccess {
+            de: self,
+            remaining: None,
+        })
+    }
+
+    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(PackAccess {
+            de: self,
+            remaining: Some(len),
+        })
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        unsupported("map")
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        unsupported("enum")
+    }
+
+    fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        unsupported("identifier")
+    }
+
+    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        unsupported("deserialize_ignored_any")
+    }
+}
+
+struct PackAccess<'a, 'de: 'a> {
+    de: &'a mut PackDeserializer<'de>,
+    // Noneは入力の終わりまで
+    remaining: Option<usize>,
+}
+
+impl<'de, 'a> SeqAccess<'de> for PackAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.remaining.as_mut() {
+            Some(0) => return Ok(None),
+            Some(x) => *x -= 1,
+            None if self.de.input.is_empty() => return Ok(None),
+            None => {}
+        }
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.remaining
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{from_pack, to_pack};
+    use crate::error::{DecodeErrorKind, Error};
+    use crate::{from_bytes, to_bytes};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Header {
+        version: u8,
+        flags: [u8; 2],
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestPack<'a> {
+        header: Header,
+        value: i16,
+        ratio: f32,
+        enabled: bool,
+        count: Option<u32>,
+        name: Option<&'a str>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestPacket<'a> {
+        #[serde(rename = "10", with = "crate::pack", borrow)]
+        pack: TestPack<'a>,
+        #[serde(
+            rename = "11",
+            with = "crate::pack",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        option: Option<(u8, u16)>,
+        #[serde(rename = "12")]
+        u8: u8,
+    }
+
+    #[test]
+    fn test_pack() {
+        let t = TestPacket {
+            pack: TestPack {
+                header: Header {
+                    version: 1,
+                    flags: [2, 3],
+                },
+                value: -2,
+                ratio: 1.0,
+                enabled: true,
+                count: Some(4),
+                name: Some("ab"),
+            },
+            option: Some((5, 6)),
+            u8: 7,
+        };
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(&buf[4..], &[
+            26,
+            10, 16, 1, 2, 3, 0xff, 0xfe, 0x3f, 0x80, 0, 0, 1, 0, 0, 0, 4, b'a', b'b',
+            11, 3, 5, 0, 6,
+            12, 1, 7,
+        ]);
+        assert_eq!(from_bytes::<TestPacket>(&buf).unwrap(), t);
+
+        // 末尾のNoneは切り詰める
+        let t = TestPacket {
+            pack: TestPack {
+                count: None,
+                name: None,
+                ..t.pack
+            },
+            option: None,
+            u8: 7,
+        };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(&buf[4..7], &[15, 10, 10]);
+        assert_eq!(from_bytes::<TestPacket>(&buf).unwrap(), t);
+    }
+
+    #[test]
+    fn test_pack_error() {
+        // Noneの後のフィールドは書けない
+        let t = TestPack {
+            header: Header {
+                version: 1,
+                flags: [0, 0],
+            },
+            value: 0,
+            ratio: 0.0,
+            enabled: false,
+            count: None,
+            name: Some("a"),
+        };
+        assert!(matches!(to_pack(&t), Err(Error::Encode(_))));
+
+        // 必須のフィールドの途中で終わる
+        let buf = [1, 0, 0, 0xff];
+        assert!(from_pack::<TestPack>(&buf).is_err());
+        assert!(from_pack::<(u8, u16)>(&[1, 2, 3, 4]).is_err());
+        assert_eq!(from_pack::<Vec<u16>>(&[0, 1, 0, 2]).unwrap(), vec![1, 2]);
+
+        let buf = [b'T', b'E', b'S', b'T', 8, 10, 3, 1, 0, 0, 12, 1, 7];
+        match from_bytes::<TestPacket>(&buf) {
+            Err(Error::Decode {
+                kind: DecodeErrorKind::Custom(msg),
+                path,
+                ..
+            }) => {
+                assert_eq!(path, vec![10]);
+                assert_eq!(msg, "pack ends before i16, expected 2 bytes but remains 0");
+            }
+            x => unreachable!("{:?}", x),
+        }
+    }
+}
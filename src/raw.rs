@@ -0,0 +1,123 @@
+use std::fmt;
+
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+/// Value bytes kept without interpretation
+///
+/// Use as field type to pass through nested local set byte-for-byte.
+///
+/// Example
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{from_bytes, to_bytes, RawKLV};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Packet<'a> {
+///     #[serde(rename = "48", borrow)]
+///     nested: RawKLV<'a>,
+/// }
+///
+/// let t = Packet { nested: RawKLV(&[1, 1, 0x01, 2, 1, 0x01]) };
+/// let buf = to_bytes(&t).unwrap();
+/// assert_eq!(&buf[4..], &[8, 48, 6, 1, 1, 0x01, 2, 1, 0x01]);
+/// assert_eq!(from_bytes::<Packet>(&buf).unwrap(), t);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawKLV<'a>(pub &'a [u8]);
+
+impl<'a> RawKLV<'a> {
+    /// get value bytes
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+}
+
+impl<'a> Serialize for RawKLV<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for RawKLV<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(RawKLVVisitor)
+    }
+}
+
+struct RawKLVVisitor;
+
+impl<'de> Visitor<'de> for RawKLVVisitor {
+    type Value = RawKLV<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("borrowed bytes")
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(RawKLV(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{from_bytes, to_bytes, KLVMap, RawKLV};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TESTDATA00000000")]
+    struct TestParsed<'a> {
+        #[serde(rename = "10")]
+        u16: u16,
+        #[serde(rename = "48", borrow)]
+        child: TestChild<'a>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestChild<'a> {
+        #[serde(rename = "1")]
+        u8: u8,
+        #[serde(rename = "3")]
+        str: &'a str,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TESTDATA00000000")]
+    struct TestRaw<'a> {
+        #[serde(rename = "10")]
+        u16: u16,
+        #[serde(rename = "48", borrow)]
+        child: RawKLV<'a>,
+    }
+
+    // 入れ子のLocal Setを解釈せずにそのまま再出力できる
+    #[test]
+    fn test_raw_passthrough() {
+        let t = TestParsed {
+            u16: 1234,
+            child: TestChild { u8: 1, str: "//US" },
+        };
+        let buf = to_bytes(&t).unwrap();
+        let raw = from_bytes::<TestRaw>(&buf).unwrap();
+        assert_eq!(
+            raw.child.as_bytes(),
+            &[1, 1, 1, 3, 4, b'/', b'/', b'U', b'S']
+        );
+        let map = KLVMap::try_from_bytes(raw.child.as_bytes());
+        assert!(map.is_ok());
+        assert_eq!(to_bytes(&raw).unwrap(), buf);
+    }
+}
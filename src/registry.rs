@@ -0,0 +1,349 @@
+use std::any::Any;
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::Deserializer;
+
+use crate::check_universal_key_len;
+use crate::de::{from_bytes_keyed_with_config, from_bytes_with_config, DeserializerConfig};
+use crate::error::{Error, KeyBytes, Result};
+use crate::UniversalKeyed;
+
+type Decode = Box<dyn Fn(&[u8]) -> Result<Box<dyn Any>> + Send + Sync>;
+
+/// Types of packets selected by the universal key at runtime
+///
+/// Decoded values are not borrowed from the input, so the types must own their data.
+///
+/// Example
+/// ```
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{from_bytes_auto, to_bytes, PacketRegistry};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "POS0")]
+/// struct Position {
+///     #[serde(rename = "10")]
+///     x: u8,
+/// }
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "STAT")]
+/// struct Status {
+///     #[serde(rename = "1")]
+///     code: String,
+/// }
+///
+/// let mut registry = PacketRegistry::new();
+/// registry.register::<Position>().unwrap().register::<Status>().unwrap();
+///
+/// let buf = to_bytes(&Status { code: "OK".to_string() }).unwrap();
+/// let packet = from_bytes_auto(&buf, &registry).unwrap();
+/// assert_eq!(packet.key(), b"STAT");
+/// assert!(!packet.is::<Position>());
+/// assert_eq!(packet.downcast::<Status>().unwrap().code, "OK");
+/// ```
+#[derive(Default)]
+pub struct PacketRegistry {
+    decoders: Vec<(Vec<u8>, Decode)>,
+    config: DeserializerConfig<'static>,
+}
+
+impl fmt::Debug for PacketRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PacketRegistry")
+            .field(
+                "keys",
+                &self.decoders.iter().map(|(k, _)| k).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl PacketRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// registry decoding the types by the config
+    ///
+    /// [`DeserializerConfig::universal_key_mask`] also applies to the selection of the type.
+    /// The table of [`DeserializerConfig::tag_names`] is kept by the registry, so it is `'static`.
+    ///
+    /// Example
+    /// ```
+    /// use serde::{Deserialize, Serialize};
+    /// use serde_klv::{to_bytes, DeserializerConfig, PacketRegistry, UniversalKey};
+    ///
+    /// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    /// #[serde(rename = "\x06\x0e\x2b\x34\x02\x0b\x01\x01\x0e\x01\x03\x01\x01\x00\x00\x00")]
+    /// struct Packet {
+    ///     #[serde(rename = "2")]
+    ///     u16: u16,
+    /// }
+    ///
+    /// let mut registry = PacketRegistry::with_config(DeserializerConfig {
+    ///     universal_key_mask: Some(UniversalKey::VERSION_MASK),
+    ///     ..Default::default()
+    /// });
+    /// registry.register::<Packet>().unwrap();
+    /// let mut buf = to_bytes(&Packet { u16: 1 }).unwrap();
+    /// // only the version byte differs
+    /// buf[7] = 0x02;
+    /// assert!(registry.decode(&buf).unwrap().is::<Packet>());
+    /// ```
+    pub fn with_config(config: DeserializerConfig<'static>) -> Self {
+        Self {
+            decoders: vec![],
+            config,
+        }
+    }
+
+    /// register the struct with the universal key of `#[serde(rename)]`
+    ///
+    /// Return error when the type is not a struct or the key is invalid.
+    pub fn register<T>(&mut self) -> Result<&mut Self>
+    where
+        T: DeserializeOwned + Any,
+    {
+        let key = struct_name::<T>()?;
+        check_universal_key_len(key.as_bytes())?;
+        let config = self.config;
+        Ok(self.register_with(key.as_bytes(), move |buf| {
+            from_bytes_with_config::<T>(buf, config)
+        }))
+    }
+
+    /// register the type with the universal key of [`UniversalKeyed`]
+    pub fn register_keyed<T>(&mut self) -> &mut Self
+    where
+        T: DeserializeOwned + UniversalKeyed + Any,
+    {
+        let config = self.config;
+        self.register_with(T::KEY, move |buf| {
+            from_bytes_keyed_with_config::<T>(buf, config)
+        })
+    }
+
+    /// register decode function of the universal key, replace when already registered
+    pub fn register_with<T, F>(&mut self, key: &[u8], f: F) -> &mut Self
+    where
+        T: Any,
+        F: Fn(&[u8]) -> Result<T> + Send + Sync + 'static,
+    {
+        let decode: Decode =
+            Box::new(move |buf: &[u8]| f(buf).map(|x| Box::new(x) as Box<dyn Any>));
+        match self.decoders.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = decode,
+            None => self.decoders.push((key.to_vec(), decode)),
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.decoders.is_empty()
+    }
+
+    /// decode the packet by the type of the universal key
+    pub fn decode(&self, buf: &[u8]) -> Result<DecodedPacket> {
+        // 短いUniversalKeyが長いものの先頭と一致する場合は長い方を選ぶ
+        let (key, decode) = self
+            .decoders
+            .iter()
+            .rev()
+            .filter(|(k, _)| buf.len() >= k.len() && self.key_matches(k, &buf[..k.len()]))
+            .max_by_key(|(k, _)| k.len())
+            .ok_or_else(|| Error::UnmatchedKey {
+                expected: &[],
+                found: KeyBytes::new(buf),
+            })?;
+        Ok(DecodedPacket {
+            key: key.clone(),
+            value: decode(buf)?,
+        })
+    }
+
+    fn key_matches(&self, expect: &[u8], key: &[u8]) -> bool {
+        match &self.config.universal_key_mask {
+            Some(mask) => crate::key::masked_eq(expect, key, mask),
+            None => expect == key,
+        }
+    }
+}
+
+/// Deserialize from bytes into the type registered for the universal key
+///
+/// See [`PacketRegistry`].
+pub fn from_bytes_auto(s: &[u8], registry: &PacketRegistry) -> Result<DecodedPacket> {
+    registry.decode(s)
+}
+
+/// Packet decoded by [`PacketRegistry`]
+pub struct DecodedPacket {
+    key: Vec<u8>,
+    value: Box<dyn Any>,
+}
+
+impl fmt::Debug for DecodedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecodedPacket")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl DecodedPacket {
+    /// universal key of the packet
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    pub fn is<T: Any>(&self) -> bool {
+        self.value.is::<T>()
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+
+    /// take the value, return self when the type is unmatched
+    pub fn downcast<T: Any>(self) -> std::result::Result<T, Self> {
+        match self.value.downcast() {
+            Ok(x) => Ok(*x),
+            Err(value) => Err(Self {
+                key: self.key,
+                value,
+            }),
+        }
+    }
+}
+
+// Deserializeの実装からserde(rename)された構造体名を取り出す
+fn struct_name<T: DeserializeOwned>() -> Result<&'static str> {
+    match T::deserialize(NameProbe) {
+        Err(ProbeError::Name(name)) => Ok(name),
+        _ => Err(Error::Unsupported(format!(
+            "{} is not a struct",
+            std::any::type_name::<T>()
+        ))),
+    }
+}
+
+struct NameProbe;
+
+#[derive(Debug)]
+enum ProbeError {
+    Name(&'static str),
+    Other,
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("probe of struct name")
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl de::Error for ProbeError {
+    fn custom<T: fmt::Display>(_msg: T) -> Self {
+        ProbeError::Other
+    }
+}
+
+impl<'de> Deserializer<'de> for NameProbe {
+    type Error = ProbeError;
+
+    fn deserialize_any<V>(self, _visitor: V) -> std::result::Result<V::Value, ProbeError>
+    where
+        V: Visitor<'de>,
+    {
+        Err(ProbeError::Other)
+    }
+
+    fn deserialize_struct<V>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> std::result::Result<V::Value, ProbeError>
+    where
+        V: Visitor<'de>,
+    {
+        Err(ProbeError::Name(name))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::PacketRegistry;
+    use crate::error::Error;
+    use crate::{to_bytes, to_bytes_keyed, UniversalKeyed};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestA {
+        #[serde(rename = "10")]
+        u8: u8,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestB {
+        #[serde(rename = "10")]
+        u16: u16,
+    }
+
+    impl UniversalKeyed for TestB {
+        const KEY: &'static [u8] = &[0xff, 0x80];
+    }
+
+    #[test]
+    fn test_packet_registry() {
+        let mut registry = PacketRegistry::new();
+        assert!(registry.is_empty());
+        registry
+            .register::<TestA>()
+            .unwrap()
+            .register_keyed::<TestB>();
+        // 構造体でない型は登録できない
+        assert!(registry.register::<u8>().is_err());
+
+        let a = to_bytes(&TestA { u8: 1 }).unwrap();
+        let b = to_bytes_keyed(&TestB { u16: 2 }).unwrap();
+        let packet = registry.decode(&a).unwrap();
+        assert_eq!(packet.downcast_ref::<TestA>(), Some(&TestA { u8: 1 }));
+        let packet = registry.decode(&b).unwrap();
+        assert_eq!(packet.key(), &[0xff, 0x80]);
+        let packet = packet.downcast::<TestA>().unwrap_err();
+        assert_eq!(packet.downcast::<TestB>().unwrap(), TestB { u16: 2 });
+
+        // 登録済みのキーは置き換える
+        registry.register_with(b"TEST", |buf| Ok(buf.len()));
+        assert_eq!(registry.decode(&a).unwrap().downcast::<usize>().unwrap(), 8);
+
+        assert!(matches!(
+            registry.decode(b"NONE\x00"),
+            Err(Error::UnmatchedKey { expected: &[], .. })
+        ));
+    }
+
+    // 登録順によらず最も長く一致するUniversalKeyを選ぶ
+    #[test]
+    fn test_registry_longest_key() {
+        let mut registry = PacketRegistry::new();
+        registry.register_with(b"TE", |_| Ok("short"));
+        registry.register::<TestA>().unwrap();
+        let a = to_bytes(&TestA { u8: 1 }).unwrap();
+        assert!(registry.decode(&a).unwrap().is::<TestA>());
+        let packet = registry.decode(b"TE\x00").unwrap();
+        assert_eq!(packet.downcast::<&str>().unwrap(), "short");
+    }
+}
@@ -0,0 +1,176 @@
+//! Repeated tag collected into `Vec<T>`
+//!
+//! Serialize one record per element with the same tag,
+//! and collect every record of the tag in the local set on deserialize.
+//! No record is written for an empty `Vec`, so combine with `#[serde(default)]`.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Targets {
+//!     #[serde(rename = "10", with = "serde_klv::repeated", default)]
+//!     ids: Vec<u8>,
+//!     #[serde(rename = "11")]
+//!     count: u8,
+//! }
+//!
+//! let t = Targets { ids: vec![1, 2], count: 2 };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(&buf[4..], &[9, 10, 1, 1, 10, 1, 2, 11, 1, 2]);
+//! assert_eq!(from_bytes::<Targets>(&buf).unwrap(), t);
+//!
+//! // records of the tag need not be adjacent
+//! let buf = [b'T', b'E', b'S', b'T', 9, 10, 1, 1, 11, 1, 2, 10, 1, 2];
+//! assert_eq!(from_bytes::<Targets>(&buf).unwrap(), t);
+//! ```
+
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::{
+    de::{SeqAccess, Visitor},
+    ser::SerializeSeq,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+// シリアライザ、デシリアライザに繰り返しフィールドであることを伝えるための名前
+pub(crate) const REPEATED: &str = "$serde_klv::repeated";
+pub(crate) const ELEMENT: &str = "$serde_klv::repeated::element";
+
+pub fn serialize<T, S>(value: &[T], serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    serializer.serialize_newtype_struct(REPEATED, &Elements(value))
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_newtype_struct(REPEATED, RepeatedVisitor(PhantomData))
+}
+
+struct Elements<'a, T>(&'a [T]);
+
+impl<'a, T: Serialize> Serialize for Elements<'a, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for v in self.0 {
+            seq.serialize_element(&Element(v))?;
+        }
+        seq.end()
+    }
+}
+
+struct Element<'a, T>(&'a T);
+
+impl<'a, T: Serialize> Serialize for Element<'a, T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(ELEMENT, self.0)
+    }
+}
+
+struct RepeatedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for RepeatedVisitor<T> {
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("records of the same tag")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut values = vec![];
+        while let Some(v) = seq.next_element()? {
+            values.push(v);
+        }
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{from_bytes, to_bytes, DeserializerConfig};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestTarget<'a> {
+        #[serde(rename = "1")]
+        id: u16,
+        #[serde(rename = "2")]
+        name: &'a str,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestRepeated<'a> {
+        #[serde(rename = "10", with = "crate::repeated", default, borrow)]
+        targets: Vec<TestTarget<'a>>,
+        #[serde(rename = "11", with = "crate::repeated", default)]
+        values: Vec<u32>,
+        #[serde(rename = "12")]
+        u8: u8,
+    }
+
+    #[test]
+    fn test_repeated() {
+        let t = TestRepeated {
+            targets: vec![
+                TestTarget { id: 1, name: "a" },
+                TestTarget { id: 2, name: "bc" },
+            ],
+            values: vec![],
+            u8: 3,
+        };
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        let expect = [
+            22,
+            10, 7, 1, 2, 0, 1, 2, 1, b'a',
+            10, 8, 1, 2, 0, 2, 2, 2, b'b', b'c',
+            12, 1, 3,
+        ];
+        assert_eq!(&buf[4..], &expect);
+        assert_eq!(from_bytes::<TestRepeated>(&buf).unwrap(), t);
+
+        // 離れた位置にある同じタグも集める。整数は短いエンコードも読める
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 16,
+            11, 4, 0, 0, 0, 1,
+            12, 1, 3,
+            11, 1, 2,
+            11, 2, 1, 0,
+        ];
+        let x = from_bytes::<TestRepeated>(&buf).unwrap();
+        assert_eq!(x.values, vec![1, 2, 256]);
+        assert!(x.targets.is_empty());
+        assert_eq!(x.u8, 3);
+
+        // 集めたレコードは重複扱いにならない
+        let config = DeserializerConfig {
+            deny_duplicate_keys: true,
+            ..Default::default()
+        };
+        let y = crate::from_bytes_with_config::<TestRepeated>(&buf, config).unwrap();
+        assert_eq!(x, y);
+    }
+}
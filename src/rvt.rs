@@ -0,0 +1,359 @@
+//! Example impl for MISB Standard 0806
+//! the Remote Video Terminal Local Set (LS)
+//! and its nested User Defined, Point of Interest (POI) and Area of Interest (AOI) Local Sets
+//! reference: MISB ST 0806.4
+//!
+//! Example
+//!
+//! ```rust
+//! use serde_klv::rvt::{PoiLocalSet, RVTLocalSet, CRC32};
+//! use serde_klv::st0603::PrecisionTimestamp;
+//! use serde_klv::{from_bytes_with_checksum, to_bytes_with_checksum};
+//!
+//! let t = RVTLocalSet {
+//!     timestamp: PrecisionTimestamp::from_micros(1_000_233_000),
+//!     uas_ls_version_number: Some(17),
+//!     poi: vec![PoiLocalSet {
+//!         number: 1,
+//!         latitude: 0x1234_5678,
+//!         longitude: -0x1234_5678,
+//!         label: Some("target"),
+//!         ..Default::default()
+//!     }],
+//!     ..Default::default()
+//! };
+//! let buf = to_bytes_with_checksum(&t, CRC32).unwrap();
+//! let x: RVTLocalSet = from_bytes_with_checksum(&buf, CRC32).unwrap();
+//! assert_eq!(x, t);
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::{CheckSumCalc, CheckSumWidth};
+use crate::st0603::PrecisionTimestamp;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename = "\x06\x0e\x2b\x34\x02\x0b\x01\x01\x0e\x01\x03\x01\x02\x00\x00\x00")]
+pub struct RVTLocalSet<'a> {
+    #[serde(rename = "2")]
+    pub timestamp: PrecisionTimestamp,
+    /// True airspeed of the platform in meters per second.
+    #[serde(rename = "3", skip_serializing_if = "Option::is_none")]
+    pub platform_true_airspeed: Option<u16>,
+    /// Indicated airspeed of the platform in meters per second.
+    #[serde(rename = "4", skip_serializing_if = "Option::is_none")]
+    pub platform_indicated_airspeed: Option<u16>,
+    /// Bit flags of the accuracy of the telemetry.
+    #[serde(rename = "5", skip_serializing_if = "Option::is_none")]
+    pub telemetry_accuracy_indicator: Option<u8>,
+    /// Radius of the fragmentation circle in meters.
+    #[serde(rename = "6", skip_serializing_if = "Option::is_none")]
+    pub frag_circle_radius: Option<u16>,
+    /// Counter of the video frame.
+    #[serde(rename = "7", skip_serializing_if = "Option::is_none")]
+    pub frame_code: Option<u32>,
+    /// Version number of MISB ST 0806.
+    #[serde(rename = "8", skip_serializing_if = "Option::is_none")]
+    pub uas_ls_version_number: Option<u8>,
+    /// Data rate of the video in bits per second.
+    #[serde(rename = "9", skip_serializing_if = "Option::is_none")]
+    pub video_data_rate: Option<u32>,
+    /// e.g. "MPEG2", "H.264".
+    #[serde(rename = "10", skip_serializing_if = "Option::is_none")]
+    pub digital_video_file_format: Option<&'a str>,
+    #[serde(
+        rename = "11",
+        with = "crate::repeated",
+        default,
+        borrow,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub user_defined: Vec<UserDefinedLocalSet<'a>>,
+    #[serde(
+        rename = "12",
+        with = "crate::repeated",
+        default,
+        borrow,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub poi: Vec<PoiLocalSet<'a>>,
+    #[serde(
+        rename = "13",
+        with = "crate::repeated",
+        default,
+        borrow,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub aoi: Vec<AoiLocalSet<'a>>,
+    /// MGRS zone of the platform. 1..60.
+    #[serde(rename = "14", skip_serializing_if = "Option::is_none")]
+    pub mgrs_zone: Option<u8>,
+    /// Latitude band and grid square of the platform. e.g. "SMG".
+    #[serde(rename = "15", skip_serializing_if = "Option::is_none")]
+    pub mgrs_latitude_band_and_grid_square: Option<&'a str>,
+    /// Easting of the platform in meters, encoded as uint24.
+    #[serde(
+        rename = "16",
+        with = "uint24",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mgrs_easting: Option<u32>,
+    /// Northing of the platform in meters, encoded as uint24.
+    #[serde(
+        rename = "17",
+        with = "uint24",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub mgrs_northing: Option<u32>,
+    /// MGRS zone of the frame center. 1..60.
+    #[serde(rename = "18", skip_serializing_if = "Option::is_none")]
+    pub frame_center_mgrs_zone: Option<u8>,
+    #[serde(rename = "19", skip_serializing_if = "Option::is_none")]
+    pub frame_center_mgrs_latitude_band_and_grid_square: Option<&'a str>,
+    #[serde(
+        rename = "20",
+        with = "uint24",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub frame_center_mgrs_easting: Option<u32>,
+    #[serde(
+        rename = "21",
+        with = "uint24",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub frame_center_mgrs_northing: Option<u32>,
+}
+
+impl<'a> Default for RVTLocalSet<'a> {
+    fn default() -> Self {
+        Self {
+            timestamp: PrecisionTimestamp::UNIX_EPOCH,
+            platform_true_airspeed: Default::default(),
+            platform_indicated_airspeed: Default::default(),
+            telemetry_accuracy_indicator: Default::default(),
+            frag_circle_radius: Default::default(),
+            frame_code: Default::default(),
+            uas_ls_version_number: Default::default(),
+            video_data_rate: Default::default(),
+            digital_video_file_format: Default::default(),
+            user_defined: Default::default(),
+            poi: Default::default(),
+            aoi: Default::default(),
+            mgrs_zone: Default::default(),
+            mgrs_latitude_band_and_grid_square: Default::default(),
+            mgrs_easting: Default::default(),
+            mgrs_northing: Default::default(),
+            frame_center_mgrs_zone: Default::default(),
+            frame_center_mgrs_latitude_band_and_grid_square: Default::default(),
+            frame_center_mgrs_easting: Default::default(),
+            frame_center_mgrs_northing: Default::default(),
+        }
+    }
+}
+
+/// User Defined Local Set (tag 11)
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct UserDefinedLocalSet<'a> {
+    /// Upper 2 bits are the data type and lower 6 bits are the id.
+    #[serde(rename = "1")]
+    pub data_type_id: u8,
+    #[serde(rename = "2")]
+    pub data: &'a [u8],
+}
+
+/// Point of Interest Local Set (tag 12)
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct PoiLocalSet<'a> {
+    #[serde(rename = "1")]
+    pub number: u16,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "2")]
+    pub latitude: i32,
+    /// Map -(2^31-1)..(2^31-1) to +/-180 degrees.
+    #[serde(rename = "3")]
+    pub longitude: i32,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "4", skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<u16>,
+    /// 1: friendly, 2: hostile, 3: target, 4: unknown.
+    #[serde(rename = "5", skip_serializing_if = "Option::is_none")]
+    pub poi_type: Option<u8>,
+    #[serde(rename = "6", skip_serializing_if = "Option::is_none", borrow)]
+    pub text: Option<&'a str>,
+    /// MIL-STD-2525 symbol code.
+    #[serde(rename = "7", skip_serializing_if = "Option::is_none")]
+    pub source_icon: Option<&'a str>,
+    #[serde(rename = "8", skip_serializing_if = "Option::is_none")]
+    pub source_id: Option<&'a str>,
+    #[serde(rename = "9", skip_serializing_if = "Option::is_none")]
+    pub label: Option<&'a str>,
+    #[serde(rename = "10", skip_serializing_if = "Option::is_none")]
+    pub operation_id: Option<&'a str>,
+}
+
+/// Area of Interest Local Set (tag 13)
+///
+/// The area is the rectangle of the two corners.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct AoiLocalSet<'a> {
+    #[serde(rename = "1")]
+    pub number: u16,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "2")]
+    pub corner_latitude_point1: i32,
+    /// Map -(2^31-1)..(2^31-1) to +/-180 degrees.
+    #[serde(rename = "3")]
+    pub corner_longitude_point1: i32,
+    #[serde(rename = "4")]
+    pub corner_latitude_point2: i32,
+    #[serde(rename = "5")]
+    pub corner_longitude_point2: i32,
+    /// 1: friendly, 2: hostile, 3: target, 4: unknown.
+    #[serde(rename = "6", skip_serializing_if = "Option::is_none")]
+    pub aoi_type: Option<u8>,
+    #[serde(rename = "7", skip_serializing_if = "Option::is_none", borrow)]
+    pub text: Option<&'a str>,
+    #[serde(rename = "8", skip_serializing_if = "Option::is_none")]
+    pub source_id: Option<&'a str>,
+    #[serde(rename = "9", skip_serializing_if = "Option::is_none")]
+    pub label: Option<&'a str>,
+    #[serde(rename = "10", skip_serializing_if = "Option::is_none")]
+    pub operation_id: Option<&'a str>,
+}
+
+/// CRC-32/MPEG-2 checksum of tag 1
+pub struct CRC32;
+
+impl CheckSumCalc for CRC32 {
+    fn checksum(&self, bytes: &[u8]) -> u32 {
+        crc::Crc::<u32>::new(&crc::CRC_32_MPEG_2).checksum(bytes)
+    }
+
+    fn width(&self) -> CheckSumWidth {
+        CheckSumWidth::U32
+    }
+}
+
+// MGRSのeasting/northingは3byte固定で書き込む
+mod uint24 {
+    use serde::{de, ser, Deserializer, Serializer};
+
+    const MAX: u32 = 0xff_ffff;
+
+    pub fn serialize<S>(value: &Option<u32>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(v) if *v > MAX => Err(ser::Error::custom(format!("value {} exceeds uint24", v))),
+            Some(v) => serializer.serialize_bytes(&v.to_be_bytes()[1..]),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v: u32 = crate::varlen::deserialize(deserializer)?;
+        if v > MAX {
+            return Err(de::Error::custom(format!("value {} exceeds uint24", v)));
+        }
+        Ok(Some(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        checksum::CheckSumCalc,
+        de::from_bytes,
+        from_bytes_with_checksum,
+        rvt::{AoiLocalSet, PoiLocalSet, RVTLocalSet, UserDefinedLocalSet, CRC32},
+        ser::to_bytes,
+        st0603::PrecisionTimestamp,
+        to_bytes_with_checksum,
+    };
+    use byteorder::{BigEndian, ByteOrder};
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(CRC32.checksum(b"123456789"), 0x0376_e6e7);
+    }
+
+    #[test]
+    fn test_rvt_local_set() {
+        let t = RVTLocalSet {
+            timestamp: PrecisionTimestamp::from_micros(1_000_233_000),
+            frame_code: Some(1),
+            uas_ls_version_number: Some(4),
+            digital_video_file_format: Some("H.264"),
+            user_defined: vec![UserDefinedLocalSet {
+                data_type_id: 0x41,
+                data: &[1, 2],
+            }],
+            poi: vec![
+                PoiLocalSet {
+                    number: 1,
+                    latitude: 1,
+                    longitude: -1,
+                    ..Default::default()
+                },
+                PoiLocalSet {
+                    number: 2,
+                    latitude: 2,
+                    longitude: -2,
+                    label: Some("b"),
+                    ..Default::default()
+                },
+            ],
+            aoi: vec![AoiLocalSet {
+                number: 3,
+                corner_latitude_point1: 10,
+                corner_longitude_point1: 20,
+                corner_latitude_point2: 30,
+                corner_longitude_point2: 40,
+                aoi_type: Some(3),
+                ..Default::default()
+            }],
+            mgrs_zone: Some(54),
+            mgrs_easting: Some(0x01_0203),
+            ..Default::default()
+        };
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(&buf[16..], &[
+            115,
+            2, 8, 0x00, 0x00, 0x00, 0x00, 0x3b, 0x9e, 0x58, 0x28,
+            7, 4, 0, 0, 0, 1,
+            8, 1, 4,
+            10, 5, b'H', b'.', b'2', b'6', b'4',
+            11, 7, 1, 1, 0x41, 2, 2, 1, 2,
+            12, 16, 1, 2, 0, 1, 2, 4, 0, 0, 0, 1, 3, 4, 0xff, 0xff, 0xff, 0xff,
+            12, 19, 1, 2, 0, 2, 2, 4, 0, 0, 0, 2, 3, 4, 0xff, 0xff, 0xff, 0xfe, 9, 1, b'b',
+            13, 31, 1, 2, 0, 3, 2, 4, 0, 0, 0, 10, 3, 4, 0, 0, 0, 20,
+                4, 4, 0, 0, 0, 30, 5, 4, 0, 0, 0, 40, 6, 1, 3,
+            14, 1, 54,
+            16, 3, 1, 2, 3,
+        ]);
+        assert_eq!(from_bytes::<RVTLocalSet>(&buf).unwrap(), t);
+
+        let buf = to_bytes_with_checksum(&t, CRC32).unwrap();
+        assert_eq!(&buf[buf.len() - 6..buf.len() - 4], &[1, 4]);
+        let crc_code = CRC32.checksum(&buf[..buf.len() - 4]);
+        assert_eq!(BigEndian::read_u32(&buf[buf.len() - 4..]), crc_code);
+        let x: RVTLocalSet = from_bytes_with_checksum(&buf, CRC32).unwrap();
+        assert_eq!(x, t);
+
+        // uint24を超える値は書き込めない
+        let t = RVTLocalSet {
+            mgrs_northing: Some(0x100_0000),
+            ..Default::default()
+        };
+        assert!(to_bytes(&t).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+//! Floating point values encoded as scaled fixed-point integers
+//!
+//! Use `Scaled::<Repr, SCALE>` with `#[serde(with = "...")]` for `f32` or `f64` fields.
+//! The wire value is `Repr` holding `value * SCALE`.
+//!
+//! - Encoding rounds to the nearest integer, ties away from zero.
+//! - Decoding divides by `SCALE` in f64, then casts to the field type with nearest rounding.
+//! - NaN or a value outside of `Repr` is encoded as the out-of-range sentinel,
+//!   `Repr::MIN` for signed and `Repr::MAX` for unsigned integers.
+//!   The sentinel is decoded as NaN.
+//!
+//! `SCALE` must not be zero.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Position {
+//!     // degree in 1e-7
+//!     #[serde(rename = "13", with = "serde_klv::scaled::Scaled::<i32, 10_000_000>")]
+//!     latitude: f64,
+//! }
+//!
+//! let t = Position { latitude: 35.1234567 };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(&buf[4..], &[6, 13, 4, 0x14, 0xef, 0x6a, 0x07]);
+//! assert_eq!(from_bytes::<Position>(&buf).unwrap(), t);
+//!
+//! // out of range
+//! let buf = to_bytes(&Position { latitude: 300.0 }).unwrap();
+//! assert_eq!(&buf[4..], &[6, 13, 4, 0x80, 0, 0, 0]);
+//! assert!(from_bytes::<Position>(&buf).unwrap().latitude.is_nan());
+//! ```
+
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Integer representation of scaled value on the wire
+pub trait ScaledRepr: Copy + PartialEq + Serialize + for<'de> Deserialize<'de> {
+    /// value of out of range
+    const SENTINEL: Self;
+    /// None when the rounded value does not fit or is the sentinel
+    fn from_f64(v: f64) -> Option<Self>;
+    fn to_f64(self) -> f64;
+}
+
+macro_rules! signed_repr {
+    ($($ty:ty),*) => {$(
+        impl ScaledRepr for $ty {
+            const SENTINEL: Self = <$ty>::MIN;
+            fn from_f64(v: f64) -> Option<Self> {
+                // MINは番兵のため範囲外とする
+                ((<$ty>::MIN as f64) < v && v <= <$ty>::MAX as f64).then_some(v as $ty)
+            }
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    )*};
+}
+
+macro_rules! unsigned_repr {
+    ($($ty:ty),*) => {$(
+        impl ScaledRepr for $ty {
+            const SENTINEL: Self = <$ty>::MAX;
+            fn from_f64(v: f64) -> Option<Self> {
+                // MAXは番兵のため範囲外とする
+                (0.0 <= v && v < <$ty>::MAX as f64).then_some(v as $ty)
+            }
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+        }
+    )*};
+}
+
+signed_repr!(i8, i16, i32, i64);
+unsigned_repr!(u8, u16, u32, u64);
+
+/// Floating point type of the field
+pub trait ScaledFloat: Copy {
+    fn from_f64(v: f64) -> Self;
+    fn to_f64(self) -> f64;
+}
+
+impl ScaledFloat for f32 {
+    fn from_f64(v: f64) -> Self {
+        v as f32
+    }
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+impl ScaledFloat for f64 {
+    fn from_f64(v: f64) -> Self {
+        v
+    }
+    fn to_f64(self) -> f64 {
+        self
+    }
+}
+
+/// Adapter of `value * SCALE` stored as `R`
+pub struct Scaled<R, const SCALE: u64>(PhantomData<R>);
+
+impl<R: ScaledRepr, const SCALE: u64> Scaled<R, SCALE> {
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: ScaledFloat,
+        S: Serializer,
+    {
+        let v = (value.to_f64() * SCALE as f64).round();
+        R::from_f64(v).unwrap_or(R::SENTINEL).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: ScaledFloat,
+        D: Deserializer<'de>,
+    {
+        let v = R::deserialize(deserializer)?;
+        if v == R::SENTINEL {
+            return Ok(T::from_f64(f64::NAN));
+        }
+        Ok(T::from_f64(v.to_f64() / SCALE as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::Scaled;
+    use crate::{from_bytes, to_bytes};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestScaled {
+        #[serde(rename = "10", with = "Scaled::<i32, 10_000_000>")]
+        latitude: f64,
+        #[serde(rename = "11", with = "Scaled::<u16, 100>")]
+        speed: f32,
+        #[serde(rename = "12", with = "Scaled::<i8, 1>")]
+        offset: f64,
+    }
+
+    #[test]
+    fn test_scaled() {
+        let t = TestScaled {
+            latitude: -35.1234567,
+            speed: 12.34,
+            offset: -127.0,
+        };
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        let expect = [
+            13,
+            10, 4, 0xeb, 0x10, 0x95, 0xf9,
+            11, 2, 0x04, 0xd2,
+            12, 1, 0x81,
+        ];
+        assert_eq!(&buf[4..], &expect);
+        assert_eq!(from_bytes::<TestScaled>(&buf).unwrap(), t);
+
+        // 最近接へ丸め、0.5は0から遠い方へ丸める
+        let t = TestScaled {
+            latitude: 0.00000004,
+            speed: 0.125,
+            offset: -1.5,
+        };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(
+            &buf[4..],
+            &[13, 10, 4, 0, 0, 0, 0, 11, 2, 0, 13, 12, 1, 0xfe]
+        );
+
+        // 範囲外とNaNは番兵になり、番兵はNaNとして読む
+        let t = TestScaled {
+            latitude: f64::NAN,
+            speed: -1.0,
+            offset: -128.0,
+        };
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        let expect = [
+            13,
+            10, 4, 0x80, 0, 0, 0,
+            11, 2, 0xff, 0xff,
+            12, 1, 0x80,
+        ];
+        assert_eq!(&buf[4..], &expect);
+        let x = from_bytes::<TestScaled>(&buf).unwrap();
+        assert!(x.latitude.is_nan());
+        assert!(x.speed.is_nan());
+        assert!(x.offset.is_nan());
+    }
+}
@@ -0,0 +1,442 @@
+//! Declaration of the tags of a packet and validation of parsed packets
+//!
+//! Example
+//!
+//! ```rust
+//! use serde_klv::schema::{validate, FieldType, Schema, TagSpec, Violation};
+//! use serde_klv::KLVMap;
+//!
+//! let mut schema = Schema::new(b"TEST").unwrap();
+//! schema
+//!     .spec(TagSpec::new(10, "speed", FieldType::U16).required().range(0.0, 500.0))
+//!     .spec(TagSpec::new(11, "name", FieldType::String).len(1, 8))
+//!     .spec(TagSpec::new(12, "mode", FieldType::U8).required());
+//!
+//! let mut buf = b"TEST".to_vec();
+//! buf.extend_from_slice(&[15, 10, 2, 0x03, 0x00, 11, 9]);
+//! buf.extend_from_slice(b"abcdefghi");
+//! let map = KLVMap::try_from_bytes(&buf).unwrap();
+//! let report = validate(&map, &schema);
+//! assert!(!report.is_ok());
+//! assert_eq!(
+//!     report.violations,
+//!     vec![
+//!         Violation::OutOfRange { path: vec![10], value: 768.0, min: 0.0, max: 500.0 },
+//!         Violation::Length { path: vec![11], length: 9, min: 1, max: 8 },
+//!         Violation::Missing { path: vec![12] },
+//!     ]
+//! );
+//! ```
+
+use std::fmt::{self, Display};
+
+use crate::de::{parse_local_set, KLVMap, KLVRaw};
+use crate::error::{Result, TagPath};
+
+/// Type of the value of a tag
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldType {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    /// UTF-8 string
+    String,
+    /// value bytes without interpretation
+    Bytes,
+    /// nested local set
+    Set(Schema),
+}
+
+impl FieldType {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            FieldType::Bool => "bool",
+            FieldType::U8 => "u8",
+            FieldType::U16 => "u16",
+            FieldType::U32 => "u32",
+            FieldType::U64 => "u64",
+            FieldType::I8 => "i8",
+            FieldType::I16 => "i16",
+            FieldType::I32 => "i32",
+            FieldType::I64 => "i64",
+            FieldType::F32 => "f32",
+            FieldType::F64 => "f64",
+            FieldType::String => "string",
+            FieldType::Bytes => "bytes",
+            FieldType::Set(_) => "local set",
+        }
+    }
+
+    // 数値型の値をf64で読む。型に合わない場合はNone
+    fn number(&self, raw: &KLVRaw) -> Option<f64> {
+        match self {
+            FieldType::U8 => raw.as_u8().map(f64::from),
+            FieldType::U16 => raw.as_u16().map(f64::from),
+            FieldType::U32 => raw.as_u32().map(f64::from),
+            FieldType::U64 => raw.as_u64().map(|x| x as f64),
+            FieldType::I8 => raw.as_i8().map(f64::from),
+            FieldType::I16 => raw.as_i16().map(f64::from),
+            FieldType::I32 => raw.as_i32().map(f64::from),
+            FieldType::I64 => raw.as_i64().map(|x| x as f64),
+            FieldType::F32 => raw.as_f32().map(f64::from),
+            FieldType::F64 => raw.as_f64(),
+            _ => None,
+        }
+    }
+}
+
+/// Declaration of a tag
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagSpec {
+    pub tag: u8,
+    pub name: String,
+    pub ty: FieldType,
+    /// the tag must appear in the local set
+    pub required: bool,
+    /// inclusive range of the value length
+    pub len: Option<(usize, usize)>,
+    /// inclusive range of the number value
+    pub range: Option<(f64, f64)>,
+}
+
+impl TagSpec {
+    pub fn new(tag: u8, name: &str, ty: FieldType) -> Self {
+        Self {
+            tag,
+            name: name.to_string(),
+            ty,
+            required: false,
+            len: None,
+            range: None,
+        }
+    }
+
+    /// mark as required
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
+    /// limit the value length
+    pub fn len(mut self, min: usize, max: usize) -> Self {
+        self.len = Some((min, max));
+        self
+    }
+
+    /// limit the number value
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+}
+
+/// Tags of a packet or nested local set
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    pub(crate) universal_key: Vec<u8>,
+    pub(crate) specs: Vec<TagSpec>,
+}
+
+impl Schema {
+    /// schema of packet with universal key
+    pub fn new(universal_key: &[u8]) -> Result<Self> {
+        crate::check_universal_key_len(universal_key)?;
+        Ok(Self {
+            universal_key: universal_key.to_vec(),
+            specs: vec![],
+        })
+    }
+
+    /// schema of nested local set without universal key
+    pub fn nested() -> Self {
+        Self::default()
+    }
+
+    /// declare the tag, replace when already declared
+    pub fn spec(&mut self, spec: TagSpec) -> &mut Self {
+        self.specs.retain(|x| x.tag != spec.tag);
+        self.specs.push(spec);
+        self
+    }
+
+    /// declare the name and type of the tag
+    pub fn field(&mut self, tag: u8, name: &str, ty: FieldType) -> &mut Self {
+        self.spec(TagSpec::new(tag, name, ty))
+    }
+
+    /// declared tags in order of declaration
+    pub fn specs(&self) -> &[TagSpec] {
+        &self.specs
+    }
+
+    pub(crate) fn by_tag(&self, tag: u8) -> Option<&TagSpec> {
+        self.specs.iter().find(|x| x.tag == tag)
+    }
+
+    pub(crate) fn by_name(&self, name: &str) -> Option<&TagSpec> {
+        self.specs.iter().find(|x| x.name == name)
+    }
+}
+
+/// Problem found by [`validate`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// required tag does not appear
+    Missing { path: Vec<u8> },
+    /// value length is out of [`TagSpec::len`]
+    Length {
+        path: Vec<u8>,
+        length: usize,
+        min: usize,
+        max: usize,
+    },
+    /// value is out of [`TagSpec::range`]
+    OutOfRange {
+        path: Vec<u8>,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+    /// value can not be read as [`TagSpec::ty`]
+    InvalidValue {
+        path: Vec<u8>,
+        length: usize,
+        expected: &'static str,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Missing { path } => write!(f, "tag {} is missing", TagPath(path)),
+            Violation::Length {
+                path,
+                length,
+                min,
+                max,
+            } => write!(
+                f,
+                "tag {} has {} bytes, expected {}..={}",
+                TagPath(path),
+                length,
+                min,
+                max
+            ),
+            Violation::OutOfRange {
+                path,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "tag {} is {}, expected {}..={}",
+                TagPath(path),
+                value,
+                min,
+                max
+            ),
+            Violation::InvalidValue {
+                path,
+                length,
+                expected,
+            } => write!(
+                f,
+                "tag {} has {} bytes which is not {}",
+                TagPath(path),
+                length,
+                expected
+            ),
+        }
+    }
+}
+
+/// Result of [`validate`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    /// violations in order of the schema, nested ones follow their parent
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    /// no violation is found
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Check parsed packet against the schema
+///
+/// The universal key and the tags not in the schema are not checked.
+/// When a tag appears more than once, every record is checked.
+pub fn validate(map: &KLVMap, schema: &Schema) -> ValidationReport {
+    let mut report = ValidationReport::default();
+    let records = map.iter().collect::<Vec<_>>();
+    validate_set(&records, schema, &[], &mut report);
+    report
+}
+
+fn validate_set(records: &[&KLVRaw], schema: &Schema, path: &[u8], report: &mut ValidationReport) {
+    for spec in schema.specs.iter() {
+        let path = [path, &[spec.tag]].concat();
+        let mut found = false;
+        for raw in records.iter().filter(|x| x.key == spec.tag) {
+            found = true;
+            validate_record(raw, spec, &path, report);
+        }
+        if spec.required && !found {
+            report.violations.push(Violation::Missing { path });
+        }
+    }
+}
+
+fn validate_record(raw: &KLVRaw, spec: &TagSpec, path: &[u8], report: &mut ValidationReport) {
+    if let Some((min, max)) = spec.len {
+        if raw.length < min || raw.length > max {
+            report.violations.push(Violation::Length {
+                path: path.to_vec(),
+                length: raw.length,
+                min,
+                max,
+            });
+        }
+    }
+    let invalid = || Violation::InvalidValue {
+        path: path.to_vec(),
+        length: raw.length,
+        expected: spec.ty.name(),
+    };
+    match &spec.ty {
+        FieldType::Bytes => {}
+        FieldType::Bool if raw.as_bool().is_none() => report.violations.push(invalid()),
+        FieldType::Bool => {}
+        FieldType::String if raw.as_str().is_none() => report.violations.push(invalid()),
+        FieldType::String => {}
+        FieldType::Set(schema) => match parse_local_set(raw.value.unwrap_or_default()) {
+            Some(nested) => {
+                let nested = nested.iter().collect::<Vec<_>>();
+                validate_set(&nested, schema, path, report);
+            }
+            None => report.violations.push(invalid()),
+        },
+        ty => match (ty.number(raw), spec.range) {
+            (None, _) => report.violations.push(invalid()),
+            (Some(value), Some((min, max))) if !(min..=max).contains(&value) => {
+                report.violations.push(Violation::OutOfRange {
+                    path: path.to_vec(),
+                    value,
+                    min,
+                    max,
+                })
+            }
+            _ => {}
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::{validate, FieldType, Schema, TagSpec, Violation};
+    use crate::{to_bytes, KLVMap};
+
+    #[derive(Debug, Serialize)]
+    #[serde(rename = "TEST")]
+    struct TestValidate<'a> {
+        #[serde(rename = "10")]
+        i16: i16,
+        #[serde(rename = "11")]
+        str: &'a str,
+        #[serde(rename = "12")]
+        child: TestChild,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct TestChild {
+        #[serde(rename = "1")]
+        f32: f32,
+        #[serde(rename = "2")]
+        flag: u16,
+    }
+
+    fn schema() -> Schema {
+        let mut child = Schema::nested();
+        child
+            .spec(TagSpec::new(1, "f32", FieldType::F32).range(-1.0, 1.0))
+            .spec(TagSpec::new(2, "flag", FieldType::Bool))
+            .spec(TagSpec::new(3, "extra", FieldType::U8).required());
+        let mut schema = Schema::new(b"TEST").unwrap();
+        schema
+            .spec(TagSpec::new(10, "i16", FieldType::I16).range(-100.0, 100.0))
+            .spec(TagSpec::new(11, "str", FieldType::String).len(1, 4))
+            .spec(TagSpec::new(12, "child", FieldType::Set(child)).required());
+        schema
+    }
+
+    #[test]
+    fn test_validate() {
+        let t = TestValidate {
+            i16: -100,
+            str: "abcd",
+            child: TestChild { f32: 2.0, flag: 1 },
+        };
+        let buf = to_bytes(&t).unwrap();
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        let report = validate(&map, &schema());
+        assert_eq!(
+            report.violations,
+            vec![
+                Violation::OutOfRange {
+                    path: vec![12, 1],
+                    value: 2.0,
+                    min: -1.0,
+                    max: 1.0
+                },
+                Violation::InvalidValue {
+                    path: vec![12, 2],
+                    length: 2,
+                    expected: "bool"
+                },
+                Violation::Missing { path: vec![12, 3] },
+            ]
+        );
+        assert_eq!(
+            report.violations[1].to_string(),
+            "tag 12/2 has 2 bytes which is not bool"
+        );
+
+        // 必須のタグのみ
+        let buf = [b'T', b'E', b'S', b'T', 5, 12, 3, 3, 1, 0];
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert!(validate(&map, &schema()).is_ok());
+
+        let buf = [b'T', b'E', b'S', b'T', 6, 10, 2, 0, 101, 11, 0];
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert_eq!(
+            validate(&map, &schema()).violations,
+            vec![
+                Violation::OutOfRange {
+                    path: vec![10],
+                    value: 101.0,
+                    min: -100.0,
+                    max: 100.0
+                },
+                Violation::Length {
+                    path: vec![11],
+                    length: 0,
+                    min: 1,
+                    max: 4
+                },
+                Violation::Missing { path: vec![12] },
+            ]
+        );
+    }
+}
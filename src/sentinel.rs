@@ -0,0 +1,176 @@
+//! Out-of-range sentinel as `None`
+//!
+//! MISB ST 0601 reserves a value of the integer such as `0x8000` of `int16`
+//! to tell that the value is out of range.
+//! The sentinel is [`ScaledRepr::SENTINEL`], `Repr::MIN` for signed and `Repr::MAX` for unsigned integers.
+//!
+//! - `Sentinel::<Repr>` for `Option<Repr>` fields of the raw integer
+//! - `ScaledSentinel::<Repr, SCALE>` for `Option<f32>` or `Option<f64>` fields of [`crate::scaled::Scaled`]
+//!
+//! `None` is written as the sentinel and the sentinel is read as `None`.
+//! Add `default` to read the absent record as `None` too.
+//! Use [`crate::scaled::Scaled`] itself to read the sentinel as NaN.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Platform {
+//!     #[serde(rename = "6", with = "serde_klv::sentinel::Sentinel::<i16>", default)]
+//!     pitch: Option<i16>,
+//!     // meter per second in 0.01
+//!     #[serde(rename = "7", with = "serde_klv::sentinel::ScaledSentinel::<u16, 100>", default)]
+//!     speed: Option<f64>,
+//! }
+//!
+//! let t = Platform { pitch: None, speed: Some(1.5) };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(&buf[4..], &[8, 6, 2, 0x80, 0x00, 7, 2, 0x00, 0x96]);
+//! assert_eq!(from_bytes::<Platform>(&buf).unwrap(), t);
+//! ```
+
+use std::marker::PhantomData;
+
+use serde::{ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::scaled::{Scaled, ScaledFloat, ScaledRepr};
+
+/// Adapter of `Option<R>` with `None` as the sentinel
+pub struct Sentinel<R>(PhantomData<R>);
+
+impl<R: ScaledRepr> Sentinel<R> {
+    /// the sentinel itself in `Some` is rejected, since it is read as `None`
+    pub fn serialize<S>(value: &Option<R>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(v) if *v == R::SENTINEL => Err(ser::Error::custom(
+                "value is the out-of-range sentinel, use None",
+            )),
+            Some(v) => v.serialize(serializer),
+            None => R::SENTINEL.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<R>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v = R::deserialize(deserializer)?;
+        Ok((v != R::SENTINEL).then_some(v))
+    }
+}
+
+/// Adapter of [`Scaled`] with `None` as the sentinel
+///
+/// NaN and values out of `R` are written as the sentinel as [`Scaled`] does,
+/// so they are read as `None`.
+pub struct ScaledSentinel<R, const SCALE: u64>(PhantomData<R>);
+
+impl<R: ScaledRepr, const SCALE: u64> ScaledSentinel<R, SCALE> {
+    pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: ScaledFloat,
+        S: Serializer,
+    {
+        match value {
+            Some(v) => Scaled::<R, SCALE>::serialize(v, serializer),
+            None => R::SENTINEL.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: ScaledFloat,
+        D: Deserializer<'de>,
+    {
+        let v: T = Scaled::<R, SCALE>::deserialize(deserializer)?;
+        // Scaledは番兵をNaNとして返す
+        Ok((!v.to_f64().is_nan()).then_some(v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{ScaledSentinel, Sentinel};
+    use crate::{from_bytes, to_bytes};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestSentinel {
+        #[serde(rename = "10", with = "Sentinel::<i16>", default)]
+        pitch: Option<i16>,
+        #[serde(rename = "11", with = "Sentinel::<u8>", default)]
+        humidity: Option<u8>,
+        #[serde(rename = "12", with = "ScaledSentinel::<i32, 10_000_000>", default)]
+        latitude: Option<f64>,
+        #[serde(rename = "13", with = "ScaledSentinel::<u16, 100>", default)]
+        speed: Option<f32>,
+    }
+
+    #[test]
+    fn test_sentinel() {
+        let t = TestSentinel {
+            pitch: Some(-0x7fff),
+            humidity: Some(0xfe),
+            latitude: Some(-35.1234567),
+            speed: Some(12.34),
+        };
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(&buf[4..], &[
+            17,
+            10, 2, 0x80, 0x01,
+            11, 1, 0xfe,
+            12, 4, 0xeb, 0x10, 0x95, 0xf9,
+            13, 2, 0x04, 0xd2,
+        ]);
+        assert_eq!(from_bytes::<TestSentinel>(&buf).unwrap(), t);
+
+        // Noneは番兵として書き、番兵はNoneとして読む
+        let t = TestSentinel {
+            pitch: None,
+            humidity: None,
+            latitude: None,
+            speed: None,
+        };
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(&buf[4..], &[
+            17,
+            10, 2, 0x80, 0x00,
+            11, 1, 0xff,
+            12, 4, 0x80, 0x00, 0x00, 0x00,
+            13, 2, 0xff, 0xff,
+        ]);
+        assert_eq!(from_bytes::<TestSentinel>(&buf).unwrap(), t);
+
+        // 範囲外とNaNも番兵になる
+        let x = TestSentinel {
+            latitude: Some(300.0),
+            speed: Some(f32::NAN),
+            ..t
+        };
+        assert_eq!(to_bytes(&x).unwrap(), buf);
+
+        // 番兵そのものは書けない
+        let x = TestSentinel {
+            pitch: Some(i16::MIN),
+            ..t
+        };
+        assert!(to_bytes(&x).is_err());
+
+        // 記録がなければNone
+        let buf = [b'T', b'E', b'S', b'T', 3, 11, 1, 1];
+        let x = from_bytes::<TestSentinel>(&buf).unwrap();
+        assert_eq!(x.pitch, None);
+        assert_eq!(x.humidity, Some(1));
+    }
+}
@@ -1,12 +1,12 @@
-use std::collections::BTreeSet;
-
-use byteorder::{BigEndian, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
 use serde::{ser, Serialize};
 
 use crate::{
-    check_universal_key_len,
-    error::{Error, Result},
-    LengthOctet,
+    check_universal_key_len, encode_length,
+    error::{Error, Result, TagPath},
+    parse_length,
+    st0603::PrecisionTimestamp,
+    KeyWidth, UniversalKeyed,
 };
 
 /// Serialize to bytes
@@ -16,12 +16,136 @@ where
 {
     let mut serializer = KLVSerializer::default();
     value.serialize(&mut serializer)?;
-    // ここでKeyを合成するのが良さそう
-    Ok(serializer.concat())
+    serializer.concat()
+}
+
+/// Serialize to bytes with the universal key of [`UniversalKeyed`] instead of the struct name
+pub fn to_bytes_keyed<T>(value: &T) -> Result<Vec<u8>>
+where
+    T: Serialize + UniversalKeyed,
+{
+    let mut serializer = KLVSerializer {
+        universal_key: Some(T::KEY),
+        ..Default::default()
+    };
+    value.serialize(&mut serializer)?;
+    serializer.concat()
+}
+
+/// Serialize to bytes with pre-allocated buffer
+///
+/// Avoid re-allocation when the size of packet is known, e.g. previous packet size.
+pub fn to_bytes_with_capacity<T>(value: &T, capacity: usize) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let mut serializer = KLVSerializer {
+        output: Vec::with_capacity(capacity),
+        ..Default::default()
+    };
+    value.serialize(&mut serializer)?;
+    serializer.concat()
+}
+
+/// Serialize to bytes with config
+///
+/// Example
+/// ```
+/// use serde::Serialize;
+/// use serde_klv::{to_bytes_with_config, LengthForm, SerializerConfig};
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     u8: u8,
+/// }
+///
+/// let config = SerializerConfig {
+///     length_form: LengthForm::ForceLong(2),
+///     ..Default::default()
+/// };
+/// let buf = to_bytes_with_config(&Packet { u8: 1 }, config).unwrap();
+/// assert_eq!(&buf[4..], &[0x82, 0, 5, 10, 0x82, 0, 1, 1]);
+/// ```
+pub fn to_bytes_with_config<T>(value: &T, config: SerializerConfig) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    let mut serializer = KLVSerializer::with_config(config);
+    value.serialize(&mut serializer)?;
+    serializer.concat()
+}
+
+/// Serialize to bytes and transform values of the registered tags
+pub fn to_bytes_with_codecs<T>(value: &T, codecs: &crate::codec::CodecRegistry) -> Result<Vec<u8>>
+where
+    T: Serialize,
+{
+    codecs.encode_packet(&to_bytes(value)?)
+}
+
+/// Serialize to bytes within `max_len` bytes
+///
+/// When the packet is larger than `max_len`, records are removed in the order of `drop_order`
+/// (lowest priority first) until it fits.
+/// Returns the packet and the tags removed from it.
+///
+/// Example
+/// ```
+/// use serde::Serialize;
+/// use serde_klv::to_bytes_with_limit;
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "TEST")]
+/// struct Packet<'a> {
+///     #[serde(rename = "10")]
+///     u8: u8,
+///     #[serde(rename = "11")]
+///     note: &'a str,
+///     #[serde(rename = "12")]
+///     name: &'a str,
+/// }
+///
+/// let t = Packet { u8: 1, note: "long long note", name: "name" };
+/// let (buf, dropped) = to_bytes_with_limit(&t, 16, &[11, 12]).unwrap();
+/// assert_eq!(dropped, vec![11]);
+/// assert_eq!(&buf[4..], &[9, 10, 1, 1, 12, 4, b'n', b'a', b'm', b'e']);
+/// ```
+pub fn to_bytes_with_limit<T>(
+    value: &T,
+    max_len: usize,
+    drop_order: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>)>
+where
+    T: Serialize,
+{
+    let buf = to_bytes(value)?;
+    if buf.len() <= max_len {
+        return Ok((buf, vec![]));
+    }
+    let mut map = crate::de::KLVMap::try_from_bytes(&buf)?;
+    let mut dropped = vec![];
+    for tag in drop_order {
+        if map.remove(*tag).is_none() {
+            continue;
+        }
+        // 同じタグが複数ある場合はすべて取り除く
+        while map.remove(*tag).is_some() {}
+        dropped.push(*tag);
+        let out = map.to_bytes();
+        if out.len() <= max_len {
+            return Ok((out, dropped));
+        }
+    }
+    Err(Error::PacketTooLarge {
+        size: map.to_bytes().len(),
+        limit: max_len,
+    })
 }
 
 /// Serialize to bytes append CRC at last field
-/// バッファの最後に16bit長のChecksumを追加する
+/// バッファの最後にCheckSumCalcで指定したChecksumレコードを追加する
 pub fn to_bytes_with_checksum<T, C: crate::checksum::CheckSumCalc>(
     value: &T,
     calc: C,
@@ -29,136 +153,591 @@ pub fn to_bytes_with_checksum<T, C: crate::checksum::CheckSumCalc>(
 where
     T: Serialize,
 {
-    let mut reserved_key = BTreeSet::new();
-    reserved_key.insert(0x01);
-    let mut serializer = KLVSerializer::with_reserved_key(reserved_key);
+    let config = SerializerConfig::default().reserve_keys(&[calc.tag().into()]);
+    let mut serializer = KLVSerializer::with_config(config);
     value.serialize(&mut serializer)?;
-    // ここでKeyを合成するのが良さそう
-    Ok(serializer.concat_with_checksum(calc))
+    serializer.concat_with_checksum(calc)
+}
+
+/// Output buffer reused across [`to_bytes_into`] calls
+///
+/// The buffer keeps its allocations, so serializing packets of similar size
+/// does not allocate after the first one.
+/// It is `Send` and can be kept in an object pool shared by threads.
+///
+/// Example
+/// ```
+/// use serde::Serialize;
+/// use serde_klv::{to_bytes_into, SerializeBuffer};
+///
+/// #[derive(Serialize)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     u8: u8,
+/// }
+///
+/// let mut buf = SerializeBuffer::new();
+/// for i in 0..3 {
+///     let packet = to_bytes_into(&mut buf, &Packet { u8: i }).unwrap();
+///     assert_eq!(packet, &[b'T', b'E', b'S', b'T', 3, 10, 1, i]);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct SerializeBuffer<'a> {
+    serializer: KLVSerializer<'a>,
+}
+
+impl<'a> SerializeBuffer<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// buffer serializing with config
+    pub fn with_config(config: SerializerConfig<'a>) -> Self {
+        Self {
+            serializer: KLVSerializer::with_config(config),
+        }
+    }
+
+    /// bytes written by the last [`to_bytes_into`]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.serializer.output
+    }
+
+    /// clear the written bytes, keeping the allocation
+    pub fn clear(&mut self) {
+        self.serializer.reset();
+    }
+
+    /// allocated byte size of the output
+    pub fn capacity(&self) -> usize {
+        self.serializer.output.capacity()
+    }
+}
+
+/// Serialize into the reusable buffer and return the written bytes
+pub fn to_bytes_into<'b, T>(buf: &'b mut SerializeBuffer, value: &T) -> Result<&'b [u8]>
+where
+    T: Serialize,
+{
+    let serializer = &mut buf.serializer;
+    serializer.reset();
+    let result = value
+        .serialize(&mut *serializer)
+        .and_then(|_| serializer.finish());
+    if let Err(e) = result {
+        // 書きかけのデータは残さない
+        serializer.reset();
+        return Err(e);
+    }
+    Ok(&serializer.output)
+}
+
+/// Options of serializer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SerializerConfig<'a> {
+    /// encoding of BER length for the packet and every field
+    pub length_form: LengthForm,
+    /// omit `None` fields instead of writing zero-length record
+    ///
+    /// Applies to `Option` of nested local sets in the same way.
+    /// The deserializer reads both conventions as `None`.
+    /// A `Some` local set whose fields are all omitted is written as zero-length record,
+    /// and it is read back as `None`.
+    pub none_as_absent: bool,
+    /// encoding of the field tags
+    pub key_width: KeyWidth,
+    /// tags that top level fields can not use, such as the checksum tag
+    pub reserved_keys: Vec<u128>,
+    /// write the records of each local set in ascending order of tag regardless of field order
+    pub sort_tags: bool,
+    /// return [`Error::LengthLimit`] when a BER length exceeds this value
+    ///
+    /// Lengths above `u32::MAX` are written in 8 byte long form.
+    pub max_value_len: Option<usize>,
+    /// top level tag filled with the timestamp at encode time, see [`SerializerConfig::stamp_tag`]
+    pub stamp: Option<(u8, TimestampSource)>,
+    /// tags of the field names, see [`SerializerConfig::tag_names`]
+    pub tag_names: &'a [(&'a str, u128)],
+}
+
+impl<'a> SerializerConfig<'a> {
+    /// add tags to `reserved_keys`
+    pub fn reserve_keys(mut self, keys: &[u128]) -> Self {
+        self.reserved_keys.extend_from_slice(keys);
+        self
+    }
+
+    /// write the fields renamed to the names of the table with their tags
+    ///
+    /// Field names not in the table are parsed as tag numbers.
+    /// The table is shared by all local sets of the packet,
+    /// read the packet with the same table in [`crate::DeserializerConfig::tag_names`].
+    /// Returns [`Error::Key`] when a tag does not fit in `key_width`, so set `key_width` first.
+    ///
+    /// Example
+    /// ```
+    /// use serde::{Deserialize, Serialize};
+    /// use serde_klv::{from_bytes_with_config, to_bytes_with_config, DeserializerConfig, SerializerConfig};
+    ///
+    /// const TAGS: &[(&str, u128)] = &[("sensor_latitude", 13), ("sensor_longitude", 14)];
+    ///
+    /// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    /// #[serde(rename = "TEST")]
+    /// struct Sensor {
+    ///     #[serde(rename = "sensor_latitude")]
+    ///     latitude: i32,
+    ///     #[serde(rename = "sensor_longitude")]
+    ///     longitude: i32,
+    ///     #[serde(rename = "15")]
+    ///     altitude: u16,
+    /// }
+    ///
+    /// let t = Sensor { latitude: 1, longitude: -1, altitude: 2 };
+    /// let config = SerializerConfig::default().tag_names(TAGS).unwrap();
+    /// let buf = to_bytes_with_config(&t, config).unwrap();
+    /// assert_eq!(&buf[4..], &[16, 13, 4, 0, 0, 0, 1, 14, 4, 0xff, 0xff, 0xff, 0xff, 15, 2, 0, 2]);
+    /// let config = DeserializerConfig::default().tag_names(TAGS).unwrap();
+    /// assert_eq!(from_bytes_with_config::<Sensor>(&buf, config).unwrap(), t);
+    /// ```
+    pub fn tag_names(mut self, names: &'a [(&'a str, u128)]) -> Result<Self> {
+        self.key_width.check_names(names)?;
+        self.tag_names = names;
+        Ok(self)
+    }
+
+    /// encode deterministic bytes for golden files and content hashes
+    ///
+    /// Records of each local set are sorted by tag keeping the order of repeated records,
+    /// and every BER length is written in the shortest form without padding.
+    /// The same value is encoded to the same bytes regardless of the field order of the struct,
+    /// and this encoding is kept across versions of the crate.
+    /// A timestamp of [`TimestampSource::SystemNow`] is not deterministic by nature.
+    ///
+    /// This is a preset of `sort_tags` and `length_form` applied when called,
+    /// it is not checked at serialize time.
+    /// Setting either field afterwards gives non-canonical bytes, so call it last.
+    ///
+    /// Example
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_klv::{to_bytes_with_config, LengthForm, SerializerConfig};
+    ///
+    /// #[derive(Serialize)]
+    /// #[serde(rename = "TEST")]
+    /// struct Packet {
+    ///     #[serde(rename = "11")]
+    ///     b: u8,
+    ///     #[serde(rename = "10")]
+    ///     a: u8,
+    /// }
+    ///
+    /// let config = SerializerConfig {
+    ///     length_form: LengthForm::ForceLong(2),
+    ///     ..Default::default()
+    /// }
+    /// .canonical();
+    /// let buf = to_bytes_with_config(&Packet { b: 2, a: 1 }, config).unwrap();
+    /// assert_eq!(&buf[4..], &[6, 10, 1, 1, 11, 1, 2]);
+    /// ```
+    pub fn canonical(mut self) -> Self {
+        self.sort_tags = true;
+        self.length_form = LengthForm::Minimal;
+        self
+    }
+
+    /// fill the top level tag with the 8 byte precision timestamp of the source
+    ///
+    /// The value of the field of the tag is ignored.
+    /// When the struct has not the field or skips it, the record is inserted at the head of the packet.
+    ///
+    /// Example
+    /// ```
+    /// use serde::Serialize;
+    /// use serde_klv::st0603::PrecisionTimestamp;
+    /// use serde_klv::{to_bytes_with_config, SerializerConfig, TimestampSource};
+    ///
+    /// #[derive(Serialize)]
+    /// #[serde(rename = "TEST")]
+    /// struct Packet {
+    ///     #[serde(rename = "10")]
+    ///     u8: u8,
+    /// }
+    ///
+    /// let ts = PrecisionTimestamp::from_micros(0x0102);
+    /// let config = SerializerConfig::default().stamp_tag(2, TimestampSource::Fixed(ts));
+    /// let buf = to_bytes_with_config(&Packet { u8: 1 }, config).unwrap();
+    /// assert_eq!(&buf[4..], &[13, 2, 8, 0, 0, 0, 0, 0, 0, 1, 2, 10, 1, 1]);
+    ///
+    /// let config = SerializerConfig::default().stamp_tag(2, TimestampSource::SystemNow);
+    /// let buf = to_bytes_with_config(&Packet { u8: 1 }, config).unwrap();
+    /// assert_eq!(&buf[5..7], &[2, 8]);
+    /// ```
+    pub fn stamp_tag(mut self, tag: u8, source: TimestampSource) -> Self {
+        self.stamp = Some((tag, source));
+        self
+    }
+}
+
+/// Source of the timestamp written by [`SerializerConfig::stamp_tag`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampSource {
+    /// system clock at the serialization of each packet
+    SystemNow,
+    /// the same timestamp for every packet, such as replaying recorded data
+    Fixed(PrecisionTimestamp),
+}
+
+impl TimestampSource {
+    pub fn timestamp(&self) -> PrecisionTimestamp {
+        match self {
+            Self::SystemNow => PrecisionTimestamp::now(),
+            Self::Fixed(ts) => *ts,
+        }
+    }
+}
+
+/// Encoding rule of BER length
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LengthForm {
+    /// shortest form out of short form and long form of 1, 2, 4 or 8 octets
+    #[default]
+    Minimal,
+    /// shortest form including long form of 3 octets for the producers writing uint24 lengths
+    Compact,
+    /// always long form with the number of subsequent octets, supported only {1,2,3,4,8}
+    ForceLong(u8),
+}
+
+// 多くのパケットは256byte未満に収まるため最初に確保しておく
+const DEFAULT_CAPACITY: usize = 256;
+
+// 使用済みKeyの集合
+// 1byteに収まるKeyは256bitのビットマップで持ちヒープを使わない
+// それより大きいKeyのみVecで持つ
+#[derive(Debug, Clone, Default)]
+struct KeySet {
+    small: [u64; 4],
+    wide: Vec<u128>,
+}
+
+impl KeySet {
+    // 新たに追加された場合はtrue
+    fn insert(&mut self, key: u128) -> bool {
+        match u8::try_from(key) {
+            Ok(key) => {
+                let (index, bit) = ((key >> 6) as usize, 1_u64 << (key & 0x3f));
+                let inserted = self.small[index] & bit == 0;
+                self.small[index] |= bit;
+                inserted
+            }
+            Err(_) if self.wide.contains(&key) => false,
+            Err(_) => {
+                self.wide.push(key);
+                true
+            }
+        }
+    }
+    fn clear(&mut self) {
+        self.small = [0; 4];
+        self.wide.clear();
+    }
+    fn contains(&self, key: u128) -> bool {
+        match u8::try_from(key) {
+            Ok(key) => self.small[(key >> 6) as usize] & (1_u64 << (key & 0x3f)) != 0,
+            Err(_) => self.wide.contains(&key),
+        }
+    }
 }
 
 // KLVシリアライザ
 // 基本的にはKLVのうちVを行う
-// structに限りKLの処理が必要でTopLevelだけはuniversal_keyで保持する
+// structに限りKLの処理が必要でTopLevelだけはuniversal_keyを書き込む
 // それより深い階層では個別のキーではなく親のkey
 #[derive(Debug)]
-struct KLVSerializer {
-    universal_key: Vec<u8>,
+struct KLVSerializer<'c> {
     // 現在の階層深さ。KLのためには1階層以上でなければならない
     depth: usize,
-    // 各階層ごとのKLVシリアライズ結果
+    // シリアライズ結果
     // KLVはVをシリアライズするまでLが分からないため
-    // depth階層のbufferをVのシリアライズ領域に使い
-    // Vのシリアライズが終わったらその長さを元にLを算出し
-    // depth-1階層にKLVで書き込む
-    output: Vec<Vec<u8>>,
+    // Lの位置に1byteの仮の値を置いてVを書き込み
+    // Vのシリアライズが終わったらその長さを元にLを書き戻す
+    output: Vec<u8>,
+    // TopLevelのVの開始位置。structでない場合はNone
+    header: Option<usize>,
+    // TopLevelが同じstructのパケットの列であるか
+    packets: bool,
     // 各層毎の使用済みKeyマップ
-    keys: Vec<BTreeSet<u8>>,
+    keys: Vec<KeySet>,
+    // 各層のstructのVの開始位置
+    starts: Vec<usize>,
+    // シリアライズ中のフィールドのKeyを親から順に並べたもの
+    path: Vec<u128>,
     // checksumのような予約済みのキー
-    reserved_key: BTreeSet<u8>,
+    reserved_key: KeySet,
+    // UniversalKeyedで与えられたUniversalKey。structの名前より優先する
+    universal_key: Option<&'static [u8]>,
+    // 直前にシリアライズした値がNoneであったか
+    none: bool,
+    // シリアライズ中のフィールドのKeyの位置
+    field_start: usize,
+    // 繰り返しフィールドのKey
+    repeated: Vec<u128>,
+    // 直前のフィールドを繰り返しフィールドとして書き込んだか
+    repeated_done: bool,
+    config: SerializerConfig<'c>,
 }
 
-impl Default for KLVSerializer {
+impl Default for KLVSerializer<'_> {
     fn default() -> Self {
         Self {
-            universal_key: vec![],
             depth: 0,
-            output: vec![vec![]],
-            keys: vec![BTreeSet::new()],
-            reserved_key: BTreeSet::new(),
+            output: Vec::with_capacity(DEFAULT_CAPACITY),
+            header: None,
+            packets: false,
+            keys: vec![KeySet::default()],
+            starts: vec![],
+            path: vec![],
+            reserved_key: KeySet::default(),
+            universal_key: None,
+            none: false,
+            field_start: 0,
+            repeated: vec![],
+            repeated_done: false,
+            config: SerializerConfig::default(),
         }
     }
 }
 
-impl KLVSerializer {
-    fn with_reserved_key(reserved_key: BTreeSet<u8>) -> Self {
+impl<'c> KLVSerializer<'c> {
+    fn with_config(config: SerializerConfig<'c>) -> Self {
+        let mut reserved_key = KeySet::default();
+        for key in config.reserved_keys.iter() {
+            reserved_key.insert(*key);
+        }
         Self {
-            universal_key: vec![],
-            depth: 0,
-            output: vec![vec![]],
-            keys: vec![BTreeSet::new()],
             reserved_key,
+            config,
+            ..Default::default()
         }
     }
     fn next_depth(&mut self) {
         self.depth += 1;
-        self.output.push(vec![]);
-        self.keys.push(BTreeSet::new());
+        self.keys.push(KeySet::default());
+        self.starts.push(self.output.len());
     }
     fn end_depth(&mut self) -> Result<()> {
-        let _cache = self.output.pop().unwrap();
         let _keys = self.keys.pop().unwrap();
+        self.starts.pop();
         self.depth -= 1;
         Ok(())
     }
-    fn write_key(&mut self, key: u8) -> Result<()> {
-        let index = self.depth - 1;
-        if index == 0 && self.reserved_key.contains(&key) {
-            return Err(Error::Key(format!("key is reserved: {}", key)));
+    // この階層のレコードをタグの昇順に並べ替える。同じタグは書き込んだ順を保つ
+    fn sort_records(&mut self) -> Result<()> {
+        let start = *self.starts.last().unwrap();
+        let mut records = vec![];
+        let mut position = start;
+        while position < self.output.len() {
+            let (tag, key_len) = self
+                .config
+                .key_width
+                .decode(&self.output[position..])
+                .ok_or_else(|| Error::Key(format!("invalid tag at offset {}", position)))?;
+            let (length_len, content_len) = parse_length(&self.output[position + key_len..])
+                .map_err(Error::UnsupportedLength)?;
+            let end = position + key_len + length_len + content_len;
+            records.push((tag, position..end));
+            position = end;
+        }
+        if records.windows(2).all(|x| x[0].0 <= x[1].0) {
+            return Ok(());
         }
-        if let Some(n) = self.keys.get_mut(index) {
+        records.sort_by_key(|x| x.0);
+        let mut sorted = Vec::with_capacity(self.output.len() - start);
+        for (_, range) in records {
+            sorted.extend_from_slice(&self.output[range]);
+        }
+        self.output.truncate(start);
+        self.output.extend_from_slice(&sorted);
+        Ok(())
+    }
+    fn write_key(&mut self, key: u128) -> Result<()> {
+        // エラー時のみKeyまでのパスを組み立てる
+        let path = |path: &[u128]| [path, &[key]].concat();
+        if self.depth == 1 && self.reserved_key.contains(key) {
+            return Err(Error::Key(format!(
+                "key is reserved: {}",
+                TagPath(&path(&self.path))
+            )));
+        }
+        if let Some(n) = self.keys.last_mut() {
             if !n.insert(key) {
                 return Err(Error::Key(format!(
-                    "already use field {} in depth {}",
-                    key, index
+                    "already use field {}",
+                    TagPath(&path(&self.path))
                 )));
             }
         } else {
             return Err(Error::Message("has not key map".to_string()));
         }
-        self.output[index].push(key);
+        self.config
+            .key_width
+            .encode(key, &mut self.output)
+            .ok_or_else(|| {
+                Error::Key(format!(
+                    "key {} does not fit {:?}",
+                    TagPath(&path(&self.path)),
+                    self.config.key_width
+                ))
+            })
+    }
+    // TopLevelのタイムスタンプのタグであればその値
+    fn stamp_of(&self, key: u128) -> Option<PrecisionTimestamp> {
+        match self.config.stamp {
+            Some((tag, source)) if self.depth == 1 && key == tag as u128 => {
+                Some(source.timestamp())
+            }
+            _ => None,
+        }
+    }
+    // タイムスタンプのレコードがなければ階層の先頭に挿入する
+    fn insert_stamp(&mut self) -> Result<()> {
+        let (tag, source) = match self.config.stamp {
+            Some(x) if self.depth == 1 => x,
+            _ => return Ok(()),
+        };
+        let used = self.keys.last().map(|x| x.contains(tag.into()));
+        if used.unwrap_or(true) {
+            return Ok(());
+        }
+        let mark = self.output.len();
+        self.write_key(tag.into())?;
+        let value_start = self.begin_length();
+        self.output
+            .extend_from_slice(&source.timestamp().as_micros().to_be_bytes());
+        self.patch_length(value_start, 0)?;
+        let start = *self.starts.last().unwrap();
+        let len = self.output.len() - mark;
+        self.output[start..].rotate_right(len);
         Ok(())
     }
     fn get_cache(&mut self) -> Result<&mut Vec<u8>> {
-        Ok(self.output.last_mut().unwrap())
-    }
-    fn write_lv(&mut self) -> Result<()> {
-        // self outputを&mut参照するのでmutable制限を超えるためにcacheを一度取り出す
-        let mut cache = self.output.pop().unwrap();
-        let output = self.output.last_mut().unwrap();
-        let len = cache.len();
-        let _len = LengthOctet::length_to_buf(output, len).map_err(Error::IO)?;
-        output.append(&mut cache);
-        self.output.push(cache);
+        Ok(&mut self.output)
+    }
+    // パケットのUniversalKeyと仮のLを書き込む
+    fn begin_packet(&mut self, key: &[u8]) -> Result<()> {
+        check_universal_key_len(key)?;
+        trace!("universal key {:02x?}", key);
+        self.output.extend_from_slice(key);
+        self.header = Some(self.begin_length());
         Ok(())
     }
-    fn concat(self) -> Vec<u8> {
-        let Self {
-            universal_key: mut key,
-            mut output,
-            ..
-        } = self;
-        let output = output.pop().unwrap();
-        LengthOctet::length_to_buf(&mut key, output.len()).unwrap();
-        key.extend_from_slice(&output);
-        key
+    // Lの仮の値を書き込みVの開始位置を返す
+    fn begin_length(&mut self) -> usize {
+        self.output.push(0);
+        self.output.len()
+    }
+    // Vの開始位置から末尾までの長さにextraを加えた値でLを書き戻す
+    // Lが1byteに収まらない場合はVを後ろにずらす
+    fn patch_length(&mut self, start: usize, extra: usize) -> Result<()> {
+        let len = self.output.len() - start + extra;
+        if let Some(limit) = self.config.max_value_len.filter(|x| len > *x) {
+            return Err(Error::LengthLimit {
+                offset: start - 1,
+                length: len,
+                limit,
+            });
+        }
+        let mut octets = [0_u8; 9];
+        let mut writer: &mut [u8] = &mut octets;
+        let n = encode_length(&mut writer, len, self.config.length_form)?;
+        if n == 1 {
+            self.output[start - 1] = octets[0];
+        } else {
+            self.output
+                .splice(start - 1..start, octets[..n].iter().copied());
+        }
+        Ok(())
+    }
+    // 確保済みの領域を残して初期状態に戻す
+    fn reset(&mut self) {
+        self.depth = 0;
+        self.output.clear();
+        self.header = None;
+        self.packets = false;
+        self.keys.truncate(1);
+        match self.keys.first_mut() {
+            Some(keys) => keys.clear(),
+            None => self.keys.push(KeySet::default()),
+        }
+        self.starts.clear();
+        self.path.clear();
+        self.none = false;
+        self.field_start = 0;
+        self.repeated.clear();
+        self.repeated_done = false;
+    }
+    // TopLevelのLを書き戻す
+    fn finish(&mut self) -> Result<()> {
+        // パケット毎にLを書き戻し済み
+        if self.packets {
+            return Ok(());
+        }
+        let start = match self.header {
+            Some(x) => x,
+            None => {
+                self.output.insert(0, 0);
+                1
+            }
+        };
+        self.patch_length(start, 0)
+    }
+    fn concat(mut self) -> Result<Vec<u8>> {
+        self.finish()?;
+        Ok(self.output)
     }
     // checksum付きのEncode
-    // MISB ST 0601.8の仕様に近いものとし、ChecksumTagのL部分までがchecksum計算の対象とする
-    fn concat_with_checksum<C: crate::checksum::CheckSumCalc>(self, crc: C) -> Vec<u8> {
-        use crate::checksum::CHECKSUM_KEY_LENGTH;
-
-        let Self {
-            universal_key: mut key,
-            mut output,
-            ..
-        } = self;
-        let output = output.pop().unwrap();
-        // 4 = K + L + V(2)
-        LengthOctet::length_to_buf(&mut key, output.len() + 4).unwrap();
-        key.extend_from_slice(&output);
-        key.extend_from_slice(CHECKSUM_KEY_LENGTH);
+    // MISB ST 0601.8の仕様に近いものとし、既定ではChecksumTagのL部分までがchecksum計算の対象とする
+    fn concat_with_checksum<C: crate::checksum::CheckSumCalc>(mut self, crc: C) -> Result<Vec<u8>> {
+        if self.packets {
+            return Err(Error::Unsupported(
+                "checksum for sequence of packets is not supported".to_string(),
+            ));
+        }
+        let start = match self.header {
+            Some(x) => x,
+            None => {
+                self.output.insert(0, 0);
+                1
+            }
+        };
+        let width = crc.width();
+        // K + L + V(width)
+        self.output
+            .extend_from_slice(&[crc.tag(), width.size() as u8]);
+        self.patch_length(start, width.size())?;
+        // Lは長形式に伸びている場合がある
+        let key_len = start - 1;
+        let (length_len, _) =
+            parse_length(&self.output[key_len..]).map_err(Error::UnsupportedLength)?;
         // calc checksum and write
-        let crc_code = crc.checksum(&key);
-        key.write_u16::<byteorder::BigEndian>(crc_code).unwrap();
-        key
+        let mut output = self.output;
+        let covered = crc
+            .coverage()
+            .covered_range_of(key_len, key_len + length_len, output.len());
+        let crc_code = width.truncate(crc.checksum(&output[covered]));
+        output
+            .write_uint::<BigEndian>(crc_code as u64, width.size())
+            .map_err(Error::IO)?;
+        Ok(output)
     }
 }
 
 // TODO
 // V変換を普通にやる
 // StructはV結果を見てLを決める
-impl<'a> ser::Serializer for &'a mut KLVSerializer {
+impl<'a, 'c> ser::Serializer for &'a mut KLVSerializer<'c> {
     type Ok = ();
     type Error = Error;
 
@@ -183,21 +762,28 @@ impl<'a> ser::Serializer for &'a mut KLVSerializer {
     fn serialize_i16(self, v: i16) -> Result<Self::Ok> {
         self.get_cache()?
             .write_i16::<BigEndian>(v)
-            .map_err(|e| Error::Encode(format!("encodind error i16 {v} to byte. {e}")))?;
+            .map_err(Error::IO)?;
         Ok(())
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok> {
         self.get_cache()?
             .write_i32::<BigEndian>(v)
-            .map_err(|e| Error::Encode(format!("encodind error i32 {v} to byte. {e}")))?;
+            .map_err(Error::IO)?;
         Ok(())
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
         self.get_cache()?
             .write_i64::<BigEndian>(v)
-            .map_err(|e| Error::Encode(format!("encodind error i64 {v} to byte. {e}")))?;
+            .map_err(Error::IO)?;
+        Ok(())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Self::Ok> {
+        self.get_cache()?
+            .write_i128::<BigEndian>(v)
+            .map_err(Error::IO)?;
         Ok(())
     }
 
@@ -209,35 +795,42 @@ impl<'a> ser::Serializer for &'a mut KLVSerializer {
     fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
         self.get_cache()?
             .write_u16::<BigEndian>(v)
-            .map_err(|e| Error::Encode(format!("encodind error u16 {v} to byte. {e}")))?;
+            .map_err(Error::IO)?;
         Ok(())
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
         self.get_cache()?
             .write_u32::<BigEndian>(v)
-            .map_err(|e| Error::Encode(format!("encodind error u32 {v} to byte. {e}")))?;
+            .map_err(Error::IO)?;
         Ok(())
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
         self.get_cache()?
             .write_u64::<BigEndian>(v)
-            .map_err(|e| Error::Encode(format!("encodind error u64 {v} to byte. {e}")))?;
+            .map_err(Error::IO)?;
+        Ok(())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Self::Ok> {
+        self.get_cache()?
+            .write_u128::<BigEndian>(v)
+            .map_err(Error::IO)?;
         Ok(())
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok> {
         self.get_cache()?
             .write_f32::<BigEndian>(v)
-            .map_err(|e| Error::Encode(format!("encodind error f32 {v} to byte. {e}")))?;
+            .map_err(Error::IO)?;
         Ok(())
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok> {
         self.get_cache()?
             .write_f64::<BigEndian>(v)
-            .map_err(|e| Error::Encode(format!("encodind error f64 {v} to byte. {e}")))?;
+            .map_err(Error::IO)?;
         Ok(())
     }
 
@@ -256,6 +849,7 @@ impl<'a> ser::Serializer for &'a mut KLVSerializer {
     }
 
     fn serialize_none(self) -> Result<Self::Ok> {
+        self.none = true;
         Ok(())
     }
 
@@ -267,50 +861,110 @@ impl<'a> ser::Serializer for &'a mut KLVSerializer {
     }
 
     fn serialize_unit(self) -> Result<Self::Ok> {
-        self.serialize_none()
+        Ok(())
     }
 
     fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
-        self.serialize_none()
+        Ok(())
     }
 
+    // UnitなEnumは値の表現が実装に依存するため書き込まない
     fn serialize_unit_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
     ) -> Result<Self::Ok> {
-        todo!()
-        // // UnitなEnumは名前が固定で実装に依存して値が可変であるためstrで保持するのが一般的
-        // println!("serialize_unit_variant {} {}", _name, variant);
-        // self.serialize_str(variant)
+        Err(Error::Unsupported(format!(
+            "unit variant {}::{} is not supported",
+            name, variant
+        )))
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(
-        self,
-        _name: &'static str,
-        _value: &T,
-    ) -> Result<Self::Ok>
+    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T) -> Result<Self::Ok>
     where
         T: Serialize,
     {
-        unimplemented!()
+        match name {
+            crate::repeated::REPEATED => {
+                // フィールドのKLを取り消して要素毎にKLVを書き込む
+                if self.depth == 0 {
+                    return Err(Error::NeedKey);
+                }
+                let (key, _) = self
+                    .config
+                    .key_width
+                    .decode(&self.output[self.field_start..])
+                    .ok_or(Error::NeedKey)?;
+                self.output.truncate(self.field_start);
+                self.repeated.push(key);
+                value.serialize(&mut *self)?;
+                self.repeated.pop();
+                self.repeated_done = true;
+                Ok(())
+            }
+            crate::repeated::ELEMENT => {
+                let key = *self.repeated.last().ok_or(Error::NeedKey)?;
+                self.config
+                    .key_width
+                    .encode(key, &mut self.output)
+                    .ok_or(Error::NeedKey)?;
+                let start = self.begin_length();
+                value.serialize(&mut *self)?;
+                self.repeated_done = false;
+                self.patch_length(start, 0)
+            }
+            crate::unknown::RECORD => {
+                // タグと値を書き込んだ後にKLVへ組み直す
+                if self.depth == 0 {
+                    return Err(Error::NeedKey);
+                }
+                let mark = self.output.len();
+                value.serialize(&mut *self)?;
+                let record = self.output.split_off(mark);
+                if record.len() < 16 {
+                    return Err(Error::NeedKey);
+                }
+                let (tag, value) = record.split_at(16);
+                let key = BigEndian::read_u128(tag);
+                self.write_key(key)?;
+                let start = self.begin_length();
+                self.output.extend_from_slice(value);
+                self.patch_length(start, 0)
+            }
+            // 通常のnewtypeは中身をそのまま書き込む
+            _ => value.serialize(self),
+        }
     }
 
+    // TopLevelのenumはvariant名をUniversalKeyとしてパケットを書き込む
     fn serialize_newtype_variant<T: ?Sized>(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
-        _value: &T,
+        variant: &'static str,
+        value: &T,
     ) -> Result<Self::Ok>
     where
         T: Serialize,
     {
-        unimplemented!()
+        if self.depth != 0 {
+            return Err(Error::Unsupported(format!(
+                "enum {} is supported only as packet",
+                name
+            )));
+        }
+        let universal_key = self.universal_key.replace(variant.as_bytes());
+        let result = value.serialize(&mut *self);
+        self.universal_key = universal_key;
+        result
     }
 
     fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        // TopLevelの列は要素毎に別のパケットとして書き込む
+        if self.depth == 0 {
+            self.packets = true;
+        }
         Ok(self)
     }
 
@@ -328,12 +982,15 @@ impl<'a> ser::Serializer for &'a mut KLVSerializer {
 
     fn serialize_tuple_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        unimplemented!()
+        Err(Error::Unsupported(format!(
+            "tuple variant {}::{} is not supported",
+            name, variant
+        )))
     }
 
     fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
@@ -342,8 +999,8 @@ impl<'a> ser::Serializer for &'a mut KLVSerializer {
 
     fn serialize_struct(self, name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
         if self.depth == 0 {
-            check_universal_key_len(name)?;
-            self.universal_key.extend_from_slice(name.as_bytes())
+            let key = self.universal_key.unwrap_or(name.as_bytes());
+            self.begin_packet(key)?;
         }
         self.next_depth();
         Ok(self)
@@ -351,16 +1008,24 @@ impl<'a> ser::Serializer for &'a mut KLVSerializer {
 
     fn serialize_struct_variant(
         self,
-        _name: &'static str,
+        name: &'static str,
         _variant_index: u32,
-        _variant: &'static str,
+        variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        unimplemented!()
+        if self.depth != 0 {
+            return Err(Error::Unsupported(format!(
+                "enum {} is supported only as packet",
+                name
+            )));
+        }
+        self.begin_packet(variant.as_bytes())?;
+        self.next_depth();
+        Ok(self)
     }
 }
 
-impl<'a> ser::SerializeStruct for &'a mut KLVSerializer {
+impl<'a, 'c> ser::SerializeStruct for &'a mut KLVSerializer<'c> {
     type Ok = ();
     type Error = Error;
 
@@ -368,20 +1033,60 @@ impl<'a> ser::SerializeStruct for &'a mut KLVSerializer {
     where
         T: ?Sized + Serialize,
     {
-        let key = key
-            .parse::<u8>()
-            .map_err(|e| Error::Key(format!("failed t kparse key str to u8 {} {}", key, e)))?;
+        // UnknownTagsは各レコードがKLVを書き込む
+        if key == crate::unknown::UNKNOWN_TAGS {
+            return value.serialize(&mut **self);
+        }
+        let key = self
+            .config
+            .key_width
+            .parse_named(self.config.tag_names, key)
+            .ok_or_else(|| {
+                Error::Key(format!(
+                    "failed to parse key str {} as {:?}",
+                    key, self.config.key_width
+                ))
+            })?;
 
-        // cacheにValue書き出し
-        value.serialize(&mut **self)?;
         // outputにKey書き出し
+        let key_start = self.output.len();
         self.write_key(key)?;
-        // outputにLengthValue書き出し
-        self.write_lv()
+        // outputにValue書き出し後にLengthを書き戻す
+        let start = self.begin_length();
+        self.none = false;
+        self.field_start = key_start;
+        self.path.push(key);
+        match self.stamp_of(key) {
+            Some(ts) => ts.serialize(&mut **self)?,
+            None => value.serialize(&mut **self)?,
+        }
+        self.path.pop();
+        if std::mem::take(&mut self.repeated_done) {
+            trace!(tag = key, offset = key_start, "repeated field");
+            return Ok(());
+        }
+        if self.none && self.config.none_as_absent {
+            // Noneの場合はKLごと取り消す
+            trace!(tag = key, offset = key_start, "omit none field");
+            self.none = false;
+            self.output.truncate(key_start);
+            return Ok(());
+        }
+        trace!(
+            tag = key,
+            offset = key_start,
+            length = self.output.len() - start,
+            "field"
+        );
+        self.patch_length(start, 0)
     }
 
     fn end(self) -> Result<()> {
         // まだ階層が低い。ここではStructのKeyを書いてCacheをLVする必要がある
+        self.insert_stamp()?;
+        if self.config.sort_tags {
+            self.sort_records()?;
+        }
         self.end_depth()?;
         Ok(())
     }
@@ -389,7 +1094,7 @@ impl<'a> ser::SerializeStruct for &'a mut KLVSerializer {
 
 // 個別のLは省略する
 // LはSeq全体長のみ、Vは全て同じ型とする
-impl<'a> ser::SerializeSeq for &'a mut KLVSerializer {
+impl<'a, 'c> ser::SerializeSeq for &'a mut KLVSerializer<'c> {
     type Ok = ();
     type Error = Error;
 
@@ -397,7 +1102,13 @@ impl<'a> ser::SerializeSeq for &'a mut KLVSerializer {
     where
         T: Serialize,
     {
-        value.serialize(&mut **self)
+        value.serialize(&mut **self)?;
+        if self.packets && self.depth == 0 {
+            // パケットはstructでなければならない
+            let start = self.header.take().ok_or(Error::NeedKey)?;
+            self.patch_length(start, 0)?;
+        }
+        Ok(())
     }
 
     fn end(self) -> Result<()> {
@@ -407,7 +1118,7 @@ impl<'a> ser::SerializeSeq for &'a mut KLVSerializer {
 
 // Seqと同じく個別のLを省略する
 // シリアライズ、デシリアライズの型が同じなら長さは自明となる
-impl<'a> ser::SerializeTuple for &'a mut KLVSerializer {
+impl<'a, 'c> ser::SerializeTuple for &'a mut KLVSerializer<'c> {
     type Ok = ();
     type Error = Error;
 
@@ -423,7 +1134,7 @@ impl<'a> ser::SerializeTuple for &'a mut KLVSerializer {
     }
 }
 
-impl<'a> ser::SerializeTupleStruct for &'a mut KLVSerializer {
+impl<'a, 'c> ser::SerializeTupleStruct for &'a mut KLVSerializer<'c> {
     type Ok = ();
     type Error = Error;
 
@@ -439,7 +1150,7 @@ impl<'a> ser::SerializeTupleStruct for &'a mut KLVSerializer {
     }
 }
 
-impl<'a> ser::SerializeTupleVariant for &'a mut KLVSerializer {
+impl<'a, 'c> ser::SerializeTupleVariant for &'a mut KLVSerializer<'c> {
     type Ok = ();
     type Error = Error;
 
@@ -455,7 +1166,7 @@ impl<'a> ser::SerializeTupleVariant for &'a mut KLVSerializer {
     }
 }
 
-impl<'a> ser::SerializeMap for &'a mut KLVSerializer {
+impl<'a, 'c> ser::SerializeMap for &'a mut KLVSerializer<'c> {
     type Ok = ();
     type Error = Error;
 
@@ -463,14 +1174,14 @@ impl<'a> ser::SerializeMap for &'a mut KLVSerializer {
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        Err(Error::Unsupported("map is not supported".to_string()))
     }
 
     fn serialize_value<T>(&mut self, _value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        Err(Error::Unsupported("map is not supported".to_string()))
     }
 
     fn end(self) -> Result<()> {
@@ -478,7 +1189,7 @@ impl<'a> ser::SerializeMap for &'a mut KLVSerializer {
     }
 }
 
-impl<'a> ser::SerializeStructVariant for &'a mut KLVSerializer {
+impl<'a, 'c> ser::SerializeStructVariant for &'a mut KLVSerializer<'c> {
     type Ok = ();
     type Error = Error;
 
@@ -486,12 +1197,11 @@ impl<'a> ser::SerializeStructVariant for &'a mut KLVSerializer {
     where
         T: ?Sized + Serialize,
     {
-        key.serialize(&mut **self)?;
-        value.serialize(&mut **self)
+        ser::SerializeStruct::serialize_field(self, key, value)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        ser::SerializeStruct::end(self)
     }
 }
 
@@ -501,9 +1211,13 @@ mod tests {
 
     use serde::{Deserialize, Serialize};
 
-    use crate::de::{from_bytes, KLVMap};
+    use crate::de::{from_bytes, from_bytes_with_config, DeserializerConfig, KLVMap};
     use crate::error::Error;
-    use crate::ser::{to_bytes, KLVSerializer};
+    use crate::ser::{
+        to_bytes, to_bytes_with_capacity, to_bytes_with_config, to_bytes_with_limit, KLVSerializer,
+        KeySet, LengthForm, SerializerConfig,
+    };
+    use crate::KeyWidth;
 
     // データが空でもエラーにならないこと
     #[test]
@@ -569,10 +1283,32 @@ mod tests {
         let t = TestSameName { bbb: true, u8: 128 };
         let res = to_bytes(&t);
         match res {
-            Err(Error::Key(_)) => {}
+            Err(Error::Key(msg)) => assert_eq!(msg, "already use field 10"),
             _ => unreachable!(),
         }
 
+        // 入れ子の重複はKeyのパスを示す
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestSameNameChild {
+            #[serde(rename = "3")]
+            bbb: bool,
+            #[serde(rename = "3")]
+            u8: u8,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TESTDATA00000000")]
+        struct TestSameNameParent {
+            #[serde(rename = "48")]
+            child: TestSameNameChild,
+        }
+        let t = TestSameNameParent {
+            child: TestSameNameChild { bbb: true, u8: 128 },
+        };
+        match to_bytes(&t) {
+            Err(Error::Key(msg)) => assert_eq!(msg, "already use field 48/3"),
+            x => unreachable!("{:?}", x),
+        }
+
         #[derive(Debug, Serialize, Deserialize, PartialEq)]
         struct TestNoUniversalKey {
             #[serde(rename = "10")]
@@ -605,7 +1341,7 @@ mod tests {
 
         let res = from_bytes::<TestTargetOtherUniversalKey>(&reference);
         match res {
-            Err(Error::Key(_)) => {}
+            Err(Error::UnmatchedKey { expected, .. }) => assert_eq!(expected, b"TESTDATA00000001"),
             _ => unreachable!(),
         }
     }
@@ -688,7 +1424,7 @@ mod tests {
         struct TestTimestamp<'a> {
             #[serde(rename = "30")]
             str: &'a str,
-            #[serde(rename = "31", with = "timestamp_micro")]
+            #[serde(rename = "31", with = "crate::timestamps::micros_u64")]
             ts: SystemTime,
         }
         let t = TestTimestamp {
@@ -803,7 +1539,7 @@ mod tests {
             str: &'a str,
             #[serde(rename = "61", with = "serde_bytes")]
             bytes: &'a [u8],
-            #[serde(rename = "62", with = "timestamp_micro")]
+            #[serde(rename = "62", with = "crate::timestamps::micros_u64")]
             ts: SystemTime,
             #[serde(rename = "63")]
             child: TestChild,
@@ -847,33 +1583,6 @@ mod tests {
         }
     }
 
-    mod timestamp_micro {
-        use std::time::{Duration, SystemTime};
-
-        use serde::{Deserialize, Deserializer, Serializer};
-
-        pub fn serialize<S>(date: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
-        where
-            S: Serializer,
-        {
-            let micros = date
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .unwrap()
-                .as_micros();
-            serializer.serialize_u64(micros as u64)
-        }
-
-        pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
-        where
-            D: Deserializer<'de>,
-        {
-            let micros = u64::deserialize(deserializer)?;
-            SystemTime::UNIX_EPOCH
-                .checked_add(Duration::from_micros(micros))
-                .ok_or_else(|| serde::de::Error::custom("failed to deserialize systemtime"))
-        }
-    }
-
     #[test]
     fn test_struct() {
         #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -908,7 +1617,7 @@ mod tests {
             &[20, 10, 10, 2, 0, 16, 11, 4, 0, 0, 0, 32]
         )
         .is_some());
-        let s = serializer.concat();
+        let s = serializer.concat().unwrap();
         let x = from_bytes::<TestParent>(&s).unwrap();
         assert_eq!(t, x);
     }
@@ -943,11 +1652,55 @@ mod tests {
             &[20, 16, 0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 0, 1, 1, 0, 0, 1]
         )
         .is_some());
-        let s = serializer.concat();
+        let s = serializer.concat().unwrap();
         let x = from_bytes::<TestParent>(&s).unwrap();
         assert_eq!(t, x);
     }
 
+    #[test]
+    fn test_packets() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "1")]
+            u8: u8,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestPacket {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11")]
+            first: TestChild,
+            #[serde(rename = "12")]
+            second: TestChild,
+        }
+        let packets = (0..3)
+            .map(|i| TestPacket {
+                u8: i,
+                first: TestChild { u8: 10 + i },
+                second: TestChild { u8: 20 + i },
+            })
+            .collect::<Vec<_>>();
+        let buf = to_bytes(&packets).unwrap();
+        let single = to_bytes(&packets[0]).unwrap();
+        assert_eq!(buf.len(), single.len() * 3);
+        assert_eq!(&buf[..single.len()], &single);
+        assert_eq!(from_bytes::<Vec<TestPacket>>(&buf).unwrap(), packets);
+        assert_eq!(
+            crate::from_bytes_multi::<TestPacket>(&buf).unwrap(),
+            packets
+        );
+
+        let empty: Vec<TestPacket> = vec![];
+        let buf = to_bytes(&empty).unwrap();
+        assert!(buf.is_empty());
+        assert!(from_bytes::<Vec<TestPacket>>(&buf).unwrap().is_empty());
+
+        // パケットはstructでなければならない
+        assert!(to_bytes(&vec![1_u8, 2]).is_err());
+        assert!(crate::to_bytes_with_checksum(&packets, crate::WrappedCRC::default()).is_err());
+    }
+
     #[test]
     fn test_tuple() {
         #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -974,7 +1727,7 @@ mod tests {
             &[20, 15, 128, 128, 0, 128, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0]
         )
         .is_some());
-        let s = serializer.concat();
+        let s = serializer.concat().unwrap();
         let x = from_bytes::<TestParent>(&s).unwrap();
         assert_eq!(t, x);
     }
@@ -1008,7 +1761,7 @@ mod tests {
             &[20, 15, 128, 128, 0, 128, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 0]
         )
         .is_some());
-        let s = serializer.concat();
+        let s = serializer.concat().unwrap();
         let x = from_bytes::<TestParent>(&s).unwrap();
         assert_eq!(t, x);
     }
@@ -1044,14 +1797,811 @@ mod tests {
             ]
         )
         .is_some());
-        let s = serializer.concat();
+        let s = serializer.concat().unwrap();
         let x = from_bytes::<TestVariant>(&s).unwrap();
         assert_eq!(t, x);
     }
 
+    // Noneのフィールドはレコードごと省略できる
+    #[test]
+    fn test_none_as_absent() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestOption {
+            #[serde(rename = "10")]
+            u8: Option<u8>,
+            #[serde(rename = "11")]
+            child: Option<TestChild>,
+            #[serde(rename = "12")]
+            unit: (),
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "1")]
+            u8: Option<u8>,
+            #[serde(rename = "2")]
+            u16: Option<u16>,
+        }
+        let config = SerializerConfig {
+            none_as_absent: true,
+            ..Default::default()
+        };
+        let t = TestOption {
+            u8: None,
+            child: None,
+            unit: (),
+        };
+        // 既定では長さ0のレコードを書き出す
+        let s = to_bytes(&t).unwrap();
+        assert_eq!(&s[4..], &[6, 10, 0, 11, 0, 12, 0]);
+        // unitはNoneではないので残る
+        let s = to_bytes_with_config(&t, config.clone()).unwrap();
+        assert_eq!(&s[4..], &[2, 12, 0]);
+        assert_eq!(from_bytes::<TestOption>(&s).unwrap(), t);
+
+        let t = TestOption {
+            u8: Some(1),
+            child: Some(TestChild {
+                u8: None,
+                u16: Some(2),
+            }),
+            unit: (),
+        };
+        let s = to_bytes_with_config(&t, config).unwrap();
+        assert_eq!(&s[4..], &[11, 10, 1, 1, 11, 4, 2, 2, 0, 2, 12, 0]);
+        assert_eq!(from_bytes::<TestOption>(&s).unwrap(), t);
+    }
+
+    // 入れ子のLocal SetのNoneはどちらの書き方でも読める
+    #[test]
+    fn test_none_nested_set() {
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestOuter {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11")]
+            child: Option<TestChild>,
+        }
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "1")]
+            inner: Option<TestInner>,
+            #[serde(rename = "2")]
+            u8: Option<u8>,
+        }
+        #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+        struct TestInner {
+            #[serde(rename = "5")]
+            u8: u8,
+        }
+        let absent = SerializerConfig {
+            none_as_absent: true,
+            ..Default::default()
+        };
+        let encode = |t: &TestOuter| {
+            let zero = to_bytes(t).unwrap();
+            let omit = to_bytes_with_config(t, absent.clone()).unwrap();
+            (zero, omit)
+        };
+
+        let t = TestOuter { u8: 1, child: None };
+        let (zero, omit) = encode(&t);
+        assert_eq!(&zero[4..], &[5, 10, 1, 1, 11, 0]);
+        assert_eq!(&omit[4..], &[3, 10, 1, 1]);
+        assert_eq!(from_bytes::<TestOuter>(&zero).unwrap(), t);
+        assert_eq!(from_bytes::<TestOuter>(&omit).unwrap(), t);
+
+        let t = TestOuter {
+            u8: 1,
+            child: Some(TestChild {
+                inner: None,
+                u8: Some(2),
+            }),
+        };
+        let (zero, omit) = encode(&t);
+        assert_eq!(&zero[4..], &[10, 10, 1, 1, 11, 5, 1, 0, 2, 1, 2]);
+        assert_eq!(&omit[4..], &[8, 10, 1, 1, 11, 3, 2, 1, 2]);
+        assert_eq!(from_bytes::<TestOuter>(&zero).unwrap(), t);
+        assert_eq!(from_bytes::<TestOuter>(&omit).unwrap(), t);
+
+        let t = TestOuter {
+            u8: 1,
+            child: Some(TestChild {
+                inner: Some(TestInner { u8: 3 }),
+                u8: None,
+            }),
+        };
+        let (_, omit) = encode(&t);
+        assert_eq!(&omit[4..], &[10, 10, 1, 1, 11, 5, 1, 3, 5, 1, 3]);
+        assert_eq!(from_bytes::<TestOuter>(&omit).unwrap(), t);
+
+        // 全てのフィールドを省略したSomeは長さ0となりNoneとして読まれる
+        let t = TestOuter {
+            u8: 1,
+            child: Some(TestChild {
+                inner: None,
+                u8: None,
+            }),
+        };
+        let (zero, omit) = encode(&t);
+        assert_eq!(&zero[4..], &[7, 10, 1, 1, 11, 4, 1, 0, 2, 0]);
+        assert_eq!(&omit[4..], &[5, 10, 1, 1, 11, 0]);
+        assert_eq!(from_bytes::<TestOuter>(&zero).unwrap(), t);
+        assert_eq!(
+            from_bytes::<TestOuter>(&omit).unwrap(),
+            TestOuter { u8: 1, child: None }
+        );
+    }
+
+    #[test]
+    fn test_limit() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestLimit {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "11", skip_serializing_if = "Option::is_none")]
+            u32: Option<u32>,
+            #[serde(rename = "12", skip_serializing_if = "Option::is_none")]
+            string: Option<String>,
+        }
+        let t = TestLimit {
+            u8: 1,
+            u32: Some(2),
+            string: Some("x".repeat(200)),
+        };
+        // 収まる場合はそのまま
+        let (s, dropped) = to_bytes_with_limit(&t, 1024, &[12, 11]).unwrap();
+        assert_eq!(s, to_bytes(&t).unwrap());
+        assert!(dropped.is_empty());
+
+        // 指定順に取り除き、Lも短くなる
+        let (s, dropped) = to_bytes_with_limit(&t, 16, &[12, 11]).unwrap();
+        assert_eq!(dropped, vec![12]);
+        assert_eq!(&s[4..], &[9, 10, 1, 1, 11, 4, 0, 0, 0, 2]);
+        let x = from_bytes::<TestLimit>(&s).unwrap();
+        assert_eq!(
+            x,
+            TestLimit {
+                u8: 1,
+                u32: Some(2),
+                string: None
+            }
+        );
+        let (s, dropped) = to_bytes_with_limit(&t, 8, &[12, 13, 11]).unwrap();
+        assert_eq!(dropped, vec![12, 11]);
+        assert_eq!(s.len(), 8);
+
+        // 取り除けるレコードがなくなっても収まらない
+        match to_bytes_with_limit(&t, 7, &[12, 11]) {
+            Err(Error::PacketTooLarge { size: 8, limit: 7 }) => {}
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_newtype_struct() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Meters(u16);
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct Name<'a>(&'a str);
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestNewtype<'a> {
+            #[serde(rename = "10")]
+            altitude: Meters,
+            #[serde(rename = "11", borrow)]
+            name: Name<'a>,
+            #[serde(rename = "12")]
+            range: Option<Meters>,
+        }
+        let t = TestNewtype {
+            altitude: Meters(1000),
+            name: Name("UAV"),
+            range: Some(Meters(2)),
+        };
+        let s = to_bytes(&t).unwrap();
+        assert_eq!(
+            &s[4..],
+            &[13, 10, 2, 0x03, 0xe8, 11, 3, b'U', b'A', b'V', 12, 2, 0, 2]
+        );
+        assert_eq!(from_bytes::<TestNewtype>(&s).unwrap(), t);
+    }
+
+    #[test]
+    fn test_key_set() {
+        let mut keys = KeySet::default();
+        for key in [0_u128, 1, 63, 64, 127, 128, 255, 256, 0x0e2b, u128::MAX] {
+            assert!(!keys.contains(key));
+            assert!(keys.insert(key));
+            assert!(!keys.insert(key));
+            assert!(keys.contains(key));
+        }
+        assert!(!keys.contains(2));
+        assert!(!keys.contains(254));
+        assert!(!keys.contains(257));
+    }
+
+    // Lが1byteに収まらない場合も正しく書き戻す
+    #[test]
+    fn test_long_length() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestParent {
+            #[serde(rename = "10")]
+            child: TestChild,
+            #[serde(rename = "11")]
+            u8: u8,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "10")]
+            string: String,
+        }
+
+        for len in [0, 127, 128, 255, 256, 65536] {
+            let t = TestParent {
+                child: TestChild {
+                    string: "x".repeat(len),
+                },
+                u8: 1,
+            };
+            let s = to_bytes(&t).unwrap();
+            let x = from_bytes::<TestParent>(&s).unwrap();
+            assert_eq!(t, x);
+            assert_eq!(s, to_bytes_with_capacity(&t, s.len()).unwrap());
+            let map = KLVMap::try_from_bytes(&s).unwrap();
+            assert_eq!(map.to_bytes(), s);
+        }
+    }
+
+    #[test]
+    fn test_force_long_length() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestParent {
+            #[serde(rename = "10")]
+            child: TestChild,
+            #[serde(rename = "11")]
+            u8: u8,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "10")]
+            u16: u16,
+        }
+        let t = TestParent {
+            child: TestChild { u16: 1 },
+            u8: 2,
+        };
+
+        for (octets, expect) in [
+            (
+                1,
+                vec![0x81, 12, 10, 0x81, 5, 10, 0x81, 2, 0, 1, 11, 0x81, 1, 2],
+            ),
+            (
+                2,
+                vec![
+                    0x82, 0, 15, 10, 0x82, 0, 6, 10, 0x82, 0, 2, 0, 1, 11, 0x82, 0, 1, 2,
+                ],
+            ),
+        ] {
+            let config = SerializerConfig {
+                length_form: LengthForm::ForceLong(octets),
+                ..Default::default()
+            };
+            let s = to_bytes_with_config(&t, config).unwrap();
+            assert_eq!(&s[4..], &expect);
+            let x = from_bytes::<TestParent>(&s).unwrap();
+            assert_eq!(t, x);
+        }
+
+        // 既定値は最短
+        let s = to_bytes_with_config(&t, SerializerConfig::default()).unwrap();
+        assert_eq!(s, to_bytes(&t).unwrap());
+
+        // 未対応のオクテット数
+        let config = SerializerConfig {
+            length_form: LengthForm::ForceLong(5),
+            ..Default::default()
+        };
+        assert!(matches!(
+            to_bytes_with_config(&t, config),
+            Err(Error::UnsupportedLength(_))
+        ));
+        // 長さが収まらない
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestLong {
+            #[serde(rename = "10")]
+            string: String,
+        }
+        let config = SerializerConfig {
+            length_form: LengthForm::ForceLong(1),
+            ..Default::default()
+        };
+        let t = TestLong {
+            string: "x".repeat(256),
+        };
+        assert!(matches!(
+            to_bytes_with_config(&t, config),
+            Err(Error::UnsupportedLength(_))
+        ));
+
+        // 設定した上限を超える値
+        let config = SerializerConfig {
+            max_value_len: Some(255),
+            ..Default::default()
+        };
+        match to_bytes_with_config(&t, config) {
+            Err(Error::LengthLimit {
+                offset: 6,
+                length: 256,
+                limit: 255,
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_key_width() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestWide {
+            #[serde(rename = "1000")]
+            u8: u8,
+            #[serde(rename = "2")]
+            child: TestWideChild,
+            #[serde(rename = "1001", with = "crate::repeated", default)]
+            ids: Vec<u8>,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestWideChild {
+            #[serde(rename = "300")]
+            u16: u16,
+        }
+        let t = TestWide {
+            u8: 1,
+            child: TestWideChild { u16: 2 },
+            ids: vec![3, 4],
+        };
+        let ser_config = SerializerConfig {
+            key_width: KeyWidth::U16,
+            ..Default::default()
+        };
+        let de_config = DeserializerConfig {
+            key_width: KeyWidth::U16,
+            ..Default::default()
+        };
+        let s = to_bytes_with_config(&t, ser_config).unwrap();
+        #[rustfmt::skip]
+        let expect = [
+            20,
+            0x03, 0xe8, 1, 1,
+            0, 2, 5, 0x01, 0x2c, 2, 0, 2,
+            0x03, 0xe9, 1, 3,
+            0x03, 0xe9, 1, 4,
+        ];
+        assert_eq!(&s[4..], &expect);
+        let x = from_bytes_with_config::<TestWide>(&s, de_config).unwrap();
+        assert_eq!(t, x);
+        // 1byteのタグには収まらない
+        assert!(matches!(to_bytes(&t), Err(Error::Key(_))));
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestOid {
+            #[serde(rename = "200")]
+            u8: u8,
+            #[serde(rename = "5")]
+            small: u8,
+        }
+        let t = TestOid { u8: 1, small: 2 };
+        let s = to_bytes_with_config(
+            &t,
+            SerializerConfig {
+                key_width: KeyWidth::BerOid,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(&s[4..], &[7, 0x81, 0x48, 1, 1, 5, 1, 2]);
+        let de_config = DeserializerConfig {
+            key_width: KeyWidth::BerOid,
+            ..Default::default()
+        };
+        let x = from_bytes_with_config::<TestOid>(&s, de_config).unwrap();
+        assert_eq!(t, x);
+        // 継続ビットのまま終わる
+        let broken = [b'T', b'E', b'S', b'T', 2, 0x81, 0x81];
+        assert!(from_bytes_with_config::<TestOid>(&broken, de_config).is_err());
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestUniversal {
+            #[serde(rename = "06.0E.2B.34.02.0B.01.01.0E.01.03.01.01.00.00.00")]
+            u8: u8,
+        }
+        let t = TestUniversal { u8: 1 };
+        let s = to_bytes_with_config(
+            &t,
+            SerializerConfig {
+                key_width: KeyWidth::Bytes16,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(s[4], 18);
+        assert_eq!(&s[5..21], crate::UniversalKey::UAS_DATALINK_LS.key);
+        assert_eq!(&s[21..], &[1, 1]);
+        let de_config = DeserializerConfig {
+            key_width: KeyWidth::Bytes16,
+            ..Default::default()
+        };
+        let x = from_bytes_with_config::<TestUniversal>(&s, de_config).unwrap();
+        assert_eq!(t, x);
+    }
+
+    #[test]
+    fn test_reserve_keys() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestReserved {
+            #[serde(rename = "5")]
+            u8: u8,
+            #[serde(rename = "6")]
+            child: TestChild,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "7")]
+            u8: u8,
+        }
+        let t = TestReserved {
+            u8: 1,
+            child: TestChild { u8: 2 },
+        };
+        let config = SerializerConfig::default().reserve_keys(&[5]);
+        assert_eq!(config.reserved_keys, vec![5]);
+        match to_bytes_with_config(&t, config) {
+            Err(Error::Key(msg)) => assert_eq!(msg, "key is reserved: 5"),
+            x => unreachable!("{:?}", x),
+        }
+        // 予約はTopLevelのみで入れ子のタグは使える
+        let config = SerializerConfig::default().reserve_keys(&[7, 8]);
+        assert!(to_bytes_with_config(&t, config).is_ok());
+
+        // 1byteに収まらないタグも予約できる
+        #[derive(Debug, Serialize)]
+        #[serde(rename = "TEST")]
+        struct TestWide {
+            #[serde(rename = "300")]
+            u8: u8,
+        }
+        let config = SerializerConfig {
+            key_width: KeyWidth::U16,
+            ..Default::default()
+        }
+        .reserve_keys(&[300]);
+        assert!(matches!(
+            to_bytes_with_config(&TestWide { u8: 1 }, config),
+            Err(Error::Key(_))
+        ));
+
+        // checksumのタグはCheckSumCalcから予約する
+        struct Tag5;
+        impl crate::CheckSumCalc for Tag5 {
+            fn checksum(&self, _bytes: &[u8]) -> u32 {
+                0
+            }
+            fn tag(&self) -> u8 {
+                5
+            }
+        }
+        assert!(matches!(
+            crate::to_bytes_with_checksum(&t, Tag5),
+            Err(Error::Key(_))
+        ));
+    }
+
+    // 再利用したバッファはエラーの後も前回の状態を持ち越さない
+    #[test]
+    fn test_serialize_buffer() {
+        use crate::ser::{to_bytes_into, SerializeBuffer};
+
+        #[derive(Debug, Serialize)]
+        struct TestChild {
+            #[serde(rename = "1")]
+            x: u8,
+        }
+
+        #[derive(Debug, Serialize)]
+        #[serde(rename = "TEST")]
+        struct TestParent {
+            #[serde(rename = "10")]
+            child: TestChild,
+            #[serde(rename = "11")]
+            u16: u16,
+        }
+
+        #[derive(Debug, Serialize)]
+        #[serde(rename = "TEST")]
+        struct TestInvalidKey {
+            #[serde(rename = "10")]
+            a: u8,
+            #[serde(rename = "x")]
+            b: u8,
+        }
+
+        fn assert_send<T: Send>() {}
+        assert_send::<SerializeBuffer>();
+
+        let t = TestParent {
+            child: TestChild { x: 1 },
+            u16: 2,
+        };
+        let expect = to_bytes(&t).unwrap();
+        let mut buf = SerializeBuffer::new();
+        assert_eq!(to_bytes_into(&mut buf, &t).unwrap(), &expect);
+        let capacity = buf.capacity();
+        assert_eq!(to_bytes_into(&mut buf, &t).unwrap(), &expect);
+        assert_eq!(buf.capacity(), capacity);
+
+        assert!(to_bytes_into(&mut buf, &TestInvalidKey { a: 1, b: 2 }).is_err());
+        assert!(buf.as_bytes().is_empty());
+        assert_eq!(to_bytes_into(&mut buf, &t).unwrap(), &expect);
+
+        let config = SerializerConfig {
+            length_form: LengthForm::ForceLong(1),
+            ..Default::default()
+        };
+        let mut buf = SerializeBuffer::with_config(config.clone());
+        assert_eq!(
+            to_bytes_into(&mut buf, &t).unwrap(),
+            &to_bytes_with_config(&t, config).unwrap()
+        );
+        buf.clear();
+        assert!(buf.as_bytes().is_empty());
+    }
+
     fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
         haystack
             .windows(needle.len())
             .position(|window| window == needle)
     }
+
+    #[test]
+    fn test_sort_tags() {
+        use crate::error::DecodeErrorKind;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "2")]
+            b: u8,
+            #[serde(rename = "1")]
+            a: u8,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestParent {
+            #[serde(rename = "12")]
+            u8: u8,
+            #[serde(rename = "10", with = "crate::repeated")]
+            ids: Vec<u8>,
+            #[serde(rename = "11")]
+            child: TestChild,
+        }
+
+        let t = TestParent {
+            u8: 1,
+            ids: vec![3, 2],
+            child: TestChild { b: 4, a: 5 },
+        };
+        let config = SerializerConfig {
+            sort_tags: true,
+            ..Default::default()
+        };
+        let s = to_bytes_with_config(&t, config).unwrap();
+        // 繰り返しフィールドは書き込んだ順を保ち、子の階層も並べ替える
+        assert_eq!(
+            &s[4..],
+            &[17, 10, 1, 3, 10, 1, 2, 11, 6, 1, 1, 5, 2, 1, 4, 12, 1, 1]
+        );
+
+        let config = DeserializerConfig {
+            deny_unordered_tags: true,
+            ..Default::default()
+        };
+        assert_eq!(from_bytes_with_config::<TestParent>(&s, config).unwrap(), t);
+        let s = to_bytes(&t).unwrap();
+        match from_bytes_with_config::<TestParent>(&s, config) {
+            Err(Error::Decode {
+                offset: 8,
+                tag: Some(10),
+                kind: DecodeErrorKind::UnorderedTag { previous: 12 },
+                ..
+            }) => {}
+            x => unreachable!("{:?}", x),
+        }
+        assert_eq!(from_bytes::<TestParent>(&s).unwrap(), t);
+    }
+
+    #[test]
+    fn test_stamp_tag() {
+        use crate::st0603::PrecisionTimestamp;
+        use crate::TimestampSource;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        struct TestChild {
+            #[serde(rename = "2")]
+            u8: u8,
+        }
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestStamp {
+            #[serde(rename = "10")]
+            u8: u8,
+            #[serde(rename = "2", skip_serializing_if = "Option::is_none")]
+            ts: Option<PrecisionTimestamp>,
+            #[serde(rename = "11")]
+            child: TestChild,
+        }
+
+        let ts = PrecisionTimestamp::from_micros(0x0102);
+        let config = SerializerConfig::default().stamp_tag(2, TimestampSource::Fixed(ts));
+        let mut t = TestStamp {
+            u8: 1,
+            ts: None,
+            child: TestChild { u8: 5 },
+        };
+        // フィールドがなければ先頭に挿入し、子の階層のタグは対象外
+        let s = to_bytes_with_config(&t, config.clone()).unwrap();
+        assert_eq!(
+            &s[4..],
+            &[19, 2, 8, 0, 0, 0, 0, 0, 0, 1, 2, 10, 1, 1, 11, 3, 2, 1, 5]
+        );
+        assert_eq!(from_bytes::<TestStamp>(&s).unwrap().ts, Some(ts));
+
+        // フィールドがあればその位置で値を置き換える
+        t.ts = Some(PrecisionTimestamp::from_micros(1));
+        let s = to_bytes_with_config(&t, config).unwrap();
+        assert_eq!(
+            &s[4..],
+            &[19, 10, 1, 1, 2, 8, 0, 0, 0, 0, 0, 0, 1, 2, 11, 3, 2, 1, 5]
+        );
+
+        let config = SerializerConfig::default().stamp_tag(2, TimestampSource::SystemNow);
+        let before = PrecisionTimestamp::now();
+        let s = to_bytes_with_config(&t, config).unwrap();
+        let x = from_bytes::<TestStamp>(&s).unwrap();
+        assert!(x.ts.unwrap() >= before);
+    }
+
+    #[test]
+    fn test_canonical() {
+        #[derive(Debug, Serialize)]
+        struct TestChildA {
+            #[serde(rename = "2")]
+            b: u16,
+            #[serde(rename = "1")]
+            a: Option<u8>,
+        }
+
+        #[derive(Debug, Serialize)]
+        #[serde(rename = "TEST")]
+        struct TestA<'a> {
+            #[serde(rename = "30")]
+            str: &'a str,
+            #[serde(rename = "20", with = "crate::repeated")]
+            ids: Vec<u8>,
+            #[serde(rename = "10")]
+            child: TestChildA,
+        }
+
+        // 同じ値をフィールドの順序を変えて定義する
+        #[derive(Debug, Serialize)]
+        struct TestChildB {
+            #[serde(rename = "1")]
+            a: Option<u8>,
+            #[serde(rename = "2")]
+            b: u16,
+        }
+
+        #[derive(Debug, Serialize)]
+        #[serde(rename = "TEST")]
+        struct TestB<'a> {
+            #[serde(rename = "10")]
+            child: TestChildB,
+            #[serde(rename = "20", with = "crate::repeated")]
+            ids: Vec<u8>,
+            #[serde(rename = "30")]
+            str: &'a str,
+        }
+
+        let long = "x".repeat(130);
+        let a = TestA {
+            str: &long,
+            ids: vec![3, 1],
+            child: TestChildA { b: 5, a: None },
+        };
+        let b = TestB {
+            child: TestChildB { a: None, b: 5 },
+            ids: vec![3, 1],
+            str: &long,
+        };
+        let config = SerializerConfig {
+            length_form: LengthForm::ForceLong(4),
+            ..Default::default()
+        }
+        .canonical();
+        let sa = to_bytes_with_config(&a, config.clone()).unwrap();
+        let sb = to_bytes_with_config(&b, config).unwrap();
+        assert_eq!(sa, sb);
+
+        // 互換性のため固定のバイト列と比較する
+        let mut expect = vec![b'T', b'E', b'S', b'T', 0x81, 147];
+        expect.extend_from_slice(&[10, 6, 1, 0, 2, 2, 0, 5, 20, 1, 3, 20, 1, 1, 30, 0x81, 130]);
+        expect.extend_from_slice(long.as_bytes());
+        assert_eq!(sa, expect);
+
+        // 既定の設定ではフィールドの順序に依存する
+        assert_ne!(to_bytes(&a).unwrap(), to_bytes(&b).unwrap());
+    }
+
+    // 対応しないserdeの機能はpanicせずエラーを返す
+    #[test]
+    fn test_unsupported() {
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        enum TestEnum {
+            Unit,
+            Tuple(u8, u8),
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestVariant {
+            #[serde(rename = "10")]
+            value: TestEnum,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestMap {
+            #[serde(rename = "10")]
+            map: BTreeMap<u8, u8>,
+        }
+
+        let cases = [
+            (
+                TestEnum::Unit,
+                "unit variant TestEnum::Unit is not supported",
+            ),
+            (
+                TestEnum::Tuple(1, 2),
+                "tuple variant TestEnum::Tuple is not supported",
+            ),
+        ];
+        for (value, expected) in cases {
+            match to_bytes(&TestVariant { value }) {
+                Err(Error::Unsupported(msg)) => assert_eq!(msg, expected),
+                x => unreachable!("{:?}", x),
+            }
+        }
+        assert!(matches!(
+            to_bytes(&TestEnum::Unit),
+            Err(Error::Unsupported(_))
+        ));
+        let t = TestMap {
+            map: BTreeMap::from([(1, 2)]),
+        };
+        assert!(matches!(to_bytes(&t), Err(Error::Unsupported(_))));
+        assert!(matches!(
+            from_bytes::<TestMap>(&[b'T', b'E', b'S', b'T', 4, 10, 2, 1, 2]),
+            Err(Error::Unsupported(_))
+        ));
+    }
 }
@@ -0,0 +1,263 @@
+//! Upper bound of the encoded size
+//!
+//! [`MaxEncodedLen`] gives the maximum byte size of the value of a type,
+//! so frame buffers can be allocated statically and packets can be checked
+//! against the transport MTU at compile time.
+//! The bound assumes 1 byte tags of [`crate::KeyWidth::U8`] and the minimal length form.
+//!
+//! Types of unbounded size such as `String` and `Vec` do not implement the trait.
+//! `#[derive(MaxEncodedLen)]` of the `derive` feature sums the records of the fields,
+//! and `#[max_len(N)]` on a field gives the bound of its value,
+//! for fields of unbounded types or with adapters changing the size.
+//! Fields with `#[serde(skip)]` are not counted.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::Serialize;
+//! use serde_klv::size::{max_packet_len, max_record_len, MaxEncodedLen};
+//! use serde_klv::to_bytes;
+//!
+//! #[derive(Serialize)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "10")]
+//!     u16: u16,
+//!     #[serde(rename = "11")]
+//!     position: Option<[i32; 2]>,
+//! }
+//!
+//! impl MaxEncodedLen for Packet {
+//!     const MAX_VALUE_LEN: usize = max_record_len(1, u16::MAX_VALUE_LEN)
+//!         + max_record_len(1, <Option<[i32; 2]>>::MAX_VALUE_LEN);
+//! }
+//!
+//! const MAX_LEN: usize = max_packet_len(4, Packet::MAX_VALUE_LEN);
+//! let mut frame = [0_u8; MAX_LEN];
+//! let buf = to_bytes(&Packet { u16: 1, position: Some([2, 3]) }).unwrap();
+//! assert_eq!(buf.len(), MAX_LEN);
+//! frame[..buf.len()].copy_from_slice(&buf);
+//! ```
+
+use std::num::{
+    NonZeroI16, NonZeroI32, NonZeroI64, NonZeroI8, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8,
+};
+
+use crate::bounded::Bounded;
+use crate::flag::Flag;
+use crate::st0603::PrecisionTimestamp;
+
+/// Type with the upper bound of the encoded value size
+///
+/// Example of `#[derive(MaxEncodedLen)]` with the `derive` feature
+///
+#[cfg_attr(feature = "derive", doc = "```")]
+#[cfg_attr(not(feature = "derive"), doc = "```ignore")]
+/// use serde::Serialize;
+/// use serde_klv::MaxEncodedLen;
+///
+/// #[derive(Serialize, MaxEncodedLen)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "10")]
+///     u16: u16,
+///     #[serde(rename = "11")]
+///     #[max_len(32)]
+///     name: String,
+/// }
+///
+/// assert_eq!(Packet::MAX_VALUE_LEN, 4 + 34);
+/// ```
+pub trait MaxEncodedLen {
+    /// maximum byte size of the value, without the tag and the length octets
+    const MAX_VALUE_LEN: usize;
+}
+
+/// byte size of BER length octets written for the length
+pub const fn ber_len(size: usize) -> usize {
+    if size <= 127 {
+        1
+    } else if size <= u8::MAX as usize {
+        2
+    } else if size <= u16::MAX as usize {
+        3
+    } else if size as u64 <= u32::MAX as u64 {
+        5
+    } else {
+        9
+    }
+}
+
+/// byte size of a record with the tag length and the value length
+pub const fn max_record_len(key_len: usize, value_len: usize) -> usize {
+    key_len + ber_len(value_len) + value_len
+}
+
+/// byte size of a packet with the universal key length and the content length
+///
+/// Add `max_record_len(1, 2)` to the content for the checksum record of [`crate::WrappedCRC`].
+pub const fn max_packet_len(universal_key_len: usize, content_len: usize) -> usize {
+    max_record_len(universal_key_len, content_len)
+}
+
+macro_rules! impl_max_encoded_len {
+    ($($ty:ty => $len:expr),* $(,)?) => {
+        $(
+            impl MaxEncodedLen for $ty {
+                const MAX_VALUE_LEN: usize = $len;
+            }
+        )*
+    };
+}
+
+impl_max_encoded_len! {
+    () => 0,
+    Flag => 0,
+    bool => 1,
+    u8 => 1,
+    u16 => 2,
+    u32 => 4,
+    u64 => 8,
+    u128 => 16,
+    i8 => 1,
+    i16 => 2,
+    i32 => 4,
+    i64 => 8,
+    i128 => 16,
+    f32 => 4,
+    f64 => 8,
+    char => 4,
+    PrecisionTimestamp => 8,
+    NonZeroU8 => 1,
+    NonZeroU16 => 2,
+    NonZeroU32 => 4,
+    NonZeroU64 => 8,
+    NonZeroI8 => 1,
+    NonZeroI16 => 2,
+    NonZeroI32 => 4,
+    NonZeroI64 => 8,
+}
+
+impl<T: MaxEncodedLen, const MIN: i128, const MAX: i128> MaxEncodedLen for Bounded<T, MIN, MAX> {
+    const MAX_VALUE_LEN: usize = T::MAX_VALUE_LEN;
+}
+
+// Noneは長さ0のレコードになる
+impl<T: MaxEncodedLen> MaxEncodedLen for Option<T> {
+    const MAX_VALUE_LEN: usize = T::MAX_VALUE_LEN;
+}
+
+// 配列の要素はLを持たずに連結される
+impl<T: MaxEncodedLen, const N: usize> MaxEncodedLen for [T; N] {
+    const MAX_VALUE_LEN: usize = T::MAX_VALUE_LEN * N;
+}
+
+impl<T: MaxEncodedLen + ?Sized> MaxEncodedLen for &T {
+    const MAX_VALUE_LEN: usize = T::MAX_VALUE_LEN;
+}
+
+impl<T: MaxEncodedLen + ?Sized> MaxEncodedLen for Box<T> {
+    const MAX_VALUE_LEN: usize = T::MAX_VALUE_LEN;
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::{ber_len, max_packet_len, max_record_len, MaxEncodedLen};
+    use crate::{to_bytes, LengthOctet};
+
+    #[derive(Serialize)]
+    struct TestChild {
+        #[serde(rename = "20")]
+        i64: i64,
+        #[serde(rename = "21")]
+        f32: Option<f32>,
+    }
+
+    impl MaxEncodedLen for TestChild {
+        const MAX_VALUE_LEN: usize = max_record_len(1, 8) + max_record_len(1, 4);
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename = "TEST")]
+    struct TestSize {
+        #[serde(rename = "10")]
+        values: [u64; 20],
+        #[serde(rename = "11")]
+        child: TestChild,
+    }
+
+    impl MaxEncodedLen for TestSize {
+        const MAX_VALUE_LEN: usize = max_record_len(1, <[u64; 20]>::MAX_VALUE_LEN)
+            + max_record_len(1, TestChild::MAX_VALUE_LEN);
+    }
+
+    #[test]
+    fn test_max_encoded_len() {
+        for size in [0, 127, 128, 255, 256, 65535, 65536] {
+            assert_eq!(ber_len(size), LengthOctet::encoded_len(size));
+        }
+
+        const MAX_LEN: usize = max_packet_len(4, TestSize::MAX_VALUE_LEN);
+        // 127byteを超える値は2byteのLになる
+        assert_eq!(MAX_LEN, 4 + 2 + (1 + 2 + 160) + (1 + 1 + 16));
+        let t = TestSize {
+            values: [0; 20],
+            child: TestChild {
+                i64: 1,
+                f32: Some(1.0),
+            },
+        };
+        assert_eq!(to_bytes(&t).unwrap().len(), MAX_LEN);
+        let t = TestSize {
+            child: TestChild { i64: 1, f32: None },
+            ..t
+        };
+        assert!(to_bytes(&t).unwrap().len() < MAX_LEN);
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn test_derive() {
+        use serde::Deserialize;
+
+        use crate::{klv, MaxEncodedLen};
+
+        #[derive(Serialize, Deserialize, MaxEncodedLen)]
+        struct Child {
+            #[serde(rename = "1")]
+            u8: u8,
+        }
+
+        #[klv(key = "TEST")]
+        #[derive(Serialize, Deserialize, MaxEncodedLen)]
+        struct Packet {
+            #[klv(tag = 10)]
+            u16: u16,
+            #[klv(tag = 11, varlen)]
+            #[max_len(8)]
+            count: u64,
+            #[klv(tag = 12)]
+            #[max_len(32)]
+            name: String,
+            #[klv(tag = 13)]
+            child: Option<Child>,
+            #[serde(skip)]
+            #[allow(dead_code)]
+            local: String,
+        }
+
+        assert_eq!(Child::MAX_VALUE_LEN, 3);
+        assert_eq!(Packet::MAX_VALUE_LEN, 4 + 10 + 34 + 5);
+        let t = Packet {
+            u16: 1,
+            count: u64::MAX,
+            name: "x".repeat(32),
+            child: Some(Child { u8: 1 }),
+            local: String::new(),
+        };
+        let len = max_packet_len(4, Packet::MAX_VALUE_LEN);
+        assert_eq!(to_bytes(&t).unwrap().len(), len);
+    }
+}
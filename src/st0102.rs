@@ -0,0 +1,225 @@
+//! Example impl for MISB Standard 0102
+//! the Security Metadata Local Set (LS)
+//! reference: MISB ST 0102.12
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename = "\x06\x0e\x2b\x34\x02\x03\x01\x01\x0e\x01\x03\x03\x02\x00\x00\x00")]
+pub struct SecurityLocalSet<'a> {
+    /// Security classification of the motion imagery or metadata.
+    #[serde(rename = "1")]
+    pub security_classification: Classification,
+    /// Coding method of classifying country and releasing instructions.
+    #[serde(rename = "2")]
+    pub country_coding_method: CountryCodingMethod,
+    /// Country that classified the data. e.g. "//US".
+    #[serde(rename = "3")]
+    pub classifying_country: &'a str,
+    #[serde(rename = "4", skip_serializing_if = "Option::is_none")]
+    pub sci_shi_information: Option<&'a str>,
+    /// Distribution caveats. e.g. "REL TO".
+    #[serde(rename = "5", skip_serializing_if = "Option::is_none")]
+    pub caveats: Option<&'a str>,
+    /// List of country codes separated by blank.
+    #[serde(rename = "6", skip_serializing_if = "Option::is_none")]
+    pub releasing_instructions: Option<&'a str>,
+    #[serde(rename = "7", skip_serializing_if = "Option::is_none")]
+    pub classified_by: Option<&'a str>,
+    #[serde(rename = "8", skip_serializing_if = "Option::is_none")]
+    pub derived_from: Option<&'a str>,
+    #[serde(rename = "9", skip_serializing_if = "Option::is_none")]
+    pub classification_reason: Option<&'a str>,
+    /// Format is YYYYMMDD.
+    #[serde(rename = "10", skip_serializing_if = "Option::is_none")]
+    pub declassification_date: Option<&'a str>,
+    #[serde(rename = "11", skip_serializing_if = "Option::is_none")]
+    pub classification_and_marking_system: Option<&'a str>,
+    #[serde(rename = "12", skip_serializing_if = "Option::is_none")]
+    pub object_country_coding_method: Option<CountryCodingMethod>,
+    #[serde(rename = "13", skip_serializing_if = "Option::is_none")]
+    pub object_country_codes: Option<&'a str>,
+    #[serde(rename = "14", skip_serializing_if = "Option::is_none")]
+    pub classification_comments: Option<&'a str>,
+    #[serde(rename = "16", skip_serializing_if = "Option::is_none")]
+    pub stream_id: Option<u8>,
+    #[serde(rename = "17", skip_serializing_if = "Option::is_none")]
+    pub transport_stream_id: Option<u16>,
+    /// Version number of MISB ST 0102.
+    #[serde(rename = "22")]
+    pub version: u16,
+    /// Format is YYYY-MM-DD.
+    #[serde(rename = "23", skip_serializing_if = "Option::is_none")]
+    pub country_coding_method_version_date: Option<&'a str>,
+    #[serde(rename = "24", skip_serializing_if = "Option::is_none")]
+    pub object_country_coding_method_version_date: Option<&'a str>,
+}
+
+/// Security classification (tag 1)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Classification {
+    #[default]
+    Unclassified = 0x01,
+    Restricted = 0x02,
+    Confidential = 0x03,
+    Secret = 0x04,
+    TopSecret = 0x05,
+}
+
+impl TryFrom<u8> for Classification {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Unclassified),
+            0x02 => Ok(Self::Restricted),
+            0x03 => Ok(Self::Confidential),
+            0x04 => Ok(Self::Secret),
+            0x05 => Ok(Self::TopSecret),
+            x => Err(x),
+        }
+    }
+}
+
+impl Serialize for Classification {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+impl<'de> Deserialize<'de> for Classification {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v = u8::deserialize(deserializer)?;
+        Self::try_from(v).map_err(|x| de::Error::custom(format!("unknown classification {}", x)))
+    }
+}
+
+/// Country coding method (tag 2 and 12)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CountryCodingMethod {
+    #[default]
+    Iso3166TwoLetter = 0x01,
+    Iso3166ThreeLetter = 0x02,
+    Fips10_4TwoLetter = 0x03,
+    Fips10_4FourLetter = 0x04,
+    Iso3166Numeric = 0x05,
+    Stanag1059TwoLetter = 0x06,
+    Stanag1059ThreeLetter = 0x07,
+    Fips10_4Mixed = 0x0a,
+    Iso3166Mixed = 0x0b,
+    Stanag1059Mixed = 0x0c,
+    GencTwoLetter = 0x0d,
+    GencThreeLetter = 0x0e,
+    GencNumeric = 0x0f,
+    GencMixed = 0x10,
+    GencAdminSub = 0x40,
+}
+
+impl TryFrom<u8> for CountryCodingMethod {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(Self::Iso3166TwoLetter),
+            0x02 => Ok(Self::Iso3166ThreeLetter),
+            0x03 => Ok(Self::Fips10_4TwoLetter),
+            0x04 => Ok(Self::Fips10_4FourLetter),
+            0x05 => Ok(Self::Iso3166Numeric),
+            0x06 => Ok(Self::Stanag1059TwoLetter),
+            0x07 => Ok(Self::Stanag1059ThreeLetter),
+            0x0a => Ok(Self::Fips10_4Mixed),
+            0x0b => Ok(Self::Iso3166Mixed),
+            0x0c => Ok(Self::Stanag1059Mixed),
+            0x0d => Ok(Self::GencTwoLetter),
+            0x0e => Ok(Self::GencThreeLetter),
+            0x0f => Ok(Self::GencNumeric),
+            0x10 => Ok(Self::GencMixed),
+            0x40 => Ok(Self::GencAdminSub),
+            x => Err(x),
+        }
+    }
+}
+
+impl Serialize for CountryCodingMethod {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u8(*self as u8)
+    }
+}
+
+impl<'de> Deserialize<'de> for CountryCodingMethod {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v = u8::deserialize(deserializer)?;
+        Self::try_from(v)
+            .map_err(|x| de::Error::custom(format!("unknown country coding method {}", x)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        de::from_bytes,
+        error::Error,
+        ser::to_bytes,
+        st0102::{Classification, CountryCodingMethod, SecurityLocalSet},
+    };
+
+    #[test]
+    fn test_security_local_set() {
+        #[rustfmt::skip]
+        let buf = vec![
+            0x06, 0x0e, 0x2b, 0x34, 0x02, 0x03, 0x01, 0x01, 0x0e, 0x01, 0x03, 0x03, 0x02, 0x00, 0x00, 0x00,
+            26,
+            1, 1, 0x01,
+            2, 1, 0x01,
+            3, 4, b'/', b'/', b'U', b'S',
+            6, 5, b'U', b'S', b' ', b'G', b'B',
+            12, 1, 0x0e,
+            22, 2, 0x00, 0x0c,
+        ];
+        let x = from_bytes::<SecurityLocalSet>(&buf).unwrap();
+        assert_eq!(x.security_classification, Classification::Unclassified);
+        assert_eq!(
+            x.country_coding_method,
+            CountryCodingMethod::Iso3166TwoLetter
+        );
+        assert_eq!(x.classifying_country, "//US");
+        assert_eq!(x.releasing_instructions, Some("US GB"));
+        assert_eq!(
+            x.object_country_coding_method,
+            Some(CountryCodingMethod::GencThreeLetter)
+        );
+        assert_eq!(x.version, 12);
+        assert_eq!(to_bytes(&x).unwrap(), buf);
+    }
+
+    #[test]
+    fn test_unknown_classification() {
+        let t = SecurityLocalSet {
+            security_classification: Classification::TopSecret,
+            classifying_country: "//JP",
+            ..Default::default()
+        };
+        let mut buf = to_bytes(&t).unwrap();
+        // tag 1の値を未定義の値に書き換える
+        assert_eq!(&buf[17..20], &[1, 1, 0x05]);
+        buf[19] = 0x06;
+        match from_bytes::<SecurityLocalSet>(&buf) {
+            Err(Error::Decode { tag: Some(1), .. }) => {}
+            x => unreachable!("{:?}", x),
+        }
+    }
+}
@@ -0,0 +1,271 @@
+//! MISB ST 0603 Precision Time Stamp
+//!
+//! MISP time is the count of microseconds from UNIX epoch without leap seconds,
+//! the same as POSIX time, and is encoded as 8 byte unsigned integer.
+//! [`PrecisionTimestamp`] implements `Serialize` and `Deserialize` in this form,
+//! so it can be used as the field without `with` attribute.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::st0603::PrecisionTimestamp;
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "2")]
+//!     ts: PrecisionTimestamp,
+//! }
+//!
+//! let t = Packet {
+//!     ts: PrecisionTimestamp::from_micros(1_234_567),
+//! };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(&buf[4..], &[10, 2, 8, 0, 0, 0, 0, 0, 0x12, 0xd6, 0x87]);
+//! assert_eq!(from_bytes::<Packet>(&buf).unwrap(), t);
+//!
+//! // leap seconds between UTC and GPS time
+//! let ts = PrecisionTimestamp::from_secs(1_483_228_800);
+//! assert_eq!(ts.tai_offset(), 37);
+//! assert_eq!(ts.gps_leap_seconds(), 18);
+//! assert_eq!(PrecisionTimestamp::from_gps_micros(ts.to_gps_micros().unwrap()), ts);
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::{Error, Result};
+
+const MICROS_PER_SEC: u64 = 1_000_000;
+
+/// GPS epoch 1980-01-06T00:00:00Z in UNIX seconds
+pub const GPS_EPOCH_SECS: u64 = 315_964_800;
+
+// TAI-UTCが変化したUTCの時刻(UNIX秒)と変化後の値
+const LEAP_SECONDS: [(u64, u32); 28] = [
+    (63_072_000, 10),
+    (78_796_800, 11),
+    (94_694_400, 12),
+    (126_230_400, 13),
+    (157_766_400, 14),
+    (189_302_400, 15),
+    (220_924_800, 16),
+    (252_460_800, 17),
+    (283_996_800, 18),
+    (315_532_800, 19),
+    (362_793_600, 20),
+    (394_329_600, 21),
+    (425_865_600, 22),
+    (489_024_000, 23),
+    (567_993_600, 24),
+    (631_152_000, 25),
+    (662_688_000, 26),
+    (709_948_800, 27),
+    (741_484_800, 28),
+    (773_020_800, 29),
+    (820_454_400, 30),
+    (867_715_200, 31),
+    (915_148_800, 32),
+    (1_136_073_600, 33),
+    (1_230_768_000, 34),
+    (1_341_100_800, 35),
+    (1_435_708_800, 36),
+    (1_483_228_800, 37),
+];
+
+// GPS時刻とTAIの差
+const GPS_TAI_OFFSET: u32 = 19;
+
+/// Microseconds from UNIX epoch without leap seconds, MISP time of ST 0603
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PrecisionTimestamp(pub u64);
+
+impl PrecisionTimestamp {
+    /// 1970-01-01T00:00:00Z
+    pub const UNIX_EPOCH: Self = Self(0);
+
+    pub const fn from_micros(micros: u64) -> Self {
+        Self(micros)
+    }
+
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(secs * MICROS_PER_SEC)
+    }
+
+    pub const fn as_micros(&self) -> u64 {
+        self.0
+    }
+
+    /// Current time of the system clock, `UNIX_EPOCH` if the clock is before it
+    pub fn now() -> Self {
+        Self::try_from(SystemTime::now()).unwrap_or_default()
+    }
+
+    /// TAI - UTC in seconds at this time
+    ///
+    /// Leap seconds known at the release are applied.
+    /// Returns 10 for the time before 1972 when the offset was not an integer.
+    pub fn tai_offset(&self) -> u32 {
+        let secs = self.0 / MICROS_PER_SEC;
+        LEAP_SECONDS
+            .iter()
+            .rev()
+            .find(|(at, _)| *at <= secs)
+            .map(|(_, offset)| *offset)
+            .unwrap_or(LEAP_SECONDS[0].1)
+    }
+
+    /// GPS - UTC in seconds at this time, 0 before GPS epoch
+    pub fn gps_leap_seconds(&self) -> u32 {
+        self.tai_offset().saturating_sub(GPS_TAI_OFFSET)
+    }
+
+    /// Microseconds from GPS epoch including leap seconds, None before GPS epoch
+    pub fn to_gps_micros(&self) -> Option<u64> {
+        let micros = self.0.checked_sub(GPS_EPOCH_SECS * MICROS_PER_SEC)?;
+        Some(micros + self.gps_leap_seconds() as u64 * MICROS_PER_SEC)
+    }
+
+    /// Convert microseconds from GPS epoch including leap seconds
+    ///
+    /// The time in a leap second is mapped to the next second.
+    pub fn from_gps_micros(micros: u64) -> Self {
+        let secs = micros / MICROS_PER_SEC;
+        // 閏秒の挿入時刻をGPS時刻に直して比較する
+        let leap = LEAP_SECONDS
+            .iter()
+            .rev()
+            .map(|(at, offset)| (*at as i64 - GPS_EPOCH_SECS as i64, *offset))
+            .map(|(at, offset)| (at + offset as i64 - GPS_TAI_OFFSET as i64, offset))
+            .find(|(at, _)| *at <= secs as i64)
+            .map(|(_, offset)| offset.saturating_sub(GPS_TAI_OFFSET))
+            .unwrap_or_default();
+        Self(micros + GPS_EPOCH_SECS * MICROS_PER_SEC - leap as u64 * MICROS_PER_SEC)
+    }
+}
+
+impl From<PrecisionTimestamp> for SystemTime {
+    fn from(ts: PrecisionTimestamp) -> Self {
+        SystemTime::UNIX_EPOCH + Duration::from_micros(ts.0)
+    }
+}
+
+impl TryFrom<SystemTime> for PrecisionTimestamp {
+    type Error = Error;
+
+    /// Sub-microsecond part is truncated
+    fn try_from(time: SystemTime) -> Result<Self> {
+        let duration = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|e| Error::Encode(format!("timestamp before UNIX epoch. {e}")))?;
+        u64::try_from(duration.as_micros())
+            .map(Self)
+            .map_err(|_| Error::Encode("timestamp overflow u64 microseconds".to_string()))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<PrecisionTimestamp> for chrono::DateTime<chrono::Utc> {
+    fn from(ts: PrecisionTimestamp) -> Self {
+        SystemTime::from(ts).into()
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::DateTime<chrono::Utc>> for PrecisionTimestamp {
+    type Error = Error;
+
+    /// Sub-microsecond part is truncated
+    fn try_from(time: chrono::DateTime<chrono::Utc>) -> Result<Self> {
+        Self::try_from(SystemTime::from(time))
+    }
+}
+
+impl Serialize for PrecisionTimestamp {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for PrecisionTimestamp {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        u64::deserialize(deserializer).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{PrecisionTimestamp, GPS_EPOCH_SECS};
+
+    #[test]
+    fn test_leap_seconds() {
+        // GPS epochでは差がない
+        let epoch = PrecisionTimestamp::from_secs(GPS_EPOCH_SECS);
+        assert_eq!(epoch.tai_offset(), 19);
+        assert_eq!(epoch.to_gps_micros(), Some(0));
+        assert_eq!(PrecisionTimestamp::from_gps_micros(0), epoch);
+        assert_eq!(
+            PrecisionTimestamp::from_secs(GPS_EPOCH_SECS - 1).to_gps_micros(),
+            None
+        );
+
+        // 2016-12-31T23:59:59Zと2017-01-01T00:00:00Zの間に閏秒が入る
+        let before = PrecisionTimestamp::from_secs(1_483_228_799);
+        let after = PrecisionTimestamp::from_secs(1_483_228_800);
+        assert_eq!(before.gps_leap_seconds(), 17);
+        assert_eq!(after.gps_leap_seconds(), 18);
+        let before_gps = before.to_gps_micros().unwrap();
+        let after_gps = after.to_gps_micros().unwrap();
+        assert_eq!(after_gps - before_gps, 2_000_000);
+        assert_eq!(PrecisionTimestamp::from_gps_micros(before_gps), before);
+        assert_eq!(PrecisionTimestamp::from_gps_micros(after_gps), after);
+        // 閏秒の間は次の秒になる
+        assert_eq!(
+            PrecisionTimestamp::from_gps_micros(before_gps + 1_000_000),
+            after
+        );
+
+        assert_eq!(PrecisionTimestamp::from_secs(0).tai_offset(), 10);
+    }
+
+    #[test]
+    fn test_system_time() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_nanos(1_663_000_000_123_456_789);
+        let ts = PrecisionTimestamp::try_from(time).unwrap();
+        assert_eq!(ts.as_micros(), 1_663_000_000_123_456);
+        assert_eq!(
+            SystemTime::from(ts),
+            SystemTime::UNIX_EPOCH + Duration::from_micros(1_663_000_000_123_456)
+        );
+        assert!(
+            PrecisionTimestamp::try_from(SystemTime::UNIX_EPOCH - Duration::from_secs(1)).is_err()
+        );
+        assert!(PrecisionTimestamp::now() > ts);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono() {
+        use chrono::{DateTime, TimeZone, Utc};
+
+        let time = Utc.timestamp_opt(1_663_000_000, 123_456_789).unwrap();
+        let ts = PrecisionTimestamp::try_from(time).unwrap();
+        assert_eq!(ts.as_micros(), 1_663_000_000_123_456);
+        assert_eq!(
+            DateTime::<Utc>::from(ts),
+            Utc.timestamp_opt(1_663_000_000, 123_456_000).unwrap()
+        );
+        assert!(PrecisionTimestamp::try_from(Utc.timestamp_opt(-1, 0).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,464 @@
+//! Example impl for MISB Standard 1108
+//! the Interpretability and Quality Metadata Local Set (LS)
+//! reference: MISB ST 1108.3
+//!
+//! The packs of fixed or variable layout are typed structs
+//! written as one value of multiple fields.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde_klv::st0603::PrecisionTimestamp;
+//! use serde_klv::st1108::{
+//!     MetricImplementer, MetricLocalSet, MetricPeriodPack, QualityLocalSet, CRC,
+//! };
+//! use serde_klv::{from_bytes_with_checksum, to_bytes_with_checksum};
+//!
+//! let t = QualityLocalSet {
+//!     assessment_point: 1,
+//!     metric_period: MetricPeriodPack {
+//!         start: PrecisionTimestamp::from_micros(1_000_233_000),
+//!         offset: 1_000_000,
+//!     },
+//!     metrics: vec![MetricLocalSet {
+//!         name: "VNIIRS",
+//!         version: "3.0",
+//!         implementer: MetricImplementer {
+//!             organization: "MISB",
+//!             subgroup: "MIQ",
+//!         },
+//!         value: 6.5,
+//!         ..Default::default()
+//!     }],
+//!     compression_level: "4.1",
+//!     ..Default::default()
+//! };
+//! let buf = to_bytes_with_checksum(&t, CRC).unwrap();
+//! let x: QualityLocalSet = from_bytes_with_checksum(&buf, CRC).unwrap();
+//! assert_eq!(x, t);
+//! ```
+
+use std::fmt;
+
+use byteorder::{BigEndian, ByteOrder};
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::checksum::CheckSumCalc;
+use crate::key::KeyWidth;
+use crate::st0603::PrecisionTimestamp;
+use crate::{encode_length, parse_length, LengthForm};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename = "\x06\x0e\x2b\x34\x02\x05\x01\x01\x0e\x01\x03\x03\x1c\x00\x00\x00")]
+pub struct QualityLocalSet<'a> {
+    /// 1: sensor, 2: sensor encoder, 3: GCS received, 4: GCS transmit, 5: library/archive.
+    #[serde(rename = "1")]
+    pub assessment_point: u8,
+    #[serde(rename = "2")]
+    pub metric_period: MetricPeriodPack,
+    /// Region of the image the metrics are calculated in. Whole image if absent.
+    #[serde(rename = "3", skip_serializing_if = "Option::is_none")]
+    pub window_corners: Option<WindowCornersPack>,
+    #[serde(rename = "4", with = "crate::repeated", default, borrow)]
+    pub metrics: Vec<MetricLocalSet<'a>>,
+    /// 0: uncompressed, 1: H.262, 2: H.264, 3: H.265, 4: JPEG 2000.
+    #[serde(rename = "5")]
+    pub compression_type: u8,
+    /// Profile of the codec. e.g. 1: main, 2: main still picture, 3: high.
+    #[serde(rename = "6")]
+    pub compression_profile: u8,
+    /// Level of the codec. e.g. "4.1".
+    #[serde(rename = "7")]
+    pub compression_level: &'a str,
+    /// Ratio of uncompressed to compressed size.
+    #[serde(rename = "8")]
+    pub compression_ratio: f32,
+    /// Bitrate of the stream in kilobits per second.
+    #[serde(rename = "9")]
+    pub stream_bitrate: u16,
+    /// Version number of MISB ST 1108.
+    #[serde(rename = "10")]
+    pub document_version: u8,
+}
+
+impl<'a> Default for QualityLocalSet<'a> {
+    fn default() -> Self {
+        Self {
+            assessment_point: Default::default(),
+            metric_period: Default::default(),
+            window_corners: Default::default(),
+            metrics: Default::default(),
+            compression_type: Default::default(),
+            compression_profile: Default::default(),
+            compression_level: Default::default(),
+            compression_ratio: Default::default(),
+            stream_bitrate: Default::default(),
+            document_version: 3,
+        }
+    }
+}
+
+/// Metric Local Set (tag 4)
+#[derive(Debug, Serialize, Deserialize, PartialEq, Default)]
+pub struct MetricLocalSet<'a> {
+    /// e.g. "VNIIRS", "RER", "GSD".
+    #[serde(rename = "1")]
+    pub name: &'a str,
+    #[serde(rename = "2")]
+    pub version: &'a str,
+    #[serde(rename = "3", borrow)]
+    pub implementer: MetricImplementer<'a>,
+    /// Parameters of the calculation.
+    #[serde(rename = "4", skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<&'a str>,
+    /// Time of the calculation.
+    #[serde(rename = "5", skip_serializing_if = "Option::is_none")]
+    pub time: Option<PrecisionTimestamp>,
+    #[serde(rename = "6")]
+    pub value: f64,
+}
+
+/// Metric Period Pack (tag 2)
+///
+/// Defined-length pack of start time and microseconds of the period, 12 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricPeriodPack {
+    pub start: PrecisionTimestamp,
+    /// Length of the period in microseconds.
+    pub offset: u32,
+}
+
+impl MetricPeriodPack {
+    const LEN: usize = 12;
+}
+
+impl Default for MetricPeriodPack {
+    fn default() -> Self {
+        Self {
+            start: PrecisionTimestamp::UNIX_EPOCH,
+            offset: 0,
+        }
+    }
+}
+
+impl Serialize for MetricPeriodPack {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut buf = [0_u8; Self::LEN];
+        BigEndian::write_u64(&mut buf[0..8], self.start.0);
+        BigEndian::write_u32(&mut buf[8..12], self.offset);
+        serializer.serialize_bytes(&buf)
+    }
+}
+
+impl<'de> Deserialize<'de> for MetricPeriodPack {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(MetricPeriodVisitor)
+    }
+}
+
+struct MetricPeriodVisitor;
+
+impl<'de> de::Visitor<'de> for MetricPeriodVisitor {
+    type Value = MetricPeriodPack;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("12 bytes of timestamp and offset")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if v.len() != MetricPeriodPack::LEN {
+            return Err(de::Error::invalid_length(v.len(), &self));
+        }
+        Ok(MetricPeriodPack {
+            start: PrecisionTimestamp(BigEndian::read_u64(&v[0..8])),
+            offset: BigEndian::read_u32(&v[8..12]),
+        })
+    }
+}
+
+/// Window Corners Pack (tag 3)
+///
+/// Variable-length pack of the pixel positions encoded by BER-OID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WindowCornersPack {
+    pub upper_left_row: u32,
+    pub upper_left_column: u32,
+    pub lower_right_row: u32,
+    pub lower_right_column: u32,
+}
+
+impl WindowCornersPack {
+    fn fields(&self) -> [u32; 4] {
+        [
+            self.upper_left_row,
+            self.upper_left_column,
+            self.lower_right_row,
+            self.lower_right_column,
+        ]
+    }
+}
+
+impl Serialize for WindowCornersPack {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut buf = Vec::with_capacity(20);
+        for x in self.fields() {
+            KeyWidth::BerOid.encode(x as u128, &mut buf);
+        }
+        serializer.serialize_bytes(&buf)
+    }
+}
+
+impl<'de> Deserialize<'de> for WindowCornersPack {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(WindowCornersVisitor)
+    }
+}
+
+struct WindowCornersVisitor;
+
+impl<'de> de::Visitor<'de> for WindowCornersVisitor {
+    type Value = WindowCornersPack;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("4 BER-OID encoded pixel positions")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let mut fields = [0_u32; 4];
+        let mut pos = 0;
+        for x in fields.iter_mut() {
+            let (value, len) = KeyWidth::BerOid
+                .decode(&v[pos..])
+                .ok_or_else(|| de::Error::invalid_value(de::Unexpected::Bytes(v), &self))?;
+            *x = u32::try_from(value)
+                .map_err(|_| de::Error::invalid_value(de::Unexpected::Bytes(v), &self))?;
+            pos += len;
+        }
+        if pos != v.len() {
+            return Err(de::Error::invalid_length(v.len(), &self));
+        }
+        Ok(WindowCornersPack {
+            upper_left_row: fields[0],
+            upper_left_column: fields[1],
+            lower_right_row: fields[2],
+            lower_right_column: fields[3],
+        })
+    }
+}
+
+/// Metric Implementer Pack (tag 3 of [`MetricLocalSet`])
+///
+/// Variable-length pack of the strings, each is preceded by BER length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MetricImplementer<'a> {
+    pub organization: &'a str,
+    pub subgroup: &'a str,
+}
+
+impl<'a> Serialize for MetricImplementer<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut buf = vec![];
+        for x in [self.organization, self.subgroup] {
+            encode_length(&mut buf, x.len(), LengthForm::Minimal).map_err(ser::Error::custom)?;
+            buf.extend_from_slice(x.as_bytes());
+        }
+        serializer.serialize_bytes(&buf)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for MetricImplementer<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(MetricImplementerVisitor)
+    }
+}
+
+struct MetricImplementerVisitor;
+
+impl<'de> de::Visitor<'de> for MetricImplementerVisitor {
+    type Value = MetricImplementer<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("organization and subgroup preceded by length")
+    }
+
+    fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        let mut rest = v;
+        let mut next = || -> Result<&'de str, E> {
+            let (len_len, len) = parse_length(rest).map_err(de::Error::custom)?;
+            // 8byteのLでは加算が溢れる
+            let end = len_len
+                .checked_add(len)
+                .filter(|x| *x <= rest.len())
+                .ok_or_else(|| de::Error::invalid_length(v.len(), &self))?;
+            let value = &rest[len_len..end];
+            rest = &rest[end..];
+            std::str::from_utf8(value).map_err(de::Error::custom)
+        };
+        let organization = next()?;
+        let subgroup = next()?;
+        if !rest.is_empty() {
+            return Err(de::Error::invalid_length(v.len(), &self));
+        }
+        Ok(MetricImplementer {
+            organization,
+            subgroup,
+        })
+    }
+}
+
+/// CRC-16-CCITT checksum of tag 11
+pub struct CRC;
+
+impl CheckSumCalc for CRC {
+    fn checksum(&self, bytes: &[u8]) -> u32 {
+        crc::Crc::<u16>::new(&crc::CRC_16_IBM_3740).checksum(bytes) as u32
+    }
+
+    fn tag(&self) -> u8 {
+        11
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        checksum::CheckSumCalc,
+        de::from_bytes,
+        error::{DecodeErrorKind, Error},
+        from_bytes_with_checksum,
+        ser::to_bytes,
+        st0603::PrecisionTimestamp,
+        st1108::{
+            MetricImplementer, MetricLocalSet, MetricPeriodPack, QualityLocalSet,
+            WindowCornersPack, CRC,
+        },
+        to_bytes_with_checksum,
+    };
+    use serde::de::value::BorrowedBytesDeserializer;
+    use serde::Deserialize;
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(CRC.checksum(b"123456789"), 0x29b1);
+    }
+
+    #[test]
+    fn test_quality_local_set() {
+        let t = QualityLocalSet {
+            assessment_point: 2,
+            metric_period: MetricPeriodPack {
+                start: PrecisionTimestamp::from_micros(1_000_233_000),
+                offset: 0x0102_0304,
+            },
+            window_corners: Some(WindowCornersPack {
+                upper_left_row: 1,
+                upper_left_column: 2,
+                lower_right_row: 480,
+                lower_right_column: 640,
+            }),
+            metrics: vec![
+                MetricLocalSet {
+                    name: "GSD",
+                    version: "1",
+                    implementer: MetricImplementer {
+                        organization: "A",
+                        subgroup: "",
+                    },
+                    value: 0.5,
+                    ..Default::default()
+                },
+                MetricLocalSet {
+                    name: "RER",
+                    version: "2",
+                    implementer: MetricImplementer {
+                        organization: "B",
+                        subgroup: "C",
+                    },
+                    time: Some(PrecisionTimestamp::from_micros(1)),
+                    value: 1.0,
+                    ..Default::default()
+                },
+            ],
+            compression_type: 2,
+            compression_profile: 3,
+            compression_level: "4.1",
+            compression_ratio: 25.0,
+            stream_bitrate: 4000,
+            document_version: 3,
+        };
+        let buf = to_bytes(&t).unwrap();
+        #[rustfmt::skip]
+        assert_eq!(&buf[16..57], &[
+            110,
+            1, 1, 2,
+            2, 12, 0x00, 0x00, 0x00, 0x00, 0x3b, 0x9e, 0x58, 0x28, 0x01, 0x02, 0x03, 0x04,
+            3, 6, 1, 2, 0x83, 0x60, 0x85, 0x00,
+            4, 23, 1, 3, b'G', b'S', b'D', 2, 1, b'1', 3, 3, 1, b'A', 0,
+        ]);
+        assert_eq!(from_bytes::<QualityLocalSet>(&buf).unwrap(), t);
+
+        let buf = to_bytes_with_checksum(&t, CRC).unwrap();
+        assert_eq!(&buf[buf.len() - 4..buf.len() - 2], &[11, 2]);
+        let x: QualityLocalSet = from_bytes_with_checksum(&buf, CRC).unwrap();
+        assert_eq!(x, t);
+    }
+
+    #[test]
+    fn test_broken_pack() {
+        let t = QualityLocalSet {
+            compression_level: "1",
+            ..Default::default()
+        };
+        let buf = to_bytes(&t).unwrap();
+        // Metric Period Packの長さを変える
+        assert_eq!(&buf[20..22], &[2, 12]);
+        let mut broken = buf[..33].to_vec();
+        broken.extend_from_slice(&buf[34..]);
+        broken[16] -= 1;
+        broken[21] = 11;
+        match from_bytes::<QualityLocalSet>(&broken) {
+            Err(Error::Decode {
+                kind: DecodeErrorKind::Custom(_),
+                path,
+                ..
+            }) => assert_eq!(path, vec![2]),
+            x => unreachable!("{:?}", x),
+        }
+
+        // 溢れる長さのImplementer
+        #[rustfmt::skip]
+        let buf = [
+            0x88, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0,
+        ];
+        let de = BorrowedBytesDeserializer::<serde::de::value::Error>::new(&buf);
+        assert!(MetricImplementer::deserialize(de).is_err());
+    }
+}
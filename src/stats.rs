@@ -0,0 +1,144 @@
+//! Statistics of decoded packets for monitoring
+//!
+//! [`DecodeStats`] counts packets, bytes and records of each top level tag,
+//! and the failures found while decoding.
+//! Attach it to [`crate::stream::AsyncKLVReader::with_stats`] of the `tokio` feature,
+//! or update it from your own read loop.
+//! Records are counted by scanning the tags and lengths of the packet, values are not decoded.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde_klv::stats::DecodeStats;
+//!
+//! let mut stats = DecodeStats::default();
+//! stats.record_packet(&[b'T', b'E', b'S', b'T', 6, 10, 1, 1, 11, 1, 2], 4);
+//! stats.record_packet(&[b'T', b'E', b'S', b'T', 3, 10, 1, 3], 4);
+//! assert_eq!(stats.packets, 2);
+//! assert_eq!(stats.bytes, 19);
+//! assert_eq!(stats.tag_count(10), 2);
+//! assert_eq!(stats.tag_count(11), 1);
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::parse_length;
+
+/// Counters of decoded packets
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// number of packets read
+    pub packets: u64,
+    /// total bytes of the packets
+    pub bytes: u64,
+    /// number of records of each top level tag
+    pub tags: BTreeMap<u8, u64>,
+    /// number of packets with unmatched or missing checksum
+    pub checksum_failures: u64,
+    /// number of records whose tags the deserialized type does not have
+    pub unknown_tags: u64,
+    /// number of packets failed to deserialize
+    pub decode_errors: u64,
+}
+
+impl DecodeStats {
+    /// count the packet and its top level records
+    ///
+    /// `key_len` is the length of the universal key.
+    /// Scanning stops at a malformed record, the packet itself is still counted.
+    pub fn record_packet(&mut self, packet: &[u8], key_len: usize) {
+        self.packets += 1;
+        self.bytes += packet.len() as u64;
+        let mut position = match packet.get(key_len..).map(parse_length) {
+            Some(Ok((length_len, _))) => key_len + length_len,
+            _ => return,
+        };
+        while position < packet.len() {
+            let tag = packet[position];
+            let (length_len, content_len) = match parse_length(&packet[position + 1..]) {
+                Ok(x) => x,
+                Err(_) => return,
+            };
+            *self.tags.entry(tag).or_default() += 1;
+            position = (position + 1 + length_len).saturating_add(content_len);
+        }
+    }
+
+    /// number of records of the tag
+    pub fn tag_count(&self, tag: u8) -> u64 {
+        self.tags.get(&tag).copied().unwrap_or_default()
+    }
+
+    pub fn record_checksum_failure(&mut self) {
+        self.checksum_failures += 1;
+    }
+
+    pub fn record_unknown_tags(&mut self, count: u64) {
+        self.unknown_tags += count;
+    }
+
+    pub fn record_decode_error(&mut self) {
+        self.decode_errors += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use super::DecodeStats;
+    use crate::to_bytes;
+
+    #[derive(Serialize)]
+    #[serde(rename = "TEST")]
+    struct TestStats {
+        #[serde(rename = "10")]
+        u8: u8,
+        #[serde(rename = "11", with = "crate::repeated")]
+        ids: Vec<u8>,
+        #[serde(rename = "12")]
+        child: TestChild,
+    }
+
+    #[derive(Serialize)]
+    struct TestChild {
+        #[serde(rename = "1")]
+        u8: u8,
+    }
+
+    #[test]
+    fn test_record_packet() {
+        let buf = to_bytes(&TestStats {
+            u8: 1,
+            ids: vec![1, 2, 3],
+            child: TestChild { u8: 1 },
+        })
+        .unwrap();
+        let mut stats = DecodeStats::default();
+        stats.record_packet(&buf, 4);
+        // 入れ子のレコードは数えない
+        assert_eq!(stats.tags.len(), 3);
+        assert_eq!(stats.tag_count(11), 3);
+        assert_eq!(stats.tag_count(1), 0);
+        assert_eq!(stats.bytes, buf.len() as u64);
+
+        // 壊れたレコードの手前まで数える
+        let broken = [b'T', b'E', b'S', b'T', 5, 10, 1, 1, 11, 0x89];
+        stats.record_packet(&broken, 4);
+        assert_eq!(stats.packets, 2);
+        assert_eq!(stats.tag_count(10), 2);
+        assert_eq!(stats.tag_count(11), 3);
+
+        stats.record_checksum_failure();
+        stats.record_unknown_tags(2);
+        stats.record_decode_error();
+        assert_eq!(
+            (
+                stats.checksum_failures,
+                stats.unknown_tags,
+                stats.decode_errors
+            ),
+            (1, 2, 1)
+        );
+    }
+}
@@ -0,0 +1,311 @@
+//! Async streaming decoder for `tokio::io::AsyncRead`
+//!
+//! Buffer the stream until a full packet (UK + BER length + content) is available.
+//!
+//! Example
+//!
+//! ```rust
+//! # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{stream::AsyncKLVReader, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Count {
+//!     #[serde(rename = "10")]
+//!     count: u16,
+//! }
+//!
+//! let mut buf = to_bytes(&Count { count: 1 }).unwrap();
+//! buf.extend(to_bytes(&Count { count: 2 }).unwrap());
+//!
+//! let mut reader = AsyncKLVReader::new(buf.as_slice(), 4);
+//! assert_eq!(reader.read::<Count>().await.unwrap(), Some(Count { count: 1 }));
+//! assert_eq!(reader.read::<Count>().await.unwrap(), Some(Count { count: 2 }));
+//! assert_eq!(reader.read::<Count>().await.unwrap(), None);
+//! # });
+//! ```
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::checksum::CheckSumCalc;
+use crate::de::{checksum, packet_len};
+use crate::error::{Error, Result};
+use crate::stats::DecodeStats;
+use crate::{DeserializerConfig, KLVDeserializer, KLVMap};
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Read KLV packets from async stream
+pub struct AsyncKLVReader<R> {
+    reader: R,
+    key_len: usize,
+    buf: Vec<u8>,
+    // 前回返したパケットの長さ。次の読み出し時にbufから取り除く
+    consumed: usize,
+    chunk: Vec<u8>,
+    // 宣言された長さがこれを超える場合はバッファせずエラーとする
+    max_content_len: usize,
+    // 設定された場合はパケット毎にchecksumを検証する
+    checksum: Option<Box<dyn CheckSumCalc + Send + Sync>>,
+    stats: Option<DecodeStats>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncKLVReader<R> {
+    /// create reader with universal key length
+    pub fn new(reader: R, key_len: usize) -> Self {
+        Self {
+            reader,
+            key_len,
+            buf: vec![],
+            consumed: 0,
+            chunk: vec![0; READ_CHUNK_SIZE],
+            max_content_len: DeserializerConfig::DEFAULT_MAX_CONTENT_LEN,
+            checksum: None,
+            stats: None,
+        }
+    }
+
+    /// set limit of the packet content length
+    pub fn with_max_content_len(mut self, max_content_len: usize) -> Self {
+        self.max_content_len = max_content_len;
+        self
+    }
+
+    /// verify the checksum record at the end of every packet
+    ///
+    /// Packets with unmatched or missing checksum are returned as error and skipped.
+    pub fn with_checksum<C>(mut self, crc: C) -> Self
+    where
+        C: CheckSumCalc + Send + Sync + 'static,
+    {
+        self.checksum = Some(Box::new(crc));
+        self
+    }
+
+    /// collect [`DecodeStats`] of the packets read
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(DecodeStats::default());
+        self
+    }
+
+    /// statistics collected since the reader was created or the last [`AsyncKLVReader::take_stats`]
+    pub fn stats(&self) -> Option<&DecodeStats> {
+        self.stats.as_ref()
+    }
+
+    /// return the statistics and reset the counters
+    pub fn take_stats(&mut self) -> Option<DecodeStats> {
+        self.stats.as_mut().map(std::mem::take)
+    }
+
+    /// read next packet bytes
+    ///
+    /// Return `None` when stream is closed at packet boundary.
+    pub async fn read_packet(&mut self) -> Result<Option<&[u8]>> {
+        self.buf.drain(..self.consumed);
+        self.consumed = 0;
+        let size = loop {
+            if let Some(len) = packet_len(&self.buf, self.key_len, self.max_content_len)? {
+                break len;
+            }
+            let n = self.reader.read(&mut self.chunk).await.map_err(Error::IO)?;
+            if n == 0 {
+                // パケットの途中でストリームが閉じた
+                return if self.buf.is_empty() {
+                    Ok(None)
+                } else {
+                    Err(Error::ContentLenght)
+                };
+            }
+            self.buf.extend_from_slice(&self.chunk[..n]);
+        };
+        self.consumed = size;
+        let packet = &self.buf[..size];
+        if let Some(stats) = self.stats.as_mut() {
+            stats.record_packet(packet, self.key_len);
+        }
+        if let Some(crc) = self.checksum.as_deref() {
+            if let Err(e) = checksum(packet, crc) {
+                if let Some(stats) = self.stats.as_mut() {
+                    stats.record_checksum_failure();
+                }
+                return Err(e);
+            }
+        }
+        Ok(Some(packet))
+    }
+
+    /// read next packet and deserialize
+    pub async fn read<T: DeserializeOwned>(&mut self) -> Result<Option<T>> {
+        if self.read_packet().await?.is_none() {
+            return Ok(None);
+        }
+        let packet = &self.buf[..self.consumed];
+        let mut unknown = 0;
+        let mut deserializer = KLVDeserializer::from_slice(packet);
+        if self.stats.is_some() {
+            deserializer.on_unknown_tag(|_, _| unknown += 1);
+        }
+        let result = T::deserialize(&mut deserializer).and_then(|x| deserializer.end().map(|_| x));
+        drop(deserializer);
+        if let Some(stats) = self.stats.as_mut() {
+            stats.record_unknown_tags(unknown);
+            if result.is_err() {
+                stats.record_decode_error();
+            }
+        }
+        result.map(Some)
+    }
+
+    /// read next packet as [`KLVMap`]
+    pub async fn read_map(&mut self) -> Result<Option<KLVMap<'_>>> {
+        if self.read_packet().await?.is_none() {
+            return Ok(None);
+        }
+        let result = KLVMap::try_from_bytes(&self.buf[..self.consumed]);
+        if let (Err(_), Some(stats)) = (&result, self.stats.as_mut()) {
+            stats.record_decode_error();
+        }
+        result.map(Some)
+    }
+
+    /// unwrap inner reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use tokio::io::AsyncWriteExt;
+
+    use crate::error::Error;
+    use crate::stream::AsyncKLVReader;
+    use crate::{to_bytes, to_bytes_with_checksum, WrappedCRC};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TESTDATA00000000")]
+    struct TestStream {
+        #[serde(rename = "10")]
+        u32: u32,
+        #[serde(rename = "11")]
+        string: String,
+    }
+
+    // 書き込みが分割されてもパケット単位で読み出せる
+    #[tokio::test]
+    async fn test_async_reader() {
+        let t = (0..3)
+            .map(|i| TestStream {
+                u32: i,
+                string: "x".repeat(i as usize * 100),
+            })
+            .collect::<Vec<_>>();
+        let mut buf = vec![];
+        for x in t.iter() {
+            buf.extend(to_bytes(x).unwrap());
+        }
+
+        let (mut tx, rx) = tokio::io::duplex(16);
+        let writer = tokio::spawn(async move {
+            for chunk in buf.chunks(7) {
+                tx.write_all(chunk).await.unwrap();
+            }
+        });
+
+        let mut reader = AsyncKLVReader::new(rx, 16);
+        let map = reader.read_map().await.unwrap().unwrap();
+        assert_eq!(map.iter().len(), 2);
+        for expect in t.iter().skip(1) {
+            let x = reader.read::<TestStream>().await.unwrap().unwrap();
+            assert_eq!(&x, expect);
+        }
+        assert!(reader.read::<TestStream>().await.unwrap().is_none());
+        writer.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_truncated() {
+        let buf = to_bytes(&TestStream {
+            u32: 1,
+            string: "truncated".to_string(),
+        })
+        .unwrap();
+        let mut reader = AsyncKLVReader::new(&buf[..buf.len() - 1], 16);
+        match reader.read_packet().await {
+            Err(Error::ContentLenght) => {}
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_length_limit() {
+        let buf = to_bytes(&TestStream {
+            u32: 1,
+            string: "x".repeat(100),
+        })
+        .unwrap();
+        // 全体を受信する前に宣言された長さで判定する
+        let mut reader = AsyncKLVReader::new(&buf[..20], 16).with_max_content_len(100);
+        match reader.read_packet().await {
+            Err(Error::LengthLimit { limit: 100, .. }) => {}
+            x => unreachable!("{:?}", x),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_stats() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TESTDATA00000000")]
+        struct TestShort {
+            #[serde(rename = "10")]
+            u32: u32,
+        }
+        let t = TestStream {
+            u32: 1,
+            string: "stats".to_string(),
+        };
+        let mut buf = to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap();
+        let packet_len = buf.len();
+        let mut broken = buf.clone();
+        broken[packet_len - 1] ^= 0xff;
+        buf.extend(broken);
+        buf.extend(to_bytes_with_checksum(&t, WrappedCRC::default()).unwrap());
+
+        let mut reader = AsyncKLVReader::new(buf.as_slice(), 16)
+            .with_checksum(WrappedCRC::default())
+            .with_stats();
+        assert_eq!(reader.read::<TestShort>().await.unwrap().unwrap().u32, 1);
+        // checksumが一致しないパケットは飛ばして次を読める
+        assert!(matches!(
+            reader.read::<TestShort>().await,
+            Err(Error::UnmatcheChecksum { .. })
+        ));
+        assert!(reader.read::<TestStream>().await.unwrap().is_some());
+        assert!(reader.read::<TestStream>().await.unwrap().is_none());
+
+        let stats = reader.take_stats().unwrap();
+        assert_eq!(stats.packets, 3);
+        assert_eq!(stats.bytes, buf.len() as u64);
+        assert_eq!(stats.tag_count(10), 3);
+        assert_eq!(stats.tag_count(1), 3);
+        assert_eq!(stats.checksum_failures, 1);
+        // 構造体にないchecksumのレコードと、TestShortにない文字列のレコード
+        assert_eq!(stats.unknown_tags, 3);
+        assert_eq!(stats.decode_errors, 0);
+        assert_eq!(reader.stats().unwrap().packets, 0);
+
+        // UniversalKeyが異なる型では読めない
+        #[derive(Debug, Deserialize)]
+        #[serde(rename = "TESTDATA00000001")]
+        struct TestOther {}
+        let mut reader = AsyncKLVReader::new(&buf[..packet_len], 16).with_stats();
+        assert!(reader.read::<TestOther>().await.is_err());
+        assert_eq!(reader.stats().unwrap().decode_errors, 1);
+        assert!(AsyncKLVReader::new(buf.as_slice(), 16).stats().is_none());
+    }
+}
@@ -0,0 +1,294 @@
+//! Known-good packets and assertions for tests
+//!
+//! [`Fixture`] holds a sample packet of MISB standards.
+//! Downstream crates can check their structs against the real vectors.
+//!
+//! - [`assert_roundtrip`] decodes the fixture, encodes it again and compares the records
+//! - [`assert_matches_fixture`] encodes the value and compares it with the fixture byte by byte
+//!
+//! Records are compared by [`crate::diff::diff`] and the differences are reported in the panic message.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::testkit::{assert_matches_fixture, assert_roundtrip, ST0102_12};
+//!
+//! #[derive(Debug, Serialize, Deserialize)]
+//! #[serde(rename = "\x06\x0e\x2b\x34\x02\x03\x01\x01\x0e\x01\x03\x03\x02\x00\x00\x00")]
+//! struct Security<'a> {
+//!     #[serde(rename = "1")]
+//!     classification: u8,
+//!     #[serde(rename = "2")]
+//!     country_coding_method: u8,
+//!     #[serde(rename = "3")]
+//!     classifying_country: &'a str,
+//!     #[serde(rename = "6")]
+//!     releasing_instructions: &'a str,
+//!     #[serde(rename = "12")]
+//!     object_country_coding_method: u8,
+//!     #[serde(rename = "22")]
+//!     version: u16,
+//! }
+//!
+//! let x: Security = assert_roundtrip(&ST0102_12);
+//! assert_eq!(x.version, 12);
+//! assert_matches_fixture(&x, &ST0102_12);
+//! ```
+
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::{CheckSumCalc, Crc16Algo, WrappedCRC};
+use crate::de::{from_bytes, from_bytes_with_checksum, KLVMap};
+use crate::diff::{diff, Difference};
+use crate::ser::{to_bytes, to_bytes_with_checksum};
+
+/// Sample packet with its source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixture {
+    /// short name of the fixture
+    pub name: &'static str,
+    /// document the packet is taken from
+    pub reference: &'static str,
+    /// checksum of the packet, `None` if it has no checksum record
+    pub checksum: Option<Crc16Algo>,
+    /// whole packet including the universal key
+    pub bytes: &'static [u8],
+}
+
+/// MISB ST 0601.8 UAS Datalink Local Set with checksum
+#[rustfmt::skip]
+pub const ST0601_8: Fixture = Fixture {
+    name: "st0601_8",
+    reference: "MISB ST 0601.8 example local set",
+    checksum: Some(Crc16Algo::Misb0601),
+    bytes: &[
+        0x06, 0x0e, 0x2b, 0x34, 0x02, 0x0b, 0x01, 0x01, 0x0e, 0x01, 0x03, 0x01, 0x01, 0x00, 0x00, 0x00,
+        129, 0x91,
+        2, 8, 0, 0x4, 0x6c, 0x8e, 0x20, 0x03, 0x83, 0x85,
+        65, 1, 1,
+        5, 2, 0x3d, 0x3b,
+        6, 2, 0x15, 0x80,
+        7, 2, 0x01, 0x52,
+        11, 3, 0x45, 0x4f, 0x4e,
+        12, 14, 0x47, 0x65, 0x6f, 0x64, 0x65, 0x74, 0x69, 0x63, 0x20, 0x57, 0x47, 0x53, 0x38, 0x34,
+        13, 4, 0x4d, 0xc4, 0xdc, 0xbb,
+        14, 4, 0xb1, 0xa8, 0x6c, 0xfe,
+        15, 2, 0x1f, 0x4a,
+        16, 2, 0x00, 0x85,
+        17, 2, 0x00, 0x4b,
+        18, 4, 0x20, 0xc8, 0xd2, 0x7d,
+        19, 4, 0xfc, 0xdd, 0x02, 0xd8,
+        20, 4, 0xfe, 0xb8, 0xcb, 0x61,
+        21, 4, 0x00, 0x8f, 0x3e, 0x61,
+        22, 4, 0x00, 0x00, 0x01, 0xc9,
+        23, 4, 0x4d, 0xdd, 0x8c, 0x2a,
+        24, 4, 0xb1, 0xbe, 0x9e, 0xf4,
+        25, 2, 0x0b, 0x85,
+        40, 4, 0x4d, 0xdd, 0x8c, 0x2a,
+        41, 4, 0xb1, 0xbe, 0x9e, 0xf4,
+        42, 2, 0x0b, 0x85,
+        56, 1, 0x2e,
+        57, 4, 0x00, 0x8d, 0xd4, 0x29,
+        1, 2, 0x1c, 0x5f,
+    ],
+};
+
+/// MISB ST 0102.12 Security Metadata Local Set
+#[rustfmt::skip]
+pub const ST0102_12: Fixture = Fixture {
+    name: "st0102_12",
+    reference: "MISB ST 0102.12 security local set",
+    checksum: None,
+    bytes: &[
+        0x06, 0x0e, 0x2b, 0x34, 0x02, 0x03, 0x01, 0x01, 0x0e, 0x01, 0x03, 0x03, 0x02, 0x00, 0x00, 0x00,
+        26,
+        1, 1, 0x01,
+        2, 1, 0x01,
+        3, 4, b'/', b'/', b'U', b'S',
+        6, 5, b'U', b'S', b' ', b'G', b'B',
+        12, 1, 0x0e,
+        22, 2, 0x00, 0x0c,
+    ],
+};
+
+/// All fixtures of this module
+pub const FIXTURES: &[Fixture] = &[ST0601_8, ST0102_12];
+
+/// Decode the fixture, encode it again and assert the records are the same
+///
+/// Order of the records and the value of the checksum record are not compared,
+/// since the struct may write the fields in its own order.
+/// Returns the decoded value for further assertions.
+#[track_caller]
+pub fn assert_roundtrip<T>(fixture: &Fixture) -> T
+where
+    T: Serialize + Deserialize<'static>,
+{
+    let (value, encoded) = match fixture.checksum {
+        Some(algo) => {
+            let crc = WrappedCRC::with_algorithm(algo);
+            let value: T = from_bytes_with_checksum(fixture.bytes, &crc)
+                .unwrap_or_else(|e| panic!("failed to deserialize {}: {}", fixture.name, e));
+            let encoded = to_bytes_with_checksum(&value, &crc)
+                .unwrap_or_else(|e| panic!("failed to serialize {}: {}", fixture.name, e));
+            (value, encoded)
+        }
+        None => {
+            let value: T = from_bytes(fixture.bytes)
+                .unwrap_or_else(|e| panic!("failed to deserialize {}: {}", fixture.name, e));
+            let encoded = to_bytes(&value)
+                .unwrap_or_else(|e| panic!("failed to serialize {}: {}", fixture.name, e));
+            (value, encoded)
+        }
+    };
+    let differences = record_differences(fixture, &encoded);
+    if !differences.is_empty() {
+        panic!(
+            "records of {} differ after round trip\n{}",
+            fixture.name,
+            format_differences(&differences)
+        );
+    }
+    value
+}
+
+/// Encode the value and assert it is the same bytes as the fixture
+#[track_caller]
+pub fn assert_matches_fixture<T: Serialize>(value: &T, fixture: &Fixture) {
+    let encoded = match fixture.checksum {
+        Some(algo) => to_bytes_with_checksum(value, WrappedCRC::with_algorithm(algo)),
+        None => to_bytes(value),
+    }
+    .unwrap_or_else(|e| panic!("failed to serialize {}: {}", fixture.name, e));
+    if encoded == fixture.bytes {
+        return;
+    }
+    let differences = record_differences(fixture, &encoded);
+    if !differences.is_empty() {
+        panic!(
+            "records differ from {}\n{}",
+            fixture.name,
+            format_differences(&differences)
+        );
+    }
+    // レコードは同じで順序や長さの表現が異なる
+    let offset = encoded
+        .iter()
+        .zip(fixture.bytes)
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| encoded.len().min(fixture.bytes.len()));
+    panic!(
+        "bytes differ from {} at offset {}, expected {} bytes but got {} bytes",
+        fixture.name,
+        offset,
+        fixture.bytes.len(),
+        encoded.len()
+    );
+}
+
+// fixtureとエンコード結果をレコード単位で比較する。checksumの値の違いは無視する
+#[track_caller]
+fn record_differences(fixture: &Fixture, encoded: &[u8]) -> Vec<Difference> {
+    let before = KLVMap::try_from_bytes(fixture.bytes)
+        .unwrap_or_else(|e| panic!("failed to parse {}: {}", fixture.name, e));
+    let after = KLVMap::try_from_bytes(encoded)
+        .unwrap_or_else(|e| panic!("failed to parse the encoded {}: {}", fixture.name, e));
+    let checksum_tag = fixture
+        .checksum
+        .map(|algo| WrappedCRC::with_algorithm(algo).tag());
+    diff(&before, &after)
+        .into_iter()
+        .filter(|d| match d {
+            Difference::Changed { path, .. } => {
+                checksum_tag.map_or(true, |tag| path.as_slice() != [tag])
+            }
+            _ => true,
+        })
+        .collect()
+}
+
+fn format_differences(differences: &[Difference]) -> String {
+    differences.iter().fold(String::new(), |mut s, d| {
+        let _ = writeln!(s, "  {}", d);
+        s
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::{assert_matches_fixture, assert_roundtrip, FIXTURES, ST0102_12};
+    use crate::checksum::WrappedCRC;
+    use crate::{verify_checksum, ChecksumLocation, ChecksumStatus, KLVMap};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "\x06\x0e\x2b\x34\x02\x03\x01\x01\x0e\x01\x03\x03\x02\x00\x00\x00")]
+    struct Security<'a> {
+        #[serde(rename = "1")]
+        classification: u8,
+        #[serde(rename = "2")]
+        country_coding_method: u8,
+        #[serde(rename = "3")]
+        classifying_country: &'a str,
+        #[serde(rename = "6")]
+        releasing_instructions: &'a str,
+        #[serde(rename = "12")]
+        object_country_coding_method: u8,
+        #[serde(rename = "22")]
+        version: u16,
+    }
+
+    #[test]
+    fn test_fixtures() {
+        for fixture in FIXTURES {
+            assert!(
+                KLVMap::try_from_bytes(fixture.bytes).is_ok(),
+                "{}",
+                fixture.name
+            );
+            if let Some(algo) = fixture.checksum {
+                let status = verify_checksum(
+                    fixture.bytes,
+                    &WrappedCRC::with_algorithm(algo),
+                    ChecksumLocation::LastRecord,
+                );
+                assert!(
+                    matches!(status, ChecksumStatus::Valid { .. }),
+                    "{}",
+                    fixture.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_assert_roundtrip() {
+        let x: Security = assert_roundtrip(&ST0102_12);
+        assert_eq!(x.classifying_country, "//US");
+        assert_eq!(x.version, 12);
+        assert_matches_fixture(&x, &ST0102_12);
+    }
+
+    #[test]
+    #[should_panic(expected = "tag 22 changed")]
+    fn test_assert_matches_fixture() {
+        let mut x: Security = crate::from_bytes(ST0102_12.bytes).unwrap();
+        x.version = 13;
+        assert_matches_fixture(&x, &ST0102_12);
+    }
+
+    #[test]
+    #[cfg(feature = "uasdls")]
+    fn test_st0601_roundtrip() {
+        use super::ST0601_8;
+        use crate::uasdls::UASDatalinkLS;
+
+        // tag 65が2番目にあるため、バイト列は一致しないがレコードは同じ
+        let x: UASDatalinkLS = assert_roundtrip(&ST0601_8);
+        assert_eq!(x.image_source_sensor, Some("EON"));
+    }
+}
@@ -0,0 +1,269 @@
+//! Adapters to encode timestamps as integer from UNIX epoch
+//!
+//! Use with `#[serde(with = "...")]`.
+//! Each module is available for `SystemTime`,
+//! and for `chrono::DateTime<Utc>` or `time::OffsetDateTime` by enabling the feature of the same name.
+//! `chrono::DateTime<Utc>` serializes itself as RFC 3339 string,
+//! so use [`chrono_micros`] for the field of ST 0601 Precision Time Stamp,
+//! or convert from [`crate::st0603::PrecisionTimestamp`] with `From`.
+//!
+//! Example
+//!
+//! ```rust
+//! use std::time::{Duration, SystemTime};
+//!
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "2", with = "serde_klv::timestamps::micros_u64")]
+//!     ts: SystemTime,
+//! }
+//!
+//! let t = Packet {
+//!     ts: SystemTime::UNIX_EPOCH + Duration::from_micros(1_234_567),
+//! };
+//! let buf = to_bytes(&t).unwrap();
+//! assert_eq!(&buf[4..], &[10, 2, 8, 0, 0, 0, 0, 0, 0x12, 0xd6, 0x87]);
+//! assert_eq!(from_bytes::<Packet>(&buf).unwrap(), t);
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+#[derive(Debug, Clone, Copy)]
+enum Unit {
+    Seconds,
+    Micros,
+    Nanos,
+}
+
+fn serialize<S>(time: SystemTime, unit: Unit, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let duration = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_err(|e| ser::Error::custom(format!("timestamp before UNIX epoch. {e}")))?;
+    match unit {
+        Unit::Seconds => serializer.serialize_u32(
+            u32::try_from(duration.as_secs())
+                .map_err(|_| ser::Error::custom("timestamp overflow u32 seconds"))?,
+        ),
+        Unit::Micros => serializer.serialize_u64(
+            u64::try_from(duration.as_micros())
+                .map_err(|_| ser::Error::custom("timestamp overflow u64 microseconds"))?,
+        ),
+        Unit::Nanos => serializer.serialize_u64(
+            u64::try_from(duration.as_nanos())
+                .map_err(|_| ser::Error::custom("timestamp overflow u64 nanoseconds"))?,
+        ),
+    }
+}
+
+fn deserialize<'de, D>(deserializer: D, unit: Unit) -> Result<SystemTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let duration = match unit {
+        Unit::Seconds => Duration::from_secs(u32::deserialize(deserializer)? as u64),
+        Unit::Micros => Duration::from_micros(u64::deserialize(deserializer)?),
+        Unit::Nanos => Duration::from_nanos(u64::deserialize(deserializer)?),
+    };
+    SystemTime::UNIX_EPOCH
+        .checked_add(duration)
+        .ok_or_else(|| de::Error::custom("failed to deserialize systemtime"))
+}
+
+// 時刻型毎にSystemTimeを経由するwithモジュールを定義する
+macro_rules! adapter {
+    ($(#[$doc:meta])* $name:ident, $ty:ty, $unit:expr) => {
+        $(#[$doc])*
+        pub mod $name {
+            use std::time::SystemTime;
+
+            use serde::{Deserializer, Serializer};
+
+            pub fn serialize<S>(time: &$ty, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                $crate::timestamps::serialize(SystemTime::from(*time), $unit, serializer)
+            }
+
+            pub fn deserialize<'de, D>(deserializer: D) -> Result<$ty, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                $crate::timestamps::deserialize(deserializer, $unit).map(<$ty>::from)
+            }
+        }
+    };
+}
+
+adapter!(
+    /// microseconds from UNIX epoch as u64, e.g. ST 0601 Precision Time Stamp
+    micros_u64,
+    std::time::SystemTime,
+    crate::timestamps::Unit::Micros
+);
+adapter!(
+    /// nanoseconds from UNIX epoch as u64
+    nanos_u64,
+    std::time::SystemTime,
+    crate::timestamps::Unit::Nanos
+);
+adapter!(
+    /// seconds from UNIX epoch as u32
+    seconds_u32,
+    std::time::SystemTime,
+    crate::timestamps::Unit::Seconds
+);
+
+/// Adapters for `chrono::DateTime<Utc>`
+#[cfg(feature = "chrono")]
+pub mod chrono {
+    adapter!(
+        /// microseconds from UNIX epoch as u64
+        micros_u64,
+        ::chrono::DateTime<::chrono::Utc>,
+        crate::timestamps::Unit::Micros
+    );
+    adapter!(
+        /// nanoseconds from UNIX epoch as u64
+        nanos_u64,
+        ::chrono::DateTime<::chrono::Utc>,
+        crate::timestamps::Unit::Nanos
+    );
+    adapter!(
+        /// seconds from UNIX epoch as u32
+        seconds_u32,
+        ::chrono::DateTime<::chrono::Utc>,
+        crate::timestamps::Unit::Seconds
+    );
+}
+
+/// microseconds from UNIX epoch as u64 for `chrono::DateTime<Utc>`, the same as [`chrono::micros_u64`]
+///
+/// Example
+/// ```
+/// use chrono::{DateTime, TimeZone, Utc};
+/// use serde::{Deserialize, Serialize};
+/// use serde_klv::{from_bytes, to_bytes};
+///
+/// #[derive(Debug, Serialize, Deserialize, PartialEq)]
+/// #[serde(rename = "TEST")]
+/// struct Packet {
+///     #[serde(rename = "2", with = "serde_klv::timestamps::chrono_micros")]
+///     ts: DateTime<Utc>,
+/// }
+///
+/// let t = Packet {
+///     ts: Utc.timestamp_opt(1, 234_567_000).unwrap(),
+/// };
+/// let buf = to_bytes(&t).unwrap();
+/// assert_eq!(&buf[4..], &[10, 2, 8, 0, 0, 0, 0, 0, 0x12, 0xd6, 0x87]);
+/// assert_eq!(from_bytes::<Packet>(&buf).unwrap(), t);
+/// ```
+#[cfg(feature = "chrono")]
+pub use self::chrono::micros_u64 as chrono_micros;
+
+/// Adapters for `time::OffsetDateTime`
+#[cfg(feature = "time")]
+pub mod time {
+    adapter!(
+        /// microseconds from UNIX epoch as u64
+        micros_u64,
+        ::time::OffsetDateTime,
+        crate::timestamps::Unit::Micros
+    );
+    adapter!(
+        /// nanoseconds from UNIX epoch as u64
+        nanos_u64,
+        ::time::OffsetDateTime,
+        crate::timestamps::Unit::Nanos
+    );
+    adapter!(
+        /// seconds from UNIX epoch as u32
+        seconds_u32,
+        ::time::OffsetDateTime,
+        crate::timestamps::Unit::Seconds
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{from_bytes, to_bytes, KLVMap};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestTimestamp {
+        #[serde(rename = "10", with = "crate::timestamps::seconds_u32")]
+        seconds: SystemTime,
+        #[serde(rename = "11", with = "crate::timestamps::micros_u64")]
+        micros: SystemTime,
+        #[serde(rename = "12", with = "crate::timestamps::nanos_u64")]
+        nanos: SystemTime,
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let ts = SystemTime::UNIX_EPOCH + Duration::from_nanos(1_663_000_000_123_456_789);
+        let t = TestTimestamp {
+            seconds: ts,
+            micros: ts,
+            nanos: ts,
+        };
+        let buf = to_bytes(&t).unwrap();
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        let lengths = map.iter().map(|x| x.length).collect::<Vec<_>>();
+        assert_eq!(lengths, vec![4, 8, 8]);
+        // 単位未満は切り捨てられる
+        let x = from_bytes::<TestTimestamp>(&buf).unwrap();
+        assert_eq!(
+            x.seconds,
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_663_000_000)
+        );
+        assert_eq!(
+            x.micros,
+            SystemTime::UNIX_EPOCH + Duration::from_micros(1_663_000_000_123_456)
+        );
+        assert_eq!(x.nanos, ts);
+
+        // UNIX epochより前は表現できない
+        let t = TestTimestamp {
+            seconds: SystemTime::UNIX_EPOCH - Duration::from_secs(1),
+            micros: ts,
+            nanos: ts,
+        };
+        assert!(to_bytes(&t).is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_timestamps() {
+        use chrono::{DateTime, TimeZone, Utc};
+
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestChrono {
+            #[serde(rename = "2", with = "crate::timestamps::chrono::micros_u64")]
+            ts: DateTime<Utc>,
+            #[serde(rename = "3", with = "crate::timestamps::chrono_micros")]
+            alias: DateTime<Utc>,
+        }
+        let ts = Utc.timestamp_opt(1_663_000_000, 123_456_000).unwrap();
+        let t = TestChrono { ts, alias: ts };
+        let buf = to_bytes(&t).unwrap();
+        let map = KLVMap::try_from_bytes(&buf).unwrap();
+        assert_eq!(map.get_bytes(2), map.get_bytes(3));
+        assert_eq!(from_bytes::<TestChrono>(&buf).unwrap(), t);
+    }
+}
@@ -1,18 +1,22 @@
 //! Example impl for MISB Standard 0601
 //! the Unmanned Air System (UAS) Datalink Local Set (LS)
-//! reference: MISB ST 0601.8
+//! reference: MISB ST 0601.8, and ST 0601.17 for [`UASDatalinkLS17`]
 
-use std::time::SystemTime;
+use std::fmt;
 
 use serde::{Deserialize, Serialize};
 
 use crate::checksum::CheckSumCalc;
+use crate::error::Error;
+use crate::raw::RawKLV;
+use crate::st0102::SecurityLocalSet;
+use crate::st0603::PrecisionTimestamp;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename = "\x06\x0e\x2b\x34\x02\x0b\x01\x01\x0e\x01\x03\x01\x01\x00\x00\x00")]
 pub struct UASDatalinkLS<'a> {
-    #[serde(rename = "2", with = "timestamp_micro")]
-    pub timestamp: SystemTime,
+    #[serde(rename = "2")]
+    pub timestamp: PrecisionTimestamp,
     /// Relative between longitudinal axis and True North measured in the horizontal plane.
     /// Map 0..(2^16-1) to 0..360.
     /// Resolution: ~5.5 milli degrees.
@@ -76,6 +80,10 @@ pub struct UASDatalinkLS<'a> {
     #[serde(rename = "42", skip_serializing_if = "Option::is_none")]
     pub target_location_elecation: Option<u16>,
 
+    /// MISB ST 0102 Security Local Set without universal key.
+    #[serde(rename = "48", skip_serializing_if = "Option::is_none", borrow)]
+    pub security_local_set: Option<SecurityLocalSet<'a>>,
+
     #[serde(rename = "56", skip_serializing_if = "Option::is_none")]
     pub plafform_ground_speed: Option<u8>,
     #[serde(rename = "57", skip_serializing_if = "Option::is_none")]
@@ -88,20 +96,15 @@ pub struct UASDatalinkLS<'a> {
 pub struct CRC;
 
 impl CheckSumCalc for CRC {
-    fn checksum(&self, bytes: &[u8]) -> u16 {
-        let mut bcc: u16 = 0;
-        for (i, v) in bytes.iter().enumerate() {
-            let x = (*v as u16) << (8 * ((i + 1) % 2));
-            bcc = bcc.wrapping_add(x);
-        }
-        bcc
+    fn checksum(&self, bytes: &[u8]) -> u32 {
+        crate::checksum::misb0601_checksum(bytes) as u32
     }
 }
 
 impl<'a> Default for UASDatalinkLS<'a> {
     fn default() -> Self {
         Self {
-            timestamp: SystemTime::UNIX_EPOCH,
+            timestamp: PrecisionTimestamp::UNIX_EPOCH,
             platform_heading_angle: Default::default(),
             platform_pitch_angle: Default::default(),
             platform_roll_angle: Default::default(),
@@ -123,6 +126,7 @@ impl<'a> Default for UASDatalinkLS<'a> {
             target_location_latitude: Default::default(),
             target_location_longitude: Default::default(),
             target_location_elecation: Default::default(),
+            security_local_set: Default::default(),
             plafform_ground_speed: Default::default(),
             ground_range: Default::default(),
             ls_version_number: Default::default(),
@@ -130,29 +134,1127 @@ impl<'a> Default for UASDatalinkLS<'a> {
     }
 }
 
-mod timestamp_micro {
-    use serde::{Deserialize, Deserializer, Serializer};
-    use std::time::{Duration, SystemTime};
-
-    pub fn serialize<S>(date: &SystemTime, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let micros = date
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap()
-            .as_micros();
-        serializer.serialize_u64(micros as u64)
-    }
-
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<SystemTime, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let micros = u64::deserialize(deserializer)?;
-        SystemTime::UNIX_EPOCH
-            .checked_add(Duration::from_micros(micros))
-            .ok_or_else(|| serde::de::Error::custom("failed to deserialize systemtime"))
+/// MISB ST 0601.17 UAS Datalink Local Set covering tags 2 to 143
+///
+/// Values are kept in the encoded integer and the mapping to the unit is noted on each field.
+/// Tags encoded as IMAPB, packs or other local sets are kept as [`RawKLV`].
+/// Checksum (tag 1) is handled by [`CRC`] and deprecated tag 66 is not defined.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename = "\x06\x0e\x2b\x34\x02\x0b\x01\x01\x0e\x01\x03\x01\x01\x00\x00\x00")]
+pub struct UASDatalinkLS17<'a> {
+    /// Microseconds from UNIX epoch, MISB ST 0603.
+    #[serde(rename = "2")]
+    pub timestamp: PrecisionTimestamp,
+    /// Descriptive mission identifier.
+    #[serde(rename = "3", skip_serializing_if = "Option::is_none")]
+    pub mission_id: Option<&'a str>,
+    /// Identifier of the platform.
+    #[serde(rename = "4", skip_serializing_if = "Option::is_none")]
+    pub platform_tail_number: Option<&'a str>,
+    /// Map 0..(2^16-1) to 0..360 degrees.
+    #[serde(rename = "5", skip_serializing_if = "Option::is_none")]
+    pub platform_heading_angle: Option<u16>,
+    /// Map -(2^15-1)..(2^15-1) to +/-20 degrees, 0x8000 is out of range.
+    #[serde(rename = "6", skip_serializing_if = "Option::is_none")]
+    pub platform_pitch_angle: Option<i16>,
+    /// Map -(2^15-1)..(2^15-1) to +/-50 degrees, 0x8000 is out of range.
+    #[serde(rename = "7", skip_serializing_if = "Option::is_none")]
+    pub platform_roll_angle: Option<i16>,
+    /// 0..255 meters/second.
+    #[serde(rename = "8", skip_serializing_if = "Option::is_none")]
+    pub platform_true_airspeed: Option<u8>,
+    /// 0..255 meters/second.
+    #[serde(rename = "9", skip_serializing_if = "Option::is_none")]
+    pub platform_indicated_airspeed: Option<u8>,
+    /// Model name of the platform.
+    #[serde(rename = "10", skip_serializing_if = "Option::is_none")]
+    pub platform_designation: Option<&'a str>,
+    /// Name of the currently active sensor.
+    #[serde(rename = "11", skip_serializing_if = "Option::is_none")]
+    pub image_source_sensor: Option<&'a str>,
+    /// Coordinate system used, e.g. "WGS-84".
+    #[serde(rename = "12", skip_serializing_if = "Option::is_none")]
+    pub image_coordinate_system: Option<&'a str>,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "13", skip_serializing_if = "Option::is_none")]
+    pub sensor_latitude: Option<i32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-180 degrees.
+    #[serde(rename = "14", skip_serializing_if = "Option::is_none")]
+    pub sensor_longitude: Option<i32>,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "15", skip_serializing_if = "Option::is_none")]
+    pub sensor_true_altitude: Option<u16>,
+    /// Map 0..(2^16-1) to 0..180 degrees.
+    #[serde(rename = "16", skip_serializing_if = "Option::is_none")]
+    pub sensor_horizontal_fov: Option<u16>,
+    /// Map 0..(2^16-1) to 0..180 degrees.
+    #[serde(rename = "17", skip_serializing_if = "Option::is_none")]
+    pub sensor_vertical_fov: Option<u16>,
+    /// Map 0..(2^32-1) to 0..360 degrees.
+    #[serde(rename = "18", skip_serializing_if = "Option::is_none")]
+    pub sensor_relative_azimuth_angle: Option<u32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-180 degrees.
+    #[serde(rename = "19", skip_serializing_if = "Option::is_none")]
+    pub sensor_relative_elevation_angle: Option<i32>,
+    /// Map 0..(2^32-1) to 0..360 degrees.
+    #[serde(rename = "20", skip_serializing_if = "Option::is_none")]
+    pub sensor_relative_roll_angle: Option<u32>,
+    /// Map 0..(2^32-1) to 0..5000000 meters.
+    #[serde(rename = "21", skip_serializing_if = "Option::is_none")]
+    pub slant_range: Option<u32>,
+    /// Map 0..(2^16-1) to 0..10000 meters.
+    #[serde(rename = "22", skip_serializing_if = "Option::is_none")]
+    pub target_width: Option<u16>,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "23", skip_serializing_if = "Option::is_none")]
+    pub frame_center_latitude: Option<i32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-180 degrees.
+    #[serde(rename = "24", skip_serializing_if = "Option::is_none")]
+    pub frame_center_longitude: Option<i32>,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "25", skip_serializing_if = "Option::is_none")]
+    pub frame_center_elevation: Option<u16>,
+    /// Map -(2^15-1)..(2^15-1) to +/-0.075 degrees from frame center.
+    #[serde(rename = "26", skip_serializing_if = "Option::is_none")]
+    pub offset_corner_latitude_point_1: Option<i16>,
+    /// Map -(2^15-1)..(2^15-1) to +/-0.075 degrees from frame center.
+    #[serde(rename = "27", skip_serializing_if = "Option::is_none")]
+    pub offset_corner_longitude_point_1: Option<i16>,
+    #[serde(rename = "28", skip_serializing_if = "Option::is_none")]
+    pub offset_corner_latitude_point_2: Option<i16>,
+    #[serde(rename = "29", skip_serializing_if = "Option::is_none")]
+    pub offset_corner_longitude_point_2: Option<i16>,
+    #[serde(rename = "30", skip_serializing_if = "Option::is_none")]
+    pub offset_corner_latitude_point_3: Option<i16>,
+    #[serde(rename = "31", skip_serializing_if = "Option::is_none")]
+    pub offset_corner_longitude_point_3: Option<i16>,
+    #[serde(rename = "32", skip_serializing_if = "Option::is_none")]
+    pub offset_corner_latitude_point_4: Option<i16>,
+    #[serde(rename = "33", skip_serializing_if = "Option::is_none")]
+    pub offset_corner_longitude_point_4: Option<i16>,
+    /// 0: detector off, 1: no icing, 2: icing detected.
+    #[serde(rename = "34", skip_serializing_if = "Option::is_none")]
+    pub icing_detected: Option<u8>,
+    /// Map 0..(2^16-1) to 0..360 degrees, direction the wind comes from.
+    #[serde(rename = "35", skip_serializing_if = "Option::is_none")]
+    pub wind_direction: Option<u16>,
+    /// Map 0..255 to 0..100 meters/second.
+    #[serde(rename = "36", skip_serializing_if = "Option::is_none")]
+    pub wind_speed: Option<u8>,
+    /// Map 0..(2^16-1) to 0..5000 millibar.
+    #[serde(rename = "37", skip_serializing_if = "Option::is_none")]
+    pub static_pressure: Option<u16>,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "38", skip_serializing_if = "Option::is_none")]
+    pub density_altitude: Option<u16>,
+    /// -128..127 degrees Celsius.
+    #[serde(rename = "39", skip_serializing_if = "Option::is_none")]
+    pub outside_air_temperature: Option<i8>,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "40", skip_serializing_if = "Option::is_none")]
+    pub target_location_latitude: Option<i32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-180 degrees.
+    #[serde(rename = "41", skip_serializing_if = "Option::is_none")]
+    pub target_location_longitude: Option<i32>,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "42", skip_serializing_if = "Option::is_none")]
+    pub target_location_elevation: Option<u16>,
+    /// Track gate width in pixels, half of the value.
+    #[serde(rename = "43", skip_serializing_if = "Option::is_none")]
+    pub target_track_gate_width: Option<u8>,
+    /// Track gate height in pixels, half of the value.
+    #[serde(rename = "44", skip_serializing_if = "Option::is_none")]
+    pub target_track_gate_height: Option<u8>,
+    /// Map 0..(2^16-1) to 0..4095 meters.
+    #[serde(rename = "45", skip_serializing_if = "Option::is_none")]
+    pub target_error_estimate_ce90: Option<u16>,
+    /// Map 0..(2^16-1) to 0..4095 meters.
+    #[serde(rename = "46", skip_serializing_if = "Option::is_none")]
+    pub target_error_estimate_le90: Option<u16>,
+    /// Bit flags of laser range, auto-track, IR polarity, icing, slant range and image invalid.
+    #[serde(rename = "47", skip_serializing_if = "Option::is_none")]
+    pub generic_flag_data: Option<GenericFlagData>,
+    /// MISB ST 0102 Security Local Set without universal key.
+    #[serde(rename = "48", skip_serializing_if = "Option::is_none", borrow)]
+    pub security_local_set: Option<SecurityLocalSet<'a>>,
+    /// Map 0..(2^16-1) to 0..5000 millibar.
+    #[serde(rename = "49", skip_serializing_if = "Option::is_none")]
+    pub differential_pressure: Option<u16>,
+    /// Map -(2^15-1)..(2^15-1) to +/-20 degrees, 0x8000 is out of range.
+    #[serde(rename = "50", skip_serializing_if = "Option::is_none")]
+    pub platform_angle_of_attack: Option<i16>,
+    /// Map -(2^15-1)..(2^15-1) to +/-180 meters/second, 0x8000 is out of range.
+    #[serde(rename = "51", skip_serializing_if = "Option::is_none")]
+    pub platform_vertical_speed: Option<i16>,
+    /// Map -(2^15-1)..(2^15-1) to +/-20 degrees, 0x8000 is out of range.
+    #[serde(rename = "52", skip_serializing_if = "Option::is_none")]
+    pub platform_sideslip_angle: Option<i16>,
+    /// Map 0..(2^16-1) to 0..5000 millibar.
+    #[serde(rename = "53", skip_serializing_if = "Option::is_none")]
+    pub airfield_barometric_pressure: Option<u16>,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "54", skip_serializing_if = "Option::is_none")]
+    pub airfield_elevation: Option<u16>,
+    /// Map 0..255 to 0..100 percent.
+    #[serde(rename = "55", skip_serializing_if = "Option::is_none")]
+    pub relative_humidity: Option<u8>,
+    /// 0..255 meters/second.
+    #[serde(rename = "56", skip_serializing_if = "Option::is_none")]
+    pub platform_ground_speed: Option<u8>,
+    /// Map 0..(2^32-1) to 0..5000000 meters.
+    #[serde(rename = "57", skip_serializing_if = "Option::is_none")]
+    pub ground_range: Option<u32>,
+    /// Map 0..(2^16-1) to 0..10000 kilograms.
+    #[serde(rename = "58", skip_serializing_if = "Option::is_none")]
+    pub platform_fuel_remaining: Option<u16>,
+    /// Call sign of the platform or operating unit.
+    #[serde(rename = "59", skip_serializing_if = "Option::is_none")]
+    pub platform_call_sign: Option<&'a str>,
+    /// Nibbles of station, hardpoint, carriage and store type.
+    #[serde(rename = "60", skip_serializing_if = "Option::is_none")]
+    pub weapon_load: Option<u16>,
+    /// Nibbles of station and hardpoint.
+    #[serde(rename = "61", skip_serializing_if = "Option::is_none")]
+    pub weapon_fired: Option<u8>,
+    /// 1111..8888, digits are in 1..8.
+    #[serde(rename = "62", skip_serializing_if = "Option::is_none")]
+    pub laser_prf_code: Option<u16>,
+    /// 0: Ultranarrow .. 7: Zoom, 8: Narrow Medium, 9: 2x Ultranarrow, 10: 4x Ultranarrow, 11: Continuous.
+    #[serde(rename = "63", skip_serializing_if = "Option::is_none")]
+    pub sensor_fov_name: Option<u8>,
+    /// Map 0..(2^16-1) to 0..360 degrees.
+    #[serde(rename = "64", skip_serializing_if = "Option::is_none")]
+    pub platform_magnetic_heading: Option<u16>,
+    /// Version number of ST 0601, 17 for ST 0601.17.
+    #[serde(rename = "65")]
+    pub ls_version_number: u8,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "67", skip_serializing_if = "Option::is_none")]
+    pub alternate_platform_latitude: Option<i32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-180 degrees.
+    #[serde(rename = "68", skip_serializing_if = "Option::is_none")]
+    pub alternate_platform_longitude: Option<i32>,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "69", skip_serializing_if = "Option::is_none")]
+    pub alternate_platform_altitude: Option<u16>,
+    /// Name of the alternate platform.
+    #[serde(rename = "70", skip_serializing_if = "Option::is_none")]
+    pub alternate_platform_name: Option<&'a str>,
+    /// Map 0..(2^16-1) to 0..360 degrees.
+    #[serde(rename = "71", skip_serializing_if = "Option::is_none")]
+    pub alternate_platform_heading: Option<u16>,
+    /// Microseconds from UNIX epoch.
+    #[serde(rename = "72", skip_serializing_if = "Option::is_none")]
+    pub event_start_time: Option<u64>,
+    /// MISB ST 0806 Remote Video Terminal Local Set.
+    #[serde(rename = "73", skip_serializing_if = "Option::is_none", borrow)]
+    pub rvt_local_set: Option<RawKLV<'a>>,
+    /// MISB ST 0903 Video Moving Target Indicator Local Set.
+    #[serde(rename = "74", skip_serializing_if = "Option::is_none", borrow)]
+    pub vmti_local_set: Option<RawKLV<'a>>,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "75", skip_serializing_if = "Option::is_none")]
+    pub sensor_ellipsoid_height: Option<u16>,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "76", skip_serializing_if = "Option::is_none")]
+    pub alternate_platform_ellipsoid_height: Option<u16>,
+    /// 0: Other, 1: Operational, 2: Training, 3: Exercise, 4: Maintenance, 5: Test.
+    #[serde(rename = "77", skip_serializing_if = "Option::is_none")]
+    pub operational_mode: Option<u8>,
+    /// Map 0..(2^16-1) to -900..19000 meters.
+    #[serde(rename = "78", skip_serializing_if = "Option::is_none")]
+    pub frame_center_height_above_ellipsoid: Option<u16>,
+    /// Map -(2^15-1)..(2^15-1) to +/-327 meters/second, 0x8000 is out of range.
+    #[serde(rename = "79", skip_serializing_if = "Option::is_none")]
+    pub sensor_north_velocity: Option<i16>,
+    /// Map -(2^15-1)..(2^15-1) to +/-327 meters/second, 0x8000 is out of range.
+    #[serde(rename = "80", skip_serializing_if = "Option::is_none")]
+    pub sensor_east_velocity: Option<i16>,
+    /// Defined-length pack of horizon pixel positions.
+    #[serde(rename = "81", skip_serializing_if = "Option::is_none", borrow)]
+    pub image_horizon_pixel_pack: Option<RawKLV<'a>>,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "82", skip_serializing_if = "Option::is_none")]
+    pub corner_latitude_point_1_full: Option<i32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-180 degrees.
+    #[serde(rename = "83", skip_serializing_if = "Option::is_none")]
+    pub corner_longitude_point_1_full: Option<i32>,
+    #[serde(rename = "84", skip_serializing_if = "Option::is_none")]
+    pub corner_latitude_point_2_full: Option<i32>,
+    #[serde(rename = "85", skip_serializing_if = "Option::is_none")]
+    pub corner_longitude_point_2_full: Option<i32>,
+    #[serde(rename = "86", skip_serializing_if = "Option::is_none")]
+    pub corner_latitude_point_3_full: Option<i32>,
+    #[serde(rename = "87", skip_serializing_if = "Option::is_none")]
+    pub corner_longitude_point_3_full: Option<i32>,
+    #[serde(rename = "88", skip_serializing_if = "Option::is_none")]
+    pub corner_latitude_point_4_full: Option<i32>,
+    #[serde(rename = "89", skip_serializing_if = "Option::is_none")]
+    pub corner_longitude_point_4_full: Option<i32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "90", skip_serializing_if = "Option::is_none")]
+    pub platform_pitch_angle_full: Option<i32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "91", skip_serializing_if = "Option::is_none")]
+    pub platform_roll_angle_full: Option<i32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-90 degrees.
+    #[serde(rename = "92", skip_serializing_if = "Option::is_none")]
+    pub platform_angle_of_attack_full: Option<i32>,
+    /// Map -(2^31-1)..(2^31-1) to +/-180 degrees.
+    #[serde(rename = "93", skip_serializing_if = "Option::is_none")]
+    pub platform_sideslip_angle_full: Option<i32>,
+    /// MISB ST 1204 Motion Imagery Identification System core identifier.
+    #[serde(rename = "94", skip_serializing_if = "Option::is_none", borrow)]
+    pub miis_core_identifier: Option<RawKLV<'a>>,
+    /// MISB ST 1206 SAR Motion Imagery Local Set.
+    #[serde(rename = "95", skip_serializing_if = "Option::is_none", borrow)]
+    pub sar_motion_imagery_local_set: Option<RawKLV<'a>>,
+    /// IMAPB(0, 1500000, length) meters.
+    #[serde(rename = "96", skip_serializing_if = "Option::is_none", borrow)]
+    pub target_width_extended: Option<RawKLV<'a>>,
+    /// MISB ST 1002 Range Image Local Set.
+    #[serde(rename = "97", skip_serializing_if = "Option::is_none", borrow)]
+    pub range_image_local_set: Option<RawKLV<'a>>,
+    /// MISB ST 1601 Geo-Registration Local Set.
+    #[serde(rename = "98", skip_serializing_if = "Option::is_none", borrow)]
+    pub geo_registration_local_set: Option<RawKLV<'a>>,
+    /// MISB ST 1602 Composite Imaging Local Set.
+    #[serde(rename = "99", skip_serializing_if = "Option::is_none", borrow)]
+    pub composite_imaging_local_set: Option<RawKLV<'a>>,
+    /// MISB ST 1607 Segment Local Set.
+    #[serde(rename = "100", skip_serializing_if = "Option::is_none", borrow)]
+    pub segment_local_set: Option<RawKLV<'a>>,
+    /// MISB ST 1607 Amend Local Set.
+    #[serde(rename = "101", skip_serializing_if = "Option::is_none", borrow)]
+    pub amend_local_set: Option<RawKLV<'a>>,
+    /// MISB ST 1010 Standard Deviation and Correlation Coefficient Floating Length Pack.
+    #[serde(rename = "102", skip_serializing_if = "Option::is_none", borrow)]
+    pub sdcc_flp: Option<RawKLV<'a>>,
+    /// IMAPB(-900, 40000, length) meters.
+    #[serde(rename = "103", skip_serializing_if = "Option::is_none", borrow)]
+    pub density_altitude_extended: Option<RawKLV<'a>>,
+    /// IMAPB(-900, 40000, length) meters.
+    #[serde(rename = "104", skip_serializing_if = "Option::is_none", borrow)]
+    pub sensor_ellipsoid_height_extended: Option<RawKLV<'a>>,
+    /// IMAPB(-900, 40000, length) meters.
+    #[serde(rename = "105", skip_serializing_if = "Option::is_none", borrow)]
+    pub alternate_platform_ellipsoid_height_extended: Option<RawKLV<'a>>,
+    /// Short name of the Motion Imagery stream.
+    #[serde(rename = "106", skip_serializing_if = "Option::is_none")]
+    pub stream_designator: Option<&'a str>,
+    /// Name of the base the platform operates from.
+    #[serde(rename = "107", skip_serializing_if = "Option::is_none")]
+    pub operational_base: Option<&'a str>,
+    /// Name of the source where the stream is broadcast.
+    #[serde(rename = "108", skip_serializing_if = "Option::is_none")]
+    pub broadcast_source: Option<&'a str>,
+    /// IMAPB(0, 21000, length) kilometers.
+    #[serde(rename = "109", skip_serializing_if = "Option::is_none", borrow)]
+    pub range_to_recovery_location: Option<RawKLV<'a>>,
+    /// Seconds since take-off, variable length.
+    #[serde(
+        rename = "110",
+        skip_serializing_if = "Option::is_none",
+        with = "crate::varlen::option",
+        default
+    )]
+    pub time_airborne: Option<u32>,
+    /// Revolutions per minute, variable length.
+    #[serde(
+        rename = "111",
+        skip_serializing_if = "Option::is_none",
+        with = "crate::varlen::option",
+        default
+    )]
+    pub propulsion_unit_speed: Option<u32>,
+    /// IMAPB(0, 360, length) degrees.
+    #[serde(rename = "112", skip_serializing_if = "Option::is_none", borrow)]
+    pub platform_course_angle: Option<RawKLV<'a>>,
+    /// IMAPB(-900, 40000, length) meters.
+    #[serde(rename = "113", skip_serializing_if = "Option::is_none", borrow)]
+    pub altitude_agl: Option<RawKLV<'a>>,
+    /// IMAPB(-900, 40000, length) meters.
+    #[serde(rename = "114", skip_serializing_if = "Option::is_none", borrow)]
+    pub radar_altimeter: Option<RawKLV<'a>>,
+    /// Defined-length pack of command id, string and timestamp.
+    #[serde(rename = "115", skip_serializing_if = "Option::is_none", borrow)]
+    pub control_command: Option<RawKLV<'a>>,
+    /// Variable-length list of command ids.
+    #[serde(rename = "116", skip_serializing_if = "Option::is_none", borrow)]
+    pub control_command_verification_list: Option<RawKLV<'a>>,
+    /// IMAPB(-1000, 1000, length) degrees/second.
+    #[serde(rename = "117", skip_serializing_if = "Option::is_none", borrow)]
+    pub sensor_azimuth_rate: Option<RawKLV<'a>>,
+    /// IMAPB(-1000, 1000, length) degrees/second.
+    #[serde(rename = "118", skip_serializing_if = "Option::is_none", borrow)]
+    pub sensor_elevation_rate: Option<RawKLV<'a>>,
+    /// IMAPB(-1000, 1000, length) degrees/second.
+    #[serde(rename = "119", skip_serializing_if = "Option::is_none", borrow)]
+    pub sensor_roll_rate: Option<RawKLV<'a>>,
+    /// IMAPB(0, 100, length) percent.
+    #[serde(rename = "120", skip_serializing_if = "Option::is_none", borrow)]
+    pub on_board_mi_storage_percent_full: Option<RawKLV<'a>>,
+    /// Variable-length list of wavelength ids.
+    #[serde(rename = "121", skip_serializing_if = "Option::is_none", borrow)]
+    pub active_wavelength_list: Option<RawKLV<'a>>,
+    /// Variable-length pack of coding method and country codes.
+    #[serde(rename = "122", skip_serializing_if = "Option::is_none", borrow)]
+    pub country_codes: Option<RawKLV<'a>>,
+    /// 0..255 satellites.
+    #[serde(rename = "123", skip_serializing_if = "Option::is_none")]
+    pub number_of_navsats_in_view: Option<u8>,
+    /// Bit flags of INS, GPS, Galileo, QZSS, NAVIC, GLONASS, BeiDou-1 and BeiDou-2.
+    #[serde(rename = "124", skip_serializing_if = "Option::is_none")]
+    pub positioning_method_source: Option<u8>,
+    /// 0: Active, 1: Pre-flight .. 14: Post-flight.
+    #[serde(rename = "125", skip_serializing_if = "Option::is_none")]
+    pub platform_status: Option<u8>,
+    /// 0: Off, 1: Home Position .. 7: Stowed.
+    #[serde(rename = "126", skip_serializing_if = "Option::is_none")]
+    pub sensor_control_mode: Option<u8>,
+    /// Defined-length pack of numerator and denominator.
+    #[serde(rename = "127", skip_serializing_if = "Option::is_none", borrow)]
+    pub sensor_frame_rate_pack: Option<RawKLV<'a>>,
+    /// Variable-length list of wavelength records.
+    #[serde(rename = "128", skip_serializing_if = "Option::is_none", borrow)]
+    pub wavelengths_list: Option<RawKLV<'a>>,
+    /// Alphanumeric identifier of the target.
+    #[serde(rename = "129", skip_serializing_if = "Option::is_none")]
+    pub target_id: Option<&'a str>,
+    /// Variable-length pack of take-off and recovery locations.
+    #[serde(rename = "130", skip_serializing_if = "Option::is_none", borrow)]
+    pub airbase_locations: Option<RawKLV<'a>>,
+    /// Microseconds from UNIX epoch.
+    #[serde(rename = "131", skip_serializing_if = "Option::is_none")]
+    pub take_off_time: Option<u64>,
+    /// IMAPB(1, 99999, length) megahertz.
+    #[serde(rename = "132", skip_serializing_if = "Option::is_none", borrow)]
+    pub transmission_frequency: Option<RawKLV<'a>>,
+    /// Gigabytes, variable length.
+    #[serde(
+        rename = "133",
+        skip_serializing_if = "Option::is_none",
+        with = "crate::varlen::option",
+        default
+    )]
+    pub on_board_mi_storage_capacity: Option<u32>,
+    /// IMAPB(0, 100, length) percent.
+    #[serde(rename = "134", skip_serializing_if = "Option::is_none", borrow)]
+    pub zoom_percentage: Option<RawKLV<'a>>,
+    /// Type of communications used with the platform.
+    #[serde(rename = "135", skip_serializing_if = "Option::is_none")]
+    pub communications_method: Option<&'a str>,
+    /// Number of leap seconds to adjust precision time stamp, variable length.
+    #[serde(
+        rename = "136",
+        skip_serializing_if = "Option::is_none",
+        with = "crate::varlen::signed::option",
+        default
+    )]
+    pub leap_seconds: Option<i32>,
+    /// Microseconds to adjust precision time stamp, variable length.
+    #[serde(
+        rename = "137",
+        skip_serializing_if = "Option::is_none",
+        with = "crate::varlen::signed::option",
+        default
+    )]
+    pub correction_offset: Option<i64>,
+    /// Variable-length list of payload records.
+    #[serde(rename = "138", skip_serializing_if = "Option::is_none", borrow)]
+    pub payload_list: Option<RawKLV<'a>>,
+    /// Bit field of active payload ids.
+    #[serde(rename = "139", skip_serializing_if = "Option::is_none", borrow)]
+    pub active_payloads: Option<RawKLV<'a>>,
+    /// Variable-length list of weapon store records.
+    #[serde(rename = "140", skip_serializing_if = "Option::is_none", borrow)]
+    pub weapons_stores: Option<RawKLV<'a>>,
+    /// Variable-length list of waypoint records.
+    #[serde(rename = "141", skip_serializing_if = "Option::is_none", borrow)]
+    pub waypoint_list: Option<RawKLV<'a>>,
+    /// Variable-length pack of azimuth, elevation and roll ranges.
+    #[serde(rename = "142", skip_serializing_if = "Option::is_none", borrow)]
+    pub view_domain: Option<RawKLV<'a>>,
+    /// Defined-length pack of substream id and sensor id.
+    #[serde(rename = "143", skip_serializing_if = "Option::is_none", borrow)]
+    pub metadata_substream_id_pack: Option<RawKLV<'a>>,
+}
+
+crate::bit_fields! {
+    /// ST 0601 tag 47 Generic Flag Data
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+    pub struct GenericFlagData: 1 {
+        /// laser range finder is on
+        pub laser_range = 0,
+        pub auto_track = 1,
+        /// black hot when set, white hot otherwise
+        pub ir_polarity = 2,
+        pub icing_detected = 3,
+        /// slant range is measured, calculated otherwise
+        pub slant_range_measured = 4,
+        pub image_invalid = 5,
+    }
+}
+
+impl<'a> Default for UASDatalinkLS17<'a> {
+    fn default() -> Self {
+        Self {
+            timestamp: PrecisionTimestamp::UNIX_EPOCH,
+            mission_id: None,
+            platform_tail_number: None,
+            platform_heading_angle: None,
+            platform_pitch_angle: None,
+            platform_roll_angle: None,
+            platform_true_airspeed: None,
+            platform_indicated_airspeed: None,
+            platform_designation: None,
+            image_source_sensor: None,
+            image_coordinate_system: None,
+            sensor_latitude: None,
+            sensor_longitude: None,
+            sensor_true_altitude: None,
+            sensor_horizontal_fov: None,
+            sensor_vertical_fov: None,
+            sensor_relative_azimuth_angle: None,
+            sensor_relative_elevation_angle: None,
+            sensor_relative_roll_angle: None,
+            slant_range: None,
+            target_width: None,
+            frame_center_latitude: None,
+            frame_center_longitude: None,
+            frame_center_elevation: None,
+            offset_corner_latitude_point_1: None,
+            offset_corner_longitude_point_1: None,
+            offset_corner_latitude_point_2: None,
+            offset_corner_longitude_point_2: None,
+            offset_corner_latitude_point_3: None,
+            offset_corner_longitude_point_3: None,
+            offset_corner_latitude_point_4: None,
+            offset_corner_longitude_point_4: None,
+            icing_detected: None,
+            wind_direction: None,
+            wind_speed: None,
+            static_pressure: None,
+            density_altitude: None,
+            outside_air_temperature: None,
+            target_location_latitude: None,
+            target_location_longitude: None,
+            target_location_elevation: None,
+            target_track_gate_width: None,
+            target_track_gate_height: None,
+            target_error_estimate_ce90: None,
+            target_error_estimate_le90: None,
+            generic_flag_data: None,
+            security_local_set: None,
+            differential_pressure: None,
+            platform_angle_of_attack: None,
+            platform_vertical_speed: None,
+            platform_sideslip_angle: None,
+            airfield_barometric_pressure: None,
+            airfield_elevation: None,
+            relative_humidity: None,
+            platform_ground_speed: None,
+            ground_range: None,
+            platform_fuel_remaining: None,
+            platform_call_sign: None,
+            weapon_load: None,
+            weapon_fired: None,
+            laser_prf_code: None,
+            sensor_fov_name: None,
+            platform_magnetic_heading: None,
+            ls_version_number: 17,
+            alternate_platform_latitude: None,
+            alternate_platform_longitude: None,
+            alternate_platform_altitude: None,
+            alternate_platform_name: None,
+            alternate_platform_heading: None,
+            event_start_time: None,
+            rvt_local_set: None,
+            vmti_local_set: None,
+            sensor_ellipsoid_height: None,
+            alternate_platform_ellipsoid_height: None,
+            operational_mode: None,
+            frame_center_height_above_ellipsoid: None,
+            sensor_north_velocity: None,
+            sensor_east_velocity: None,
+            image_horizon_pixel_pack: None,
+            corner_latitude_point_1_full: None,
+            corner_longitude_point_1_full: None,
+            corner_latitude_point_2_full: None,
+            corner_longitude_point_2_full: None,
+            corner_latitude_point_3_full: None,
+            corner_longitude_point_3_full: None,
+            corner_latitude_point_4_full: None,
+            corner_longitude_point_4_full: None,
+            platform_pitch_angle_full: None,
+            platform_roll_angle_full: None,
+            platform_angle_of_attack_full: None,
+            platform_sideslip_angle_full: None,
+            miis_core_identifier: None,
+            sar_motion_imagery_local_set: None,
+            target_width_extended: None,
+            range_image_local_set: None,
+            geo_registration_local_set: None,
+            composite_imaging_local_set: None,
+            segment_local_set: None,
+            amend_local_set: None,
+            sdcc_flp: None,
+            density_altitude_extended: None,
+            sensor_ellipsoid_height_extended: None,
+            alternate_platform_ellipsoid_height_extended: None,
+            stream_designator: None,
+            operational_base: None,
+            broadcast_source: None,
+            range_to_recovery_location: None,
+            time_airborne: None,
+            propulsion_unit_speed: None,
+            platform_course_angle: None,
+            altitude_agl: None,
+            radar_altimeter: None,
+            control_command: None,
+            control_command_verification_list: None,
+            sensor_azimuth_rate: None,
+            sensor_elevation_rate: None,
+            sensor_roll_rate: None,
+            on_board_mi_storage_percent_full: None,
+            active_wavelength_list: None,
+            country_codes: None,
+            number_of_navsats_in_view: None,
+            positioning_method_source: None,
+            platform_status: None,
+            sensor_control_mode: None,
+            sensor_frame_rate_pack: None,
+            wavelengths_list: None,
+            target_id: None,
+            airbase_locations: None,
+            take_off_time: None,
+            transmission_frequency: None,
+            on_board_mi_storage_capacity: None,
+            zoom_percentage: None,
+            communications_method: None,
+            leap_seconds: None,
+            correction_offset: None,
+            payload_list: None,
+            active_payloads: None,
+            weapons_stores: None,
+            waypoint_list: None,
+            view_domain: None,
+            metadata_substream_id_pack: None,
+        }
+    }
+}
+
+/// [`UASDatalinkLS`] in engineering units
+///
+/// Angles are in degrees and distances in meters.
+/// The out-of-range indicator of the signed fields is NaN.
+/// [`From`] the local set and [`TryFrom`] to the local set convert the values,
+/// and the round trip from the local set reproduces the same integers.
+#[derive(Debug, PartialEq, Default)]
+pub struct UASDatalinkEngineering<'a> {
+    pub timestamp: PrecisionTimestamp,
+    /// 0..360.
+    pub platform_heading_angle: f64,
+    /// +/-20.
+    pub platform_pitch_angle: f64,
+    /// +/-50.
+    pub platform_roll_angle: f64,
+    pub image_source_sensor: Option<&'a str>,
+    pub image_coordinate_sensor: Option<&'a str>,
+    /// +/-90.
+    pub sensor_latitude: Option<f64>,
+    /// +/-180.
+    pub sensor_longitude: Option<f64>,
+    /// -900..19000 meters.
+    pub sensor_true_altitude: Option<f64>,
+    /// 0..180.
+    pub sensor_horizontal_fov: Option<f64>,
+    /// 0..180.
+    pub sensor_vertical_fov: Option<f64>,
+    /// 0..360.
+    pub sensor_relative_azimuth_angle: Option<f64>,
+    /// +/-180.
+    pub sensor_relative_elevation_angle: Option<f64>,
+    /// 0..360.
+    pub sensor_relative_roll_angle: Option<f64>,
+    /// 0..5000000 meters.
+    pub slant_range: Option<f64>,
+    /// 0..10000 meters.
+    ///
+    /// The local set keeps `u32`, raw values above `u16::MAX` exceed 10000 meters.
+    pub target_width: Option<f64>,
+    /// +/-90.
+    pub frame_center_latitude: Option<f64>,
+    /// +/-180.
+    pub frame_center_longitude: Option<f64>,
+    /// -900..19000 meters.
+    pub frame_center_elevation: Option<f64>,
+    /// +/-90.
+    pub target_location_latitude: Option<f64>,
+    /// +/-180.
+    pub target_location_longitude: Option<f64>,
+    /// -900..19000 meters.
+    pub target_location_elevation: Option<f64>,
+    pub security_local_set: Option<SecurityLocalSet<'a>>,
+    /// 0..255 meters/second.
+    pub platform_ground_speed: Option<f64>,
+    /// 0..5000000 meters.
+    pub ground_range: Option<f64>,
+    pub ls_version_number: u8,
+}
+
+// 整数の全範囲と工学単位の範囲の線形写像
+// 符号付きは±(2^(bits-1)-1)を使い、-(2^(bits-1))を範囲外の番兵とする
+struct LinearMap {
+    bits: u32,
+    signed: bool,
+    min: f64,
+    max: f64,
+}
+
+impl LinearMap {
+    const HEADING: Self = Self::unsigned(16, 0.0, 360.0);
+    const PITCH: Self = Self::signed(16, -20.0, 20.0);
+    const ROLL: Self = Self::signed(16, -50.0, 50.0);
+    const LATITUDE: Self = Self::signed(32, -90.0, 90.0);
+    const LONGITUDE: Self = Self::signed(32, -180.0, 180.0);
+    const ALTITUDE: Self = Self::unsigned(16, -900.0, 19000.0);
+    const FOV: Self = Self::unsigned(16, 0.0, 180.0);
+    const AZIMUTH: Self = Self::unsigned(32, 0.0, 360.0);
+    const RELATIVE_ELEVATION: Self = Self::signed(32, -180.0, 180.0);
+    const RANGE: Self = Self::unsigned(32, 0.0, 5_000_000.0);
+    const GROUND_SPEED: Self = Self::unsigned(8, 0.0, 255.0);
+    const TARGET_WIDTH: Self = Self::unsigned(16, 0.0, 10000.0);
+
+    const fn unsigned(bits: u32, min: f64, max: f64) -> Self {
+        Self {
+            bits,
+            signed: false,
+            min,
+            max,
+        }
+    }
+
+    const fn signed(bits: u32, min: f64, max: f64) -> Self {
+        Self {
+            bits,
+            signed: true,
+            min,
+            max,
+        }
+    }
+
+    fn raw_range(&self) -> (i64, i64) {
+        if self.signed {
+            let max = (1_i64 << (self.bits - 1)) - 1;
+            (-max, max)
+        } else {
+            (0, (1_i64 << self.bits) - 1)
+        }
+    }
+
+    fn to_unit<R: Into<i64>>(&self, raw: R) -> f64 {
+        let (raw_min, raw_max) = self.raw_range();
+        let raw = raw.into();
+        if self.signed && raw < raw_min {
+            return f64::NAN;
+        }
+        self.min + (raw - raw_min) as f64 * (self.max - self.min) / (raw_max - raw_min) as f64
+    }
+
+    // 範囲外の値はNone
+    fn to_raw<R: TryFrom<i64>>(&self, value: f64) -> Option<R> {
+        let (raw_min, raw_max) = self.raw_range();
+        let raw = if self.signed && value.is_nan() {
+            raw_min - 1
+        } else if (self.min..=self.max).contains(&value) {
+            let x = (value - self.min) * (raw_max - raw_min) as f64 / (self.max - self.min);
+            (x.round() as i64 + raw_min).clamp(raw_min, raw_max)
+        } else {
+            return None;
+        };
+        R::try_from(raw).ok()
+    }
+
+    fn encode<R: TryFrom<i64>>(&self, value: f64, field: &str) -> Result<R, Error> {
+        self.to_raw(value).ok_or_else(|| {
+            Error::Encode(format!(
+                "{} {} is out of range {}..={}",
+                field, value, self.min, self.max
+            ))
+        })
+    }
+
+    fn to_raw_option<R: TryFrom<i64>>(
+        &self,
+        value: Option<f64>,
+        field: &str,
+    ) -> Result<Option<R>, Error> {
+        value.map(|x| self.encode(x, field)).transpose()
+    }
+}
+
+impl<'a> From<UASDatalinkLS<'a>> for UASDatalinkEngineering<'a> {
+    fn from(x: UASDatalinkLS<'a>) -> Self {
+        let latitude = |v: Option<i32>| v.map(|v| LinearMap::LATITUDE.to_unit(v));
+        let longitude = |v: Option<i32>| v.map(|v| LinearMap::LONGITUDE.to_unit(v));
+        let altitude = |v: Option<u16>| v.map(|v| LinearMap::ALTITUDE.to_unit(v));
+        Self {
+            timestamp: x.timestamp,
+            platform_heading_angle: LinearMap::HEADING.to_unit(x.platform_heading_angle),
+            platform_pitch_angle: LinearMap::PITCH.to_unit(x.platform_pitch_angle),
+            platform_roll_angle: LinearMap::ROLL.to_unit(x.platform_roll_angle),
+            image_source_sensor: x.image_source_sensor,
+            image_coordinate_sensor: x.image_coordinate_sensor,
+            sensor_latitude: latitude(x.sensor_latitude),
+            sensor_longitude: longitude(x.sensor_longtude),
+            sensor_true_altitude: altitude(x.sensor_true_altitude),
+            sensor_horizontal_fov: x.sensor_horizontal_fov.map(|v| LinearMap::FOV.to_unit(v)),
+            sensor_vertical_fov: x.sensor_vertical_fov.map(|v| LinearMap::FOV.to_unit(v)),
+            sensor_relative_azimuth_angle: x
+                .sensor_relative_azimuth_angle
+                .map(|v| LinearMap::AZIMUTH.to_unit(v)),
+            sensor_relative_elevation_angle: x
+                .sensor_relative_elevation_angle
+                .map(|v| LinearMap::RELATIVE_ELEVATION.to_unit(v)),
+            // 仕様ではuint32のためビット列をそのまま符号なしとして読む
+            sensor_relative_roll_angle: x
+                .sensor_relative_roll_angle
+                .map(|v| LinearMap::AZIMUTH.to_unit(v as u32)),
+            slant_range: x.slant_range.map(|v| LinearMap::RANGE.to_unit(v)),
+            target_width: x.target_width.map(|v| LinearMap::TARGET_WIDTH.to_unit(v)),
+            frame_center_latitude: latitude(x.frame_center_latitude),
+            frame_center_longitude: longitude(x.frame_center_longitude),
+            frame_center_elevation: altitude(x.frame_center_elevation),
+            target_location_latitude: latitude(x.target_location_latitude),
+            target_location_longitude: longitude(x.target_location_longitude),
+            target_location_elevation: altitude(x.target_location_elecation),
+            security_local_set: x.security_local_set,
+            platform_ground_speed: x
+                .plafform_ground_speed
+                .map(|v| LinearMap::GROUND_SPEED.to_unit(v)),
+            ground_range: x.ground_range.map(|v| LinearMap::RANGE.to_unit(v)),
+            ls_version_number: x.ls_version_number,
+        }
+    }
+}
+
+impl<'a> TryFrom<UASDatalinkEngineering<'a>> for UASDatalinkLS<'a> {
+    type Error = Error;
+
+    /// return [`Error::Encode`] if a value is out of the range of the field
+    fn try_from(x: UASDatalinkEngineering<'a>) -> Result<Self, Self::Error> {
+        let latitude = LinearMap::LATITUDE;
+        let longitude = LinearMap::LONGITUDE;
+        let altitude = LinearMap::ALTITUDE;
+        Ok(Self {
+            timestamp: x.timestamp,
+            platform_heading_angle: LinearMap::HEADING
+                .encode(x.platform_heading_angle, "platform_heading_angle")?,
+            platform_pitch_angle: LinearMap::PITCH
+                .encode(x.platform_pitch_angle, "platform_pitch_angle")?,
+            platform_roll_angle: LinearMap::ROLL
+                .encode(x.platform_roll_angle, "platform_roll_angle")?,
+            image_source_sensor: x.image_source_sensor,
+            image_coordinate_sensor: x.image_coordinate_sensor,
+            sensor_latitude: latitude.to_raw_option(x.sensor_latitude, "sensor_latitude")?,
+            sensor_longtude: longitude.to_raw_option(x.sensor_longitude, "sensor_longitude")?,
+            sensor_true_altitude: altitude
+                .to_raw_option(x.sensor_true_altitude, "sensor_true_altitude")?,
+            sensor_horizontal_fov: LinearMap::FOV
+                .to_raw_option(x.sensor_horizontal_fov, "sensor_horizontal_fov")?,
+            sensor_vertical_fov: LinearMap::FOV
+                .to_raw_option(x.sensor_vertical_fov, "sensor_vertical_fov")?,
+            sensor_relative_azimuth_angle: LinearMap::AZIMUTH.to_raw_option(
+                x.sensor_relative_azimuth_angle,
+                "sensor_relative_azimuth_angle",
+            )?,
+            sensor_relative_elevation_angle: LinearMap::RELATIVE_ELEVATION.to_raw_option(
+                x.sensor_relative_elevation_angle,
+                "sensor_relative_elevation_angle",
+            )?,
+            sensor_relative_roll_angle: LinearMap::AZIMUTH
+                .to_raw_option::<u32>(x.sensor_relative_roll_angle, "sensor_relative_roll_angle")?
+                .map(|v| v as i32),
+            slant_range: LinearMap::RANGE.to_raw_option(x.slant_range, "slant_range")?,
+            // 仕様のuint16を超える値は範囲外とする
+            target_width: LinearMap::TARGET_WIDTH
+                .to_raw_option::<u16>(x.target_width, "target_width")?
+                .map(u32::from),
+            frame_center_latitude: latitude
+                .to_raw_option(x.frame_center_latitude, "frame_center_latitude")?,
+            frame_center_longitude: longitude
+                .to_raw_option(x.frame_center_longitude, "frame_center_longitude")?,
+            frame_center_elevation: altitude
+                .to_raw_option(x.frame_center_elevation, "frame_center_elevation")?,
+            target_location_latitude: latitude
+                .to_raw_option(x.target_location_latitude, "target_location_latitude")?,
+            target_location_longitude: longitude
+                .to_raw_option(x.target_location_longitude, "target_location_longitude")?,
+            target_location_elecation: altitude
+                .to_raw_option(x.target_location_elevation, "target_location_elevation")?,
+            security_local_set: x.security_local_set,
+            plafform_ground_speed: LinearMap::GROUND_SPEED
+                .to_raw_option(x.platform_ground_speed, "platform_ground_speed")?,
+            ground_range: LinearMap::RANGE.to_raw_option(x.ground_range, "ground_range")?,
+            ls_version_number: x.ls_version_number,
+        })
+    }
+}
+
+/// Violation of MISB ST 0601 found by [`UASDatalinkLSBuilder::build`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    /// mandatory tag is not set
+    MissingTag(u8),
+    /// value in the unit is out of the range of the tag
+    OutOfRange {
+        tag: u8,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+    /// string exceeds the maximum length of the tag
+    TooLong { tag: u8, len: usize, limit: usize },
+}
+
+impl ValidationError {
+    /// tag of the violation
+    pub fn tag(&self) -> u8 {
+        match self {
+            ValidationError::MissingTag(tag)
+            | ValidationError::OutOfRange { tag, .. }
+            | ValidationError::TooLong { tag, .. } => *tag,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::MissingTag(tag) => {
+                write!(formatter, "mandatory tag {} is not set", tag)
+            }
+            ValidationError::OutOfRange {
+                tag,
+                value,
+                min,
+                max,
+            } => write!(
+                formatter,
+                "value {} of tag {} is out of range {}..={}",
+                value, tag, min, max
+            ),
+            ValidationError::TooLong { tag, len, limit } => write!(
+                formatter,
+                "string of tag {} is {} bytes, exceeds {}",
+                tag, len, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl<'a> UASDatalinkLS<'a> {
+    /// builder checking the constraints of MISB ST 0601
+    ///
+    /// Values are given in the unit of [`UASDatalinkEngineering`] and encoded on set.
+    /// Checksum is not a field, write it by [`crate::to_bytes_with_checksum`] with [`CRC`].
+    ///
+    /// Example
+    ///
+    /// ```rust
+    /// use serde_klv::st0603::PrecisionTimestamp;
+    /// use serde_klv::to_bytes_with_checksum;
+    /// use serde_klv::uasdls::{UASDatalinkLS, ValidationError, CRC};
+    ///
+    /// let x = UASDatalinkLS::builder()
+    ///     .timestamp(PrecisionTimestamp::from_micros(1_000_233_000))
+    ///     .ls_version_number(8)
+    ///     .platform_heading_angle(90.0)
+    ///     .sensor_latitude(35.5)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(x.platform_heading_angle, 0x4000);
+    /// assert!(to_bytes_with_checksum(&x, CRC).is_ok());
+    ///
+    /// let errors = UASDatalinkLS::builder()
+    ///     .platform_pitch_angle(30.0)
+    ///     .build()
+    ///     .unwrap_err();
+    /// assert_eq!(errors.len(), 3);
+    /// assert_eq!(errors[0], ValidationError::MissingTag(2));
+    /// assert_eq!(errors[1].tag(), 6);
+    /// ```
+    pub fn builder() -> UASDatalinkLSBuilder<'a> {
+        UASDatalinkLSBuilder::default()
+    }
+}
+
+// 工学単位の値を受け取るsetter
+macro_rules! unit_setters {
+    ($($name:ident = $map:ident, $tag:literal;)*) => {$(
+        pub fn $name(mut self, value: f64) -> Self {
+            self.ls.$name = self.encode(&LinearMap::$map, $tag, value);
+            self
+        }
+    )*};
+}
+
+/// Builder of [`UASDatalinkLS`] created by [`UASDatalinkLS::builder`]
+///
+/// Violations are collected on set and returned together by [`Self::build`].
+/// Setting the tag again replaces its violation.
+#[derive(Debug, Default)]
+pub struct UASDatalinkLSBuilder<'a> {
+    ls: UASDatalinkLS<'a>,
+    timestamp: Option<PrecisionTimestamp>,
+    ls_version_number: Option<u8>,
+    errors: Vec<ValidationError>,
+}
+
+impl<'a> UASDatalinkLSBuilder<'a> {
+    /// maximum length of the strings of tag 11 and 12
+    pub const MAX_STRING_LEN: usize = 127;
+
+    // 同じタグの以前の違反は置き換える
+    fn encode<R: TryFrom<i64>>(&mut self, map: &LinearMap, tag: u8, value: f64) -> Option<R> {
+        self.errors.retain(|e| e.tag() != tag);
+        let raw = map.to_raw(value);
+        if raw.is_none() {
+            self.errors.push(ValidationError::OutOfRange {
+                tag,
+                value,
+                min: map.min,
+                max: map.max,
+            });
+        }
+        raw
+    }
+
+    fn check_len(&mut self, tag: u8, value: &str) {
+        self.errors.retain(|e| e.tag() != tag);
+        if value.len() > Self::MAX_STRING_LEN {
+            self.errors.push(ValidationError::TooLong {
+                tag,
+                len: value.len(),
+                limit: Self::MAX_STRING_LEN,
+            });
+        }
+    }
+
+    /// mandatory
+    pub fn timestamp(mut self, value: PrecisionTimestamp) -> Self {
+        self.timestamp = Some(value);
+        self
+    }
+
+    /// mandatory
+    pub fn ls_version_number(mut self, value: u8) -> Self {
+        self.ls_version_number = Some(value);
+        self
+    }
+
+    pub fn platform_heading_angle(mut self, value: f64) -> Self {
+        self.ls.platform_heading_angle = self
+            .encode(&LinearMap::HEADING, 5, value)
+            .unwrap_or_default();
+        self
+    }
+
+    /// NaN is the out-of-range indicator
+    pub fn platform_pitch_angle(mut self, value: f64) -> Self {
+        self.ls.platform_pitch_angle = self.encode(&LinearMap::PITCH, 6, value).unwrap_or_default();
+        self
+    }
+
+    /// NaN is the out-of-range indicator
+    pub fn platform_roll_angle(mut self, value: f64) -> Self {
+        self.ls.platform_roll_angle = self.encode(&LinearMap::ROLL, 7, value).unwrap_or_default();
+        self
+    }
+
+    pub fn image_source_sensor(mut self, value: &'a str) -> Self {
+        self.check_len(11, value);
+        self.ls.image_source_sensor = Some(value);
+        self
+    }
+
+    pub fn image_coordinate_sensor(mut self, value: &'a str) -> Self {
+        self.check_len(12, value);
+        self.ls.image_coordinate_sensor = Some(value);
+        self
+    }
+
+    unit_setters! {
+        sensor_latitude = LATITUDE, 13;
+        sensor_true_altitude = ALTITUDE, 15;
+        sensor_horizontal_fov = FOV, 16;
+        sensor_vertical_fov = FOV, 17;
+        sensor_relative_azimuth_angle = AZIMUTH, 18;
+        sensor_relative_elevation_angle = RELATIVE_ELEVATION, 19;
+        slant_range = RANGE, 21;
+        frame_center_latitude = LATITUDE, 23;
+        frame_center_longitude = LONGITUDE, 24;
+        frame_center_elevation = ALTITUDE, 25;
+        target_location_latitude = LATITUDE, 40;
+        target_location_longitude = LONGITUDE, 41;
+        ground_range = RANGE, 57;
+    }
+
+    pub fn sensor_longitude(mut self, value: f64) -> Self {
+        self.ls.sensor_longtude = self.encode(&LinearMap::LONGITUDE, 14, value);
+        self
+    }
+
+    pub fn sensor_relative_roll_angle(mut self, value: f64) -> Self {
+        self.ls.sensor_relative_roll_angle = self
+            .encode::<u32>(&LinearMap::AZIMUTH, 20, value)
+            .map(|v| v as i32);
+        self
+    }
+
+    pub fn target_width(mut self, value: f64) -> Self {
+        self.ls.target_width = self
+            .encode::<u16>(&LinearMap::TARGET_WIDTH, 22, value)
+            .map(u32::from);
+        self
+    }
+
+    pub fn target_location_elevation(mut self, value: f64) -> Self {
+        self.ls.target_location_elecation = self.encode(&LinearMap::ALTITUDE, 42, value);
+        self
+    }
+
+    pub fn security_local_set(mut self, value: SecurityLocalSet<'a>) -> Self {
+        self.ls.security_local_set = Some(value);
+        self
+    }
+
+    pub fn platform_ground_speed(mut self, value: f64) -> Self {
+        self.ls.plafform_ground_speed = self.encode(&LinearMap::GROUND_SPEED, 56, value);
+        self
+    }
+
+    /// return all violations in the order of the tag
+    pub fn build(self) -> Result<UASDatalinkLS<'a>, Vec<ValidationError>> {
+        let mut errors = self.errors;
+        if self.timestamp.is_none() {
+            errors.push(ValidationError::MissingTag(2));
+        }
+        if self.ls_version_number.is_none() {
+            errors.push(ValidationError::MissingTag(65));
+        }
+        if !errors.is_empty() {
+            errors.sort_by_key(|e| e.tag());
+            return Err(errors);
+        }
+        Ok(UASDatalinkLS {
+            timestamp: self.timestamp.unwrap_or(PrecisionTimestamp::UNIX_EPOCH),
+            ls_version_number: self.ls_version_number.unwrap_or_default(),
+            ..self.ls
+        })
     }
 }
 
@@ -161,20 +1263,28 @@ mod tests {
     use crate::{
         checksum::CheckSumCalc,
         de::from_bytes,
+        error::Error,
         from_bytes_with_checksum,
         ser::to_bytes,
-        uasdls::{UASDatalinkLS, CRC},
+        st0102::{Classification, SecurityLocalSet},
+        st0603::PrecisionTimestamp,
+        to_bytes_with_checksum,
+        uasdls::{
+            GenericFlagData, LinearMap, UASDatalinkEngineering, UASDatalinkLS, UASDatalinkLS17,
+            ValidationError, CRC,
+        },
+        RawKLV,
     };
     use byteorder::{BigEndian, ByteOrder};
     use chrono::{DateTime, Utc};
-    use std::time::{Duration, SystemTime};
+    use std::time::SystemTime;
 
     #[test]
     fn test_checksum() {
         let testdata = &[0x06_u8, 0x0e, 0x2b, 0x34, 0x02, 0x00, 0x81, 0xbb];
         let c = CRC {};
         let checksum = c.checksum(testdata);
-        let expect = BigEndian::read_u16(&[0xb4, 0xfd]);
+        let expect = BigEndian::read_u16(&[0xb4, 0xfd]) as u32;
         assert_eq!(checksum, expect);
     }
 
@@ -213,7 +1323,7 @@ mod tests {
             ];
 
         let x: UASDatalinkLS = from_bytes_with_checksum(&buf, CRC {}).unwrap();
-        let datetime: DateTime<Utc> = x.timestamp.into();
+        let datetime: DateTime<Utc> = SystemTime::from(x.timestamp).into();
         assert_eq!(
             DateTime::parse_from_rfc3339("2009-06-17T16:53:05.099653+00:00").unwrap(),
             datetime
@@ -227,9 +1337,7 @@ mod tests {
 
     #[test]
     fn test_serialize() {
-        let ts = SystemTime::UNIX_EPOCH
-            .checked_add(Duration::from_micros(1_000_233_000))
-            .unwrap();
+        let ts = PrecisionTimestamp::from_micros(1_000_233_000);
         let t = UASDatalinkLS {
             timestamp: ts,
             platform_heading_angle: 123,
@@ -242,6 +1350,85 @@ mod tests {
         let x = from_bytes::<UASDatalinkLS>(&s).unwrap();
         assert_eq!(t, x);
     }
+    #[test]
+    fn test_serialize_security_local_set() {
+        let t = UASDatalinkLS {
+            security_local_set: Some(SecurityLocalSet {
+                security_classification: Classification::Restricted,
+                classifying_country: "//JP",
+                version: 12,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let s = to_bytes(&t).unwrap();
+        // 入れ子のLocal SetはUniversal Keyを持たない
+        #[rustfmt::skip]
+        let expect = [
+            48, 16,
+            1, 1, 0x02,
+            2, 1, 0x01,
+            3, 4, b'/', b'/', b'J', b'P',
+            22, 2, 0x00, 0x0c,
+        ];
+        assert!(s.windows(expect.len()).any(|x| x == expect));
+        let x = from_bytes::<UASDatalinkLS>(&s).unwrap();
+        assert_eq!(t, x);
+    }
+
+    #[test]
+    fn test_uas_datalink_ls17() {
+        // 旧バージョンのデータも読める
+        #[rustfmt::skip]
+        let buf = vec![
+            0x06, 0x0e, 0x2b, 0x34, 0x02, 0x0b, 0x01, 0x01, 0x0e, 0x01, 0x03, 0x01, 0x01, 0x00, 0x00, 0x00,
+            26,
+            2, 8, 0, 0x4, 0x6c, 0x8e, 0x20, 0x03, 0x83, 0x85,
+            65, 1, 1,
+            22, 4, 0x00, 0x00, 0x01, 0xc9,
+            56, 1, 0x2e,
+            1, 2, 0x00, 0x00,
+        ];
+        let x = from_bytes::<UASDatalinkLS17>(&buf).unwrap();
+        assert_eq!(x.ls_version_number, 1);
+        assert_eq!(x.target_width, Some(0x01c9));
+        assert_eq!(x.platform_ground_speed, Some(0x2e));
+        assert_eq!(x.wind_speed, None);
+
+        let t = UASDatalinkLS17 {
+            mission_id: Some("MISSION01"),
+            wind_direction: Some(0x8000),
+            wind_speed: Some(50),
+            outside_air_temperature: Some(-20),
+            security_local_set: Some(SecurityLocalSet {
+                security_classification: Classification::Restricted,
+                classifying_country: "//JP",
+                version: 12,
+                ..Default::default()
+            }),
+            generic_flag_data: Some(GenericFlagData {
+                auto_track: true,
+                image_invalid: true,
+                ..Default::default()
+            }),
+            time_airborne: Some(3600),
+            leap_seconds: Some(-1),
+            airbase_locations: Some(RawKLV(&[0x01, 0x02, 0x03])),
+            weapons_stores: Some(RawKLV(&[0x0a, 0x0b])),
+            ..Default::default()
+        };
+        assert_eq!(t.ls_version_number, 17);
+        let buf = to_bytes_with_checksum(&t, CRC {}).unwrap();
+        let x: UASDatalinkLS17 = from_bytes_with_checksum(&buf, CRC {}).unwrap();
+        assert_eq!(t, x);
+        // 値はそのままのバイト列として書き込まれる
+        assert!(buf.windows(4).any(|x| x == [140, 2, 0x0a, 0x0b]));
+        assert!(buf.windows(3).any(|x| x == [47, 1, 0b0010_0010]));
+        // 可変長の整数は最短のバイト数で書き込まれる
+        assert!(buf.windows(4).any(|x| x == [110, 2, 0x0e, 0x10]));
+        assert!(buf.windows(3).any(|x| x == [136, 1, 0xff]));
+    }
+
     #[test]
     fn test_deserialize_error() {
         let buf = vec![
@@ -250,7 +1437,9 @@ mod tests {
         ];
         let err = from_bytes::<UASDatalinkLS>(&buf).unwrap_err();
         match err {
-            crate::error::Error::Key(_) => {}
+            crate::error::Error::UnmatchedKey { found, .. } => {
+                assert_eq!(found.as_bytes(), &buf[..16])
+            }
             _ => unreachable!(),
         }
         let buf = vec![
@@ -263,4 +1452,187 @@ mod tests {
             _ => unreachable!(),
         }
     }
+
+    #[test]
+    fn test_engineering_units() {
+        let t = UASDatalinkLS {
+            platform_heading_angle: 0x3d3b,
+            platform_pitch_angle: 0x1580,
+            platform_roll_angle: i16::MIN,
+            sensor_latitude: Some(0x4dc4dcbb),
+            sensor_true_altitude: Some(0x1f4a),
+            sensor_relative_roll_angle: Some(-1),
+            target_width: Some(0x1234),
+            ls_version_number: 1,
+            ..Default::default()
+        };
+        let buf = to_bytes(&t).unwrap();
+        let x = UASDatalinkEngineering::from(from_bytes::<UASDatalinkLS>(&buf).unwrap());
+        assert!((x.platform_heading_angle - 86.1066606).abs() < 1e-6);
+        assert!((x.platform_pitch_angle - 3.3594775).abs() < 1e-6);
+        assert!(x.platform_roll_angle.is_nan());
+        assert!((x.sensor_latitude.unwrap() - 54.6813233).abs() < 1e-6);
+        assert!((x.sensor_true_altitude.unwrap() - 1532.2728313).abs() < 1e-6);
+        assert_eq!(x.sensor_relative_roll_angle, Some(360.0));
+        assert_eq!(x.sensor_longitude, None);
+        assert!((x.target_width.unwrap() - 711.0704204).abs() < 1e-6);
+
+        // 工学単位から戻すと同じ整数になる
+        let y = UASDatalinkLS::try_from(x).unwrap();
+        assert_eq!(to_bytes(&y).unwrap(), buf);
+
+        let x = UASDatalinkEngineering {
+            platform_heading_angle: 360.0,
+            platform_pitch_angle: -20.0,
+            sensor_latitude: Some(-90.0),
+            sensor_true_altitude: Some(-900.0),
+            ..Default::default()
+        };
+        let y = UASDatalinkLS::try_from(x).unwrap();
+        assert_eq!(y.platform_heading_angle, u16::MAX);
+        assert_eq!(y.platform_pitch_angle, -i16::MAX);
+        assert_eq!(y.sensor_latitude, Some(-i32::MAX));
+        assert_eq!(y.sensor_true_altitude, Some(0));
+
+        // 範囲外の値は変換できない
+        let x = UASDatalinkEngineering {
+            platform_pitch_angle: 20.1,
+            ..Default::default()
+        };
+        match UASDatalinkLS::try_from(x) {
+            Err(Error::Encode(msg)) => {
+                assert_eq!(msg, "platform_pitch_angle 20.1 is out of range -20..=20")
+            }
+            x => unreachable!("{:?}", x),
+        }
+        let x = UASDatalinkEngineering {
+            platform_heading_angle: f64::NAN,
+            ..Default::default()
+        };
+        assert!(UASDatalinkLS::try_from(x).is_err());
+
+        // uint16を超えるtarget_widthは戻せない
+        let x = UASDatalinkEngineering::from(UASDatalinkLS {
+            target_width: Some(0x10000),
+            ..Default::default()
+        });
+        assert!(x.target_width.unwrap() > 10000.0);
+        assert!(UASDatalinkLS::try_from(x).is_err());
+    }
+
+    #[test]
+    fn test_linear_map_round_trip() {
+        fn check<R>(map: &LinearMap, raw: &[R])
+        where
+            R: Copy + Into<i64> + TryFrom<i64> + PartialEq + std::fmt::Debug,
+        {
+            for v in raw {
+                let x = map.to_unit(*v);
+                assert_eq!(map.to_raw::<R>(x), Some(*v), "{}", x);
+            }
+        }
+        let u16s = (0..=u16::MAX)
+            .step_by(7)
+            .chain([u16::MAX])
+            .collect::<Vec<_>>();
+        let i16s = (i16::MIN..=i16::MAX)
+            .step_by(7)
+            .chain([i16::MAX])
+            .collect::<Vec<_>>();
+        let u32s = (0..=u32::MAX)
+            .step_by(65_521)
+            .chain([1, u32::MAX - 1, u32::MAX])
+            .collect::<Vec<_>>();
+        let i32s = (i32::MIN..=i32::MAX)
+            .step_by(65_521)
+            .chain([-i32::MAX, -1, 0, 1, i32::MAX])
+            .collect::<Vec<_>>();
+        check(&LinearMap::HEADING, &u16s);
+        check(&LinearMap::ALTITUDE, &u16s);
+        check(&LinearMap::FOV, &u16s);
+        check(&LinearMap::TARGET_WIDTH, &u16s);
+        check(&LinearMap::PITCH, &i16s);
+        check(&LinearMap::ROLL, &i16s);
+        check(&LinearMap::AZIMUTH, &u32s);
+        check(&LinearMap::RANGE, &u32s);
+        check(&LinearMap::LATITUDE, &i32s);
+        check(&LinearMap::LONGITUDE, &i32s);
+        check(&LinearMap::RELATIVE_ELEVATION, &i32s);
+        check(&LinearMap::GROUND_SPEED, &(0..=u8::MAX).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_builder() {
+        let ts = PrecisionTimestamp::from_micros(1_000_233_000);
+        let x = UASDatalinkLS::builder()
+            .timestamp(ts)
+            .ls_version_number(8)
+            .platform_pitch_angle(f64::NAN)
+            .image_source_sensor("EON")
+            .sensor_latitude(-90.0)
+            .sensor_longitude(180.0)
+            .sensor_relative_roll_angle(360.0)
+            .platform_ground_speed(10.0)
+            .build()
+            .unwrap();
+        assert_eq!(x.timestamp, ts);
+        assert_eq!(x.ls_version_number, 8);
+        assert_eq!(x.platform_pitch_angle, i16::MIN);
+        assert_eq!(x.image_source_sensor, Some("EON"));
+        assert_eq!(x.sensor_latitude, Some(-i32::MAX));
+        assert_eq!(x.sensor_longtude, Some(i32::MAX));
+        assert_eq!(x.sensor_relative_roll_angle, Some(-1));
+        assert_eq!(x.plafform_ground_speed, Some(10));
+        assert_eq!(x.frame_center_latitude, None);
+
+        // 違反は全てタグ順に返す
+        let long = "x".repeat(128);
+        let errors = UASDatalinkLS::builder()
+            .ls_version_number(8)
+            .target_location_elevation(20000.0)
+            .image_coordinate_sensor(&long)
+            .platform_heading_angle(-1.0)
+            .sensor_horizontal_fov(180.0)
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ValidationError::MissingTag(2),
+                ValidationError::OutOfRange {
+                    tag: 5,
+                    value: -1.0,
+                    min: 0.0,
+                    max: 360.0
+                },
+                ValidationError::TooLong {
+                    tag: 12,
+                    len: 128,
+                    limit: 127
+                },
+                ValidationError::OutOfRange {
+                    tag: 42,
+                    value: 20000.0,
+                    min: -900.0,
+                    max: 19000.0
+                },
+            ]
+        );
+        assert_eq!(
+            errors[3].to_string(),
+            "value 20000 of tag 42 is out of range -900..=19000"
+        );
+
+        // 設定し直すと違反は消える
+        let x = UASDatalinkLS::builder()
+            .timestamp(ts)
+            .ls_version_number(8)
+            .platform_heading_angle(400.0)
+            .image_coordinate_sensor(&long)
+            .platform_heading_angle(360.0)
+            .image_coordinate_sensor("Geodetic WGS84")
+            .build()
+            .unwrap();
+        assert_eq!(x.platform_heading_angle, u16::MAX);
+    }
 }
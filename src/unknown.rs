@@ -0,0 +1,198 @@
+//! Catch-all field preserving records of unknown tags
+//!
+//! A struct with a `UnknownTags` field renamed to `"$unknown_tags"` collects
+//! every record whose tag is not a field of the struct on deserialize,
+//! and writes them back at the position of the field on serialize.
+//! Combine with `#[serde(default)]` so that other formats can omit it.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{from_bytes, to_bytes, unknown::UnknownTags};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Packet {
+//!     #[serde(rename = "10")]
+//!     u8: u8,
+//!     #[serde(rename = "$unknown_tags", default)]
+//!     extra: UnknownTags,
+//! }
+//!
+//! let buf = [b'T', b'E', b'S', b'T', 9, 10, 1, 1, 20, 1, 2, 21, 1, 3];
+//! let mut x = from_bytes::<Packet>(&buf).unwrap();
+//! assert_eq!(x.extra.0, vec![(20, vec![2]), (21, vec![3])]);
+//!
+//! // edit a known field and re-encode without losing the unknown records
+//! x.u8 = 5;
+//! let out = to_bytes(&x).unwrap();
+//! assert_eq!(&out[4..], &[9, 10, 1, 5, 20, 1, 2, 21, 1, 3]);
+//! ```
+
+use std::fmt;
+
+use serde::{
+    de::{MapAccess, Visitor},
+    ser::SerializeSeq,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+// シリアライザ、デシリアライザにUnknownTagsのフィールドであることを伝えるための名前
+pub(crate) const UNKNOWN_TAGS: &str = "$unknown_tags";
+pub(crate) const RECORD: &str = "$serde_klv::unknown::record";
+
+/// Records of unknown tags in the order of appearance
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UnknownTags(pub Vec<(u128, Vec<u8>)>);
+
+impl Serialize for UnknownTags {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for (tag, value) in self.0.iter() {
+            seq.serialize_element(&Record(*tag, Bytes(value)))?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for UnknownTags {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(UnknownTagsVisitor)
+    }
+}
+
+// タグと値の組。シリアライザはこれをKLVのレコードに組み直す
+struct Record<'a>(u128, Bytes<'a>);
+
+impl<'a> Serialize for Record<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_struct(RECORD, &(self.0, &self.1))
+    }
+}
+
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for Bytes<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+struct ByteBuf(Vec<u8>);
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+struct ByteBufVisitor;
+
+impl<'de> Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("value bytes of a record")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(ByteBuf(v.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(ByteBuf(v))
+    }
+}
+
+struct UnknownTagsVisitor;
+
+impl<'de> Visitor<'de> for UnknownTagsVisitor {
+    type Value = UnknownTags;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("records of unknown tags")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut values = vec![];
+        while let Some((tag, ByteBuf(value))) = map.next_entry::<u128, ByteBuf>()? {
+            values.push((tag, value));
+        }
+        Ok(UnknownTags(values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::UnknownTags;
+    use crate::{from_bytes, to_bytes};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct TestChild {
+        #[serde(rename = "1")]
+        x: u8,
+        #[serde(rename = "$unknown_tags", default)]
+        extra: UnknownTags,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestParent {
+        #[serde(rename = "10")]
+        child: TestChild,
+        #[serde(rename = "11")]
+        u8: u8,
+    }
+
+    #[test]
+    fn test_unknown_tags() {
+        #[rustfmt::skip]
+        let buf = vec![
+            b'T', b'E', b'S', b'T', 14,
+            10, 6, 2, 1, 9, 1, 1, 7,
+            12, 1, 3,
+            11, 1, 4,
+        ];
+        let x = from_bytes::<TestParent>(&buf).unwrap();
+        assert_eq!(
+            x,
+            TestParent {
+                child: TestChild {
+                    x: 7,
+                    extra: UnknownTags(vec![(2, vec![9])]),
+                },
+                u8: 4,
+            }
+        );
+
+        // 親の不明なタグは捨てられ、子の不明なタグはフィールドの位置に書き戻される
+        let out = to_bytes(&x).unwrap();
+        assert_eq!(&out[4..], &[11, 10, 6, 1, 1, 7, 2, 1, 9, 11, 1, 4]);
+
+        // 既知のタグと重複する場合はエラー
+        let mut x = x;
+        x.child.extra.0.push((1, vec![0]));
+        assert!(to_bytes(&x).is_err());
+    }
+}
@@ -0,0 +1,316 @@
+//! Schema-less document of KLV packet
+//!
+//! Example
+//! ```
+//! use serde::Serialize;
+//! use serde_klv::{from_bytes_to_value, to_bytes, value::Value};
+//!
+//! #[derive(Serialize)]
+//! #[serde(rename = "TEST")]
+//! struct Packet<'a> {
+//!     #[serde(rename = "10")]
+//!     u16: u16,
+//!     #[serde(rename = "11")]
+//!     str: &'a str,
+//! }
+//!
+//! let buf = to_bytes(&Packet { u16: 1, str: "abc" }).unwrap();
+//! let x = from_bytes_to_value(&buf).unwrap();
+//! assert_eq!(
+//!     x,
+//!     Value::Packet {
+//!         universal_key: b"TEST".to_vec(),
+//!         records: vec![
+//!             (10, Value::Bytes(vec![0, 1])),
+//!             (11, Value::String("abc".to_string())),
+//!         ],
+//!     }
+//! );
+//! ```
+
+use serde::ser::{Serialize, SerializeMap, SerializeStruct, Serializer};
+
+use crate::de::KLVMap;
+use crate::error::Result;
+
+/// Parse packet to [`Value::Packet`]
+///
+/// Values are not interpreted as nested local set.
+/// Use [`Value::parse_set`] to decode them.
+pub fn from_bytes_to_value(buf: &[u8]) -> Result<Value> {
+    let map = KLVMap::try_from_bytes(buf)?;
+    Ok(Value::Packet {
+        universal_key: map.universal_key().to_vec(),
+        records: records(&map),
+    })
+}
+
+/// Tag to value tree of KLV
+///
+/// Bytes do not tell the type of the value, so [`from_bytes_to_value`] reads
+/// numbers as [`Value::Bytes`]. Convert them by [`Value::to_unsigned`],
+/// [`Value::to_signed`] or [`Value::to_float`] when the type of the tag is known.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// top level packet
+    Packet {
+        universal_key: Vec<u8>,
+        records: Vec<(u8, Value)>,
+    },
+    /// nested local set, records in order of appearance
+    Set(Vec<(u8, Value)>),
+    /// printable UTF-8 string
+    String(String),
+    /// value which is not printable string
+    Bytes(Vec<u8>),
+    /// big endian unsigned integer
+    Unsigned(u64),
+    /// big endian two's complement integer
+    Signed(i64),
+    /// IEEE 754 float of 4 or 8 bytes
+    Float(f64),
+    /// zero length value
+    Empty,
+}
+
+impl Value {
+    /// guess type of value bytes
+    pub fn from_value_bytes(bytes: &[u8]) -> Self {
+        if bytes.is_empty() {
+            return Self::Empty;
+        }
+        match std::str::from_utf8(bytes) {
+            Ok(s) if !s.chars().any(char::is_control) => Self::String(s.to_string()),
+            _ => Self::Bytes(bytes.to_vec()),
+        }
+    }
+
+    /// decode the value as nested local set
+    ///
+    /// Return `None` if the value is not bytes of local set.
+    pub fn parse_set(&self) -> Option<Self> {
+        let bytes = match self {
+            Self::String(s) => s.as_bytes(),
+            Self::Bytes(b) => b.as_slice(),
+            _ => return None,
+        };
+        let mut records = vec![];
+        let mut position = 0;
+        while position < bytes.len() {
+            let key = bytes[position];
+            let (length_len, content_len) = crate::parse_length(&bytes[position + 1..]).ok()?;
+            let start = position + 1 + length_len;
+            let value = bytes.get(start..start + content_len)?;
+            records.push((key, Self::from_value_bytes(value)));
+            position = start + content_len;
+        }
+        Some(Self::Set(records))
+    }
+
+    /// get first value of the tag in packet or set
+    pub fn get(&self, tag: u8) -> Option<&Value> {
+        match self {
+            Self::Packet { records, .. } | Self::Set(records) => {
+                records.iter().find(|(k, _)| *k == tag).map(|(_, v)| v)
+            }
+            _ => None,
+        }
+    }
+
+    /// get first value of the tag in packet or set to replace it
+    ///
+    /// Example
+    /// ```
+    /// use serde_klv::{from_bytes_to_value, value::Value};
+    ///
+    /// let buf = [b'T', b'E', b'S', b'T', 4, 10, 2, 0xff, 0xfe];
+    /// let mut x = from_bytes_to_value(&buf).unwrap();
+    /// let v = x.get_mut(10).unwrap();
+    /// *v = v.to_signed().unwrap();
+    /// assert_eq!(x.get(10), Some(&Value::Signed(-2)));
+    /// ```
+    pub fn get_mut(&mut self, tag: u8) -> Option<&mut Value> {
+        match self {
+            Self::Packet { records, .. } | Self::Set(records) => {
+                records.iter_mut().find(|(k, _)| *k == tag).map(|(_, v)| v)
+            }
+            _ => None,
+        }
+    }
+
+    /// read the value as unsigned integer of 1 to 8 bytes
+    pub fn to_unsigned(&self) -> Option<Self> {
+        let bytes = self.number_bytes()?;
+        Some(Self::Unsigned(
+            bytes.iter().fold(0_u64, |acc, b| acc << 8 | *b as u64),
+        ))
+    }
+
+    /// read the value as signed integer of 1 to 8 bytes
+    pub fn to_signed(&self) -> Option<Self> {
+        let bytes = self.number_bytes()?;
+        let v = bytes.iter().fold(0_u64, |acc, b| acc << 8 | *b as u64);
+        // 上位bitを符号で埋める
+        let shift = 64 - 8 * bytes.len() as u32;
+        Some(Self::Signed(((v << shift) as i64) >> shift))
+    }
+
+    /// read the value as float of 4 or 8 bytes
+    pub fn to_float(&self) -> Option<Self> {
+        let bytes = self.number_bytes()?;
+        match bytes.len() {
+            4 => Some(Self::Float(
+                f32::from_be_bytes(bytes.try_into().ok()?) as f64
+            )),
+            8 => Some(Self::Float(f64::from_be_bytes(bytes.try_into().ok()?))),
+            _ => None,
+        }
+    }
+
+    // 数値として読めるbyte列
+    fn number_bytes(&self) -> Option<&[u8]> {
+        let bytes = match self {
+            Self::String(s) => s.as_bytes(),
+            Self::Bytes(b) => b.as_slice(),
+            _ => return None,
+        };
+        (1..=8).contains(&bytes.len()).then_some(bytes)
+    }
+}
+
+fn records(map: &KLVMap) -> Vec<(u8, Value)> {
+    map.iter()
+        .map(|x| (x.key, Value::from_value_bytes(x.value.unwrap_or_default())))
+        .collect()
+}
+
+// タグをkeyとしたmapとして出力する
+struct Records<'a>(&'a [(u8, Value)]);
+
+impl<'a> Serialize for Records<'a> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (k, v) in self.0 {
+            map.serialize_entry(k, v)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Value::Packet {
+                universal_key,
+                records,
+            } => {
+                let mut s = serializer.serialize_struct("Packet", 2)?;
+                s.serialize_field("universal_key", universal_key)?;
+                s.serialize_field("records", &Records(records))?;
+                s.end()
+            }
+            Value::Set(records) => Records(records).serialize(serializer),
+            Value::String(v) => serializer.serialize_str(v),
+            Value::Bytes(v) => serializer.serialize_bytes(v),
+            Value::Unsigned(v) => serializer.serialize_u64(*v),
+            Value::Signed(v) => serializer.serialize_i64(*v),
+            Value::Float(v) => serializer.serialize_f64(*v),
+            Value::Empty => serializer.serialize_unit(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+
+    use crate::{from_bytes_to_value, to_bytes, value::Value};
+
+    #[derive(Debug, Serialize)]
+    #[serde(rename = "TEST")]
+    struct TestValue<'a> {
+        #[serde(rename = "10")]
+        u8: u8,
+        #[serde(rename = "11")]
+        str: &'a str,
+        #[serde(rename = "12")]
+        none: Option<u8>,
+        #[serde(rename = "48")]
+        child: TestChild<'a>,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct TestChild<'a> {
+        #[serde(rename = "1")]
+        u16: u16,
+        #[serde(rename = "3")]
+        str: &'a str,
+    }
+
+    #[test]
+    fn test_value() {
+        let t = TestValue {
+            u8: 0x0a,
+            str: "string",
+            none: None,
+            child: TestChild {
+                u16: 0xffff,
+                str: "//US",
+            },
+        };
+        let buf = to_bytes(&t).unwrap();
+        let x = from_bytes_to_value(&buf).unwrap();
+        assert_eq!(x.get(10), Some(&Value::Bytes(vec![0x0a])));
+        assert_eq!(x.get(11), Some(&Value::String("string".to_string())));
+        assert_eq!(x.get(12), Some(&Value::Empty));
+        assert_eq!(x.get(13), None);
+        let child = x.get(48).unwrap().parse_set().unwrap();
+        assert_eq!(
+            child,
+            Value::Set(vec![
+                (1, Value::Bytes(vec![0xff, 0xff])),
+                (3, Value::String("//US".to_string())),
+            ])
+        );
+        // 入れ子のLocal Setではない
+        assert!(x.get(11).unwrap().parse_set().is_none());
+
+        let json = serde_json::to_string(&x).unwrap();
+        assert_eq!(
+            json,
+            r#"{"universal_key":[84,69,83,84],"records":{"10":[10],"11":"string","12":null,"48":[1,2,255,255,3,4,47,47,85,83]}}"#
+        );
+        let json = serde_json::to_string(&child).unwrap();
+        assert_eq!(json, r#"{"1":[255,255],"3":"//US"}"#);
+    }
+
+    #[test]
+    fn test_typed_value() {
+        let v = Value::Bytes(vec![0xff, 0xfe]);
+        assert_eq!(v.to_unsigned(), Some(Value::Unsigned(0xfffe)));
+        assert_eq!(v.to_signed(), Some(Value::Signed(-2)));
+        assert_eq!(v.to_float(), None);
+        let v = Value::Bytes(1.5_f32.to_be_bytes().to_vec());
+        assert_eq!(v.to_float(), Some(Value::Float(1.5)));
+        let v = Value::Bytes((-0.25_f64).to_be_bytes().to_vec());
+        assert_eq!(v.to_float(), Some(Value::Float(-0.25)));
+        assert_eq!(
+            Value::Bytes(vec![0x80; 8]).to_signed(),
+            Some(Value::Signed(i64::from_be_bytes([0x80; 8])))
+        );
+        // 8byteを超える値と空の値は数値ではない
+        assert_eq!(Value::Bytes(vec![0; 9]).to_unsigned(), None);
+        assert_eq!(Value::Empty.to_unsigned(), None);
+
+        let mut set = Value::Set(vec![(1, Value::Bytes(vec![0, 1])), (2, Value::Empty)]);
+        let v = set.get_mut(1).unwrap();
+        *v = v.to_unsigned().unwrap();
+        assert_eq!(serde_json::to_string(&set).unwrap(), r#"{"1":1,"2":null}"#);
+    }
+}
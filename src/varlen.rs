@@ -0,0 +1,345 @@
+//! Variable-length encoding for unsigned integer fields
+//!
+//! Write the value with the fewest big-endian bytes needed
+//! and reconstruct it from the length of the record on deserialize.
+//! [`option`] is for `Option` fields and [`signed`] for two's complement integers.
+//!
+//! Example
+//!
+//! ```rust
+//! use serde::{Deserialize, Serialize};
+//! use serde_klv::{from_bytes, to_bytes};
+//!
+//! #[derive(Debug, Serialize, Deserialize, PartialEq)]
+//! #[serde(rename = "TEST")]
+//! struct Counter {
+//!     #[serde(rename = "10", with = "serde_klv::varlen")]
+//!     count: u64,
+//! }
+//!
+//! let t = Counter { count: 300 };
+//! let buf = to_bytes(&t).unwrap();
+//! // UK(4) + L(1) + K(1) + L(1) + V(2)
+//! assert_eq!(buf.len(), 9);
+//! assert_eq!(from_bytes::<Counter>(&buf).unwrap(), t);
+//! ```
+
+use std::fmt;
+
+use serde::{
+    de::{self, Visitor},
+    Deserializer, Serializer,
+};
+
+/// serialize unsigned integer by minimal big-endian bytes
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Copy + Into<u64>,
+    S: Serializer,
+{
+    let v: u64 = (*value).into();
+    let buf = v.to_be_bytes();
+    // 0であっても1byteは出力する
+    let skip = (v.leading_zeros() as usize / 8).min(buf.len() - 1);
+    serializer.serialize_bytes(&buf[skip..])
+}
+
+/// deserialize unsigned integer from 1 to 8 bytes
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: TryFrom<u64>,
+    D: Deserializer<'de>,
+{
+    let v = deserializer.deserialize_bytes(VarlenVisitor)?;
+    T::try_from(v).map_err(|_| de::Error::custom(format!("varlen value {} is out of range", v)))
+}
+
+/// `Option` of unsigned integer, `None` is a zero-length record
+///
+/// Add `default` to the field to read a packet without the tag.
+pub mod option {
+    use serde::{de, Deserializer, Serializer};
+
+    use super::{OptionVisitor, VarlenVisitor};
+
+    pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Copy + Into<u64>,
+        S: Serializer,
+    {
+        match value {
+            Some(v) => super::serialize(v, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        T: TryFrom<u64>,
+        D: Deserializer<'de>,
+    {
+        match deserializer.deserialize_option(OptionVisitor(VarlenVisitor))? {
+            Some(v) => T::try_from(v)
+                .map(Some)
+                .map_err(|_| de::Error::custom(format!("varlen value {} is out of range", v))),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Variable-length two's complement integer, e.g. ST 0601 tag 136 and 137
+pub mod signed {
+    use serde::{de, Deserializer, Serializer};
+
+    use super::SignedVisitor;
+
+    /// serialize signed integer by minimal big-endian bytes
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Copy + Into<i64>,
+        S: Serializer,
+    {
+        let v: i64 = (*value).into();
+        let buf = v.to_be_bytes();
+        // 符号bitを残せる範囲で先頭の0x00か0xffを省く
+        let redundant = if v < 0 {
+            v.leading_ones()
+        } else {
+            v.leading_zeros()
+        };
+        let skip = ((redundant as usize - 1) / 8).min(buf.len() - 1);
+        serializer.serialize_bytes(&buf[skip..])
+    }
+
+    /// deserialize signed integer from 1 to 8 bytes
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: TryFrom<i64>,
+        D: Deserializer<'de>,
+    {
+        let v = deserializer.deserialize_bytes(SignedVisitor)?;
+        T::try_from(v).map_err(|_| de::Error::custom(format!("varlen value {} is out of range", v)))
+    }
+
+    /// `Option` of signed integer, `None` is a zero-length record
+    pub mod option {
+        use serde::{de, Deserializer, Serializer};
+
+        use super::super::{OptionVisitor, SignedVisitor};
+
+        pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            T: Copy + Into<i64>,
+            S: Serializer,
+        {
+            match value {
+                Some(v) => super::serialize(v, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+        where
+            T: TryFrom<i64>,
+            D: Deserializer<'de>,
+        {
+            match deserializer.deserialize_option(OptionVisitor(SignedVisitor))? {
+                Some(v) => T::try_from(v)
+                    .map(Some)
+                    .map_err(|_| de::Error::custom(format!("varlen value {} is out of range", v))),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+// 長さ0のレコードをNoneとして読む
+struct OptionVisitor<V>(V);
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for OptionVisitor<V> {
+    type Value = Option<V::Value>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.0.expecting(formatter)
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(None)
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(self.0).map(Some)
+    }
+}
+
+struct SignedVisitor;
+
+impl<'de> Visitor<'de> for SignedVisitor {
+    type Value = i64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("big-endian two's complement integer up to 8 bytes")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        if v.is_empty() || v.len() > 8 {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        // 先頭byteの符号で上位を埋める
+        let init = if v[0] & 0x80 != 0 { -1_i64 } else { 0 };
+        Ok(v.iter().fold(init, |acc, b| (acc << 8) | *b as i64))
+    }
+}
+
+struct VarlenVisitor;
+
+impl<'de> Visitor<'de> for VarlenVisitor {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("big-endian unsigned integer from 1 to 8 bytes")
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        // 長さ0はsignedと同じく不正な長さとする。OptionではNoneとして読まれここには来ない
+        if v.is_empty() || v.len() > 8 {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        Ok(v.iter().fold(0_u64, |acc, b| (acc << 8) | *b as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use crate::{from_bytes, to_bytes, KLVMap};
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(rename = "TEST")]
+    struct TestVarlen {
+        #[serde(rename = "10", with = "crate::varlen")]
+        u64: u64,
+        #[serde(rename = "11", with = "crate::varlen")]
+        u32: u32,
+        #[serde(rename = "12", with = "crate::varlen")]
+        u8: u8,
+    }
+
+    #[test]
+    fn test_varlen_length() {
+        let cases = [
+            (0_u64, 1),
+            (255, 1),
+            (256, 2),
+            (u32::MAX as u64, 4),
+            (u32::MAX as u64 + 1, 5),
+            (u64::MAX, 8),
+        ];
+        for (v, expect_len) in cases {
+            let t = TestVarlen {
+                u64: v,
+                u32: 0,
+                u8: 0,
+            };
+            let buf = to_bytes(&t).unwrap();
+            let map = KLVMap::try_from_bytes(&buf).unwrap();
+            let record = map.iter().find(|x| x.key == 10).unwrap();
+            assert_eq!(record.length, expect_len);
+            let x = from_bytes::<TestVarlen>(&buf).unwrap();
+            assert_eq!(t, x);
+        }
+    }
+
+    #[test]
+    fn test_varlen_out_of_range() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestNarrow {
+            #[serde(rename = "10", with = "crate::varlen")]
+            u8: u8,
+        }
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestWide {
+            #[serde(rename = "10", with = "crate::varlen")]
+            u16: u16,
+        }
+        let buf = to_bytes(&TestWide { u16: 256 }).unwrap();
+        assert!(from_bytes::<TestNarrow>(&buf).is_err());
+        let buf = to_bytes(&TestWide { u16: 255 }).unwrap();
+        assert_eq!(
+            from_bytes::<TestNarrow>(&buf).unwrap(),
+            TestNarrow { u8: 255 }
+        );
+
+        // 長さ0は0として読まない
+        assert!(from_bytes::<TestNarrow>(&[b'T', b'E', b'S', b'T', 2, 10, 0]).is_err());
+    }
+
+    #[test]
+    fn test_varlen_signed_option() {
+        #[derive(Debug, Serialize, Deserialize, PartialEq)]
+        #[serde(rename = "TEST")]
+        struct TestSigned {
+            #[serde(rename = "10", with = "crate::varlen::signed")]
+            i64: i64,
+            #[serde(rename = "11", with = "crate::varlen::signed::option", default)]
+            i32: Option<i32>,
+            #[serde(rename = "12", with = "crate::varlen::option", default)]
+            u32: Option<u32>,
+        }
+        let cases = [
+            (0_i64, 1),
+            (127, 1),
+            (128, 2),
+            (-1, 1),
+            (-128, 1),
+            (-129, 2),
+            (i64::MIN, 8),
+            (i64::MAX, 8),
+        ];
+        for (v, expect_len) in cases {
+            let t = TestSigned {
+                i64: v,
+                i32: None,
+                u32: None,
+            };
+            let buf = to_bytes(&t).unwrap();
+            let map = KLVMap::try_from_bytes(&buf).unwrap();
+            let record = map.iter().find(|x| x.key == 10).unwrap();
+            assert_eq!(record.length, expect_len, "{}", v);
+            assert_eq!(from_bytes::<TestSigned>(&buf).unwrap(), t);
+        }
+
+        let t = TestSigned {
+            i64: 0,
+            i32: Some(-300),
+            u32: Some(300),
+        };
+        let buf = to_bytes(&t).unwrap();
+        assert_eq!(&buf[5..], &[10, 1, 0, 11, 2, 0xfe, 0xd4, 12, 2, 0x01, 0x2c]);
+        assert_eq!(from_bytes::<TestSigned>(&buf).unwrap(), t);
+        // タグがなければNone
+        assert_eq!(
+            from_bytes::<TestSigned>(&[b'T', b'E', b'S', b'T', 3, 10, 1, 0xff]).unwrap(),
+            TestSigned {
+                i64: -1,
+                i32: None,
+                u32: None,
+            }
+        );
+    }
+}